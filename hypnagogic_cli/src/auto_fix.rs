@@ -0,0 +1,85 @@
+use std::path::Path;
+use std::{fs, io};
+
+use hypnagogic_core::config::blocks::cutters::Prefab;
+use hypnagogic_core::operations::IconOperation;
+use owo_colors::OwoColorize;
+use toml_edit::{value, Document, Item};
+use tracing::warn;
+
+use crate::error::Error;
+
+/// Moves prefabs that share a position with a corner block to free slots,
+/// rewriting the `[prefabs]` of the config at `path` in place so its comments
+/// and formatting survive. Prefabs that come from a template are only warned
+/// about, since fixing the template would change every config using it
+#[allow(clippy::result_large_err)]
+pub fn fix_prefab_overlaps(path: &Path, operation: &mut IconOperation) -> Result<(), Error> {
+    let Some(config) = operation.bitmask_slice_mut() else {
+        return Ok(());
+    };
+    let fixes = config.prefab_overlap_fixes();
+    if fixes.is_empty() {
+        return Ok(());
+    }
+
+    if path.extension().is_none_or(|extension| extension != "toml") {
+        warn!(
+            path = ?path,
+            "Prefabs overlap corner positions, but only toml configs can be fixed automatically"
+        );
+        return Ok(());
+    }
+
+    let text = fs::read_to_string(path)?;
+    let mut document: Document = text
+        .parse()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let Some(prefabs) = document
+        .get_mut("prefabs")
+        .and_then(Item::as_table_like_mut)
+        .filter(|prefabs| {
+            fixes
+                .keys()
+                .all(|junction| prefabs.contains_key(&junction.to_string()))
+        })
+    else {
+        warn!(
+            path = ?path,
+            "Overlapping prefabs come from a template, fix them in the template instead"
+        );
+        return Ok(());
+    };
+    for (junction, position) in &fixes {
+        let Some(item) = prefabs.get_mut(&junction.to_string()) else {
+            continue;
+        };
+        // keeps any comment after the old value
+        let decor = item.as_value().map(|old| old.decor().clone());
+        *item = value(i64::from(*position));
+        if let (Some(decor), Some(new)) = (decor, item.as_value_mut()) {
+            *new.decor_mut() = decor;
+        }
+    }
+    fs::write(path, document.to_string())?;
+
+    let moves = fixes
+        .iter()
+        .map(|(junction, position)| format!("{junction} to {position}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    println!(
+        "{}",
+        format!("Moved overlapping prefabs in {}: {moves}", path.display()).yellow()
+    );
+    config
+        .prefabs
+        .get_or_insert_with(Default::default)
+        .0
+        .extend(
+            fixes
+                .into_iter()
+                .map(|(junction, position)| (junction, Prefab::sheet(position))),
+        );
+    Ok(())
+}
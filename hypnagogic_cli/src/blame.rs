@@ -0,0 +1,64 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use hypnagogic_core::config::key_sources::KeySource;
+use hypnagogic_core::config::read_config_sources;
+use owo_colors::OwoColorize;
+use user_error::UFE;
+
+use crate::{config_dir, config_error, config_format, TemplateSources};
+
+/// Prints every value of the config at `path` once its templates are
+/// resolved, with the template or config that set it. Only keys under one of
+/// `keys` are printed, if any are given
+/// # Errors
+/// Errors if the config can't be read or its templates can't be resolved
+pub fn blame(path: &Path, keys: &[String], templates: &TemplateSources) -> Result<()> {
+    let file = File::open(path)?;
+    let resolved = match read_config_sources(
+        &mut BufReader::new(file),
+        config_format(path),
+        config_dir(path),
+        templates,
+    ) {
+        Ok(resolved) => resolved,
+        Err(err) => {
+            config_error(path, templates, err).print();
+            return Err(anyhow!("Couldn't resolve {}", path.display()));
+        }
+    };
+    let resolved: Vec<_> = resolved
+        .into_iter()
+        .filter(|key| {
+            keys.is_empty()
+                || keys.iter().any(|wanted| {
+                    key.path == *wanted || key.path.starts_with(&format!("{wanted}."))
+                })
+        })
+        .collect();
+    if resolved.is_empty() {
+        return Err(anyhow!(
+            "{} doesn't set any of {}",
+            path.display(),
+            keys.join(", ")
+        ));
+    }
+
+    println!("{}", path.display().blue().italic());
+    let assignments: Vec<String> = resolved
+        .iter()
+        .map(|key| format!("{} = {}", key.path, key.value))
+        .collect();
+    let width = assignments.iter().map(String::len).max().unwrap_or(0);
+    for (assignment, key) in assignments.iter().zip(&resolved) {
+        let source = format!("from {}", key.source);
+        match key.source {
+            KeySource::Config => println!("{assignment:width$}  {}", source.dimmed()),
+            KeySource::Template(_) => println!("{assignment:width$}  {}", source.yellow()),
+            KeySource::Include(_) => println!("{assignment:width$}  {}", source.cyan()),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use std::fs;
+use std::path::Path;
+
+use dmi::icon::Icon;
+use hypnagogic_core::operations::OutputError;
+use hypnagogic_core::util::file_safe_name;
+use hypnagogic_core::util::icon_diff::{before_after_composite, diff_icons, StateChange};
+use tracing::info;
+
+use crate::error::Error;
+
+/// Compares a freshly generated dmi with the one it's about to replace, writing
+/// a summary, a before/after composite and an image per changed state into a
+/// `-DIFF` folder next to it. Nothing is written if the two are identical
+#[allow(clippy::result_large_err)]
+pub fn write_icon_diff(path: &Path, old: &Icon, new: &Icon) -> Result<(), Error> {
+    let stem = path.file_stem().unwrap().to_string_lossy();
+    let diff_dir = path.with_file_name(format!("{stem}-DIFF"));
+    // stale diffs from a previous run would be misleading
+    if diff_dir.exists() {
+        fs::remove_dir_all(&diff_dir)?;
+    }
+
+    let diff = diff_icons(old, new);
+    if diff.is_identical() {
+        info!(path = ?path, "Output is identical to the existing dmi");
+        return Ok(());
+    }
+
+    fs::create_dir_all(&diff_dir)?;
+    let summary = diff.summary();
+    info!(path = ?path, summary = %summary, "Output differs from the existing dmi");
+    fs::write(diff_dir.join("summary.txt"), summary)?;
+    if let Some(composite) = before_after_composite(old, new, &diff) {
+        composite
+            .save(diff_dir.join("before-after.png"))
+            .map_err(OutputError::from)?;
+    }
+    for state in &diff.states {
+        let StateChange::Changed {
+            diff: Some(image), ..
+        } = &state.change
+        else {
+            continue;
+        };
+        let mut file_name = file_safe_name(&state.name);
+        if state.movement {
+            file_name.push_str("-movement");
+        }
+        image
+            .save(diff_dir.join(format!("{file_name}.png")))
+            .map_err(OutputError::from)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use hypnagogic_core::util::image_hash::ImageHashes;
+use image::DynamicImage;
+use owo_colors::OwoColorize;
+
+/// Perceptual hash distance at or under which two sheets count as near
+/// identical
+const NEAR_IDENTICAL_DISTANCE: u32 = 6;
+
+/// Collects hashes of every input sheet in a run, to report copies of the same
+/// art at the end
+#[derive(Default)]
+pub struct DuplicateFinder {
+    sheets: Mutex<Vec<(PathBuf, ImageHashes)>>,
+}
+
+impl DuplicateFinder {
+    pub fn add_sheet(&self, path: &Path, image: &DynamicImage) {
+        let hashes = ImageHashes::new(image);
+        self.sheets
+            .lock()
+            .unwrap()
+            .push((path.to_path_buf(), hashes));
+    }
+
+    /// Prints every group of identical sheets, and every pair of near
+    /// identical or same shaped sheets
+    pub fn report(&self) {
+        let mut sheets = self.sheets.lock().unwrap();
+        // inputs are processed in parallel, so sort to keep reports stable
+        sheets.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut identical: BTreeMap<u64, Vec<&Path>> = BTreeMap::new();
+        for (path, hashes) in sheets.iter() {
+            identical.entry(hashes.exact).or_default().push(path);
+        }
+        let identical: Vec<Vec<&Path>> = identical
+            .into_values()
+            .filter(|paths| paths.len() > 1)
+            .collect();
+
+        let mut near_identical = vec![];
+        let mut recolors = vec![];
+        for (index, (path, hashes)) in sheets.iter().enumerate() {
+            for (other_path, other_hashes) in &sheets[index + 1..] {
+                if hashes.exact == other_hashes.exact {
+                    continue;
+                }
+                let distance = hashes.distance(other_hashes);
+                if distance <= NEAR_IDENTICAL_DISTANCE {
+                    near_identical.push((path, other_path, distance));
+                } else if hashes.shape.is_some() && hashes.shape == other_hashes.shape {
+                    recolors.push((path, other_path));
+                }
+            }
+        }
+
+        if identical.is_empty() && near_identical.is_empty() && recolors.is_empty() {
+            println!("{}", "No duplicate input sheets found".green());
+            return;
+        }
+        if !identical.is_empty() {
+            println!(
+                "{}",
+                "Identical input sheets, consider using one input with a template:".yellow()
+            );
+            for paths in identical {
+                let paths: Vec<String> = paths
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect();
+                println!("  {}", paths.join(", "));
+            }
+        }
+        if !near_identical.is_empty() {
+            println!("{}", "Near identical input sheets:".yellow());
+            for (path, other_path, distance) in near_identical {
+                println!(
+                    "  {} and {} (distance {distance})",
+                    path.display(),
+                    other_path.display()
+                );
+            }
+        }
+        if !recolors.is_empty() {
+            println!(
+                "{}",
+                "Input sheets with the same shape but different colors, possibly recolors:"
+                    .yellow()
+            );
+            for (path, other_path) in recolors {
+                println!("  {} and {}", path.display(), other_path.display());
+            }
+        }
+    }
+}
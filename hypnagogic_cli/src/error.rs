@@ -1,7 +1,8 @@
 use std::io;
 use std::path::PathBuf;
 
-use hypnagogic_core::config::error::ConfigError;
+use hypnagogic_core::config::error::{ConfigError, ParseError};
+use hypnagogic_core::config::unknown_keys::UnknownKey;
 use hypnagogic_core::operations::error::ProcessorError;
 use hypnagogic_core::operations::{InputError, OutputError};
 use thiserror::Error;
@@ -20,12 +21,40 @@ pub enum Error {
         source_config: String,
         config_error: ConfigError,
     },
+    #[error("Invalid Template")]
+    InvalidTemplate {
+        source_config: String,
+        template: String,
+        error: ParseError,
+    },
+    #[error("Unknown Config Keys")]
+    UnknownKeys {
+        source_config: String,
+        keys: Vec<UnknownKey>,
+    },
     #[error("Template Not Found")]
     TemplateNotFound {
         source_config: String,
         template_string: String,
         expected_path: PathBuf,
+        /// Closest available templates, closest first
+        suggestions: Vec<String>,
+        available: Vec<String>,
     },
+    #[error("Template Not Cached")]
+    TemplateNotCached {
+        source_config: String,
+        template_string: String,
+        cache_path: PathBuf,
+    },
+    #[error("Remote Template Failed")]
+    RemoteTemplateFailed {
+        source_config: String,
+        template_string: String,
+        reason: String,
+    },
+    #[error("Operation Needs A Config")]
+    NeedsConfig { input: String, operation: String },
     #[error("Image Parsing Failed")]
     InputParsingFailed(#[from] InputError),
     #[error("Processing Failed")]
@@ -36,6 +65,85 @@ pub enum Error {
     NoTemplateFolder(PathBuf),
     #[error("Generic IO Error")]
     IO(#[from] io::Error),
+    #[error("Found {} Problems", .0.len())]
+    Multiple(Vec<Error>),
+}
+
+impl Error {
+    /// Combines every problem found with a file into one error to report them
+    /// together. Problems that read the same as an earlier one are dropped
+    ///
+    /// # Panics
+    /// If there are no problems
+    #[must_use]
+    pub fn all(problems: Vec<Error>) -> Self {
+        let mut distinct: Vec<Error> = vec![];
+        for problem in problems {
+            let seen = distinct.iter().any(|existing| {
+                existing.summary() == problem.summary() && existing.reasons() == problem.reasons()
+            });
+            if !seen {
+                distinct.push(problem);
+            }
+        }
+        match distinct.len() {
+            0 => panic!("No problems to report"),
+            1 => distinct.remove(0),
+            _ => Error::Multiple(distinct),
+        }
+    }
+}
+
+/// Most available templates listed when one isn't found
+const MAX_LISTED_TEMPLATES: usize = 20;
+
+/// Joins the first `max` of `items`, noting how many were left out
+fn list_truncated(items: &[String], max: usize) -> String {
+    let listed = items
+        .iter()
+        .take(max)
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(", ");
+    if items.len() > max {
+        format!("{listed} and {} more", items.len() - max)
+    } else {
+        listed
+    }
+}
+
+/// Describes a parse error in `file`, showing the line it's on when known
+fn describe_parse_error(file: &str, error: &ParseError) -> String {
+    let Some(location) = &error.location else {
+        return format!("In {file}:\n{}", error.message);
+    };
+    let gutter = " ".repeat(location.line.to_string().len());
+    format!(
+        "In {file}, line {}, column {}:\n{gutter} |\n{} | {}\n{gutter} | {}^\n{}",
+        location.line,
+        location.column,
+        location.line,
+        location.line_text,
+        " ".repeat(location.column - 1),
+        error.message
+    )
+}
+
+/// Describes an error reading the config `file`, naming the included config
+/// it's really in if it's in one
+fn describe_config_error(file: &str, error: &ConfigError) -> String {
+    match error {
+        ConfigError::Parse(parse_error) => describe_parse_error(file, parse_error),
+        ConfigError::Include { path, source }
+            if matches!(
+                source.as_ref(),
+                ConfigError::Parse(_) | ConfigError::Include { .. }
+            ) =>
+        {
+            describe_config_error(&path.display().to_string(), source)
+        }
+        _ => error.to_string(),
+    }
 }
 
 impl UFE for Error {
@@ -62,13 +170,34 @@ impl UFE for Error {
             } => {
                 Some(vec![
                     format!("Error within config \"{source_config}\""),
-                    format!("{}", config_error),
+                    describe_config_error(source_config, config_error),
                 ])
             }
+            Error::InvalidTemplate {
+                source_config,
+                template,
+                error,
+            } => {
+                Some(vec![
+                    format!("Failed to read a template referenced in a config ({source_config})"),
+                    describe_parse_error(&format!("template `{template}`"), error),
+                ])
+            }
+            Error::UnknownKeys {
+                source_config,
+                keys,
+            } => {
+                let mut reasons = vec![format!(
+                    "Config \"{source_config}\" sets keys that nothing reads"
+                )];
+                reasons.extend(keys.iter().map(ToString::to_string));
+                Some(reasons)
+            }
             Error::TemplateNotFound {
                 source_config,
                 template_string,
                 expected_path,
+                ..
             } => {
                 Some(vec![
                     format!("Failed to find the template referenced in a config ({source_config})"),
@@ -76,12 +205,45 @@ impl UFE for Error {
                     format!("Expected to find a config at {expected_path:?}"),
                 ])
             }
+            Error::TemplateNotCached {
+                source_config,
+                template_string,
+                cache_path,
+            } => {
+                Some(vec![
+                    format!(
+                        "Failed to resolve a template referenced in a config ({source_config})"
+                    ),
+                    format!("Config string was \"{template_string}\""),
+                    format!("Running offline, and it isn't cached at {cache_path:?}"),
+                ])
+            }
+            Error::RemoteTemplateFailed {
+                source_config,
+                template_string,
+                reason,
+            } => {
+                Some(vec![
+                    format!(
+                        "Failed to resolve a remote template referenced in a config \
+                         ({source_config})"
+                    ),
+                    format!("Config string was \"{template_string}\""),
+                    reason.clone(),
+                ])
+            }
             Error::NoTemplateFolder(folder) => {
                 Some(vec![
                     format!("Failed to find template folder"),
                     format!("Expected template folder at {folder:?}"),
                 ])
             }
+            Error::NeedsConfig { input, operation } => {
+                Some(vec![
+                    format!("No config found for \"{input}\""),
+                    format!("{operation} has settings that can't be defaulted"),
+                ])
+            }
             Error::InputParsingFailed(image_error) => image_error.reasons(),
             Error::ProcessorFailed(process_error) => process_error.reasons(),
             Error::OutputWriteFailed(output_error) => output_error.reasons(),
@@ -91,6 +253,25 @@ impl UFE for Error {
                     err.kind()
                 )])
             }
+            Error::Multiple(problems) => {
+                Some(
+                    problems
+                        .iter()
+                        .flat_map(|problem| {
+                            let summary = problem.summary();
+                            match problem.reasons() {
+                                Some(reasons) if !reasons.is_empty() => {
+                                    reasons
+                                        .into_iter()
+                                        .map(|reason| format!("{summary}: {reason}"))
+                                        .collect()
+                                }
+                                _ => vec![summary],
+                            }
+                        })
+                        .collect(),
+                )
+            }
         }
     }
 
@@ -108,9 +289,52 @@ impl UFE for Error {
                         .to_string(),
                 )
             }
-            Error::TemplateNotFound { .. } => {
+            Error::InvalidTemplate { .. } => {
+                Some("Fix the error in the template, it isn't valid toml".to_string())
+            }
+            Error::UnknownKeys { .. } => {
+                Some("Fix or remove the keys, or run without --strict to ignore them".to_string())
+            }
+            Error::TemplateNotFound {
+                suggestions,
+                available,
+                ..
+            } => {
+                let mut help = match suggestions.as_slice() {
+                    [] => {
+                        "Make sure you have spelled the template correctly, and that it exists"
+                            .to_string()
+                    }
+                    [suggestion] => format!("Did you mean `{suggestion}`?"),
+                    [rest @ .., last] => {
+                        format!(
+                            "Did you mean {} or `{last}`?",
+                            rest.iter()
+                                .map(|suggestion| format!("`{suggestion}`"))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )
+                    }
+                };
+                if !available.is_empty() {
+                    help.push_str(&format!(
+                        "\nAvailable templates: {}",
+                        list_truncated(available, MAX_LISTED_TEMPLATES)
+                    ));
+                }
+                Some(help)
+            }
+            Error::TemplateNotCached { .. } => {
+                Some(
+                    "Run once with network access to cache the template, or turn off offline mode \
+                     (--offline or HYPNAGOGIC_OFFLINE)"
+                        .to_string(),
+                )
+            }
+            Error::RemoteTemplateFailed { .. } => {
                 Some(
-                    "Make sure you have spelled the template correctly, and that it exists"
+                    "Check the remote_templates in hypnagogic.toml, and if a checksum doesn't \
+                     match, that the pack hasn't changed unexpectedly before updating it"
                         .to_string(),
                 )
             }
@@ -121,6 +345,12 @@ impl UFE for Error {
                         .to_string(),
                 )
             }
+            Error::NeedsConfig { input, .. } => {
+                Some(format!(
+                    "Write a config for \"{input}\", or pick an operation that works with its \
+                     defaults"
+                ))
+            }
             Error::InputParsingFailed(image_error) => image_error.helptext(),
             Error::ProcessorFailed(process_error) => process_error.helptext(),
             Error::OutputWriteFailed(output_error) => output_error.helptext(),
@@ -131,6 +361,15 @@ impl UFE for Error {
                         .to_string(),
                 )
             }
+            Error::Multiple(problems) => {
+                let mut helptexts: Vec<String> = vec![];
+                for helptext in problems.iter().filter_map(UFE::helptext) {
+                    if !helptexts.contains(&helptext) {
+                        helptexts.push(helptext);
+                    }
+                }
+                (!helptexts.is_empty()).then(|| helptexts.join("\n"))
+            }
         }
     }
 }
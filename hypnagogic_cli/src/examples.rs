@@ -0,0 +1,135 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use owo_colors::OwoColorize;
+
+/// Embeds the inputs of an example from `examples/corpus`, as its name and
+/// every file with its contents
+macro_rules! example {
+    ($name:literal, [$($file:literal),+ $(,)?]) => {
+        (
+            $name,
+            &[$((
+                $file,
+                include_bytes!(concat!("../../examples/corpus/", $name, "/input/", $file))
+                    as &[u8],
+            )),+],
+        )
+    };
+}
+
+type Example = (&'static str, &'static [(&'static str, &'static [u8])]);
+
+/// The same examples `cargo test` checks the output of, so they're known to
+/// work as starting points
+const EXAMPLES: &[Example] = &[
+    example!("bitmask-slice", ["wall.png", "wall.png.toml"]),
+    example!("bitmask-slice-diagonals", ["wall.png", "wall.png.toml"]),
+    example!("bitmask-slice-groups", ["wall.png", "wall.png.toml"]),
+    example!("bitmask-directional-vis", ["wall.png", "wall.png.toml"]),
+    example!("bitmask-windows", ["window.png", "window.png.toml"]),
+    example!("bitmask-slice-reconstruct", ["wall.dmi", "wall.dmi.toml"]),
+    example!(
+        "bitmask-slice-shared-sheet",
+        ["walls.png", "walls-prefab.toml", "walls-dirs.toml"]
+    ),
+    example!(
+        "bitmask-slice-layers",
+        ["wall.png", "decals.png", "grime.png", "wall.png.toml"]
+    ),
+    example!("bitmask-slice-manifest", ["wall.png", "build.toml"]),
+    example!("bitmask-slice-variants", ["wall.png", "wall.png.toml"]),
+    example!(
+        "bitmask-slice-corner-atlas",
+        ["wall.png", "edges.png", "wall.png.toml"]
+    ),
+    example!("bitmask-slice-post-process", ["wall.png", "wall.png.toml"]),
+    example!(
+        "bitmask-slice-greyscale",
+        ["wall.png", "wall-mask.png", "wall.png.toml"]
+    ),
+    example!("bitmask-slice-recolors", ["wall.png", "wall.png.toml"]),
+    example!(
+        "bitmask-slice-damage",
+        ["wall.png", "cracks.png", "wall.png.toml"]
+    ),
+    example!("turf-edges", ["grass.png", "grass.png.toml"]),
+    example!("bitmask-pipes", ["cable.png", "cable.png.toml"]),
+    example!("bitmask-lattice", ["lattice.png", "lattice.png.toml"]),
+    example!("bitmask-corner-overlays", ["wall.png", "wall.png.toml"]),
+    example!(
+        "directional-rotation",
+        ["conveyor.png", "conveyor.png.toml"]
+    ),
+    example!(
+        "bitmask-texture-mask",
+        ["rock.png", "bricks.png", "rock.png.toml"]
+    ),
+    example!("bitmask-wall-tops", ["wall.png", "wall.png.toml"]),
+    example!("grid-slice", ["items.png", "items.png.toml"]),
+    example!("glyph-sheet", ["digits.png", "digits.png.toml"]),
+    example!(
+        "dmi-merge",
+        ["doors.dmi", "doors-extra.dmi", "doors.dmi.toml"]
+    ),
+    example!("dmi-split", ["legacy.dmi", "legacy.dmi.toml"]),
+    example!("dmi-rename", ["wall.dmi", "wall.dmi.toml"]),
+    example!("dmi-optimize", ["lamp.dmi", "lamp.dmi.toml"]),
+    example!(
+        "dmi-validate",
+        [
+            "old-wall.dmi",
+            "old-wall.dmi.toml",
+            "wall.png",
+            "wall.png.toml"
+        ]
+    ),
+    example!("dmi-export", ["lamp.dmi", "lamp.dmi.toml"]),
+    example!("dmi-import", ["lamp.png", "lamp.png.toml", "lamp.json"]),
+    example!(
+        "dmi-aseprite",
+        [
+            "lamp.dmi",
+            "lamp.dmi.toml",
+            "lamp-edit.aseprite",
+            "lamp-edit.aseprite.toml"
+        ]
+    ),
+    example!(
+        "bitmask-slice-diagonal-walls",
+        ["shuttle.png", "shuttle.png.toml"]
+    ),
+];
+
+/// Copies every example to its own folder in `dir`. Files that already exist
+/// are left alone, so edited copies aren't lost by running it again
+/// # Errors
+/// Errors if a folder or file can't be written
+pub fn copy_examples(dir: &Path) -> Result<()> {
+    for (name, files) in EXAMPLES {
+        let example_dir = dir.join(name);
+        fs::create_dir_all(&example_dir)?;
+        for (file_name, contents) in *files {
+            let path = example_dir.join(file_name);
+            if path.exists() {
+                println!(
+                    "{}",
+                    format!("Skipped {}, it already exists", path.display()).yellow()
+                );
+                continue;
+            }
+            fs::write(&path, contents)?;
+        }
+    }
+    println!(
+        "{}",
+        format!(
+            "Copied {} examples to {}, run hypnagogic on it to cut them",
+            EXAMPLES.len(),
+            dir.display()
+        )
+        .bright_green()
+    );
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use anyhow::{anyhow, Result};
+use hypnagogic_core::generation::junction_diagram::generate_junction_diagram;
+use hypnagogic_core::util::adjacency::Adjacency;
+use hypnagogic_core::util::corners::Corner;
+use owo_colors::OwoColorize;
+
+/// Every neighbour of a junction, in the order they're listed
+const NEIGHBOURS: [(Adjacency, &str); 8] = [
+    (Adjacency::N, "north"),
+    (Adjacency::S, "south"),
+    (Adjacency::E, "east"),
+    (Adjacency::W, "west"),
+    (Adjacency::NE, "north-east"),
+    (Adjacency::SE, "south-east"),
+    (Adjacency::SW, "south-west"),
+    (Adjacency::NW, "north-west"),
+];
+
+/// Parses a junction like `255`, `14-d` (explicitly smoothed diagonally) or a
+/// full state name like `wall-14`, returning the junction and whether it's
+/// smoothed diagonally. Junctions with diagonal bits set are always diagonal
+fn parse_junction(junction: &str) -> Option<(Adjacency, bool)> {
+    let (rest, explicit_diagonal) = match junction.strip_suffix("-d") {
+        Some(rest) => (rest, true),
+        None => (junction, false),
+    };
+    let signature = rest.rsplit('-').next()?.parse::<u8>().ok()?;
+    let adjacency = Adjacency::from_bits(signature)?;
+    let diagonal = explicit_diagonal || !(adjacency - Adjacency::CARDINALS).is_empty();
+    Some((adjacency, diagonal))
+}
+
+const CORNERS: [(Corner, &str); 4] = [
+    (Corner::NorthEast, "north-east"),
+    (Corner::SouthEast, "south-east"),
+    (Corner::SouthWest, "south-west"),
+    (Corner::NorthWest, "north-west"),
+];
+
+/// Prints what a junction's neighbours and corners are, and writes a diagram
+/// of it to `diagram`, or `junction-<number>.png` if not set
+/// # Errors
+/// Errors if the junction can't be parsed or the diagram can't be written
+pub fn explain(junction: &str, diagram: Option<String>) -> Result<()> {
+    let (adjacency, diagonal) = parse_junction(junction).ok_or_else(|| {
+        anyhow!("\"{junction}\" isn't a junction, expected a number from 0 to 255 like 14 or 14-d")
+    })?;
+    let signature = adjacency.bits();
+    let smoothing = if diagonal { "diagonal" } else { "cardinal" };
+    println!(
+        "{}",
+        format!("Junction {signature} ({smoothing} smoothing)").bright_green()
+    );
+
+    let names = |set: bool| -> Vec<&str> {
+        NEIGHBOURS
+            .iter()
+            .filter(|(direction, _)| adjacency.contains(*direction) == set)
+            .map(|(_, name)| *name)
+            .collect()
+    };
+    let list = |names: Vec<&str>| {
+        if names.is_empty() {
+            "none".to_string()
+        } else {
+            names.join(", ")
+        }
+    };
+    println!("Connected to: {}", list(names(true)));
+    println!("Not connected to: {}", list(names(false)));
+    if !diagonal {
+        println!(
+            "{}",
+            "Cardinal smoothing ignores diagonals, add -d to explain it as smoothed diagonally"
+                .dimmed()
+        );
+    }
+
+    let resolved = if diagonal {
+        adjacency
+    } else {
+        adjacency & Adjacency::CARDINALS
+    };
+    println!("Corners:");
+    for (corner, name) in CORNERS {
+        println!("  {name}: {}", resolved.get_corner_type(corner));
+    }
+    if !adjacency.has_no_orphaned_corner() {
+        println!(
+            "{}",
+            "Only generated with orphaned_corners set, it has a diagonal connection without both \
+             of the cardinals next to it"
+                .yellow()
+        );
+    }
+
+    let path = diagram.unwrap_or_else(|| format!("junction-{signature}.png"));
+    generate_junction_diagram(adjacency, diagonal).save(&path)?;
+    println!(
+        "Wrote a diagram to {path}. Corners are colored convex red, concave green, horizontal \
+         blue, vertical yellow and flat grey"
+    );
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::{fs, io};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use dmi::icon::Icon;
+use hypnagogic_core::operations::OutputError;
+use hypnagogic_core::util::animation::{encode_apng, frames_for_dir};
+
+/// Names of dmi dirs, in the order they are stored in a dmi
+const DIR_NAMES: [&str; 8] = [
+    "south",
+    "north",
+    "east",
+    "west",
+    "southeast",
+    "southwest",
+    "northeast",
+    "northwest",
+];
+
+/// A single dir of an icon state, already encoded as a (possibly animated) png
+struct GalleryCell {
+    state_name: String,
+    dir_name: Option<&'static str>,
+    frames: u32,
+    encoded_png: String,
+}
+
+struct GalleryIcon {
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    state_count: usize,
+    cells: Vec<GalleryCell>,
+}
+
+/// Collects every dmi produced during a run, to be written out as one
+/// self-contained html page at the end
+#[derive(Default)]
+pub struct Gallery {
+    icons: Mutex<Vec<GalleryIcon>>,
+}
+
+impl Gallery {
+    /// Renders every state of `icon` and adds it to the gallery
+    /// # Errors
+    /// Errors if any state fails to be encoded as a png
+    pub fn add_icon(&self, path: &Path, icon: &Icon) -> Result<(), OutputError> {
+        let mut cells = vec![];
+        for state in &icon.states {
+            for dir_index in 0..state.dirs {
+                let frames = frames_for_dir(state, dir_index);
+                if frames.is_empty() {
+                    continue;
+                }
+                let encoded = encode_apng(&frames, state.delay.as_deref(), state.rewind)?;
+                cells.push(GalleryCell {
+                    state_name: state.name.clone(),
+                    dir_name: (state.dirs > 1).then(|| DIR_NAMES[dir_index as usize % 8]),
+                    frames: state.frames,
+                    encoded_png: STANDARD.encode(encoded),
+                });
+            }
+        }
+        self.icons.lock().unwrap().push(GalleryIcon {
+            path: path.to_path_buf(),
+            width: icon.width,
+            height: icon.height,
+            state_count: icon.states.len(),
+            cells,
+        });
+        Ok(())
+    }
+
+    /// Writes the gallery out as html to `path`
+    /// # Errors
+    /// Errors if the file can't be written
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut icons = self.icons.lock().unwrap();
+        // files are processed in parallel, so sort to keep the page stable between runs
+        icons.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut html = String::new();
+        html.push_str(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Hypnagogic \
+             Gallery</title>\n<style>\nbody { font-family: sans-serif; background: #2b2b2b; \
+             color: #eeeeee; }\n.icons { display: flex; flex-wrap: wrap; gap: 8px; }\n.cell { \
+             background: #3c3c3c; padding: 4px; text-align: center; font-size: 12px; }\n.cell img \
+             { image-rendering: pixelated; background: #555555; }\n</style>\n</head>\n<body>\n",
+        );
+        for icon in icons.iter() {
+            let _ = writeln!(
+                html,
+                "<h2>{}</h2>",
+                escape(&icon.path.display().to_string())
+            );
+            let _ = writeln!(
+                html,
+                "<p>{} states, {}x{}</p>",
+                icon.state_count, icon.width, icon.height
+            );
+            html.push_str("<div class=\"icons\">\n");
+            for cell in &icon.cells {
+                let mut label = escape(&cell.state_name);
+                if let Some(dir_name) = cell.dir_name {
+                    let _ = write!(label, " ({dir_name})");
+                }
+                if cell.frames > 1 {
+                    let _ = write!(label, " [{} frames]", cell.frames);
+                }
+                let _ = writeln!(
+                    html,
+                    "<div class=\"cell\"><img src=\"data:image/png;base64,{}\" width=\"{}\" \
+                     height=\"{}\"><br>{label}</div>",
+                    cell.encoded_png,
+                    icon.width * 2,
+                    icon.height * 2,
+                );
+            }
+            html.push_str("</div>\n");
+        }
+        html.push_str("</body>\n</html>\n");
+        fs::write(path, html)
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -1,19 +1,67 @@
+mod auto_fix;
+mod blame;
+mod diff;
+mod duplicates;
 mod error;
+mod examples;
+mod explain;
+mod gallery;
+mod merge;
+mod migrate;
+mod progress;
+mod serve;
 
-use std::fs;
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::fs::{metadata, File};
-use std::io::BufReader;
+use std::io::{self, BufReader, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use std::time::Instant;
+use std::{fs, iter};
 
 use anyhow::{anyhow, Result};
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use dmi::icon::Icon;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use hypnagogic_core::config::damage::{Damage, DAMAGE_KEY};
 use hypnagogic_core::config::error::ConfigError;
-use hypnagogic_core::config::read_config;
-use hypnagogic_core::config::template_resolver::error::TemplateError;
+use hypnagogic_core::config::format::ConfigFormat;
+use hypnagogic_core::config::hsv::HsvShift;
+use hypnagogic_core::config::include::read_includes;
+use hypnagogic_core::config::layers::{Layer, LAYERS_KEY};
+use hypnagogic_core::config::manifest::{Job, Manifest};
+use hypnagogic_core::config::provenance::Provenance;
+use hypnagogic_core::config::schema::config_schema;
+use hypnagogic_core::config::template_resolver::caching_resolver::CachingResolver;
+use hypnagogic_core::config::template_resolver::embedded_resolver::EmbeddedResolver;
+use hypnagogic_core::config::template_resolver::error::{TemplateError, TemplateResult};
 use hypnagogic_core::config::template_resolver::file_resolver::FileResolver;
+#[cfg(feature = "remote")]
+use hypnagogic_core::config::template_resolver::network::NetworkSettings;
+#[cfg(feature = "remote")]
+use hypnagogic_core::config::template_resolver::remote_resolver::RemoteResolver;
+use hypnagogic_core::config::template_resolver::{
+    closest_templates,
+    FallbackResolver,
+    TemplateResolver,
+};
+use hypnagogic_core::config::workspace::{WorkspaceConfig, WORKSPACE_CONFIG_NAME};
+use hypnagogic_core::config::{
+    read_config_file,
+    read_config_file_as,
+    read_config_input,
+    ConfigFile,
+};
+use hypnagogic_core::generation::contact_sheet::{generate_contact_sheet, DEFAULT_COLUMNS};
+use hypnagogic_core::operations::error::ProcessorWarning;
+use hypnagogic_core::operations::format_converter::dmi_merge::ConflictPolicy;
+use hypnagogic_core::operations::post_process::PostProcess;
+use hypnagogic_core::operations::side_files::SideFiles;
 use hypnagogic_core::operations::{
+    IconOperation,
     IconOperationConfig,
+    InputError,
     InputIcon,
     NamedIcon,
     OperationMode,
@@ -23,80 +71,527 @@ use hypnagogic_core::operations::{
     OutputText,
     ProcessorPayload,
 };
+use hypnagogic_core::util::blend::blend_onto;
+use hypnagogic_core::util::state_inventory::StateInventory;
+use image::ImageFormat;
 use owo_colors::OwoColorize;
 use rayon::prelude::*;
-use tracing::{debug, info, Level};
+use tracing::{debug, info, warn};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
 use user_error::UFE;
 use walkdir::WalkDir;
 
+use crate::auto_fix::fix_prefab_overlaps;
+use crate::blame::blame;
+use crate::diff::write_icon_diff;
+use crate::duplicates::DuplicateFinder;
 use crate::error::Error;
+use crate::examples::copy_examples;
+use crate::explain::explain;
+use crate::gallery::Gallery;
+use crate::merge::merge;
+use crate::migrate::migrate;
+use crate::progress::{emit, ProgressEvent, ProgressLayer, PROGRESS_SCHEMA_VERSION};
+use crate::serve::serve;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     /// Print paths and operations
     #[arg(short, long)]
     verbose: bool,
     /// Output as flat files instead of mirroring directory tree
     #[arg(short, long)]
     flatten: bool,
-    /// Print debug information and produce debug outputs
+    /// Print debug information and produce debug outputs. Dmis that already
+    /// exist at the output path are diffed against before being replaced
     #[arg(short, long)]
     debug: bool,
     /// Doesn't wait for a keypress after running. For CI or toolchain usage.
     #[arg(short = 'w', long)]
     dont_wait: bool,
+    /// Writes newline delimited json events to stderr as the run goes (file
+    /// started, file finished, warning, error and totals), for GUIs and CI
+    /// dashboards to show live progress
+    #[arg(long)]
+    progress_json: bool,
     /// Output directory of folders. If not set, output will match the file tree
     /// and output adjacent to input
     #[arg(short, long)]
     output: Option<String>,
-    /// Location of the templates folder
-    #[arg(short, long, default_value_t = String::from(hypnagogic_core::config::DEFAULT_TEMPLATE_LOCATION))]
+    /// Appended to the name of every output file, before its extensions, so
+    /// `--suffix _newstyle` writes `wall_newstyle.dmi` next to `wall.dmi` for
+    /// comparing in game
+    #[arg(long)]
+    suffix: Option<String>,
+    /// Forces every config through the named operation, like
+    /// `BitmaskSliceReconstruct`, with anything the config doesn't set taken
+    /// from the operation's defaults. Pngs and dmis without a config are
+    /// processed with just the defaults, for one-off conversions
+    #[arg(long)]
+    operation: Option<String>,
+    /// Rewrites configs whose prefabs share a position with a corner block,
+    /// moving the prefabs to the first free slots. Off by one prefab
+    /// positions otherwise cut the wrong art
+    #[arg(long)]
+    auto_fix: bool,
+    /// Fails configs that set keys nothing reads, like a misspelled
+    /// `anmation`, naming the key, the config or template that set it, and
+    /// the closest valid key. They're silently ignored otherwise
+    #[arg(long, global = true)]
+    strict: bool,
+    /// Location of the templates folder. The standard templates are built in,
+    /// and used for anything the folder doesn't have or if it doesn't exist
+    #[arg(short, long, global = true, default_value_t = String::from(hypnagogic_core::config::DEFAULT_TEMPLATE_LOCATION))]
     templates: String,
-    /// List of space separated output directory/file(s)
-    #[arg(num_args = 1.., value_delimiter = ' ', required = true)]
+    /// Writes full debug level logging to the given file, regardless of what
+    /// is printed to the console
+    #[arg(long)]
+    log_file: Option<String>,
+    /// Writes an html page previewing every generated icon state to the given
+    /// file
+    #[arg(long)]
+    gallery: Option<String>,
+    /// Also writes a png next to each dmi with every icon state laid out in a
+    /// labelled grid, for pasting into PRs
+    #[arg(long)]
+    contact_sheet: bool,
+    /// Also writes a `.states.json` next to each dmi listing every icon state
+    /// with its dirs, frames, delays and what produced it, for linters and
+    /// code generation
+    #[arg(long)]
+    state_inventory: bool,
+    /// Reports input sheets that are identical or near identical to each
+    /// other once the run finishes
+    #[arg(long)]
+    find_duplicates: bool,
+    /// Never access the network, resolving anything network based from the
+    /// cache only and failing if it isn't cached. Also turned on by setting
+    /// HYPNAGOGIC_OFFLINE
+    #[cfg(feature = "remote")]
+    #[arg(long, global = true)]
+    offline: bool,
+    /// Proxy to send network requests through. Defaults to the HTTPS_PROXY,
+    /// HTTP_PROXY or ALL_PROXY environment variables, respecting NO_PROXY
+    #[cfg(feature = "remote")]
+    #[arg(long, global = true)]
+    proxy: Option<String>,
+    /// Only process configs whose path matches this glob, like
+    /// `icons/obj/**/window*`. Can be passed more than once to match any of
+    /// several patterns
+    #[arg(long, global = true)]
+    filter: Vec<String>,
+    /// Reads newline separated paths to process from a file, or from stdin if
+    /// `-`, as well as any passed as arguments. Images are swapped for their
+    /// config, and anything without a config or that doesn't exist is skipped,
+    /// so `git diff --name-only | hypnagogic --files-from -` just works
+    #[arg(long, global = true)]
+    files_from: Option<String>,
+    /// Copies a set of example configs and inputs, one for every operation, to
+    /// the given directory (`hypnagogic-examples` if not set) to start from,
+    /// then exits
+    #[arg(long, num_args = 0..=1, default_missing_value = "hypnagogic-examples")]
+    examples: Option<String>,
+    /// List of space separated output directory/file(s). Manifests, tomls
+    /// declaring `[[job]]` tables that each name an `input` image, a
+    /// `template`, `overrides` to set over it and where to write the `output`,
+    /// have every job they declare processed
+    #[arg(
+        num_args = 1..,
+        value_delimiter = ' ',
+        required_unless_present_any = ["files_from", "examples"]
+    )]
     input: Vec<String>,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Checks every config is valid, without reading or writing any images
+    ///
+    /// Resolves templates and verifies each config, reporting every invalid
+    /// one. Exits with an error if any config is invalid, and never waits for
+    /// a keypress, so it can be used as a pre-commit hook
+    Validate {
+        /// List of space separated config files or directories to check
+        #[arg(
+            num_args = 1..,
+            value_delimiter = ' ',
+            required_unless_present = "files_from"
+        )]
+        input: Vec<String>,
+    },
+    /// Explains what a junction's number means
+    ///
+    /// Prints which neighbours are connected and what type each corner
+    /// resolves to, and writes a small diagram of it
+    Explain {
+        /// Junction number like `255`, or `14-d` to explain it as smoothed
+        /// diagonally. Full state names like `wall-14` also work
+        junction: String,
+        /// Where to write the diagram, defaults to `junction-<number>.png`
+        #[arg(long)]
+        diagram: Option<String>,
+    },
+    /// Upgrades configs written for older versions to the current schema
+    ///
+    /// Moves keys that have been renamed, like `icon_size_x`, to where they
+    /// live now, and converts json and yaml configs to toml. Anything that
+    /// couldn't be moved, and any key no operation reads, is reported. Only
+    /// configs that change are rewritten, and comments in them aren't kept
+    Migrate {
+        /// List of space separated config files or directories to migrate
+        #[arg(
+            num_args = 1..,
+            value_delimiter = ' ',
+            required_unless_present = "files_from"
+        )]
+        input: Vec<String>,
+        /// Report what would change without writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Shows where each value of a config comes from
+    ///
+    /// Resolves the config's templates and prints every value of the result,
+    /// along with the template (or the config itself) that set it, for
+    /// tracking down a bad value somewhere in a chain of templates
+    Blame {
+        /// Config to resolve
+        config: String,
+        /// Only show these keys, and anything under them, like `cut_pos` or
+        /// `positions.convex`
+        keys: Vec<String>,
+    },
+    /// Merges several dmis in to one
+    ///
+    /// States are kept in order, those of the first dmi and then each after
+    /// it. Every dmi has to be the same size. States whose name an earlier dmi
+    /// already used fail the merge, unless `--on-conflict` renames them or
+    /// keeps the first
+    Merge {
+        /// Dmis to merge, in order
+        #[arg(num_args = 2.., required = true)]
+        inputs: Vec<String>,
+        /// Where to write the merged dmi
+        #[arg(long)]
+        into: String,
+        /// What to do with a state whose name is already taken, `error`,
+        /// `rename` or `prefer_first`
+        #[arg(long, default_value = "error")]
+        on_conflict: ConflictPolicy,
+        /// Added to the names of states `rename` renames, with `{index}`
+        /// swapped for which dmi they came from, counting from 0. `-{index}`
+        /// if not set
+        #[arg(long)]
+        rename_suffix: Option<String>,
+    },
+    /// Prints a config with its templates resolved, as toml
+    ///
+    /// Merges the config's templates and includes, fills in the default of
+    /// every key it leaves unset and prints the result as canonical toml,
+    /// which reads the same as the original without needing any templates
+    PrintConfig {
+        /// Config to resolve
+        config: String,
+    },
+    /// Writes a JSON schema of config files, for editor completion
+    ///
+    /// Covers every key configs can set, and the keys of each operation once
+    /// `mode` picks it. Tomls can use it with a `#:schema <path>` comment on
+    /// their first line in editors using taplo
+    Schema {
+        /// Where to write the schema
+        #[arg(default_value = "hypnagogic.schema.json")]
+        path: String,
+    },
+    /// Runs a JSON-RPC server, for editor plugins and asset pipelines
+    ///
+    /// Accepts one JSON-RPC 2.0 request per line over TCP. `process` (or its
+    /// aliases `cut` and `restore`) takes `{"path": "<config>"}`, plus
+    /// optional `output`, `flatten` and `debug`, and returns the written
+    /// files and any warnings. `validate` checks a config, and `ping` returns
+    /// the version
+    Serve {
+        /// Address to listen on, which has to be a loopback address
+        #[arg(long, default_value = "127.0.0.1:7170")]
+        address: String,
+    },
+}
+
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 fn main() -> Result<()> {
     let now = Instant::now();
     let args = Args::parse();
     let Args {
+        command,
         verbose,
         flatten,
         debug,
         dont_wait,
+        progress_json,
         output,
+        suffix,
+        operation,
+        auto_fix,
+        strict,
         templates,
-        input,
+        log_file,
+        gallery,
+        contact_sheet,
+        state_inventory,
+        find_duplicates,
+        #[cfg(feature = "remote")]
+        offline,
+        #[cfg(feature = "remote")]
+        proxy,
+        filter,
+        files_from,
+        examples,
+        mut input,
     } = args;
 
-    println!("Hypnagogic CLI v{VERSION}");
+    // printed configs are meant to be piped into a file as is
+    if !matches!(command, Some(Command::PrintConfig { .. })) {
+        println!("Hypnagogic CLI v{VERSION}");
+    }
 
-    // subscribers are of different generic types so can't be put into one binding
-    // this is why each branch has its own binding and call to set_global_default
-    if debug {
-        let subscriber = tracing_subscriber::fmt()
+    // console layers are of different generic types, so they get boxed to be
+    // able to share one binding
+    let console_layer = if debug {
+        tracing_subscriber::fmt::layer()
             .pretty()
-            .with_max_level(Level::DEBUG)
-            .finish();
-        tracing::subscriber::set_global_default(subscriber)?;
+            .with_filter(LevelFilter::DEBUG)
+            .boxed()
     } else if verbose {
-        let subscriber = tracing_subscriber::fmt()
-            .with_max_level(Level::INFO)
+        tracing_subscriber::fmt::layer()
             .compact()
-            .finish();
-        tracing::subscriber::set_global_default(subscriber)?;
+            .with_filter(LevelFilter::INFO)
+            .boxed()
     } else {
-        let subscriber = tracing_subscriber::fmt()
+        tracing_subscriber::fmt::layer()
             .compact()
-            .with_max_level(Level::WARN)
-            .finish();
-        tracing::subscriber::set_global_default(subscriber)?;
+            .with_filter(LevelFilter::WARN)
+            .boxed()
+    };
+    // thread ids are included so output from files processed in parallel can be
+    // told apart
+    let file_layer = match &log_file {
+        Some(log_path) => {
+            let log_file = File::create(log_path)?;
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .with_thread_ids(true)
+                    .with_writer(Mutex::new(log_file))
+                    .with_filter(LevelFilter::DEBUG),
+            )
+        }
+        None => None,
+    };
+    let subscriber = tracing_subscriber::registry()
+        .with(console_layer)
+        .with(file_layer)
+        .with(progress_json.then_some(ProgressLayer));
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    if let Some(examples) = examples {
+        return copy_examples(Path::new(&examples));
+    }
+
+    #[cfg(feature = "remote")]
+    let template_sources = {
+        let mut network = NetworkSettings::from_env();
+        network.offline |= offline;
+        if proxy.is_some() {
+            network.proxy = proxy;
+        }
+        debug!(network = ?network, "Network settings");
+        TemplateSources::new(&templates, network)?
+    };
+    #[cfg(not(feature = "remote"))]
+    let template_sources = TemplateSources::new(&templates)?;
+
+    let listed = match &files_from {
+        Some(source) => read_file_list(source, operation.is_some())?,
+        None => vec![],
+    };
+
+    match command {
+        Some(Command::Validate { mut input }) => {
+            input.extend(listed);
+            return validate(
+                &collect_tasks(collect_inputs(input, &filter, false)?)?,
+                &template_sources,
+                strict,
+            );
+        }
+        Some(Command::Explain { junction, diagram }) => return explain(&junction, diagram),
+        Some(Command::Migrate { mut input, dry_run }) => {
+            input.extend(listed);
+            let mut files = collect_inputs(input, &filter, false)?;
+            // manifests aren't configs, and have no older versions
+            take_manifests(&mut files);
+            return migrate(&files, dry_run, &template_sources);
+        }
+        Some(Command::Blame { config, keys }) => {
+            return blame(Path::new(&config), &keys, &template_sources);
+        }
+        Some(Command::Merge {
+            inputs,
+            into,
+            on_conflict,
+            rename_suffix,
+        }) => return merge(&inputs, Path::new(&into), on_conflict, rename_suffix),
+        Some(Command::PrintConfig { config }) => {
+            let path = Path::new(&config);
+            let config = match load_config(path, &template_sources, None, false) {
+                Ok(config) => config,
+                Err(err) => {
+                    err.print();
+                    return Err(anyhow!("Couldn't resolve {}", path.display()));
+                }
+            };
+            print!("{}", config.to_resolved_toml()?);
+            return Ok(());
+        }
+        Some(Command::Schema { path }) => {
+            let schema = serde_json::to_string_pretty(&config_schema())?;
+            fs::write(&path, schema)?;
+            println!(
+                "{}",
+                format!("Wrote config schema to {path}").bright_green()
+            );
+            return Ok(());
+        }
+        Some(Command::Serve { address }) => return serve(&address, &template_sources),
+        None => {}
+    }
+
+    if let Some(operation) = &operation {
+        if !IconOperation::MODES.contains(&operation.as_str()) {
+            return Err(anyhow!(
+                "There's no operation called \"{operation}\", expected one of {}",
+                IconOperation::MODES.join(", ")
+            ));
+        }
+    }
+
+    input.extend(listed);
+    let files_to_process = collect_tasks(collect_inputs(input, &filter, operation.is_some())?)?;
+
+    debug!(files = ?files_to_process, "Files to process");
+
+    let num_files = files_to_process.len();
+    println!("Found {num_files} files!");
+    if progress_json {
+        emit(&ProgressEvent::RunStarted {
+            schema: PROGRESS_SCHEMA_VERSION,
+            version: VERSION,
+            files: num_files,
+        });
+    }
+
+    let gallery_collector = gallery.as_ref().map(|_| Gallery::default());
+    let duplicate_finder = find_duplicates.then(DuplicateFinder::default);
+
+    let context = RunContext {
+        flatten,
+        debug,
+        contact_sheet,
+        state_inventory,
+        output: &output,
+        suffix: suffix.as_deref(),
+        operation: operation.as_deref(),
+        auto_fix,
+        strict,
+        templates: &template_sources,
+        gallery: gallery_collector.as_ref(),
+        duplicate_finder: duplicate_finder.as_ref(),
     };
+    let files_failed = files_to_process
+        .par_iter()
+        .filter(|task| {
+            let started = Instant::now();
+            let path = &task.label();
+            if progress_json {
+                emit(&ProgressEvent::FileStarted { path });
+            }
+            let result = match task {
+                Task::File(path) => process_icon(&context, path),
+                Task::Job { manifest, job, .. } => process_job(&context, manifest, job),
+            };
+            let error = match result {
+                Ok(processed) => {
+                    if progress_json {
+                        emit(&ProgressEvent::file_finished(
+                            path,
+                            &processed.written,
+                            started.elapsed(),
+                        ));
+                    }
+                    return false;
+                }
+                Err(error) => error,
+            };
+            println!("{}", path.display().blue().italic());
+            // errors are printed to stderr, which is reserved for events
+            if progress_json {
+                emit(&ProgressEvent::error(path, &error));
+            } else {
+                error.print();
+            }
+            true
+        })
+        .count();
+    let files_succeeded = num_files - files_failed;
+    if progress_json {
+        emit(&ProgressEvent::Totals {
+            processed: files_succeeded,
+            failed: files_failed,
+            duration_ms: now.elapsed().as_millis(),
+        });
+    }
+
+    if files_failed > 0 {
+        println!(
+            "{}",
+            format!("Failed to process {files_failed} files!").bright_red()
+        );
+    }
+    println!(
+        "{}",
+        format!("Successfully processed {files_succeeded} files!").bright_green()
+    );
+    if let (Some(gallery_path), Some(gallery_collector)) = (&gallery, &gallery_collector) {
+        gallery_collector.write(Path::new(gallery_path))?;
+        println!("{}", format!("Wrote gallery to {gallery_path}").blue());
+    }
+    if let Some(duplicate_finder) = &duplicate_finder {
+        duplicate_finder.report();
+    }
+    println!("{}", format!("Took {:.2?}", now.elapsed()).blue());
+
+    if !dont_wait {
+        dont_disappear::any_key_to_continue::default();
+    }
+
+    Ok(())
+}
 
+/// Expands the input paths in to every config file to process, walking any
+/// directories. With `bare_images`, walks also pick up pngs and dmis that no
+/// config uses
+fn collect_inputs(
+    input: Vec<String>,
+    filter: &[String],
+    bare_images: bool,
+) -> Result<Vec<PathBuf>> {
+    let filter = build_filter(filter)?;
     let mut invalid_paths: Vec<String> = vec![];
     let mut inaccessible_paths: Vec<std::io::Error> = vec![];
     let files_to_process: Vec<PathBuf> = input
@@ -120,13 +615,20 @@ fn main() -> Result<()> {
             Some(
                 WalkDir::new(potential_path)
                     .into_iter()
+                    // hidden folders like .git and the remote template cache
+                    // never hold configs to process
+                    .filter_entry(|e| {
+                        e.depth() == 0
+                            || !e.file_type().is_dir()
+                            || !e.file_name().to_string_lossy().starts_with('.')
+                    })
                     .filter_map(Result::ok)
                     .filter(|e| e.file_type().is_file())
                     .filter(|e| {
-                        if let Some(extension) = e.path().extension() {
-                            extension == "toml"
+                        if is_config(e.path()) {
+                            e.file_name() != WORKSPACE_CONFIG_NAME
                         } else {
-                            false
+                            bare_images && is_image(e.path()) && !has_config(e.path())
                         }
                     })
                     .map(|e| e.into_path())
@@ -134,6 +636,11 @@ fn main() -> Result<()> {
             )
         })
         .flatten()
+        .filter(|path: &PathBuf| {
+            filter
+                .as_ref()
+                .is_none_or(|filter| filter.is_match(path.strip_prefix("./").unwrap_or(path)))
+        })
         .collect();
 
     if !invalid_paths.is_empty() || !inaccessible_paths.is_empty() {
@@ -152,112 +659,795 @@ fn main() -> Result<()> {
         }
         return Err(anyhow!("{}", error_text));
     }
+    Ok(without_fragments(files_to_process))
+}
 
-    debug!(files = ?files_to_process, "Files to process");
+/// Something processed in a run
+#[derive(Debug)]
+enum Task {
+    /// A config, or an image to force through `--operation`
+    File(PathBuf),
+    /// A job declared in a manifest, numbered from 1
+    Job {
+        manifest: PathBuf,
+        number: usize,
+        job: Job,
+    },
+}
 
-    let num_files = files_to_process.len();
-    println!("Found {num_files} files!");
+impl Task {
+    /// What the task is reported as. Jobs are `<manifest>#<number>`
+    fn label(&self) -> PathBuf {
+        match self {
+            Task::File(path) => path.clone(),
+            Task::Job {
+                manifest, number, ..
+            } => PathBuf::from(format!("{}#{number}", manifest.display())),
+        }
+    }
+}
 
-    let files_failed = files_to_process
-        .par_iter()
+/// Turns collected inputs into tasks, expanding every manifest among them in
+/// to its jobs
+fn collect_tasks(mut files: Vec<PathBuf>) -> Result<Vec<Task>> {
+    let manifests = take_manifests(&mut files);
+    let mut tasks: Vec<Task> = files.into_iter().map(Task::File).collect();
+    for manifest in manifests {
+        let loaded = Manifest::load(&manifest)
+            .map_err(|err| anyhow!("Invalid manifest {}: {err}", manifest.display()))?;
+        debug!(manifest = ?manifest, jobs = loaded.jobs.len(), "Loaded manifest");
+        tasks.extend(loaded.jobs.into_iter().enumerate().map(|(index, job)| {
+            Task::Job {
+                manifest: manifest.clone(),
+                number: index + 1,
+                job,
+            }
+        }));
+    }
+    Ok(tasks)
+}
+
+/// Takes the manifests out of `files`, returning them
+fn take_manifests(files: &mut Vec<PathBuf>) -> Vec<PathBuf> {
+    let (manifests, configs) = std::mem::take(files)
+        .into_iter()
+        .partition(|path| is_manifest(path));
+    *files = configs;
+    manifests
+}
+
+/// Whether the toml at `path` is a manifest rather than a config
+fn is_manifest(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "toml")
+        && fs::read_to_string(path)
+            .ok()
+            .and_then(|text| toml::from_str(&text).ok())
+            .is_some_and(|value| Manifest::is_manifest(&value))
+}
+
+/// Drops every config that another config in `files` includes, since
+/// fragments are only pieces of configs and can't be processed on their own
+fn without_fragments(files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let fragments: HashSet<PathBuf> = files
+        .iter()
+        .filter(|path| is_config(path))
+        .filter_map(|config| {
+            let mut reader = BufReader::new(File::open(config).ok()?);
+            let includes = read_includes(&mut reader, config_format(config)).ok()?;
+            Some(
+                includes
+                    .into_iter()
+                    .filter_map(|include| config_dir(config).join(include).canonicalize().ok())
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .flatten()
+        .collect();
+    if fragments.is_empty() {
+        return files;
+    }
+    files
+        .into_iter()
         .filter(|path| {
-            let Err(error) = process_icon(flatten, debug, &output, &templates, path) else {
+            path.canonicalize()
+                .map_or(true, |path| !fragments.contains(&path))
+        })
+        .collect()
+}
+
+/// Reads the config paths listed in `source` (or stdin if it's `-`), one per
+/// line. Images are swapped for their config (or every config naming them as
+/// their input), and paths without a config or that don't exist (like files
+/// deleted in a diff) are skipped. With `bare_images`, pngs and dmis without a
+/// config are kept as they are
+fn read_file_list(source: &str, bare_images: bool) -> Result<Vec<String>> {
+    let text = if source == "-" {
+        io::read_to_string(io::stdin())?
+    } else {
+        fs::read_to_string(source)?
+    };
+    let mut configs = vec![];
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let path = Path::new(line);
+        let config = if is_config(path) {
+            Some(path.to_path_buf()).filter(|config| config.is_file())
+        } else {
+            config_for(path)
+        };
+        if let Some(config) = config {
+            configs.push(config.display().to_string());
+            continue;
+        }
+        let naming = configs_naming(path);
+        if naming.is_empty() {
+            if bare_images && is_image(path) && path.is_file() {
+                configs.push(line.to_string());
+                continue;
+            }
+            debug!(path = line, "Skipping listed path without a config");
+        }
+        configs.extend(naming.iter().map(|config| config.display().to_string()));
+    }
+    Ok(configs)
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "png" || extension == "dmi")
+}
+
+/// Whether `path` is named like a config, in any format
+fn is_config(path: &Path) -> bool {
+    ConfigFormat::from_path(path).is_some()
+}
+
+/// The format to read the config at `path` as. Paths given explicitly are
+/// read by their extension, even if they aren't named after an image
+fn config_format(path: &Path) -> ConfigFormat {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(ConfigFormat::from_extension)
+        .unwrap_or(ConfigFormat::Toml)
+}
+
+/// The directory the config at `path` is in, that its includes are relative
+/// to
+fn config_dir(path: &Path) -> &Path {
+    path.parent().unwrap_or_else(|| Path::new(""))
+}
+
+/// The config named after `image`, like `wall.png.toml` or `wall.png.json`
+fn config_for(image: &Path) -> Option<PathBuf> {
+    ConfigFormat::EXTENSIONS
+        .iter()
+        .map(|extension| PathBuf::from(format!("{}.{extension}", image.display())))
+        .find(|config| config.is_file())
+}
+
+/// Whether `image` has a config, either named after it or naming it as its
+/// input
+fn has_config(image: &Path) -> bool {
+    config_for(image).is_some() || !configs_naming(image).is_empty()
+}
+
+/// Finds the configs next to `image` that name it with an explicit input.
+/// Paths are compared once resolved, so `./walls.png` names `walls.png`
+fn configs_naming(image: &Path) -> Vec<PathBuf> {
+    let Ok(resolved_image) = image.canonicalize() else {
+        return vec![];
+    };
+    let dir = image.parent().unwrap_or(Path::new(""));
+    let Ok(entries) = fs::read_dir(
+        if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        },
+    ) else {
+        return vec![];
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| dir.join(entry.file_name()))
+        .filter(|config| {
+            let Some(format) = ConfigFormat::from_path(config) else {
                 return false;
             };
-            println!("{}", path.display().blue().italic());
-            error.print();
-            true
+            File::open(config)
+                .ok()
+                .and_then(|mut file| read_config_input(&mut file, format).ok().flatten())
+                .and_then(|input| dir.join(input).canonicalize().ok())
+                .is_some_and(|input| input == resolved_image)
         })
-        .count();
-    let files_succeeded = num_files - files_failed;
+        .collect()
+}
 
-    if files_failed > 0 {
-        println!(
-            "{}",
-            format!("Failed to process {files_failed} files!").bright_red()
-        );
+/// Builds a matcher for `--filter` patterns, `None` if there aren't any.
+/// Patterns that aren't absolute can match anywhere in a path, so
+/// `icons/obj/**` matches the same files whether the input is `.` or an
+/// absolute path to the repo
+fn build_filter(patterns: &[String]) -> Result<Option<GlobSet>> {
+    if patterns.is_empty() {
+        return Ok(None);
     }
-    println!(
-        "{}",
-        format!("Successfully processed {files_succeeded} files!").bright_green()
-    );
-    println!("{}", format!("Took {:.2?}", now.elapsed()).blue());
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let mut add = |pattern: &str| -> Result<()> {
+            let glob = GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .build()
+                .map_err(|err| anyhow!("Invalid filter \"{pattern}\": {err}"))?;
+            builder.add(glob);
+            Ok(())
+        };
+        add(pattern)?;
+        if !pattern.starts_with('/') && !pattern.starts_with("**") {
+            add(&format!("**/{pattern}"))?;
+        }
+    }
+    Ok(Some(builder.build()?))
+}
 
-    if !dont_wait {
-        dont_disappear::any_key_to_continue::default();
+/// Everywhere templates are resolved from: the templates folder first, then
+/// the workspace's remote template pack, then the built in templates. Each
+/// template is only resolved once per run, however many configs use it
+#[derive(Clone, Debug)]
+struct TemplateSources {
+    folder_path: String,
+    resolver: CachingResolver<TemplateChain>,
+}
+
+#[derive(Clone, Debug)]
+struct TemplateChain {
+    folder: Option<FileResolver>,
+    #[cfg(feature = "remote")]
+    remote: Option<RemoteResolver>,
+}
+
+impl TemplateSources {
+    /// Loads the closest workspace config to the working directory for its
+    /// remote template pack, if it has one
+    fn new(folder_path: &str, #[cfg(feature = "remote")] network: NetworkSettings) -> Result<Self> {
+        let workspace = match WorkspaceConfig::find(&std::env::current_dir()?) {
+            Some(path) => {
+                let workspace = WorkspaceConfig::load(&path)
+                    .map_err(|err| anyhow!("Invalid workspace config {}: {err}", path.display()))?;
+                debug!(path = ?path, workspace = ?workspace, "Loaded workspace config");
+                Some((path, workspace))
+            }
+            None => None,
+        };
+        #[cfg(feature = "remote")]
+        let remote = workspace.and_then(|(path, workspace)| {
+            workspace
+                .remote_templates
+                .clone()
+                .map(|remote| RemoteResolver::new(remote, &workspace.cache_dir(&path), network))
+        });
+        #[cfg(not(feature = "remote"))]
+        if let Some((
+            path,
+            WorkspaceConfig {
+                remote_templates: Some(_),
+                ..
+            },
+        )) = &workspace
+        {
+            return Err(anyhow!(
+                "{} declares remote templates, but this build of hypnagogic can't fetch them. \
+                 Build it with the `remote` feature",
+                path.display()
+            ));
+        }
+        let folder = FileResolver::new(Path::new(folder_path)).ok();
+        if folder.is_none() {
+            debug!(folder_path, "No template folder");
+        }
+        Ok(Self {
+            folder_path: folder_path.to_string(),
+            resolver: CachingResolver::new(TemplateChain {
+                folder,
+                #[cfg(feature = "remote")]
+                remote,
+            }),
+        })
     }
 
-    Ok(())
+    /// Forgets every template resolved so far, so edits to them are picked up
+    fn forget_resolved(&self) {
+        self.resolver.clear();
+    }
 }
 
-/// Gnarly, effectful function hoisted out here so that I can still use ? but
-/// parallelize with rayon
+impl TemplateResolver for TemplateSources {
+    fn resolve(&self, input: &str) -> TemplateResult {
+        self.resolver.resolve(input)
+    }
+
+    fn available(&self) -> Vec<String> {
+        self.resolver.available()
+    }
+}
+
+impl TemplateResolver for TemplateChain {
+    fn resolve(&self, input: &str) -> TemplateResult {
+        #[cfg(feature = "remote")]
+        let fallback: Box<dyn TemplateResolver> = match &self.remote {
+            Some(remote) => {
+                Box::new(FallbackResolver {
+                    primary: remote,
+                    fallback: EmbeddedResolver,
+                })
+            }
+            None => Box::new(EmbeddedResolver),
+        };
+        #[cfg(not(feature = "remote"))]
+        let fallback: Box<dyn TemplateResolver> = Box::new(EmbeddedResolver);
+        match &self.folder {
+            Some(folder) => {
+                FallbackResolver {
+                    primary: folder,
+                    fallback: &*fallback,
+                }
+                .resolve(input)
+            }
+            None => fallback.resolve(input),
+        }
+    }
+
+    fn available(&self) -> Vec<String> {
+        let mut available = EmbeddedResolver.available();
+        if let Some(folder) = &self.folder {
+            available.extend(folder.available());
+        }
+        #[cfg(feature = "remote")]
+        if let Some(remote) = &self.remote {
+            available.extend(remote.available());
+        }
+        available.sort();
+        available.dedup();
+        available
+    }
+}
+
+/// Draws layers the config at `path` lists under `key` over its input, in
+/// order
 #[allow(clippy::result_large_err)]
-fn process_icon(
-    flatten: bool,
-    debug: bool,
-    output: &Option<String>,
-    templates: &String,
-    path: &PathBuf,
+fn draw_layers(
+    path: &Path,
+    input: &mut InputIcon,
+    layers: &[Layer],
+    key: &str,
 ) -> Result<(), Error> {
-    info!(path = ?path, "Found toml at path");
-    let in_file_toml = File::open(path.as_path())?;
-    let mut in_toml_reader = BufReader::new(in_file_toml);
-    let config = read_config(
-        &mut in_toml_reader,
-        FileResolver::new(Path::new(&templates))
-            .map_err(|_err| Error::NoTemplateFolder(PathBuf::from(templates)))?,
-    )
-    .map_err(|err| {
-        let source_config = path
-            .clone()
-            .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string();
-        match err {
-            ConfigError::Template(template_err) => {
-                match template_err {
-                    TemplateError::NoTemplateDir(dir_path) => Error::NoTemplateFolder(dir_path),
-                    TemplateError::FailedToFindTemplate(template_string, expected_path) => {
-                        Error::TemplateNotFound {
-                            source_config,
-                            template_string,
-                            expected_path,
-                        }
+    if layers.is_empty() {
+        return Ok(());
+    }
+    let source_config = path.file_name().unwrap().to_str().unwrap().to_string();
+    let InputIcon::DynamicImage(image) = input else {
+        return Err(Error::InvalidConfig {
+            source_config,
+            config_error: ConfigError::Config(format!("`{key}` can only be drawn over png inputs")),
+        });
+    };
+    for layer in layers {
+        let reader = open_side_file(path, &layer.path)?;
+        let layer_image = image::load(reader, ImageFormat::Png).map_err(InputError::from)?;
+        debug!(layer = ?layer, "Drawing layer");
+        blend_onto(
+            image,
+            &layer_image,
+            i64::from(layer.offset.x),
+            i64::from(layer.offset.y),
+            layer.blend,
+        );
+    }
+    Ok(())
+}
+
+/// `input` with `hsv` applied, if there is one
+fn shifted<'a>(input: &'a InputIcon, hsv: Option<&HsvShift>) -> Cow<'a, InputIcon> {
+    match hsv {
+        Some(hsv) => {
+            let mut input = input.clone();
+            hsv.apply(&mut input);
+            Cow::Owned(input)
+        }
+        None => Cow::Borrowed(input),
+    }
+}
+
+/// Where `file`, named relative to the config at `path`, is
+#[allow(clippy::result_large_err)]
+fn side_file_path(path: &Path, file: &str) -> Result<PathBuf, Error> {
+    let file_path = config_dir(path).join(file);
+    if !file_path.is_file() {
+        return Err(Error::InputNotFound {
+            source_config: path.file_name().unwrap().to_str().unwrap().to_string(),
+            expected: file.to_string(),
+            search_dir: config_dir(path).to_path_buf(),
+        });
+    }
+    Ok(file_path)
+}
+
+/// Opens `file`, named relative to the config at `path`
+#[allow(clippy::result_large_err)]
+fn open_side_file(path: &Path, file: &str) -> Result<BufReader<File>, Error> {
+    let file_path = side_file_path(path, file)?;
+    debug!(file = ?file_path, "Reading side file");
+    Ok(BufReader::new(File::open(file_path)?))
+}
+
+/// The files the config at `path` names besides its input
+struct ConfigFiles<'a> {
+    path: &'a Path,
+    templates: &'a TemplateSources,
+}
+
+#[allow(clippy::result_large_err)]
+impl SideFiles for ConfigFiles<'_> {
+    type Error = Error;
+    type Reader = BufReader<File>;
+
+    fn open(&self, file: &str) -> Result<Self::Reader, Self::Error> {
+        open_side_file(self.path, file)
+    }
+
+    fn config(&self, file: &str) -> Result<IconOperation, Self::Error> {
+        let config_path = side_file_path(self.path, file)?;
+        Ok(load_config(&config_path, self.templates, None, false)?.operation)
+    }
+}
+
+/// Reads the config at `path`, resolving its templates. `operation` forces it
+/// through that operation instead of its own. With `strict`, keys nothing
+/// reads are an error
+#[allow(clippy::result_large_err)]
+fn load_config(
+    path: &Path,
+    templates: &TemplateSources,
+    operation: Option<&str>,
+    strict: bool,
+) -> Result<ConfigFile, Error> {
+    let config_file = File::open(path)?;
+    let mut config_reader = BufReader::new(config_file);
+    let format = config_format(path);
+    let dir = config_dir(path);
+    let result = match operation {
+        Some(operation) => {
+            read_config_file_as(&mut config_reader, format, dir, templates, operation)
+        }
+        None => read_config_file(&mut config_reader, format, dir, templates),
+    };
+    let config = result.map_err(|err| config_error(path, templates, err))?;
+    check_unknown_keys(path, config, strict)
+}
+
+/// Reads the job `job` of the manifest at `path` like [`load_config`]
+#[allow(clippy::result_large_err)]
+fn load_job(
+    path: &Path,
+    job: &Job,
+    templates: &TemplateSources,
+    operation: Option<&str>,
+    strict: bool,
+) -> Result<ConfigFile, Error> {
+    let config = job
+        .read_config(config_dir(path), templates, operation)
+        .map_err(|err| config_error(path, templates, err))?;
+    check_unknown_keys(path, config, strict)
+}
+
+/// Logs the keys nothing reads in the config read from `path`, failing it if
+/// `strict`
+#[allow(clippy::result_large_err)]
+fn check_unknown_keys(path: &Path, config: ConfigFile, strict: bool) -> Result<ConfigFile, Error> {
+    for key in &config.unknown_keys {
+        debug!(path = ?path, key = %key, "Unknown key");
+    }
+    if strict && !config.unknown_keys.is_empty() {
+        return Err(Error::UnknownKeys {
+            source_config: path.file_name().unwrap().to_str().unwrap().to_string(),
+            keys: config.unknown_keys,
+        });
+    }
+    Ok(config)
+}
+
+/// Turns an error reading the config at `path` into one to report to the user
+fn config_error(path: &Path, templates: &TemplateSources, err: ConfigError) -> Error {
+    let source_config = path.file_name().unwrap().to_str().unwrap().to_string();
+    match err {
+        ConfigError::Template(template_err) => {
+            match template_err {
+                TemplateError::NoTemplateDir(dir_path) => Error::NoTemplateFolder(dir_path),
+                // only returned when there's no templates folder to look in
+                TemplateError::NotEmbedded(_) => {
+                    Error::NoTemplateFolder(PathBuf::from(&templates.folder_path))
+                }
+                TemplateError::FetchFailed(template_string, reason) => {
+                    Error::RemoteTemplateFailed {
+                        source_config,
+                        template_string,
+                        reason,
                     }
-                    TemplateError::TOMLError(err) => {
-                        Error::InvalidConfig {
-                            source_config,
-                            config_error: err.into(),
-                        }
+                }
+                error @ TemplateError::ChecksumMismatch { .. } => {
+                    let TemplateError::ChecksumMismatch { template, .. } = &error else {
+                        unreachable!()
+                    };
+                    Error::RemoteTemplateFailed {
+                        source_config,
+                        template_string: template.clone(),
+                        reason: error.to_string(),
                     }
-                    TemplateError::IOError(err) => err.into(),
                 }
-            }
-            ConfigError::Toml(err) => {
-                Error::InvalidConfig {
-                    source_config,
-                    config_error: ConfigError::Toml(err),
+                TemplateError::OutsidePack(template_string) => {
+                    Error::RemoteTemplateFailed {
+                        source_config,
+                        template_string,
+                        reason: "its name leads outside the template pack".to_string(),
+                    }
+                }
+                TemplateError::FailedToFindTemplate(template_string, expected_path) => {
+                    let available = templates.available();
+                    Error::TemplateNotFound {
+                        source_config,
+                        suggestions: closest_templates(&template_string, &available),
+                        template_string,
+                        expected_path,
+                        available,
+                    }
+                }
+                TemplateError::NotCached(template_string, cache_path) => {
+                    Error::TemplateNotCached {
+                        source_config,
+                        template_string,
+                        cache_path,
+                    }
+                }
+                TemplateError::Parse { template, error } => {
+                    Error::InvalidTemplate {
+                        source_config,
+                        template,
+                        error,
+                    }
                 }
+                error @ (TemplateError::Cycle(_) | TemplateError::TooDeep(_)) => {
+                    Error::InvalidConfig {
+                        source_config,
+                        config_error: error.into(),
+                    }
+                }
+                TemplateError::TOMLError(err) => {
+                    Error::InvalidConfig {
+                        source_config,
+                        config_error: err.into(),
+                    }
+                }
+                TemplateError::IOError(err) => err.into(),
+            }
+        }
+        ConfigError::Toml(_)
+        | ConfigError::Parse(_)
+        | ConfigError::Json(_)
+        | ConfigError::Yaml(_)
+        | ConfigError::Include { .. }
+        | ConfigError::UnsetVariable { .. }
+        | ConfigError::Config(_) => {
+            Error::InvalidConfig {
+                source_config,
+                config_error: err,
             }
-            ConfigError::Config(_) => {
-                Error::InvalidConfig {
-                    source_config,
-                    config_error: err,
+        }
+        ConfigError::IO(err) => err.into(),
+    }
+}
+
+/// Checks every config (and manifest job) in `files` can be read and passes
+/// `verify_config`, reporting every failure rather than stopping at the first
+#[allow(clippy::result_large_err)]
+fn validate(files: &[Task], templates: &TemplateSources, strict: bool) -> Result<()> {
+    let now = Instant::now();
+    println!("Found {} configs!", files.len());
+    let failed = files
+        .par_iter()
+        .filter(|task| {
+            let config = match task {
+                Task::File(path) => load_config(path, templates, None, strict),
+                Task::Job { manifest, job, .. } => load_job(manifest, job, templates, None, strict),
+            };
+            let result = config.and_then(|config| {
+                config.operation.verify_config()?;
+                Ok(config.operation.config_warnings())
+            });
+            let error = match result {
+                Ok(warnings) => {
+                    for warning in warnings {
+                        warn!(path = ?task.label(), "{warning}");
+                    }
+                    return false;
+                }
+                Err(error) => error,
+            };
+            println!("{}", task.label().display().blue().italic());
+            error.print();
+            true
+        })
+        .count();
+    println!("{}", format!("Took {:.2?}", now.elapsed()).blue());
+    if failed > 0 {
+        return Err(anyhow!("{failed} of {} configs are invalid", files.len()));
+    }
+    println!(
+        "{}",
+        format!("All {} configs are valid!", files.len()).bright_green()
+    );
+    Ok(())
+}
+
+/// Options and collectors shared by every file processed in a run
+struct RunContext<'a> {
+    flatten: bool,
+    debug: bool,
+    contact_sheet: bool,
+    state_inventory: bool,
+    output: &'a Option<String>,
+    suffix: Option<&'a str>,
+    /// Operation to force every config through
+    operation: Option<&'a str>,
+    /// Fix prefabs overlapping corner positions in configs before using them
+    auto_fix: bool,
+    /// Fail configs that set keys nothing reads
+    strict: bool,
+    templates: &'a TemplateSources,
+    gallery: Option<&'a Gallery>,
+    duplicate_finder: Option<&'a DuplicateFinder>,
+}
+
+/// Inserts `suffix` into a file name before its first extension, so double
+/// extensions like `.png.toml` stay intact
+fn add_suffix(path: &mut PathBuf, suffix: &str) {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return;
+    };
+    let name = match name.split_once('.') {
+        Some((stem, extensions)) => format!("{stem}{suffix}.{extensions}"),
+        None => format!("{name}{suffix}"),
+    };
+    path.set_file_name(name);
+}
+
+/// What processing a config did
+struct Processed {
+    /// Every file written
+    written: Vec<PathBuf>,
+    /// Every warning the config and its variants ran in to
+    warnings: Vec<ProcessorWarning>,
+}
+
+/// Gnarly, effectful function hoisted out here so that I can still use ? but
+/// parallelize with rayon
+#[allow(clippy::result_large_err)]
+#[tracing::instrument(skip_all, fields(path = ?path))]
+fn process_icon(context: &RunContext, path: &PathBuf) -> Result<Processed, Error> {
+    let RunContext {
+        operation,
+        auto_fix,
+        strict,
+        templates,
+        ..
+    } = *context;
+    let bare_image = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| ConfigFormat::from_extension(extension).is_none());
+    let mut config = match operation {
+        // only collected when there's an operation to process them with
+        Some(operation) if bare_image => {
+            info!(path = ?path, operation, "Found image without a config");
+            let config = IconOperation::default_for(operation).ok_or_else(|| {
+                Error::NeedsConfig {
+                    input: path.display().to_string(),
+                    operation: operation.to_string(),
                 }
+            })?;
+            ConfigFile {
+                operation: config,
+                input: None,
+                layers: vec![],
+                damage: Damage::default(),
+                hsv: None,
+                post_process: PostProcess::default(),
+                recolors: vec![],
+                variants: vec![],
+                unknown_keys: vec![],
             }
-            _ => panic!("Unexpected error: {:#?}", err),
         }
-    })?;
+        _ => {
+            info!(path = ?path, "Found config at path");
+            load_config(path, templates, operation, strict)?
+        }
+    };
+    if auto_fix && !bare_image {
+        fix_prefab_overlaps(path, &mut config.operation)?;
+    }
+
+    // outputs are named after the config when it names its input, so several
+    // configs can share one sheet
+    let output_name = config.input.is_some().then(|| path.clone());
+    let input_icon_path = if bare_image {
+        path.clone()
+    } else if let Some(input) = &config.input {
+        path.parent().unwrap_or(Path::new("")).join(input)
+    } else {
+        let mut input_icon_path = path.clone();
+        // funny hack: for double extensioned files (eg, .png.toml) calling
+        // set_extension with a blank string clears out the second extension,
+        // (.png.toml -> .png)
+        input_icon_path.set_extension("");
+        input_icon_path
+    };
+    process_input(context, path, &config, &input_icon_path, output_name)
+}
 
-    let mut input_icon_path = path.clone();
-    // funny hack: for double extensioned files (eg, .png.toml) calling
-    // set_extension with a blank string clears out the second extension,
-    // (.png.toml -> .png)
-    input_icon_path.set_extension("");
+/// Processes the job `job` of the manifest at `path`, writing its output
+/// where the job says, relative to the manifest
+#[allow(clippy::result_large_err)]
+#[tracing::instrument(skip_all, fields(path = ?path, input = job.input))]
+fn process_job(context: &RunContext, path: &Path, job: &Job) -> Result<Processed, Error> {
+    info!(path = ?path, input = job.input, "Found job in manifest");
+    let config = load_job(
+        path,
+        job,
+        context.templates,
+        context.operation,
+        context.strict,
+    )?;
+    let dir = config_dir(path);
+    process_input(
+        context,
+        path,
+        &config,
+        &dir.join(&job.input),
+        job.output.as_ref().map(|output| dir.join(output)),
+    )
+}
 
+/// Runs `config`, read from the config at `path`, over the image at
+/// `input_icon_path` and writes everything it and its variants output, and
+/// every recolor of it. Outputs are named after `output_name` if set, or else
+/// the input, with variants' and recolors' names added on
+#[allow(clippy::result_large_err)]
+fn process_input(
+    context: &RunContext,
+    path: &Path,
+    config: &ConfigFile,
+    input_icon_path: &Path,
+    output_name: Option<PathBuf>,
+) -> Result<Processed, Error> {
+    let RunContext {
+        flatten,
+        debug,
+        contact_sheet,
+        state_inventory,
+        output,
+        suffix,
+        gallery,
+        duplicate_finder,
+        templates,
+        ..
+    } = *context;
+    let mut operations: Vec<(Option<&String>, IconOperation, Option<HsvShift>)> =
+        iter::once((None, config.operation.clone(), config.hsv))
+            .chain(
+                config
+                    .variants
+                    .iter()
+                    .map(|variant| (Some(&variant.name), variant.operation.clone(), variant.hsv)),
+            )
+            .collect();
     if !input_icon_path.exists() {
         let source_config = path.file_name().unwrap().to_str().unwrap().to_string();
         let expected = input_icon_path
@@ -267,78 +1457,275 @@ fn process_icon(
             .unwrap()
             .to_string();
         let search_dir = path.parent().unwrap().to_path_buf();
-        return Err(Error::InputNotFound {
+        // anything wrong with the config is worth knowing about too, rather
+        // than only after the input's fixed
+        let mut problems = vec![Error::InputNotFound {
             source_config,
             expected,
             search_dir,
-        });
+        }];
+        problems.extend(
+            operations
+                .iter()
+                .filter_map(|(_, operation, _)| operation.verify_config().err())
+                .map(Error::from),
+        );
+        return Err(Error::all(problems));
     }
     let actual_extension = input_icon_path
         .extension()
-        .unwrap()
-        .to_os_string()
-        .into_string()
-        .unwrap();
-    let icon_file = File::open(&input_icon_path)?;
+        .map(|extension| extension.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let icon_file = File::open(input_icon_path)?;
     let mut reader = BufReader::new(icon_file);
-    let input = InputIcon::from_reader(&mut reader, &actual_extension)?;
+    let mut input = InputIcon::from_reader(&mut reader, &actual_extension)?;
+    draw_layers(path, &mut input, &config.layers, LAYERS_KEY)?;
+    let mut damaged_inputs = vec![];
+    for level in &config.damage.levels {
+        let mut damaged = input.clone();
+        draw_layers(path, &mut damaged, level, DAMAGE_KEY)?;
+        damaged_inputs.push(damaged);
+    }
+    for (_, operation, hsv) in &mut operations {
+        operation.load_side_files(&ConfigFiles { path, templates })?;
+        if let (Some(hsv), IconOperation::BitmaskTextureMask(config)) = (&*hsv, &mut *operation) {
+            // the texture is where the colors come from, so it's shifted
+            // along with the template
+            if let Some(texture) = &mut config.texture_image {
+                hsv.apply_to_image(texture);
+            }
+        }
+        if let (Some(hsv), Some(config)) = (hsv, operation.bitmask_slice_mut()) {
+            // read as part of the input, so they're shifted along with it
+            for image in config
+                .corner_atlas_image
+                .iter_mut()
+                .chain(config.prefab_images.values_mut())
+            {
+                hsv.apply_to_image(image);
+            }
+        }
+    }
+    if let (Some(duplicate_finder), InputIcon::DynamicImage(image)) = (duplicate_finder, &input) {
+        duplicate_finder.add_sheet(input_icon_path, image);
+    }
 
     let mode = if debug {
         OperationMode::Debug
     } else {
         OperationMode::Standard
     };
-    let out = config.do_operation(&input, mode)?;
-
     if let Some(output) = &output {
         let output_path = Path::new(output);
         fs::create_dir_all(output_path)?;
     }
 
-    let out_paths: Vec<(PathBuf, Output)> = handle_payload(out, input_icon_path, output, flatten);
+    let output_name_path = match output_name {
+        Some(output_name) => output_name.with_extension(&actual_extension),
+        None => input_icon_path.to_path_buf(),
+    };
+    let mut out_paths: Vec<(PathBuf, Output, &IconOperation)> = vec![];
+    let mut problems = vec![];
+    let mut all_warnings = vec![];
+    for (variant, operation, hsv) in &operations {
+        // every variant is run so their problems are all reported together
+        let out = match operation.do_operation(&shifted(&input, hsv.as_ref()), mode) {
+            Ok(out) => out,
+            Err(error) => {
+                problems.push(Error::from(error));
+                continue;
+            }
+        };
+        let (mut out, warnings) = out.take_warnings();
+        for warning in warnings {
+            warn!(variant, "{warning}");
+            all_warnings.push(match variant {
+                Some(variant) => ProcessorWarning::new(format!("{variant}: {warning}")),
+                None => warning,
+            });
+        }
+        // damaged inputs warn about the same things as the undamaged one, so
+        // only its warnings are shown
+        for (index, damaged) in damaged_inputs.iter().enumerate() {
+            match operation.do_operation(&shifted(damaged, hsv.as_ref()), mode) {
+                Ok(damaged) => {
+                    if let Err(error) =
+                        config
+                            .damage
+                            .add_states(&mut out, damaged.take_warnings().0, index + 1)
+                    {
+                        problems.push(Error::from(error));
+                    }
+                }
+                Err(error) => problems.push(Error::from(error)),
+            }
+        }
+        let mut name_path = output_name_path.clone();
+        if let Some(variant) = variant {
+            add_suffix(&mut name_path, &format!("-{variant}"));
+        }
+        // recolored before post processing, so outlines and the like keep
+        // their own colors
+        let mut recolored = vec![];
+        for recolor in &config.recolors {
+            let mut out = out.clone();
+            if let Err(error) = recolor.apply(&mut out) {
+                problems.push(Error::from(error));
+                continue;
+            }
+            let mut name_path = name_path.clone();
+            add_suffix(&mut name_path, &format!("-{}", recolor.name));
+            recolored.push((out, name_path));
+        }
+        for (mut out, name_path) in iter::once((out, name_path)).chain(recolored) {
+            if let Err(error) = config.post_process.apply(&mut out) {
+                problems.push(Error::from(error));
+                continue;
+            }
+            // previewed last, so they show the icon as it's written
+            let out = match operation.add_animation_previews(out) {
+                Ok(out) => out,
+                Err(error) => {
+                    problems.push(Error::from(error));
+                    continue;
+                }
+            };
+            out_paths.extend(
+                handle_payload(out, name_path, output, flatten)
+                    .into_iter()
+                    .map(|(path, output)| (path, output, operation)),
+            );
+        }
+    }
+    if !problems.is_empty() {
+        return Err(Error::all(problems));
+    }
+    if let Some(suffix) = suffix {
+        for (path, ..) in &mut out_paths {
+            add_suffix(path, suffix);
+        }
+    }
 
-    for (mut path, output) in out_paths {
-        let parent_dir = path.parent().expect(
-            "Failed to get parent? (this is a program error, not a config error! Please report!)",
-        );
+    let mut written = vec![];
+    for (mut path, output, operation) in out_paths {
+        written.push(path.clone());
+        if let Some(parent_dir) = path.parent() {
+            fs::create_dir_all(parent_dir)?;
+        }
 
-        fs::create_dir_all(parent_dir).expect(
-            "Failed to create dirs (This is a program error, not a config error! Please report!)",
-        );
+        // has to be read before the output file gets truncated below
+        let existing_icon = if debug
+            && matches!(
+                output,
+                Output::Image(OutputImage::Dmi(_) | OutputImage::EncodedDmi(_))
+            ) {
+            read_existing_icon(&path)
+        } else {
+            None
+        };
 
-        let mut file = File::create(path.as_path()).expect(
-            "Failed to create output file (This is a program error, not a config error! Please \
-             report!)",
-        );
+        let mut file = File::create(path.as_path())?;
 
         match output {
             Output::Image(icon) => {
-                match icon {
+                let wants_icon = gallery.is_some()
+                    || contact_sheet
+                    || state_inventory
+                    || existing_icon.is_some();
+                let icon = match icon {
                     OutputImage::Png(png) => {
                         if let Err(error) = png.save(&mut path) {
                             return Err(Error::from(OutputError::from(error)));
                         };
+                        None
+                    }
+                    OutputImage::Apng(data)
+                    | OutputImage::Gif(data)
+                    | OutputImage::Aseprite(data) => {
+                        if let Err(error) = file.write_all(&data) {
+                            return Err(Error::from(error));
+                        };
+                        None
+                    }
+                    OutputImage::EncodedDmi(data) => {
+                        file.write_all(&data)?;
+                        let before = fs::metadata(input_icon_path)?.len();
+                        let after = data.len() as u64;
+                        println!(
+                            "{}",
+                            format!(
+                                "Optimized {} from {before} to {after} bytes, {} saved",
+                                input_icon_path.display(),
+                                before.saturating_sub(after)
+                            )
+                            .blue()
+                        );
+                        // only decoded again for what needs its states
+                        if wants_icon {
+                            Some(Icon::load(data.as_slice()).map_err(OutputError::from)?)
+                        } else {
+                            None
+                        }
                     }
                     OutputImage::Dmi(dmi) => {
                         if let Err(error) = dmi.save(&mut file) {
                             return Err(Error::from(OutputError::from(error)));
                         };
+                        Some(dmi)
+                    }
+                };
+                if let Some(dmi) = icon {
+                    if let Some(gallery) = gallery {
+                        gallery.add_icon(&path, &dmi)?;
+                    }
+                    if contact_sheet {
+                        let stem = path.file_stem().unwrap().to_string_lossy();
+                        generate_contact_sheet(&dmi, DEFAULT_COLUMNS)
+                            .save(path.with_file_name(format!("{stem}-contact-sheet.png")))
+                            .map_err(OutputError::from)?;
+                    }
+                    if state_inventory {
+                        let inventory =
+                            StateInventory::new(&dmi, |state| operation.state_origin(&state.name));
+                        let json =
+                            serde_json::to_string_pretty(&inventory).map_err(io::Error::from)?;
+                        fs::write(path.with_extension("states.json"), json)?;
+                    }
+                    if let Some(existing_icon) = existing_icon {
+                        write_icon_diff(&path, &existing_icon, &dmi)?;
                     }
                 }
             }
             Output::Text(text) => {
-                match text {
+                let text = match text {
                     OutputText::PngConfig(config) | OutputText::DmiConfig(config) => {
-                        fs::write(path, config).expect(
-                            "Failed to write config text, (This is a program error, not a config \
-                             error! Please report!)",
-                        )
+                        Provenance::stamp_source(&config, &input_icon_path.display().to_string())
                     }
-                }
+                    OutputText::DmCode(code)
+                    | OutputText::ValidationReport(code)
+                    | OutputText::SheetManifest(code) => code,
+                };
+                fs::write(path, text)?;
             }
         }
     }
-    Ok(())
+    Ok(Processed {
+        written,
+        warnings: all_warnings,
+    })
+}
+
+/// Loads the dmi already at `path`, if there is one, so it can be diffed
+/// against. A dmi that fails to load just gets skipped
+fn read_existing_icon(path: &Path) -> Option<Icon> {
+    let file = File::open(path).ok()?;
+    match Icon::load(BufReader::new(file)) {
+        Ok(icon) => Some(icon),
+        Err(error) => {
+            warn!(path = ?path, error = %error, "Failed to read existing dmi, skipping diff");
+            None
+        }
+    }
 }
 
 #[allow(clippy::result_large_err)]
@@ -401,6 +1788,11 @@ fn handle_payload(
             let mut contained = handle_payload(*payload, input_path, output_at, flatten);
             out_paths.append(&mut contained);
         }
+        ProcessorPayload::Warned(payload, _) => {
+            out_paths.append(&mut handle_payload(
+                *payload, input_path, output_at, flatten,
+            ));
+        }
     }
     out_paths
 }
@@ -0,0 +1,79 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::iter;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use dmi::icon::Icon;
+use hypnagogic_core::operations::cancellation::CancellationToken;
+use hypnagogic_core::operations::error::ProcessorError;
+use hypnagogic_core::operations::format_converter::dmi_merge::{ConflictPolicy, DmiMerge};
+use hypnagogic_core::operations::{IconOperationConfig, InputError, InputIcon};
+use owo_colors::OwoColorize;
+use user_error::UFE;
+
+use crate::error::Error;
+
+fn load_icon(path: &str) -> Result<Icon> {
+    let reader =
+        BufReader::new(File::open(path).map_err(|err| anyhow!("Couldn't open {path}: {err}"))?);
+    Icon::load(reader)
+        .map_err(InputError::from)
+        .map_err(|err| anyhow!("Couldn't read {path}: {err}"))
+}
+
+/// Merges the dmis at `inputs` in to one written to `into`, settling states
+/// with clashing names by `on_conflict`
+/// # Errors
+/// Errors if any input can't be read, they aren't all the same size, or
+/// states clash and `on_conflict` is `error`
+pub fn merge(
+    inputs: &[String],
+    into: &Path,
+    on_conflict: ConflictPolicy,
+    rename_suffix: Option<String>,
+) -> Result<()> {
+    let Some((first, rest)) = inputs.split_first() else {
+        return Err(anyhow!("Nothing to merge"));
+    };
+    let first = load_icon(first)?;
+    let config = DmiMerge {
+        with: rest.to_vec(),
+        on_conflict,
+        rename_suffix,
+        with_icons: rest
+            .iter()
+            .map(|path| load_icon(path))
+            .collect::<Result<_>>()?,
+    };
+    let icons: Vec<&Icon> = iter::once(&first).chain(&config.with_icons).collect();
+    let merged = config
+        .verify_config()
+        .and_then(|()| {
+            ProcessorError::check_all(config.input_problems(&InputIcon::Dmi(first.clone())))
+        })
+        .and_then(|()| config.merge(&icons, &CancellationToken::new()));
+    let merged = match merged {
+        Ok(merged) => merged,
+        Err(err) => {
+            Error::from(err).print();
+            return Err(anyhow!("Couldn't merge {}", inputs.join(", ")));
+        }
+    };
+    for warning in config.config_warnings() {
+        println!("{}", warning.yellow());
+    }
+    let mut file = File::create(into)?;
+    merged.save(&mut file)?;
+    println!(
+        "{}",
+        format!(
+            "Merged {} icons in to {} with {} states",
+            inputs.len(),
+            into.display(),
+            merged.states.len()
+        )
+        .bright_green()
+    );
+    Ok(())
+}
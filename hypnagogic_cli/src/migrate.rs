@@ -0,0 +1,101 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use hypnagogic_core::config::format::ConfigFormat;
+use hypnagogic_core::config::migrate::{migrate_config, unrecognised_keys};
+use owo_colors::OwoColorize;
+
+use crate::{config_dir, config_format, TemplateSources};
+
+/// Rewrites every config in `files` to the current toml schema, moving keys
+/// older versions used to where they live now and converting json and yaml
+/// configs to toml. Reports anything that couldn't be mapped. With `dry_run`
+/// nothing is written
+/// # Errors
+/// Errors if any config can't be read or written
+pub fn migrate(files: &[PathBuf], dry_run: bool, templates: &TemplateSources) -> Result<()> {
+    let mut migrated = 0;
+    let mut failed = 0;
+    for path in files {
+        match migrate_file(path, dry_run, templates) {
+            Ok(true) => migrated += 1,
+            Ok(false) => {}
+            Err(err) => {
+                println!("{}", path.display().blue().italic());
+                println!("{}", format!("  {err}").red());
+                failed += 1;
+            }
+        }
+    }
+    let verb = if dry_run { "Would migrate" } else { "Migrated" };
+    println!(
+        "{}",
+        format!("{verb} {migrated} of {} configs", files.len()).bright_green()
+    );
+    if failed > 0 {
+        return Err(anyhow!("{failed} configs couldn't be migrated"));
+    }
+    Ok(())
+}
+
+/// Migrates one config, returning whether it needed to be
+fn migrate_file(path: &Path, dry_run: bool, templates: &TemplateSources) -> Result<bool> {
+    let format = config_format(path);
+    let mut config = format.parse(&fs::read_to_string(path)?)?;
+    let report = migrate_config(&mut config);
+    let unrecognised = unrecognised_keys(&config, config_dir(path), templates);
+
+    let converted = format != ConfigFormat::Toml;
+    let changed = converted || !report.moved.is_empty();
+    let has_unrecognised = unrecognised.as_ref().map_or(true, |keys| !keys.is_empty());
+    if !changed && report.conflicts.is_empty() && !has_unrecognised {
+        return Ok(false);
+    }
+
+    println!("{}", path.display().blue().italic());
+    for (old, new) in &report.moved {
+        println!("  moved {old} to {new}");
+    }
+    for (old, new) in &report.conflicts {
+        println!(
+            "{}",
+            format!("  couldn't move {old}, {new} is already set").yellow()
+        );
+    }
+    match &unrecognised {
+        Ok(keys) => {
+            for key in keys {
+                println!("{}", format!("  {key}").yellow());
+            }
+        }
+        Err(err) => {
+            println!(
+                "{}",
+                format!("  couldn't check the migrated config is valid: {err}").yellow()
+            );
+        }
+    }
+    if !changed {
+        return Ok(false);
+    }
+
+    // wall.png.yaml becomes wall.png.toml
+    let out_path = path.with_extension("toml");
+    if converted {
+        if out_path.exists() {
+            return Err(anyhow!(
+                "{} already exists, so it can't be converted to toml",
+                out_path.display()
+            ));
+        }
+        println!("  converted to {}", out_path.display());
+    }
+    if !dry_run {
+        fs::write(&out_path, toml::to_string(&config)?)?;
+        if converted {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(true)
+}
@@ -0,0 +1,173 @@
+use std::fmt::Debug;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Serialize;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+use user_error::UFE;
+
+use crate::error::Error;
+
+/// Bumped whenever an event changes in a way that could break consumers.
+/// Adding fields or events doesn't count
+pub const PROGRESS_SCHEMA_VERSION: u32 = 1;
+
+/// One line of `--progress-json` output
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent<'a> {
+    RunStarted {
+        schema: u32,
+        version: &'a str,
+        files: usize,
+    },
+    FileStarted {
+        path: &'a Path,
+    },
+    FileFinished {
+        path: &'a Path,
+        written: &'a [PathBuf],
+        duration_ms: u128,
+    },
+    Warning {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        path: Option<&'a str>,
+        message: String,
+    },
+    Error {
+        path: &'a Path,
+        message: String,
+        reasons: Option<Vec<String>>,
+        helptext: Option<String>,
+    },
+    Totals {
+        processed: usize,
+        failed: usize,
+        duration_ms: u128,
+    },
+}
+
+impl ProgressEvent<'_> {
+    pub fn error<'a>(path: &'a Path, error: &Error) -> ProgressEvent<'a> {
+        ProgressEvent::Error {
+            path,
+            message: error.summary(),
+            reasons: error.reasons(),
+            helptext: error.helptext(),
+        }
+    }
+
+    pub fn file_finished<'a>(
+        path: &'a Path,
+        written: &'a [PathBuf],
+        duration: Duration,
+    ) -> ProgressEvent<'a> {
+        ProgressEvent::FileFinished {
+            path,
+            written,
+            duration_ms: duration.as_millis(),
+        }
+    }
+}
+
+/// Writes `event` to stderr as a single line of json. Stderr is locked for
+/// the write, so events from files processed in parallel never interleave
+pub fn emit(event: &ProgressEvent) {
+    let Ok(mut line) = serde_json::to_string(event) else {
+        return;
+    };
+    line.push('\n');
+    // nothing sensible to do if stderr is gone
+    let _ = io::stderr().lock().write_all(line.as_bytes());
+}
+
+/// Path of the file a span is processing, stashed in its extensions
+struct SpanPath(String);
+
+/// Forwards every warning logged while running as a [`ProgressEvent::Warning`],
+/// tagged with the file being processed when there is one
+pub struct ProgressLayer;
+
+impl<S> Layer<S> for ProgressLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::new("path");
+        attrs.record(&mut visitor);
+        if let (Some(path), Some(span)) = (visitor.found, ctx.span(id)) {
+            span.extensions_mut().insert(SpanPath(path));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if *event.metadata().level() != Level::WARN {
+            return;
+        }
+        let mut visitor = FieldVisitor::new("message");
+        event.record(&mut visitor);
+        let path = ctx.event_scope(event).and_then(|scope| {
+            scope.from_root().find_map(|span| {
+                span.extensions()
+                    .get::<SpanPath>()
+                    .map(|path| path.0.clone())
+            })
+        });
+        emit(&ProgressEvent::Warning {
+            path: path.as_deref(),
+            message: visitor.describe(),
+        });
+    }
+}
+
+/// Pulls one field out of a span or event, and keeps every other field around
+/// so warnings can still include them
+struct FieldVisitor {
+    name: &'static str,
+    found: Option<String>,
+    others: Vec<String>,
+}
+
+impl FieldVisitor {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            found: None,
+            others: vec![],
+        }
+    }
+
+    /// The found field followed by every other field, like tracing's own
+    /// compact output
+    fn describe(self) -> String {
+        self.found
+            .into_iter()
+            .chain(self.others)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == self.name {
+            self.found = Some(value.to_string());
+        } else {
+            self.others.push(format!("{}={value}", field.name()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == self.name {
+            self.found = Some(format!("{value:?}").trim_matches('"').to_string());
+        } else {
+            self.others.push(format!("{}={value:?}", field.name()));
+        }
+    }
+}
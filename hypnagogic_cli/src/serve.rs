@@ -0,0 +1,230 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use hypnagogic_core::operations::IconOperationConfig;
+use owo_colors::OwoColorize;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+use user_error::UFE;
+
+use crate::error::Error;
+use crate::{load_config, process_icon, RunContext, TemplateSources};
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+/// Returned when a request was understood but processing it failed
+const PROCESSING_FAILED: i64 = 1;
+
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+#[derive(Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+impl From<Error> for RpcError {
+    fn from(error: Error) -> Self {
+        Self {
+            code: PROCESSING_FAILED,
+            message: error.summary(),
+            data: Some(json!({
+                "reasons": error.reasons(),
+                "helptext": error.helptext(),
+            })),
+        }
+    }
+}
+
+/// Params of `process` and `validate`
+#[derive(Deserialize)]
+struct ConfigParams {
+    /// Path of the config to use
+    path: PathBuf,
+    #[serde(default)]
+    output: Option<String>,
+    #[serde(default)]
+    flatten: bool,
+    #[serde(default)]
+    debug: bool,
+}
+
+/// Listens on `address` for JSON-RPC 2.0 requests, one per line, until the
+/// process is killed. Every connection is handled on its own thread, and
+/// requests on a connection are answered in order. Requests read and write
+/// any file the server can, so only loopback addresses are listened on
+/// # Errors
+/// Errors if `address` isn't a loopback address, or can't be bound to
+pub fn serve(address: &str, templates: &TemplateSources) -> Result<()> {
+    let addresses: Vec<_> = address.to_socket_addrs()?.collect();
+    if let Some(remote) = addresses.iter().find(|address| !address.ip().is_loopback()) {
+        return Err(anyhow!(
+            "Refusing to listen on {remote}, which isn't a loopback address. Anyone who can reach \
+             the server can read and write files through it"
+        ));
+    }
+    let listener = TcpListener::bind(addresses.as_slice())?;
+    println!(
+        "{}",
+        format!(
+            "Listening for JSON-RPC requests on {}",
+            listener.local_addr()?
+        )
+        .bright_green()
+    );
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                warn!(error = %error, "Failed to accept connection");
+                continue;
+            }
+        };
+        let templates = templates.clone();
+        thread::spawn(move || {
+            if let Err(error) = handle_connection(stream, &templates) {
+                warn!(error = %error, "Connection closed with an error");
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, templates: &TemplateSources) -> std::io::Result<()> {
+    info!(peer = ?stream.peer_addr(), "Accepted connection");
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = handle_request(&line, templates);
+        let mut text = serde_json::to_string(&response)?;
+        text.push('\n');
+        writer.write_all(text.as_bytes())?;
+    }
+    Ok(())
+}
+
+fn handle_request(line: &str, templates: &TemplateSources) -> Response {
+    let (id, outcome) = match serde_json::from_str::<Request>(line) {
+        Ok(request) => {
+            info!(method = request.method, "Handling request");
+            // templates may have been edited since the last request
+            templates.forget_resolved();
+            // a bug in one request shouldn't take the connection down with it
+            let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+                dispatch(&request.method, request.params, templates)
+            }))
+            .unwrap_or_else(|_| {
+                Err(RpcError::new(
+                    INTERNAL_ERROR,
+                    "Processing the request panicked, this is a bug, please report it",
+                ))
+            });
+            (request.id, outcome)
+        }
+        Err(error) => {
+            (
+                Value::Null,
+                Err(RpcError::new(PARSE_ERROR, error.to_string())),
+            )
+        }
+    };
+    let (result, error) = match outcome {
+        Ok(result) => (Some(result), None),
+        Err(error) => (None, Some(error)),
+    };
+    Response {
+        jsonrpc: "2.0",
+        id,
+        result,
+        error,
+    }
+}
+
+fn dispatch(method: &str, params: Value, templates: &TemplateSources) -> Result<Value, RpcError> {
+    match method {
+        // cutting and restoring are both decided by the config's mode
+        "process" | "cut" | "restore" => {
+            let params = parse_params(params)?;
+            let context = RunContext {
+                flatten: params.flatten,
+                debug: params.debug,
+                contact_sheet: false,
+                state_inventory: false,
+                output: &params.output,
+                suffix: None,
+                operation: None,
+                auto_fix: false,
+                strict: false,
+                templates,
+                gallery: None,
+                duplicate_finder: None,
+            };
+            let processed = process_icon(&context, &params.path)?;
+            Ok(json!({ "written": processed.written, "warnings": processed.warnings }))
+        }
+        "validate" => {
+            let params = parse_params(params)?;
+            let operation = load_config(&params.path, templates, None, false)?.operation;
+            operation.verify_config().map_err(Error::from)?;
+            Ok(json!({ "valid": true, "warnings": operation.config_warnings() }))
+        }
+        "ping" => Ok(json!({ "version": crate::VERSION })),
+        _ => {
+            Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method \"{method}\""),
+            ))
+        }
+    }
+}
+
+fn parse_params(params: Value) -> Result<ConfigParams, RpcError> {
+    let params: ConfigParams = serde_json::from_value(params)
+        .map_err(|error| RpcError::new(INVALID_PARAMS, error.to_string()))?;
+    if !params.path.is_file() {
+        return Err(RpcError::new(
+            INVALID_PARAMS,
+            format!("{} isn't a file", params.path.display()),
+        ));
+    }
+    Ok(params)
+}
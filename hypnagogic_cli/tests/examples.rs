@@ -0,0 +1,80 @@
+#[macro_use]
+mod util;
+
+use std::path::{Path, PathBuf};
+
+use assert_cmd::prelude::*;
+use util::run::run_with_args;
+use walkdir::WalkDir;
+
+/// Every example in `examples/corpus`, cut and compared against what it's
+/// expected to produce
+mod examples {
+    use util::dir_tester::DirTester;
+
+    use super::*;
+
+    test_example!("bitmask-slice");
+    test_example!("bitmask-slice-diagonals");
+    test_example!("bitmask-slice-groups");
+    test_example!("bitmask-directional-vis");
+    test_example!("bitmask-windows");
+    test_example!("bitmask-slice-reconstruct");
+    test_example!("bitmask-slice-shared-sheet");
+    test_example!("bitmask-slice-layers");
+    test_example!("bitmask-slice-manifest");
+    test_example!("bitmask-slice-variants");
+    test_example!("bitmask-slice-corner-atlas");
+    test_example!("bitmask-slice-post-process");
+    test_example!("bitmask-slice-greyscale");
+    test_example!("bitmask-slice-recolors");
+    test_example!("bitmask-slice-damage");
+    test_example!("turf-edges");
+    test_example!("bitmask-pipes");
+    test_example!("bitmask-lattice");
+    test_example!("bitmask-corner-overlays");
+    test_example!("directional-rotation");
+    test_example!("bitmask-texture-mask");
+    test_example!("bitmask-wall-tops");
+    test_example!("grid-slice");
+    test_example!("glyph-sheet");
+    test_example!("dmi-merge");
+    test_example!("dmi-split");
+    test_example!("dmi-rename");
+    test_example!("dmi-optimize");
+    test_example!("dmi-validate");
+    test_example!("dmi-export");
+    test_example!("dmi-import");
+    test_example!("dmi-aseprite");
+    test_example!("bitmask-slice-diagonal-walls");
+}
+
+/// Configs the examples are expected to write, like a reconstructed sheet's,
+/// have to pass validation themselves
+#[test]
+fn expected_configs_validate() {
+    let corpus = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../examples/corpus"));
+    let configs: Vec<PathBuf> = WalkDir::new(corpus)
+        .into_iter()
+        .filter_map(Result::ok)
+        .map(walkdir::DirEntry::into_path)
+        .filter(|path| path.components().any(|part| part.as_os_str() == "expected"))
+        .filter(|path| {
+            // configs are named after their input, unlike reports
+            path.extension()
+                .is_some_and(|extension| extension == "toml")
+                && path
+                    .file_stem()
+                    .map(Path::new)
+                    .and_then(Path::extension)
+                    .is_some_and(|extension| extension == "png" || extension == "dmi")
+        })
+        .collect();
+    assert!(!configs.is_empty());
+    for config in configs {
+        let name = config.file_name().unwrap().to_str().unwrap().to_string();
+        let mut command = run_with_args(vec!["validate".to_string(), name]).unwrap();
+        command.current_dir(config.parent().unwrap());
+        command.assert().success();
+    }
+}
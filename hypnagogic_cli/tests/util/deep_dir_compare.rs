@@ -4,9 +4,9 @@ use std::path::{Path, PathBuf};
 use dmi::icon::Icon;
 use image::DynamicImage;
 use thiserror::Error;
-use tracing::error;
 use walkdir::WalkDir;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Error)]
 pub enum DmiCompareError {
     #[error("Different icon sizes: {0:?} vs {1:?}")]
@@ -87,10 +87,16 @@ pub fn compare_dmi(dmi1: &Icon, dmi2: &Icon) -> Result<(), DmiCompareError> {
 pub enum CompareFailureReasonError {
     #[error("Error comparing DMIs: {0}")]
     DmiCompareError(#[from] DmiCompareError),
+    #[error("Different png pixel data")]
+    DifferentPixelData,
+    #[error("Different config values")]
+    DifferentConfig,
     #[error("Error walking directory: {0}")]
     IoError(#[from] std::io::Error),
 }
 
+// fields are only read through the Debug impl when a comparison panics
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct CompareFailureError {
     pub a: PathBuf,
@@ -104,27 +110,51 @@ impl CompareFailureError {
     }
 }
 
+/// Compares two files of the same kind. Dmis are compared state by state,
+/// pngs by their pixels, and tomls by their values, leaving out the
+/// `[provenance]` written alongside generated configs since it's timestamped.
+/// Anything else is skipped
+fn compare_file(path1: &Path, path2: &Path) -> Result<(), CompareFailureReasonError> {
+    match path1.extension().and_then(|extension| extension.to_str()) {
+        Some("dmi") => {
+            let dmi1 = Icon::load(std::fs::File::open(path1)?).unwrap();
+            let dmi2 = Icon::load(std::fs::File::open(path2)?).unwrap();
+            compare_dmi(&dmi1, &dmi2)?;
+        }
+        Some("png") => {
+            let image1 = image::open(path1).unwrap().into_rgba8();
+            let image2 = image::open(path2).unwrap().into_rgba8();
+            if image1 != image2 {
+                return Err(CompareFailureReasonError::DifferentPixelData);
+            }
+        }
+        Some("toml") => {
+            let read = |path: &Path| -> Result<toml::Table, std::io::Error> {
+                let mut table: toml::Table = std::fs::read_to_string(path)?.parse().unwrap();
+                table.remove("provenance");
+                Ok(table)
+            };
+            if read(path1)? != read(path2)? {
+                return Err(CompareFailureReasonError::DifferentConfig);
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
 pub fn deep_compare_path(path1: &Path, path2: &Path) -> Result<(), Vec<CompareFailureError>> {
-    let path1_iter = WalkDir::new(path1).into_iter();
-    let path2_iter = WalkDir::new(path2).into_iter();
+    let path1_iter = WalkDir::new(path1).sort_by_file_name().into_iter();
+    let path2_iter = WalkDir::new(path2).sort_by_file_name().into_iter();
 
     let res: Vec<_> = path1_iter
         .zip(path2_iter)
         .filter_map(|(entry1, entry2)| {
             if let (Ok(entry1), Ok(entry2)) = (entry1, entry2) {
                 if entry1.file_type().is_file() && entry2.file_type().is_file() {
-                    let file1 = std::fs::File::open(entry1.path()).unwrap();
-                    let dmi1 = Icon::load(file1).unwrap();
-
-                    let file2 = std::fs::File::open(entry2.path()).unwrap();
-                    let dmi2 = Icon::load(file2).unwrap();
-                    let res = compare_dmi(&dmi1, &dmi2);
+                    let res = compare_file(entry1.path(), entry2.path());
                     res.err().map(|inner_err| {
-                        CompareFailureError::new(
-                            entry1.into_path(),
-                            entry2.into_path(),
-                            CompareFailureReasonError::DmiCompareError(inner_err),
-                        )
+                        CompareFailureError::new(entry1.into_path(), entry2.into_path(), inner_err)
                     })
                 } else {
                     None
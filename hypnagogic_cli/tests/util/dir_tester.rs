@@ -15,8 +15,8 @@ impl DirTester {
     }
 
     pub fn run(&mut self) {
-        let args_path = self.dir.join("args.txt");
-        let args = read_to_string(args_path).unwrap();
+        // examples take no extra arguments, so don't have to ship an empty file
+        let args = read_to_string(self.dir.join("args.txt")).unwrap_or_default();
         let mut args: Vec<String> = args.lines().map(|s| s.to_string()).collect();
 
         args.push("--output".to_string());
@@ -52,3 +52,18 @@ macro_rules! test_dir {
         }
     };
 }
+
+#[macro_export]
+macro_rules! test_example {
+    ($dir:literal) => {
+        ::paste::paste! {
+            #[test]
+            fn [<example_ $dir:snake>]() {
+                let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../examples/corpus/", $dir);
+                let dir = std::path::Path::new(dir);
+                let mut tester = DirTester::new(dir);
+                tester.run();
+            }
+        }
+    };
+}
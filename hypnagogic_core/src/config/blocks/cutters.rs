@@ -1,11 +1,19 @@
+use std::borrow::Cow;
 use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::num::NonZeroU32;
 
+use dmi::icon::{Hotspot, Looping};
 use fixed_map::Map;
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::de::IntoDeserializer;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::util::corners::{CornerType, Side};
+use crate::util::adjacency::Adjacency;
+use crate::util::blend::BlendMode;
+use crate::util::corners::{Corner, CornerType, Side};
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct IconSize {
     pub x: u32,
     pub y: u32,
@@ -17,13 +25,13 @@ impl Default for IconSize {
     }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize, Default)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize, Default, JsonSchema)]
 pub struct OutputIconPosition {
     pub x: u32,
     pub y: u32,
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct OutputIconSize {
     pub x: u32,
     pub y: u32,
@@ -35,7 +43,18 @@ impl Default for OutputIconSize {
     }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+/// The logical tile within an oversized icon, the part that lines up with the
+/// map grid, measured from the icon's top left. Anything outside of it
+/// overhangs on to the neighbouring tiles
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct TileBounds {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct CutPosition {
     pub x: u32,
     pub y: u32,
@@ -47,20 +66,51 @@ impl Default for CutPosition {
     }
 }
 
+/// Where each corner type's block is in the input. A `None` position is a
+/// slot marked as intentionally empty (`"none"` in configs), for work in
+/// progress sheets
 #[derive(Clone, Eq, PartialEq, Debug)]
-pub struct Positions(pub Map<CornerType, u32>);
+pub struct Positions(pub Map<CornerType, Option<u32>>);
 
 impl Positions {
+    /// Position of a corner type's block, `None` if it's missing or empty
     #[must_use]
     pub fn get(&self, key: CornerType) -> Option<u32> {
-        self.0.get(key).copied()
+        self.0.get(key).copied().flatten()
+    }
+
+    /// Whether a corner type's slot is marked as intentionally empty
+    #[must_use]
+    pub fn is_empty_slot(&self, key: CornerType) -> bool {
+        matches!(self.0.get(key), Some(None))
     }
 }
 
+/// Parses a key of the `table` table as one of the names of `T`, so a typo is
+/// reported with every name it could have been
+fn parse_key<'de, T: Deserialize<'de>, D: Deserializer<'de>>(
+    key: &str,
+    table: &str,
+) -> Result<T, D::Error> {
+    T::deserialize(key.into_deserializer()).map_err(|err: serde::de::value::Error| {
+        serde::de::Error::custom(format!("{err} in {table}"))
+    })
+}
+
+/// Value written in config files to mark a position slot as empty
+const EMPTY_POSITION: &str = "none";
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum PositionValue {
+    Position(u32),
+    Named(String),
+}
+
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[serde(transparent)]
 struct PositionsHelper {
-    map: BTreeMap<String, u32>,
+    map: BTreeMap<String, PositionValue>,
 }
 
 impl Serialize for Positions {
@@ -71,7 +121,11 @@ impl Serialize for Positions {
         let mut map = BTreeMap::new();
 
         for (k, v) in self.0.iter() {
-            map.insert(k.to_string(), *v);
+            let value = match v {
+                Some(position) => PositionValue::Position(*position),
+                None => PositionValue::Named(EMPTY_POSITION.to_string()),
+            };
+            map.insert(k.to_string(), value);
         }
 
         PositionsHelper { map }.serialize(serializer)
@@ -83,23 +137,42 @@ impl<'de> Deserialize<'de> for Positions {
     where
         D: Deserializer<'de>,
     {
-        Deserialize::deserialize(deserializer).map(|PositionsHelper { map }| {
-            let mut result = Map::new();
-            for (k, v) in map {
-                result.insert(k.as_str().into(), v);
-            }
-            Positions(result)
-        })
+        let PositionsHelper { map } = Deserialize::deserialize(deserializer)?;
+        let mut result = Map::new();
+        for (k, v) in map {
+            let position = match v {
+                PositionValue::Position(position) => Some(position),
+                PositionValue::Named(name) if name == EMPTY_POSITION => None,
+                PositionValue::Named(name) => {
+                    return Err(serde::de::Error::custom(format!(
+                        "invalid position \"{name}\" for {k}, expected a number or \
+                         \"{EMPTY_POSITION}\""
+                    )));
+                }
+            };
+            result.insert(parse_key::<CornerType, D>(&k, "positions")?, position);
+        }
+        Ok(Positions(result))
+    }
+}
+
+impl JsonSchema for Positions {
+    fn schema_name() -> Cow<'static, str> {
+        "Positions".into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        BTreeMap::<CornerType, PositionValue>::json_schema(generator)
     }
 }
 
 impl Default for Positions {
     fn default() -> Self {
         let mut map = Map::new();
-        map.insert(CornerType::Convex, 0);
-        map.insert(CornerType::Concave, 1);
-        map.insert(CornerType::Horizontal, 2);
-        map.insert(CornerType::Vertical, 3);
+        map.insert(CornerType::Convex, Some(0));
+        map.insert(CornerType::Concave, Some(1));
+        map.insert(CornerType::Horizontal, Some(2));
+        map.insert(CornerType::Vertical, Some(3));
         Positions(map)
     }
 }
@@ -114,7 +187,7 @@ impl StringMap {
     }
 }
 
-#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(transparent)]
 struct StringMapHelper {
     map: HashMap<String, String>,
@@ -150,13 +223,97 @@ impl<'de> Deserialize<'de> for StringMap {
     }
 }
 
+impl JsonSchema for StringMap {
+    fn schema_name() -> Cow<'static, str> {
+        "StringMap".into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        StringMapHelper::json_schema(generator)
+    }
+}
+
 #[derive(Clone, Eq, PartialEq, Debug, Default)]
-pub struct Prefabs(pub BTreeMap<u8, u32>);
+pub struct Prefabs(pub BTreeMap<u8, Prefab>);
+
+impl Prefabs {
+    /// Junctions and positions of the prefabs read from the input
+    pub fn sheet_positions(&self) -> impl Iterator<Item = (u8, u32)> + '_ {
+        self.0
+            .iter()
+            .filter(|(_, prefab)| prefab.file.is_none())
+            .map(|(junction, prefab)| (*junction, prefab.position))
+    }
+
+    /// Every separate file prefabs are read from, with the positions read
+    /// from each
+    #[must_use]
+    pub fn files(&self) -> BTreeMap<&str, Vec<u32>> {
+        let mut files: BTreeMap<&str, Vec<u32>> = BTreeMap::new();
+        for prefab in self.0.values() {
+            if let Some(file) = &prefab.file {
+                files.entry(file).or_default().push(prefab.position);
+            }
+        }
+        files
+    }
+}
+
+/// Where the block a junction's state is copied from is
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Prefab {
+    /// Position of the block, counted the same way as `positions`
+    pub position: u32,
+    /// Png, relative to the config, the block is read from instead of the
+    /// input
+    pub file: Option<String>,
+    /// Corners of the junction's state replaced by the same corners of the
+    /// block, leaving the rest cut from corners as usual. The whole state is
+    /// replaced if unset
+    pub corners: Option<Vec<Corner>>,
+}
+
+impl Prefab {
+    /// Read from the input
+    #[must_use]
+    pub const fn sheet(position: u32) -> Self {
+        Self {
+            position,
+            file: None,
+            corners: None,
+        }
+    }
+
+    /// If `corner` of the junction's state comes from the prefab
+    #[must_use]
+    pub fn covers(&self, corner: Corner) -> bool {
+        self.corners
+            .as_ref()
+            .is_none_or(|corners| corners.contains(&corner))
+    }
+}
+
+/// Prefabs are written as just their position when read from the input
+/// whole
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum PrefabValue {
+    Position(u32),
+    Table {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default)]
+        file: Option<String>,
+        position: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default)]
+        corners: Option<Vec<Corner>>,
+    },
+}
 
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 #[serde(transparent)]
 struct PrefabsHelper {
-    map: BTreeMap<String, u32>,
+    map: BTreeMap<String, PrefabValue>,
 }
 
 impl Serialize for Prefabs {
@@ -167,7 +324,16 @@ impl Serialize for Prefabs {
         let mut map = BTreeMap::new();
 
         for (k, v) in &self.0 {
-            map.insert(k.to_string(), *v);
+            let value = if *v == Prefab::sheet(v.position) {
+                PrefabValue::Position(v.position)
+            } else {
+                PrefabValue::Table {
+                    file: v.file.clone(),
+                    position: v.position,
+                    corners: v.corners.clone(),
+                }
+            };
+            map.insert(k.to_string(), value);
         }
 
         PrefabsHelper { map }.serialize(serializer)
@@ -179,23 +345,107 @@ impl<'de> Deserialize<'de> for Prefabs {
     where
         D: Deserializer<'de>,
     {
-        Deserialize::deserialize(deserializer).map(|PrefabsHelper { map }| {
-            let mut result = BTreeMap::new();
-            for (k, v) in map {
-                result.insert(k.parse().unwrap(), v);
+        let PrefabsHelper { map } = Deserialize::deserialize(deserializer)?;
+        let mut result = BTreeMap::new();
+        let mut keys: BTreeMap<u8, String> = BTreeMap::new();
+        for (k, v) in map {
+            let junction: u8 = k.parse().map_err(|_| {
+                serde::de::Error::custom(format!(
+                    "prefab key `{k}` isn't a junction, a number from 0 to 255"
+                ))
+            })?;
+            let prefab = match v {
+                PrefabValue::Position(position) => Prefab::sheet(position),
+                PrefabValue::Table {
+                    file,
+                    position,
+                    corners,
+                } => {
+                    Prefab {
+                        position,
+                        file,
+                        corners,
+                    }
+                }
+            };
+            // `4` and `04` are the same junction, and there's no telling which
+            // was meant
+            if let Some(other) = keys.insert(junction, k.clone()) {
+                return Err(serde::de::Error::custom(format!(
+                    "prefab keys `{other}` and `{k}` are both junction {junction}, only one of \
+                     them can be set"
+                )));
             }
-            Prefabs(result)
-        })
+            result.insert(junction, prefab);
+        }
+        Ok(Prefabs(result))
     }
 }
 
-#[derive(Clone, Eq, PartialEq, Debug, Default)]
-pub struct PrefabOverlays(pub BTreeMap<u8, Vec<u32>>);
+impl JsonSchema for Prefabs {
+    fn schema_name() -> Cow<'static, str> {
+        "Prefabs".into()
+    }
 
-#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        BTreeMap::<u8, PrefabValue>::json_schema(generator)
+    }
+}
+
+/// Blocks of the input drawn over the states of some junctions, in order
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct PrefabOverlays(pub BTreeMap<u8, Vec<PrefabOverlay>>);
+
+impl PrefabOverlays {
+    /// Every position an overlay is read from
+    pub fn positions(&self) -> impl Iterator<Item = u32> + '_ {
+        self.0.values().flatten().map(|overlay| overlay.position)
+    }
+}
+
+/// A block of the input drawn over a junction's state
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PrefabOverlay {
+    pub position: u32,
+    pub blend: BlendMode,
+    /// From 0 for invisible to 1 for as drawn
+    pub opacity: f32,
+}
+
+impl PrefabOverlay {
+    /// Drawn over the top as is
+    #[must_use]
+    pub const fn normal(position: u32) -> Self {
+        Self {
+            position,
+            blend: BlendMode::Normal,
+            opacity: 1.0,
+        }
+    }
+}
+
+const fn full_opacity() -> f32 {
+    1.0
+}
+
+/// Overlays are written as just their position when drawn normally
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum OverlayValue {
+    Position(u32),
+    Blended {
+        position: u32,
+        #[serde(default)]
+        blend: BlendMode,
+        #[serde(default = "full_opacity")]
+        opacity: f32,
+    },
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[serde(transparent)]
 struct PrefabOverlaysHelper {
-    map: BTreeMap<String, Vec<u32>>,
+    map: BTreeMap<String, Vec<OverlayValue>>,
 }
 
 impl Serialize for PrefabOverlays {
@@ -206,7 +456,21 @@ impl Serialize for PrefabOverlays {
         let mut map = BTreeMap::new();
 
         for (k, v) in &self.0 {
-            map.insert(k.to_string(), v.clone());
+            let values = v
+                .iter()
+                .map(|overlay| {
+                    if *overlay == PrefabOverlay::normal(overlay.position) {
+                        OverlayValue::Position(overlay.position)
+                    } else {
+                        OverlayValue::Blended {
+                            position: overlay.position,
+                            blend: overlay.blend,
+                            opacity: overlay.opacity,
+                        }
+                    }
+                })
+                .collect();
+            map.insert(k.to_string(), values);
         }
 
         PrefabOverlaysHelper { map }.serialize(serializer)
@@ -218,20 +482,367 @@ impl<'de> Deserialize<'de> for PrefabOverlays {
     where
         D: Deserializer<'de>,
     {
-        Deserialize::deserialize(deserializer).map(|PrefabOverlaysHelper { map }| {
-            let mut result = BTreeMap::new();
-            for (k, v) in map {
-                result.insert(k.parse().unwrap(), v);
-            }
-            PrefabOverlays(result)
-        })
+        let PrefabOverlaysHelper { map } = Deserialize::deserialize(deserializer)?;
+        let mut result = BTreeMap::new();
+        for (k, v) in map {
+            let junction: u8 = k.parse().map_err(|_| {
+                serde::de::Error::custom(format!(
+                    "prefab_overlays key `{k}` isn't a junction, a number from 0 to 255"
+                ))
+            })?;
+            let overlays = v
+                .into_iter()
+                .map(|value| {
+                    match value {
+                        OverlayValue::Position(position) => PrefabOverlay::normal(position),
+                        OverlayValue::Blended {
+                            position,
+                            blend,
+                            opacity,
+                        } => {
+                            PrefabOverlay {
+                                position,
+                                blend,
+                                opacity,
+                            }
+                        }
+                    }
+                })
+                .collect();
+            result.insert(junction, overlays);
+        }
+        Ok(PrefabOverlays(result))
     }
 }
 
-#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+impl JsonSchema for PrefabOverlays {
+    fn schema_name() -> Cow<'static, str> {
+        "PrefabOverlays".into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        BTreeMap::<u8, Vec<OverlayValue>>::json_schema(generator)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
 pub struct Animation {
     pub delays: Vec<f32>,
+    /// Unit `delays` are written in, deciseconds if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub delay_unit: Option<DelayUnit>,
     pub rewind: Option<bool>,
+    /// How many times the animation plays before stopping on its last frame,
+    /// forever if unset
+    #[serde(rename = "loop")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub loop_count: Option<u32>,
+    /// Marks every state as a movement state, used while the atom glides
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub movement: Option<bool>,
+    /// Expected number of frames. If set, the input's height has to match
+    /// exactly instead of the frame count being inferred from it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub frames: Option<u32>,
+    /// Collapses runs of identical frames in to one frame with their delays
+    /// added together. Defaults to true
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub dedupe: Option<bool>,
+    /// How far apart each channel of a pixel can be for frames to still count
+    /// as identical when deduping
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub dedupe_tolerance: Option<u8>,
+    /// Emits animated previews alongside the dmi when set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub preview: Option<AnimationPreview>,
+}
+
+impl Animation {
+    /// How states loop, going by `loop`
+    #[must_use]
+    pub fn looping(&self) -> Looping {
+        self.loop_count
+            .and_then(NonZeroU32::new)
+            .map_or(Looping::Indefinitely, Looping::NTimes)
+    }
+
+    /// `delays` in the deciseconds byond uses
+    #[must_use]
+    pub fn delays_in_deciseconds(&self) -> Vec<f32> {
+        let unit = self.delay_unit.unwrap_or_default();
+        self.delays
+            .iter()
+            .map(|delay| unit.to_deciseconds(*delay))
+            .collect()
+    }
+}
+
+/// Unit animation delays are written in
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DelayUnit {
+    /// Tenths of a second, what byond uses
+    #[default]
+    Deciseconds,
+    /// Server ticks, at the 20 a second most codebases run at
+    Ticks,
+    Milliseconds,
+}
+
+impl DelayUnit {
+    /// How many of this unit there are in a decisecond
+    #[must_use]
+    pub const fn per_decisecond(self) -> f32 {
+        match self {
+            DelayUnit::Deciseconds => 1.0,
+            DelayUnit::Ticks => 2.0,
+            DelayUnit::Milliseconds => 100.0,
+        }
+    }
+
+    #[must_use]
+    pub fn to_deciseconds(self, delay: f32) -> f32 {
+        delay / self.per_decisecond()
+    }
+
+    #[must_use]
+    pub fn from_deciseconds(self, delay: f32) -> f32 {
+        delay * self.per_decisecond()
+    }
+}
+
+impl fmt::Display for DelayUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DelayUnit::Deciseconds => write!(f, "deciseconds"),
+            DelayUnit::Ticks => write!(f, "ticks"),
+            DelayUnit::Milliseconds => write!(f, "milliseconds"),
+        }
+    }
+}
+
+/// Which way positions run in an input sheet
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum InputLayout {
+    /// Positions run left to right, with animation frames stacked downwards
+    #[default]
+    Horizontal,
+    /// Positions run top to bottom, with animation frames running rightwards
+    Vertical,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewFormat {
+    #[default]
+    Apng,
+    Gif,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewLayout {
+    /// One preview per animated state, with every dir side by side
+    #[default]
+    PerState,
+    /// One preview of every state in a grid, all playing at once
+    PerDmi,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct AnimationPreview {
+    #[serde(default)]
+    pub format: PreviewFormat,
+    #[serde(default)]
+    pub layout: PreviewLayout,
+}
+
+/// Cut positions for single corners, overriding `cut_pos` for them
+#[derive(Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct CornerCutPositions(pub BTreeMap<Corner, CutPosition>);
+
+/// Alternative positions for each corner type's block, picked between along
+/// with the one in `positions`
+#[derive(Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct CornerVariants(pub BTreeMap<CornerType, Vec<u32>>);
+
+/// Output positions for single dirs, overriding `output_icon_pos` for the
+/// states facing them
+#[derive(Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct DirOutputPositions(pub BTreeMap<Side, OutputIconPosition>);
+
+/// Hotspot set on every generated state, for cursors and held items. Measured
+/// from the bottom left of the output icon, like byond does
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct IconHotspot {
+    pub x: u32,
+    pub y: u32,
+    /// Frame the hotspot is on, counting from 1. Only the first frame can be
+    /// written to a dmi
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub frame: Option<u32>,
+}
+
+impl IconHotspot {
+    #[must_use]
+    pub const fn dmi_hotspot(self) -> Hotspot {
+        Hotspot {
+            x: self.x,
+            y: self.y,
+        }
+    }
+}
+
+/// Emits a set of junctions a second time on a different sized canvas, as
+/// extra states with `name` inserted into their state names
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SizeOverride {
+    pub name: String,
+    pub junctions: Vec<u8>,
+    pub output_icon_size: OutputIconSize,
+    /// Where the regular output is placed on the override's canvas
+    #[serde(default)]
+    pub output_icon_pos: OutputIconPosition,
+}
+
+/// Name inserted into diagonal wall states when none is set
+pub const DEFAULT_DIAGONAL_WALL_NAME: &str = "d";
+
+fn default_diagonal_wall_name() -> String {
+    DEFAULT_DIAGONAL_WALL_NAME.to_string()
+}
+
+/// The 45 degree diagonal walls emitted for a tile connected on two sides that
+/// meet at a corner, as extra states with `name` inserted into their state
+/// names. Each is copied whole from the input, like a prefab, and drawn solid
+/// towards the corner its connections meet at
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DiagonalWalls {
+    #[serde(default = "default_diagonal_wall_name")]
+    pub name: String,
+    /// Position of the wall connected north and east
+    pub ne: u32,
+    /// Position of the wall connected south and east
+    pub se: u32,
+    /// Position of the wall connected south and west
+    pub sw: u32,
+    /// Position of the wall connected north and west
+    pub nw: u32,
+}
+
+impl DiagonalWalls {
+    /// Each diagonal with the position of its wall
+    #[must_use]
+    pub fn positions(&self) -> [(Adjacency, u32); 4] {
+        [
+            (Adjacency::NE, self.ne),
+            (Adjacency::SE, self.se),
+            (Adjacency::SW, self.sw),
+            (Adjacency::NW, self.nw),
+        ]
+    }
+}
+
+/// Limits the states of some junctions to part of the input's frames, so they
+/// can stay still while the rest animate, or the other way around
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct FrameRange {
+    pub junctions: Vec<u8>,
+    /// First frame used, counting from 0
+    #[serde(default)]
+    pub start: u32,
+    /// Last frame used, defaults to the last frame of the input
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub end: Option<u32>,
+}
+
+/// Where the blocks for corners touching something of the same smoothing
+/// group, rather than the same type, are in the input. A corner uses these if
+/// any side it connects along touches the group. Concave and flat corners
+/// touching the group along only one side use the matching `_horizontal` or
+/// `_vertical` block, falling back to the block for both sides if it's unset
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct GroupPositions {
+    pub horizontal: u32,
+    pub vertical: u32,
+    pub concave: u32,
+    /// Required when smoothing diagonally
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub flat: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub concave_horizontal: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub concave_vertical: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub flat_horizontal: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub flat_vertical: Option<u32>,
+}
+
+impl GroupPositions {
+    /// Position of the block for a corner of `corner_type`, given whether its
+    /// horizontal and vertical sides touch the group. `None` if it doesn't
+    /// touch the group at all, or it's a flat corner without a flat block
+    #[must_use]
+    pub fn get(
+        &self,
+        corner_type: CornerType,
+        horizontal_group: bool,
+        vertical_group: bool,
+    ) -> Option<u32> {
+        match (corner_type, horizontal_group, vertical_group) {
+            (CornerType::Horizontal, true, _) => Some(self.horizontal),
+            (CornerType::Vertical, _, true) => Some(self.vertical),
+            (CornerType::Convex | CornerType::Horizontal | CornerType::Vertical, ..)
+            | (_, false, false) => None,
+            (CornerType::Concave, true, true) => Some(self.concave),
+            (CornerType::Concave, true, false) => {
+                Some(self.concave_horizontal.unwrap_or(self.concave))
+            }
+            (CornerType::Concave, false, true) => {
+                Some(self.concave_vertical.unwrap_or(self.concave))
+            }
+            (CornerType::Flat, true, true) => self.flat,
+            (CornerType::Flat, true, false) => self.flat_horizontal.or(self.flat),
+            (CornerType::Flat, false, true) => self.flat_vertical.or(self.flat),
+        }
+    }
+
+    /// Every position blocks are cut from
+    #[must_use]
+    pub fn all(&self) -> Vec<u32> {
+        [
+            Some(self.horizontal),
+            Some(self.vertical),
+            Some(self.concave),
+            self.flat,
+            self.concave_horizontal,
+            self.concave_vertical,
+            self.flat_horizontal,
+            self.flat_vertical,
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
 }
 
 #[derive(Clone, Eq, PartialEq, Debug)]
@@ -270,13 +881,22 @@ impl<'de> Deserialize<'de> for SlicePoint {
     where
         D: Deserializer<'de>,
     {
-        Deserialize::deserialize(deserializer).map(|SlicePointHelper { map }| {
-            let mut result = Map::new();
-            for (k, v) in map {
-                result.insert(k.as_str().into(), v);
-            }
-            SlicePoint(result)
-        })
+        let SlicePointHelper { map } = Deserialize::deserialize(deserializer)?;
+        let mut result = Map::new();
+        for (k, v) in map {
+            result.insert(parse_key::<Side, D>(&k, "slice_point")?, v);
+        }
+        Ok(SlicePoint(result))
+    }
+}
+
+impl JsonSchema for SlicePoint {
+    fn schema_name() -> Cow<'static, str> {
+        "SlicePoint".into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        BTreeMap::<Side, u32>::json_schema(generator)
     }
 }
 
@@ -1,3 +1,4 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::generation::rect::{Border, BorderStyle};
@@ -5,7 +6,7 @@ use crate::generation::text::Alignment;
 use crate::util::color::Color;
 use crate::util::icon_ops::pick_contrasting_colors;
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum Position {
     TopLeft,
@@ -40,7 +41,7 @@ fn default_alignment() -> Alignment {
     Alignment::Right
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct MapIcon {
     pub icon_state_name: String,
     #[serde(default)]
@@ -0,0 +1,258 @@
+//! Damaged copies of a config's states, each cut from the input with overlays
+//! like cracks drawn over it and added to the same icons under a suffix, so
+//! damage states don't need a second copy of the whole config
+
+use dmi::icon::{Icon, IconState};
+use serde::Serialize;
+use toml::Value;
+
+use crate::config::error::{ConfigError, ConfigResult};
+use crate::config::layers::{read_layers, Layer};
+use crate::operations::error::{ProcessorError, ProcessorResult};
+use crate::operations::{OutputImage, ProcessorPayload};
+
+/// Key of the table a config lists its damage levels in, like
+/// `[damage] levels = [["cracks.png"], ["cracks.png", "burns.png"]]`
+pub const DAMAGE_KEY: &str = "damage";
+
+/// Added to the names of damaged states before their level when no suffix is
+/// set, giving names like `wall-12-damage1`
+pub const DEFAULT_DAMAGE_SUFFIX: &str = "damage";
+
+/// Levels of damage cut along with a config's own states
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Damage {
+    /// Added to the names of damaged states, followed by their level
+    pub suffix: String,
+    /// Overlays drawn over the input for each level, the first being level 1.
+    /// Each level's are drawn by themselves, not over the last level's
+    pub levels: Vec<Vec<Layer>>,
+}
+
+impl Default for Damage {
+    fn default() -> Self {
+        Self {
+            suffix: DEFAULT_DAMAGE_SUFFIX.to_string(),
+            levels: vec![],
+        }
+    }
+}
+
+impl Damage {
+    /// Name given to `state_name`'s copy at damage `level`
+    #[must_use]
+    pub fn state_name(&self, state_name: &str, level: usize) -> String {
+        format!("{state_name}-{}{level}", self.suffix)
+    }
+
+    /// Adds the states of the icons in `damaged`, cut from the input with the
+    /// overlays of damage `level`, to the icons they match in `payload`.
+    /// Icons are matched by their order, so `damaged` should come from the
+    /// same operation. Anything that isn't an icon, like previews, is dropped
+    /// # Errors
+    /// Errors if `payload` has no icons to add the states to, like the pngs
+    /// and already encoded files some operations output
+    pub fn add_states(
+        &self,
+        payload: &mut ProcessorPayload,
+        damaged: ProcessorPayload,
+        level: usize,
+    ) -> ProcessorResult<()> {
+        let mut damaged_icons = vec![];
+        into_icons(damaged, &mut damaged_icons);
+        let mut icons = vec![];
+        icons_mut(payload, &mut icons);
+        if icons.is_empty() {
+            return Err(ProcessorError::ConfigError(format!(
+                "`{DAMAGE_KEY}` can't be applied, the operation doesn't output any dmis to add \
+                 damage states to"
+            )));
+        }
+        for (icon, damaged) in icons.into_iter().zip(damaged_icons) {
+            icon.states.extend(damaged.states.into_iter().map(|state| {
+                IconState {
+                    name: self.state_name(&state.name, level),
+                    ..state
+                }
+            }));
+        }
+        Ok(())
+    }
+}
+
+fn icons_mut<'a>(payload: &'a mut ProcessorPayload, icons: &mut Vec<&'a mut Icon>) {
+    let mut add = |image: &'a mut OutputImage| {
+        if let OutputImage::Dmi(icon) = image {
+            icons.push(icon);
+        }
+    };
+    match payload {
+        ProcessorPayload::Single(image) => add(image),
+        ProcessorPayload::SingleNamed(named) => add(&mut named.image),
+        ProcessorPayload::MultipleNamed(named) => {
+            for named in named {
+                add(&mut named.image);
+            }
+        }
+        ProcessorPayload::ConfigWrapped(payload, _) | ProcessorPayload::Warned(payload, _) => {
+            icons_mut(payload, icons);
+        }
+    }
+}
+
+fn into_icons(payload: ProcessorPayload, icons: &mut Vec<Icon>) {
+    let mut add = |image: OutputImage| {
+        if let OutputImage::Dmi(icon) = image {
+            icons.push(icon);
+        }
+    };
+    match payload {
+        ProcessorPayload::Single(image) => add(*image),
+        ProcessorPayload::SingleNamed(named) => add(named.image),
+        ProcessorPayload::MultipleNamed(named) => {
+            for named in named {
+                add(named.image);
+            }
+        }
+        ProcessorPayload::ConfigWrapped(payload, _) | ProcessorPayload::Warned(payload, _) => {
+            into_icons(*payload, icons);
+        }
+    }
+}
+
+/// Removes [`DAMAGE_KEY`] from a config, returning its damage levels
+pub(crate) fn take_damage(value: &mut Value) -> ConfigResult<Damage> {
+    let Value::Table(table) = value else {
+        return Ok(Damage::default());
+    };
+    let Some(damage) = table.remove(DAMAGE_KEY) else {
+        return Ok(Damage::default());
+    };
+    let Value::Table(mut damage) = damage else {
+        return Err(ConfigError::Config(format!(
+            "`{DAMAGE_KEY}` must be a table with the `levels` of damage to cut"
+        )));
+    };
+    let suffix = match damage.remove("suffix") {
+        Some(Value::String(suffix)) if !suffix.is_empty() && !suffix.contains(['/', '\\']) => {
+            suffix
+        }
+        Some(_) => {
+            return Err(ConfigError::Config(format!(
+                "`{DAMAGE_KEY}.suffix` must be a string that can go in a state name"
+            )))
+        }
+        None => DEFAULT_DAMAGE_SUFFIX.to_string(),
+    };
+    let Some(Value::Array(levels)) = damage.remove("levels") else {
+        return Err(ConfigError::Config(format!(
+            "`{DAMAGE_KEY}.levels` must be an array of levels, each an array of images to draw \
+             over the input"
+        )));
+    };
+    if let Some(key) = damage.keys().next() {
+        return Err(ConfigError::Config(format!(
+            "`{DAMAGE_KEY}` doesn't take a `{key}`, only `suffix` and `levels`"
+        )));
+    }
+    let levels = levels
+        .into_iter()
+        .enumerate()
+        .map(|(index, level)| {
+            let key = format!("{DAMAGE_KEY}.levels[{index}]");
+            let layers = read_layers(level, &key)?;
+            if layers.is_empty() {
+                return Err(ConfigError::Config(format!(
+                    "`{key}` has nothing to draw, every level needs an image"
+                )));
+            }
+            Ok(layers)
+        })
+        .collect::<ConfigResult<_>>()?;
+    Ok(Damage { suffix, levels })
+}
+
+#[cfg(test)]
+mod test {
+    use dmi::icon::{Icon, IconState};
+    use image::DynamicImage;
+
+    use super::*;
+    use crate::operations::single_dmi;
+    use crate::util::blend::BlendMode;
+
+    fn icon(names: &[&str]) -> Icon {
+        Icon {
+            width: 1,
+            height: 1,
+            states: names
+                .iter()
+                .map(|name| {
+                    IconState {
+                        name: (*name).to_string(),
+                        images: vec![DynamicImage::new_rgba8(1, 1)],
+                        ..Default::default()
+                    }
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reads_damage() {
+        let mut value: Value = toml::from_str(
+            r#"
+            [damage]
+            levels = [["cracks.png"], ["cracks.png", { path = "burns.png", blend = "multiply" }]]
+            "#,
+        )
+        .unwrap();
+        let damage = take_damage(&mut value).unwrap();
+        assert!(value.get(DAMAGE_KEY).is_none());
+        assert_eq!(damage.suffix, DEFAULT_DAMAGE_SUFFIX);
+        assert_eq!(damage.levels.len(), 2);
+        assert_eq!(damage.levels[1][1].blend, BlendMode::Multiply);
+
+        for bad in [
+            "damage = [\"cracks.png\"]",
+            "[damage]\nsuffix = \"d\"",
+            "[damage]\nlevels = [[]]",
+            "[damage]\nlevels = [[\"cracks.png\"]]\nlevel = 2",
+        ] {
+            let mut value: Value = toml::from_str(bad).unwrap();
+            assert!(take_damage(&mut value).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn adds_states() {
+        let damage = Damage {
+            suffix: "broken".to_string(),
+            levels: vec![],
+        };
+        let mut payload = ProcessorPayload::from_icon(icon(&["wall-0", "wall-1"]));
+        damage
+            .add_states(
+                &mut payload,
+                ProcessorPayload::from_icon(icon(&["wall-0", "wall-1"])),
+                2,
+            )
+            .unwrap();
+        let icon = single_dmi(payload);
+        let names: Vec<&str> = icon
+            .states
+            .iter()
+            .map(|state| state.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            ["wall-0", "wall-1", "wall-0-broken2", "wall-1-broken2"]
+        );
+
+        let mut png =
+            ProcessorPayload::Single(Box::new(OutputImage::Png(DynamicImage::new_rgba8(1, 1))));
+        let damaged = png.clone();
+        assert!(damage.add_states(&mut png, damaged, 1).is_err());
+    }
+}
@@ -0,0 +1,123 @@
+//! Expanding `${VAR}` in config strings with environment variables, so build
+//! setups can inject things like per-branch output locations without editing
+//! configs
+
+use std::env;
+
+use toml::Value;
+
+use crate::config::error::{ConfigError, ConfigResult};
+
+/// Replaces every `${VAR}` in the strings of `value` (keys aren't touched)
+/// with the environment variable `VAR`. `$${` is left as a literal `${`
+/// # Errors
+/// Errors if a string uses a variable that isn't set, or isn't valid unicode
+pub fn expand_env_vars(value: &mut Value) -> ConfigResult<()> {
+    expand_with(value, "", &|name| env::var(name).ok())
+}
+
+/// Expands `${VAR}` in one string, like [`expand_env_vars`]. `key` is only
+/// used to say where an unset variable was used
+/// # Errors
+/// Errors if `text` uses a variable that isn't set
+pub(crate) fn expand_env_string(text: &str, key: &str) -> ConfigResult<String> {
+    expand_string(text, key, &|name| env::var(name).ok())
+}
+
+fn expand_with(
+    value: &mut Value,
+    path: &str,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> ConfigResult<()> {
+    match value {
+        Value::String(string) => *string = expand_string(string, path, lookup)?,
+        Value::Array(array) => {
+            for item in array {
+                expand_with(item, path, lookup)?;
+            }
+        }
+        Value::Table(table) => {
+            for (key, item) in table.iter_mut() {
+                let inner_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                expand_with(item, &inner_path, lookup)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn expand_string(
+    text: &str,
+    key: &str,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> ConfigResult<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(escaped) = rest.strip_prefix("$${") {
+            out.push_str("${");
+            rest = escaped;
+            continue;
+        }
+        let Some(length) = rest.strip_prefix("${").and_then(|inner| inner.find('}')) else {
+            out.push('$');
+            rest = &rest[1..];
+            continue;
+        };
+        let name = &rest[2..2 + length];
+        let Some(variable) = lookup(name) else {
+            return Err(ConfigError::UnsetVariable {
+                key: key.to_string(),
+                name: name.to_string(),
+            });
+        };
+        out.push_str(&variable);
+        rest = &rest[3 + length..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn expands() {
+        let lookup = |name: &str| (name == "BRANCH").then(|| "master".to_string());
+        let mut value: Value = toml::from_str(
+            r#"
+            output_name = "wall-${BRANCH}"
+            cost = "$5, $${BRANCH}"
+            [animation]
+            names = ["${BRANCH}"]
+            "#,
+        )
+        .unwrap();
+        expand_with(&mut value, "", &lookup).unwrap();
+        let expected: Value = toml::from_str(
+            r#"
+            output_name = "wall-master"
+            cost = "$5, ${BRANCH}"
+            [animation]
+            names = ["master"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(value, expected);
+
+        let mut unset: Value = toml::from_str("[output]\nname = \"${MISSING}\"").unwrap();
+        let error = expand_with(&mut unset, "", &lookup).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "`output.name` uses the environment variable MISSING, which isn't set"
+        );
+    }
+}
@@ -1,3 +1,7 @@
+use std::fmt;
+use std::ops::Range;
+use std::path::PathBuf;
+
 use thiserror::Error;
 
 use crate::config::template_resolver::error::TemplateError;
@@ -8,10 +12,114 @@ pub enum ConfigError {
     Template(#[from] TemplateError),
     #[error("Error while parsing config into toml:\n{0}")]
     Toml(#[from] toml::de::Error),
-    #[error("error in config")]
+    #[error("Error while parsing config into toml {0}")]
+    Parse(#[from] ParseError),
+    #[error("Error while parsing json config:\n{0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Error while parsing yaml config:\n{0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("Error in included config {}:\n{source}", path.display())]
+    Include {
+        path: PathBuf,
+        source: Box<ConfigError>,
+    },
+    #[error("`{key}` uses the environment variable {name}, which isn't set")]
+    UnsetVariable { key: String, name: String },
+    #[error("error in config: {0}")]
     Config(String),
     #[error("Generic IO Error: {0}")]
     IO(#[from] std::io::Error),
 }
 
 pub type ConfigResult<T> = Result<T, ConfigError>;
+
+/// An error parsing toml text, with where in the text it is when toml knows
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub struct ParseError {
+    pub message: String,
+    pub location: Option<Location>,
+}
+
+/// A position in a file's text, lines and columns counting from 1
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+    /// The whole line the position is on, for showing it
+    pub line_text: String,
+}
+
+impl ParseError {
+    /// Locates `err`, from parsing `text`, in it
+    #[must_use]
+    pub fn new(text: &str, err: &toml::de::Error) -> Self {
+        Self {
+            message: err.message().trim_end().to_string(),
+            location: err.span().map(|span| Location::of(text, span)),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(location) => {
+                write!(
+                    f,
+                    "at line {}, column {}:\n{}",
+                    location.line, location.column, self.message
+                )
+            }
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl Location {
+    /// Where the start of `span`, a byte range of `text`, is
+    #[must_use]
+    pub fn of(text: &str, span: Range<usize>) -> Self {
+        let start = span.start.min(text.len());
+        let before = &text[..start];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        let line_end = text[start..]
+            .find('\n')
+            .map_or(text.len(), |end| start + end);
+        Self {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            line_text: text[line_start..line_end]
+                .trim_end_matches('\r')
+                .to_string(),
+        }
+    }
+}
+
+/// Parses toml text into `T`, locating any error in it
+/// # Errors
+/// Errors if `text` isn't valid toml, or doesn't describe a `T`
+pub fn parse_toml<T: serde::de::DeserializeOwned>(text: &str) -> Result<T, ParseError> {
+    toml::from_str(text).map_err(|err| ParseError::new(text, &err))
+}
+
+#[cfg(test)]
+mod test {
+    use toml::Value;
+
+    use super::*;
+
+    #[test]
+    fn locates_errors() {
+        let text = "mode = \"BitmaskSlice\"\nproduce_dirs = \n[icon_size]\n";
+        let err = parse_toml::<Value>(text).unwrap_err();
+        assert_eq!(
+            err.location,
+            Some(Location {
+                line: 2,
+                column: 16,
+                line_text: "produce_dirs = ".to_string(),
+            })
+        );
+        assert!(err.to_string().starts_with("at line 2, column 16:\n"));
+    }
+}
@@ -0,0 +1,95 @@
+use std::path::Path;
+
+use toml::Value;
+
+use crate::config::error::{parse_toml, ConfigResult};
+
+/// A format configs can be written in. Every format is read in to the same
+/// toml value, so templates (which are always toml) and everything after
+/// don't care which it was
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// Extensions of every format, in the order a config for an image is
+    /// looked for
+    pub const EXTENSIONS: [&'static str; 4] = ["toml", "json", "yaml", "yml"];
+
+    #[must_use]
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "toml" => Some(Self::Toml),
+            "json" => Some(Self::Json),
+            "yaml" | "yml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+
+    /// The format of the config at `path`, or `None` if it isn't one. Any
+    /// `.toml` is a config, but json and yaml only are when named after an
+    /// image, like `wall.png.json`, since plenty of other json ends up next
+    /// to icons
+    #[must_use]
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let format = Self::from_extension(path.extension()?.to_str()?)?;
+        if format == Self::Toml {
+            return Some(format);
+        }
+        let image_extension = Path::new(path.file_stem()?).extension()?;
+        ["png", "dmi", "aseprite", "ase"]
+            .iter()
+            .any(|extension| image_extension == *extension)
+            .then_some(format)
+    }
+
+    /// Parses a config written in this format
+    /// # Errors
+    /// Errors if `text` isn't valid in this format, or has values toml can't
+    /// hold, like nulls
+    pub fn parse(self, text: &str) -> ConfigResult<Value> {
+        Ok(match self {
+            Self::Toml => parse_toml(text)?,
+            Self::Json => serde_json::from_str(text)?,
+            Self::Yaml => serde_yaml::from_str(text)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn formats_match() {
+        let toml = "
+        produce_dirs = true
+        [icon_size]
+        x = 32
+        y = 48
+        ";
+        let json = r#"{"produce_dirs": true, "icon_size": {"x": 32, "y": 48}}"#;
+        let yaml = "
+        produce_dirs: true
+        icon_size:
+          x: 32
+          y: 48
+        ";
+        let expected = ConfigFormat::Toml.parse(toml).unwrap();
+        assert_eq!(ConfigFormat::Json.parse(json).unwrap(), expected);
+        assert_eq!(ConfigFormat::Yaml.parse(yaml).unwrap(), expected);
+
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("wall.png.json")),
+            Some(ConfigFormat::Json)
+        );
+        assert_eq!(ConfigFormat::from_path(Path::new("wall.states.json")), None);
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("door.toml")),
+            Some(ConfigFormat::Toml)
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//! Hue, saturation and value shifts a config makes to its input before it's
+//! cut, so darker or weathered variants don't need their own art
+
+use image::{DynamicImage, Rgba};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use toml::Value;
+
+use crate::config::error::{ConfigError, ConfigResult};
+use crate::operations::InputIcon;
+
+/// Key of the table a config or variant shifts the colors of its input with,
+/// like `hsv = { hue = 30, saturation = -0.2, value = -0.1 }`
+pub const HSV_KEY: &str = "hsv";
+
+/// A shift made to the color of every pixel of an input
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct HsvShift {
+    /// Degrees the hue is turned by, wrapping around
+    #[serde(default)]
+    pub hue: f32,
+    /// Added to the saturation, from -1 to 1
+    #[serde(default)]
+    pub saturation: f32,
+    /// Added to the value (brightness), from -1 to 1
+    #[serde(default)]
+    pub value: f32,
+}
+
+impl HsvShift {
+    /// Shifts every frame of `input`
+    pub fn apply(&self, input: &mut InputIcon) {
+        match input {
+            InputIcon::DynamicImage(image) => self.apply_to_image(image),
+            InputIcon::Dmi(icon) | InputIcon::Aseprite(icon) => {
+                for frame in icon.states.iter_mut().flat_map(|state| &mut state.images) {
+                    self.apply_to_image(frame);
+                }
+            }
+        }
+    }
+
+    /// Shifts every pixel of `image` that isn't fully transparent
+    pub fn apply_to_image(&self, image: &mut DynamicImage) {
+        if *self == Self::default() {
+            return;
+        }
+        let mut rgba = image.to_rgba8();
+        for pixel in rgba.pixels_mut() {
+            let [red, green, blue, alpha] = pixel.0;
+            if alpha == 0 {
+                continue;
+            }
+            let (hue, saturation, value) = to_hsv(red, green, blue);
+            let [red, green, blue] = from_hsv(
+                (hue + self.hue).rem_euclid(360.0),
+                (saturation + self.saturation).clamp(0.0, 1.0),
+                (value + self.value).clamp(0.0, 1.0),
+            );
+            *pixel = Rgba([red, green, blue, alpha]);
+        }
+        *image = DynamicImage::ImageRgba8(rgba);
+    }
+
+    fn problems(&self) -> Vec<String> {
+        [("saturation", self.saturation), ("value", self.value)]
+            .into_iter()
+            .filter(|(_, shift)| !(-1.0..=1.0).contains(shift))
+            .map(|(name, shift)| format!("{name} shift {shift} has to be from -1 to 1"))
+            .collect()
+    }
+}
+
+/// Hue in degrees, then saturation and value from 0 to 1
+fn to_hsv(red: u8, green: u8, blue: u8) -> (f32, f32, f32) {
+    let max = red.max(green).max(blue);
+    let range = f32::from(max - red.min(green).min(blue)) / 255.0;
+    let [red_f, green_f, blue_f] = [red, green, blue].map(|channel| f32::from(channel) / 255.0);
+    // compared as channels rather than floats, so ties are exact
+    let hue = if range == 0.0 {
+        0.0
+    } else if max == red {
+        60.0 * ((green_f - blue_f) / range).rem_euclid(6.0)
+    } else if max == green {
+        60.0 * ((blue_f - red_f) / range + 2.0)
+    } else {
+        60.0 * ((red_f - green_f) / range + 4.0)
+    };
+    let value = f32::from(max) / 255.0;
+    let saturation = if max == 0 { 0.0 } else { range / value };
+    (hue, saturation, value)
+}
+
+fn from_hsv(hue: f32, saturation: f32, value: f32) -> [u8; 3] {
+    let chroma = value * saturation;
+    let sector = hue / 60.0;
+    let second = chroma * (1.0 - (sector.rem_euclid(2.0) - 1.0).abs());
+    let (red, green, blue) = match sector as u32 {
+        0 => (chroma, second, 0.0),
+        1 => (second, chroma, 0.0),
+        2 => (0.0, chroma, second),
+        3 => (0.0, second, chroma),
+        4 => (second, 0.0, chroma),
+        _ => (chroma, 0.0, second),
+    };
+    let lightest = value - chroma;
+    [red, green, blue].map(|channel| ((channel + lightest) * 255.0).round() as u8)
+}
+
+/// Removes [`HSV_KEY`] from a config or variant, returning the shift it sets
+pub(crate) fn take_hsv(value: &mut Value) -> ConfigResult<Option<HsvShift>> {
+    let Value::Table(table) = value else {
+        return Ok(None);
+    };
+    let Some(hsv) = table.remove(HSV_KEY) else {
+        return Ok(None);
+    };
+    let hsv: HsvShift = hsv
+        .try_into()
+        .map_err(|err| ConfigError::Config(format!("invalid `{HSV_KEY}`: {err}")))?;
+    match hsv.problems().first() {
+        Some(problem) => {
+            Err(ConfigError::Config(format!(
+                "invalid `{HSV_KEY}`: {problem}"
+            )))
+        }
+        None => Ok(Some(hsv)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::GenericImageView;
+
+    use super::*;
+
+    #[test]
+    fn shifts_colors() {
+        for color in [[255, 0, 0], [12, 200, 99], [128, 128, 128], [0, 0, 0]] {
+            let (hue, saturation, value) = to_hsv(color[0], color[1], color[2]);
+            assert_eq!(from_hsv(hue, saturation, value), color);
+        }
+
+        let mut image = DynamicImage::new_rgba8(2, 1);
+        let pixels = image.as_mut_rgba8().unwrap();
+        pixels.put_pixel(0, 0, Rgba([255, 0, 0, 200]));
+        pixels.put_pixel(1, 0, Rgba([255, 0, 0, 0]));
+        HsvShift {
+            hue: 120.0,
+            saturation: 0.0,
+            value: -0.5,
+        }
+        .apply_to_image(&mut image);
+        assert_eq!(image.get_pixel(0, 0), Rgba([0, 128, 0, 200]));
+        assert_eq!(image.get_pixel(1, 0), Rgba([255, 0, 0, 0]));
+
+        let mut grey = DynamicImage::new_rgba8(1, 1);
+        grey.as_mut_rgba8()
+            .unwrap()
+            .put_pixel(0, 0, Rgba([0, 0, 255, 255]));
+        HsvShift {
+            saturation: -1.0,
+            ..Default::default()
+        }
+        .apply_to_image(&mut grey);
+        assert_eq!(grey.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn reads_hsv() {
+        let mut value: Value = toml::from_str("hsv = { hue = -30, value = 0.25 }").unwrap();
+        assert_eq!(
+            take_hsv(&mut value).unwrap(),
+            Some(HsvShift {
+                hue: -30.0,
+                saturation: 0.0,
+                value: 0.25,
+            })
+        );
+        assert!(value.get(HSV_KEY).is_none());
+
+        for bad in ["hsv = { value = 2.0 }", "hsv = { lightness = 0.1 }"] {
+            let mut value: Value = toml::from_str(bad).unwrap();
+            assert!(take_hsv(&mut value).is_err(), "{bad}");
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! Including config fragments that sit next to the art, like a prefab table
+//! several configs in a folder share. Unlike templates they're found relative
+//! to the config including them, not in the template folder
+
+use std::fs;
+use std::io::{read_to_string, Read};
+use std::path::Path;
+
+use toml::map::Map;
+use toml::Value;
+use tracing::trace;
+
+use crate::config::error::{ConfigError, ConfigResult};
+use crate::config::format::ConfigFormat;
+use crate::config::key_sources::{KeySource, KeySources};
+use crate::util::deep_merge_toml;
+
+/// Key a config includes fragments with, either a single path or an array of
+/// them, relative to the config. Fragments are merged in order under the
+/// config's own values, and can include fragments of their own
+pub const INCLUDE_KEY: &str = "include";
+
+/// Most fragments an include can chain through, to stop fragments that
+/// include each other from looping forever
+const MAX_INCLUDE_DEPTH: u32 = 32;
+
+/// Reads just the paths a config includes, relative to it, without reading
+/// the fragments themselves, for telling fragments apart from configs to
+/// process
+/// # Errors
+/// Errors if the config isn't valid in `format`, or its includes aren't paths
+pub fn read_includes<R: Read>(input: &mut R, format: ConfigFormat) -> ConfigResult<Vec<String>> {
+    let mut value = format.parse(&read_to_string(input)?)?;
+    extract_includes(&mut value)
+}
+
+/// Merges every fragment `value` includes, relative to `dir`, under it.
+/// Returns what set each value that came from a fragment and wasn't
+/// overridden by `value` itself
+pub(crate) fn resolve_includes(value: &mut Value, dir: &Path) -> ConfigResult<KeySources> {
+    resolve_includes_impl(value, dir, Path::new(""), 0)
+}
+
+/// Resolves the includes of a config or fragment in `root.join(relative)`.
+/// Fragments are named relative to `root`, the directory of the config that
+/// started it
+fn resolve_includes_impl(
+    value: &mut Value,
+    root: &Path,
+    relative: &Path,
+    depth: u32,
+) -> ConfigResult<KeySources> {
+    let includes = extract_includes(value)?;
+    if includes.is_empty() {
+        return Ok(KeySources::default());
+    }
+    if depth >= MAX_INCLUDE_DEPTH {
+        return Err(ConfigError::Config(format!(
+            "includes are nested more than {MAX_INCLUDE_DEPTH} deep, do they include each other?"
+        )));
+    }
+
+    let mut out = Value::Table(Map::new());
+    let mut sources = KeySources::default();
+    for include in includes {
+        let name = relative.join(&include);
+        let (fragment, fragment_sources) = read_fragment(root, &name, depth).map_err(|err| {
+            ConfigError::Include {
+                path: name.clone(),
+                source: Box::new(err),
+            }
+        })?;
+        trace!(include, fragment = ?fragment, "Included fragment");
+        deep_merge_toml(&mut out, fragment);
+        sources.merge(fragment_sources);
+    }
+
+    // the config's own values take priority, so they stop being attributed
+    // to whatever fragment they replaced
+    let own = KeySources::of(value, &KeySource::Config);
+    let own_value = std::mem::replace(value, out);
+    deep_merge_toml(value, own_value);
+    sources.merge(own);
+    sources.forget(&KeySource::Config);
+    Ok(sources)
+}
+
+/// Reads the fragment at `root.join(name)`, in the format its extension says
+/// (or toml), along with where each of its values came from
+fn read_fragment(root: &Path, name: &Path, depth: u32) -> ConfigResult<(Value, KeySources)> {
+    let format = name
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(ConfigFormat::from_extension)
+        .unwrap_or(ConfigFormat::Toml);
+    let mut fragment = format.parse(&fs::read_to_string(root.join(name))?)?;
+    let dir = name.parent().unwrap_or_else(|| Path::new(""));
+    let nested = resolve_includes_impl(&mut fragment, root, dir, depth + 1)?;
+    let source = KeySource::Include(name.display().to_string());
+    let mut sources = KeySources::of(&fragment, &source);
+    sources.merge(nested);
+    Ok((fragment, sources))
+}
+
+/// Takes the included paths out of a value
+fn extract_includes(value: &mut Value) -> ConfigResult<Vec<String>> {
+    let Value::Table(table) = value else {
+        return Ok(vec![]);
+    };
+    let error = || {
+        ConfigError::Config(format!(
+            "`{INCLUDE_KEY}` must be a path relative to the config, or an array of them"
+        ))
+    };
+    match table.remove(INCLUDE_KEY) {
+        Some(Value::String(path)) => Ok(vec![path]),
+        Some(Value::Array(paths)) => {
+            paths
+                .into_iter()
+                .map(|path| {
+                    match path {
+                        Value::String(path) => Ok(path),
+                        _ => Err(error()),
+                    }
+                })
+                .collect()
+        }
+        Some(_) => Err(error()),
+        None => Ok(vec![]),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn nested_includes() {
+        let dir = env::temp_dir().join(format!("hypnagogic-include-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("shared")).unwrap();
+        fs::write(
+            dir.join("shared/prefabs.toml"),
+            "include = \"delays.toml\"\n[prefabs]\n5 = 4\n6 = 5\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("shared/delays.toml"),
+            "[animation]\ndelays = [1]\n",
+        )
+        .unwrap();
+
+        let mut config: Value =
+            toml::from_str("include = [\"shared/prefabs.toml\"]\n[prefabs]\n6 = 7\n").unwrap();
+        let sources = resolve_includes(&mut config, &dir).unwrap();
+        let expected: Value = toml::from_str(
+            "
+            [prefabs]
+            5 = 4
+            6 = 7
+            [animation]
+            delays = [1]
+            ",
+        )
+        .unwrap();
+        assert_eq!(config, expected);
+
+        let include = |path: &str| Some(KeySource::Include(Path::new(path).display().to_string()));
+        assert_eq!(
+            sources.get("animation.delays").cloned(),
+            include("shared/delays.toml")
+        );
+        assert_eq!(
+            sources.get("prefabs.5").cloned(),
+            include("shared/prefabs.toml")
+        );
+        assert_eq!(sources.get("prefabs.6"), None);
+
+        let mut missing: Value = toml::from_str("include = \"nowhere.toml\"").unwrap();
+        assert!(matches!(
+            resolve_includes(&mut missing, &dir),
+            Err(ConfigError::Include { .. })
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::Serialize;
+use toml::Value;
+
+/// What set a key of a resolved config
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", content = "name", rename_all = "snake_case")]
+pub enum KeySource {
+    /// The config itself
+    Config,
+    /// The template with this name, somewhere in the config's template chain
+    Template(String),
+    /// The fragment at this path, included by the config or another fragment
+    Include(String),
+}
+
+impl fmt::Display for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Config => write!(f, "the config"),
+            Self::Template(name) => write!(f, "template {name}"),
+            Self::Include(path) => write!(f, "include {path}"),
+        }
+    }
+}
+
+/// One value of a resolved config, and what set it
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ResolvedKey {
+    /// Dotted path of the key, like `cut_pos.x`
+    pub path: String,
+    pub value: Value,
+    pub source: KeySource,
+}
+
+/// What set each value of a config as it's merged together, by dotted path.
+/// Only values are tracked, tables are whatever their values are
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct KeySources(BTreeMap<String, KeySource>);
+
+impl KeySources {
+    /// Every value in `value`, as set by `source`
+    pub(crate) fn of(value: &Value, source: &KeySource) -> Self {
+        let mut sources = BTreeMap::new();
+        for_each_value(value, String::new(), &mut |path, _| {
+            sources.insert(path, source.clone());
+        });
+        Self(sources)
+    }
+
+    /// Records `over` being deep merged on top of what these sources describe.
+    /// Anything a value of `over` replaces, whether it's the same key, a table
+    /// it replaces or a value it turns in to a table, stops being tracked
+    pub(crate) fn merge(&mut self, over: Self) {
+        for (path, source) in over.0 {
+            let nested = format!("{path}.");
+            self.0.retain(|existing, _| {
+                let replaced = existing == &path
+                    || existing.starts_with(&nested)
+                    || path.starts_with(&format!("{existing}."));
+                !replaced
+            });
+            self.0.insert(path, source);
+        }
+    }
+
+    /// What set the value at `path`
+    pub(crate) fn get(&self, path: &str) -> Option<&KeySource> {
+        self.0.get(path)
+    }
+
+    /// Stops tracking a top level key, for keys that are taken out of a config
+    pub(crate) fn remove(&mut self, key: &str) {
+        let nested = format!("{key}.");
+        self.0
+            .retain(|existing, _| existing != key && !existing.starts_with(&nested));
+    }
+
+    /// Stops tracking every value set by `source`
+    pub(crate) fn forget(&mut self, source: &KeySource) {
+        self.0.retain(|_, existing| existing != source);
+    }
+
+    /// Every value of the resolved `value`, in order of path, with what set
+    /// it
+    pub(crate) fn resolved_keys(&self, value: &Value) -> Vec<ResolvedKey> {
+        let mut keys = vec![];
+        for_each_value(value, String::new(), &mut |path, value| {
+            let source = self.0.get(&path).cloned().unwrap_or(KeySource::Config);
+            keys.push(ResolvedKey {
+                path,
+                value: value.clone(),
+                source,
+            });
+        });
+        keys
+    }
+}
+
+/// Calls `f` with the dotted path of every value that isn't a table
+pub(crate) fn for_each_value(value: &Value, path: String, f: &mut impl FnMut(String, &Value)) {
+    match value {
+        Value::Table(table) => {
+            for (key, inner) in table {
+                let inner_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                for_each_value(inner, inner_path, f);
+            }
+        }
+        _ if path.is_empty() => {}
+        _ => f(path, value),
+    }
+}
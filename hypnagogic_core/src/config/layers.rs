@@ -0,0 +1,123 @@
+//! Extra images a config draws over its input before it's cut, so base walls
+//! and decals can be kept in separate files
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use toml::Value;
+
+use crate::config::env::expand_env_string;
+use crate::config::error::{ConfigError, ConfigResult};
+use crate::util::blend::BlendMode;
+
+/// Key a config lists the images layered over its input with, drawn in order.
+/// Each is a path relative to the config, or a table like
+/// `{ path = "grime.png", blend = "multiply", offset = { x = 0, y = 2 } }`
+pub const LAYERS_KEY: &str = "layers";
+
+/// An image drawn over a config's input
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Layer {
+    /// Path of the image, relative to the config
+    pub path: String,
+    #[serde(default)]
+    pub blend: BlendMode,
+    /// Where the layer's top left goes on the input, in pixels
+    #[serde(default)]
+    pub offset: LayerOffset,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct LayerOffset {
+    #[serde(default)]
+    pub x: i32,
+    #[serde(default)]
+    pub y: i32,
+}
+
+impl Layer {
+    fn from_value(value: Value, key: &str) -> ConfigResult<Self> {
+        match value {
+            Value::String(path) => {
+                Ok(Self {
+                    path,
+                    blend: BlendMode::default(),
+                    offset: LayerOffset::default(),
+                })
+            }
+            value => {
+                value
+                    .try_into()
+                    .map_err(|err| ConfigError::Config(format!("invalid entry in `{key}`: {err}")))
+            }
+        }
+    }
+}
+
+/// Removes [`LAYERS_KEY`] from a config, returning its layers in the order
+/// they're drawn, with any environment variables their paths use expanded
+pub(crate) fn take_layers(value: &mut Value) -> ConfigResult<Vec<Layer>> {
+    let Value::Table(table) = value else {
+        return Ok(vec![]);
+    };
+    match table.remove(LAYERS_KEY) {
+        Some(layers) => read_layers(layers, LAYERS_KEY),
+        None => Ok(vec![]),
+    }
+}
+
+/// Reads an array of layers set under `key`, in the order they're drawn, with
+/// any environment variables their paths use expanded
+pub(crate) fn read_layers(layers: Value, key: &str) -> ConfigResult<Vec<Layer>> {
+    let Value::Array(layers) = layers else {
+        return Err(ConfigError::Config(format!(
+            "`{key}` must be an array of images to draw over the input"
+        )));
+    };
+    layers
+        .into_iter()
+        .map(|layer| {
+            let mut layer = Layer::from_value(layer, key)?;
+            layer.path = expand_env_string(&layer.path, key)?;
+            Ok(layer)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_layers() {
+        let mut value: Value = toml::from_str(
+            r#"
+            layers = [
+                "decals.png",
+                { path = "grime.png", blend = "multiply", offset = { y = 2 } },
+            ]
+            "#,
+        )
+        .unwrap();
+        let layers = take_layers(&mut value).unwrap();
+        assert_eq!(
+            layers,
+            vec![
+                Layer {
+                    path: "decals.png".to_string(),
+                    blend: BlendMode::Normal,
+                    offset: LayerOffset::default(),
+                },
+                Layer {
+                    path: "grime.png".to_string(),
+                    blend: BlendMode::Multiply,
+                    offset: LayerOffset { x: 0, y: 2 },
+                },
+            ]
+        );
+        assert!(value.get(LAYERS_KEY).is_none());
+
+        let mut bad: Value =
+            toml::from_str("layers = [{ path = \"a.png\", blend = \"melt\" }]").unwrap();
+        assert!(take_layers(&mut bad).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+//! Manifests, single files declaring many jobs, for projects that would
+//! rather keep one central build file than a config next to every image
+
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use toml::map::Map;
+use toml::Value;
+
+use crate::config::error::{parse_toml, ConfigResult};
+use crate::config::template_resolver::TemplateResolver;
+use crate::config::{read_config_value, ConfigFile, INPUT_KEY};
+
+/// Key of the array of tables a manifest declares its jobs in, as `[[job]]`.
+/// A toml with it at the top level is a manifest rather than a config
+pub const JOBS_KEY: &str = "job";
+
+/// A file declaring several jobs, each cutting one image
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(rename = "job")]
+    #[serde(default)]
+    pub jobs: Vec<Job>,
+}
+
+/// One image to process, and how. Paths are relative to the manifest
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Job {
+    /// The image to process
+    pub input: String,
+    /// Where to write the output, its extension picked by the operation.
+    /// Defaults to next to the input, named after it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub output: Option<String>,
+    /// Template (or array of templates) to start from, as in a config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub template: Option<Value>,
+    /// Keys set over the template, anything a config can have
+    #[serde(default)]
+    pub overrides: Map<String, Value>,
+}
+
+impl Manifest {
+    /// Reads the manifest at `path`
+    /// # Errors
+    /// Errors if it can't be read or isn't a valid manifest
+    pub fn load(path: &Path) -> ConfigResult<Self> {
+        Ok(parse_toml(&fs::read_to_string(path)?)?)
+    }
+
+    /// Whether a parsed toml file is a manifest rather than a config
+    #[must_use]
+    pub fn is_manifest(value: &Value) -> bool {
+        value.get(JOBS_KEY).is_some_and(Value::is_array)
+    }
+}
+
+impl Job {
+    /// Reads the job as if it were a config in `dir`, the manifest's
+    /// directory, resolving its template and overrides. `mode` forces it
+    /// through that operation, like
+    /// [`read_config_file_as`](crate::config::read_config_file_as)
+    /// # Errors
+    /// Errors if the job's template or includes can't be resolved, or it
+    /// doesn't describe a valid operation
+    pub fn read_config(
+        &self,
+        dir: &Path,
+        resolver: impl TemplateResolver,
+        mode: Option<&str>,
+    ) -> ConfigResult<ConfigFile> {
+        let mut table = self.overrides.clone();
+        table.insert(INPUT_KEY.to_string(), Value::String(self.input.clone()));
+        if let Some(template) = &self.template {
+            table.insert("template".to_string(), template.clone());
+        }
+        read_config_value(Value::Table(table), dir, resolver, mode)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::template_resolver::error::TemplateResult;
+    use crate::operations::cutters::bitmask_slice::BitmaskSlice;
+    use crate::operations::IconOperation;
+
+    struct SliceResolver;
+
+    impl TemplateResolver for SliceResolver {
+        fn resolve(&self, _input: &str) -> TemplateResult {
+            let operation: IconOperation = BitmaskSlice::default().into();
+            Ok(Value::try_from(operation).unwrap())
+        }
+    }
+
+    #[test]
+    fn reads_jobs() {
+        let text = r#"
+        [[job]]
+        input = "walls/wall.png"
+        template = "slice"
+
+        [[job]]
+        input = "walls/wall.png"
+        output = "walls/wall_dirs"
+        template = "slice"
+        overrides = { produce_dirs = true }
+        "#;
+        assert!(Manifest::is_manifest(&toml::from_str(text).unwrap()));
+        let manifest: Manifest = toml::from_str(text).unwrap();
+        assert_eq!(manifest.jobs.len(), 2);
+        assert_eq!(manifest.jobs[1].output.as_deref(), Some("walls/wall_dirs"));
+
+        let plain = manifest.jobs[0]
+            .read_config(Path::new(""), SliceResolver, None)
+            .unwrap();
+        assert_eq!(plain.input.as_deref(), Some("walls/wall.png"));
+        assert_eq!(plain.operation, BitmaskSlice::default().into());
+
+        let dirs = manifest.jobs[1]
+            .read_config(Path::new(""), SliceResolver, None)
+            .unwrap();
+        assert_eq!(
+            dirs.operation,
+            BitmaskSlice {
+                produce_dirs: true,
+                ..Default::default()
+            }
+            .into()
+        );
+
+        assert!(!Manifest::is_manifest(
+            &toml::from_str("mode = \"BitmaskSlice\"").unwrap()
+        ));
+    }
+}
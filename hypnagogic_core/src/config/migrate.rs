@@ -0,0 +1,152 @@
+//! Upgrades configs written for older versions of hypnagogic to the current
+//! schema
+
+use std::io::Cursor;
+use std::path::Path;
+
+use toml::map::Map;
+use toml::Value;
+
+use crate::config::error::{ConfigError, ConfigResult};
+use crate::config::format::ConfigFormat;
+use crate::config::key_sources::KeySource;
+use crate::config::read_config_file;
+use crate::config::template_resolver::TemplateResolver;
+use crate::config::unknown_keys::UnknownKey;
+
+/// Keys older versions of hypnagogic used, and the dotted path each one lives
+/// at now. Coordinates used to be flat keys before they were grouped into
+/// tables
+const RENAMED_KEYS: &[(&str, &str)] = &[
+    ("icon_size_x", "icon_size.x"),
+    ("icon_size_y", "icon_size.y"),
+    ("output_icon_pos_x", "output_icon_pos.x"),
+    ("output_icon_pos_y", "output_icon_pos.y"),
+    ("output_icon_size_x", "output_icon_size.x"),
+    ("output_icon_size_y", "output_icon_size.y"),
+    ("cut_pos_x", "cut_pos.x"),
+    ("cut_pos_y", "cut_pos.y"),
+    ("cut_position_x", "cut_pos.x"),
+    ("cut_position_y", "cut_pos.y"),
+];
+
+/// What [`migrate_config`] changed
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// Every legacy key that was moved, and where it was moved to
+    pub moved: Vec<(String, String)>,
+    /// Legacy keys that weren't moved because their new path was already set.
+    /// They're left as they were
+    pub conflicts: Vec<(String, String)>,
+}
+
+impl MigrationReport {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.moved.is_empty() && self.conflicts.is_empty()
+    }
+}
+
+/// Moves every legacy key of `config` to where it lives now
+pub fn migrate_config(config: &mut Value) -> MigrationReport {
+    let mut report = MigrationReport::default();
+    let Value::Table(table) = config else {
+        return report;
+    };
+    for (old, new) in RENAMED_KEYS {
+        let Some(value) = table.get(*old) else {
+            continue;
+        };
+        let (parent, key) = new.split_once('.').expect("renamed keys are nested");
+        let parent_table = match table.get(parent) {
+            // a template could be meant to fill in the rest of the table, so
+            // it's only a conflict if the key itself is set
+            Some(Value::Table(existing)) if existing.contains_key(key) => None,
+            Some(Value::Table(_)) | None => Some(value.clone()),
+            Some(_) => None,
+        };
+        let Some(value) = parent_table else {
+            report.conflicts.push((old.to_string(), new.to_string()));
+            continue;
+        };
+        table.remove(*old);
+        if let Value::Table(parent_table) = table
+            .entry(parent)
+            .or_insert_with(|| Value::Table(Map::new()))
+        {
+            parent_table.insert(key.to_string(), value);
+        }
+        report.moved.push((old.to_string(), new.to_string()));
+    }
+    report
+}
+
+/// Every key set by `config` itself that the operation it resolves to doesn't
+/// read, with its includes looked for in `dir`. These would be silently
+/// ignored, so they're usually typos or keys from an older version
+/// # Errors
+/// Errors if the config's includes or templates can't be resolved, or it
+/// doesn't describe a valid operation
+pub fn unrecognised_keys(
+    config: &Value,
+    dir: &Path,
+    resolver: impl TemplateResolver,
+) -> ConfigResult<Vec<UnknownKey>> {
+    let text = toml::to_string(config).map_err(|err| ConfigError::Config(err.to_string()))?;
+    let config_file = read_config_file(&mut Cursor::new(text), ConfigFormat::Toml, dir, resolver)?;
+    Ok(config_file
+        .unknown_keys
+        .into_iter()
+        .filter(|key| key.source == KeySource::Config)
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::template_resolver::NullResolver;
+
+    #[test]
+    fn flat_keys() {
+        let mut config: Value = toml::from_str(
+            r#"
+            mode = "BitmaskSlice"
+            produce_dirs = false
+            smooth_diagonally = false
+            file_prefix = "GENERATED-"
+            icon_size_x = 32
+            icon_size_y = 32
+            output_icon_pos_x = 0
+            output_icon_pos_y = 0
+            output_icon_size_x = 32
+            output_icon_size_y = 32
+            cut_position_x = 16
+            cut_pos_y = 16
+            [cut_pos]
+            x = 12
+            [positions]
+            convex = 0
+            concave = 1
+            horizontal = 2
+            vertical = 3
+            "#,
+        )
+        .unwrap();
+        let report = migrate_config(&mut config);
+        assert_eq!(report.moved.len(), 7);
+        assert_eq!(
+            report.conflicts,
+            vec![("cut_position_x".to_string(), "cut_pos.x".to_string())]
+        );
+        assert_eq!(config["icon_size"]["x"].as_integer(), Some(32));
+        assert_eq!(config["cut_pos"]["x"].as_integer(), Some(12));
+        assert_eq!(config["cut_pos"]["y"].as_integer(), Some(16));
+
+        let unrecognised: Vec<String> = unrecognised_keys(&config, Path::new(""), NullResolver)
+            .unwrap()
+            .into_iter()
+            .map(|key| key.path)
+            .collect();
+        assert_eq!(unrecognised, ["cut_position_x", "file_prefix"]);
+    }
+}
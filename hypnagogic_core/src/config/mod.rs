@@ -1,89 +1,502 @@
+use std::collections::BTreeMap;
 use std::io::{read_to_string, Read, Seek};
+use std::path::Path;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use template_resolver::TemplateResolver;
 use toml::map::Map;
 use toml::Value;
 use tracing::{debug, trace};
 
-use crate::config::error::ConfigResult;
-use crate::config::template_resolver::error::TemplateResult;
+use crate::config::damage::{take_damage, Damage, DAMAGE_KEY};
+use crate::config::env::{expand_env_string, expand_env_vars};
+use crate::config::error::{ConfigError, ConfigResult};
+use crate::config::format::ConfigFormat;
+use crate::config::hsv::{take_hsv, HsvShift, HSV_KEY};
+use crate::config::include::resolve_includes;
+use crate::config::key_sources::{KeySource, KeySources, ResolvedKey};
+use crate::config::layers::{take_layers, Layer, LAYERS_KEY};
+use crate::config::provenance::PROVENANCE_KEY;
+use crate::config::template_resolver::error::{TemplateError, TemplateResult};
+use crate::config::unknown_keys::{find_unknown_keys, UnknownKey};
+use crate::config::variants::{take_variants, Variant, VARIANTS_KEY};
+use crate::operations::post_process::{take_post_process, PostProcess, POST_PROCESS_KEY};
+use crate::operations::recolors::{recolors_value, take_recolors, Recolor, RECOLORS_KEY};
 use crate::operations::IconOperation;
-use crate::util::deep_merge_toml;
+use crate::util::{deep_merge_toml, deep_merge_toml_with, MergeStrategy};
 
 pub mod blocks;
+pub mod damage;
+pub mod env;
 pub mod error;
+pub mod format;
+pub mod hsv;
+pub mod include;
+pub mod key_sources;
+pub mod layers;
+pub mod manifest;
+pub mod migrate;
+pub mod provenance;
+pub mod schema;
 pub mod template_resolver;
+pub mod unknown_keys;
+pub mod variants;
+pub mod workspace;
 
 pub const DEFAULT_TEMPLATE_LOCATION: &str = "templates";
 
+/// Key a config can name its input image with, relative to the config, for
+/// when the input can't be found by dropping the config's `.toml` extension.
+/// Only read from the config itself, never from templates
+pub const INPUT_KEY: &str = "input";
+
+/// Key of the table a config or template sets how its values are merged over
+/// its templates' with, as dotted paths mapped to a [`MergeStrategy`], like
+/// `merge = { prefabs = "replace", extract = "append" }`. Anything not listed
+/// is merged, replacing everything but tables
+pub const MERGE_KEY: &str = "merge";
+
+/// A config read by [`read_config_file`]
+#[derive(Clone, PartialEq, Debug)]
+pub struct ConfigFile {
+    pub operation: IconOperation,
+    /// The input image named by the config's [`INPUT_KEY`], relative to the
+    /// config
+    pub input: Option<String>,
+    /// Images drawn over the input before it's cut, from the config's
+    /// [`LAYERS_KEY`](layers::LAYERS_KEY)
+    pub layers: Vec<Layer>,
+    /// Damaged copies of the config's states, from its [`DAMAGE_KEY`]
+    pub damage: Damage,
+    /// Shift made to the input before it's cut, from the config's [`HSV_KEY`]
+    pub hsv: Option<HsvShift>,
+    /// Filters run over every frame the config generates, from its
+    /// [`POST_PROCESS_KEY`]
+    pub post_process: PostProcess,
+    /// Recolors of everything the config and its variants output, from its
+    /// [`RECOLORS_KEY`]
+    pub recolors: Vec<Recolor>,
+    /// Variants output alongside the config, from its
+    /// [`VARIANTS_KEY`](variants::VARIANTS_KEY)
+    pub variants: Vec<Variant>,
+    /// Keys the config or its templates set that the operation doesn't read
+    pub unknown_keys: Vec<UnknownKey>,
+}
+
+impl ConfigFile {
+    /// The config as canonical toml, with its templates and includes resolved
+    /// and every default filled in, so it reads the same on its own
+    /// # Errors
+    /// Errors if the operation can't be written as toml
+    pub fn to_resolved_toml(&self) -> ConfigResult<String> {
+        let Value::Table(mut table) = to_value(&self.operation)? else {
+            unreachable!("operations are tables");
+        };
+        if let Some(input) = &self.input {
+            table.insert(INPUT_KEY.to_string(), Value::String(input.clone()));
+        }
+        if !self.layers.is_empty() {
+            table.insert(LAYERS_KEY.to_string(), to_value(&self.layers)?);
+        }
+        if !self.damage.levels.is_empty() {
+            table.insert(DAMAGE_KEY.to_string(), to_value(&self.damage)?);
+        }
+        if let Some(hsv) = &self.hsv {
+            table.insert(HSV_KEY.to_string(), to_value(hsv)?);
+        }
+        if !self.post_process.filters.is_empty() {
+            table.insert(POST_PROCESS_KEY.to_string(), to_value(&self.post_process)?);
+        }
+        if !self.recolors.is_empty() {
+            table.insert(RECOLORS_KEY.to_string(), recolors_value(&self.recolors));
+        }
+        if !self.variants.is_empty() {
+            let mut variants = Map::new();
+            for variant in &self.variants {
+                let mut variant_value = to_value(&variant.operation)?;
+                if let (Value::Table(table), Some(hsv)) = (&mut variant_value, &variant.hsv) {
+                    table.insert(HSV_KEY.to_string(), to_value(hsv)?);
+                }
+                variants.insert(variant.name.clone(), variant_value);
+            }
+            table.insert(VARIANTS_KEY.to_string(), Value::Table(variants));
+        }
+        toml::to_string(&table).map_err(|err| ConfigError::Config(err.to_string()))
+    }
+}
+
+fn to_value<T: Serialize>(value: &T) -> ConfigResult<Value> {
+    Value::try_from(value).map_err(|err| ConfigError::Config(err.to_string()))
+}
+
 #[tracing::instrument(skip(resolver, input))]
 pub fn read_config<R: Read + Seek>(
     input: &mut R,
     resolver: impl TemplateResolver,
 ) -> ConfigResult<IconOperation> {
+    Ok(read_config_file(input, ConfigFormat::Toml, Path::new(""), resolver)?.operation)
+}
+
+/// Reads a config written in `format` like [`read_config`], along with the
+/// input image it names. Fragments it includes are looked for in `dir`, the
+/// directory the config is in
+/// # Errors
+/// Errors if the config can't be read, its includes or templates can't be
+/// resolved, it doesn't describe a valid operation, or its input isn't a
+/// string
+#[tracing::instrument(skip(resolver, input))]
+pub fn read_config_file<R: Read + Seek>(
+    input: &mut R,
+    format: ConfigFormat,
+    dir: &Path,
+    resolver: impl TemplateResolver,
+) -> ConfigResult<ConfigFile> {
+    read_config_file_impl(input, format, dir, resolver, None)
+}
+
+/// Reads a config like [`read_config_file`], but forces it through the
+/// operation called `mode` whatever its own mode is. Anything the config
+/// doesn't set is taken from the operation's default settings, when it has
+/// them
+/// # Errors
+/// Same as [`read_config_file`], and errors if there's no operation called
+/// `mode`
+#[tracing::instrument(skip(resolver, input))]
+pub fn read_config_file_as<R: Read + Seek>(
+    input: &mut R,
+    format: ConfigFormat,
+    dir: &Path,
+    resolver: impl TemplateResolver,
+    mode: &str,
+) -> ConfigResult<ConfigFile> {
+    read_config_file_impl(input, format, dir, resolver, Some(mode))
+}
+
+fn read_config_file_impl<R: Read + Seek>(
+    input: &mut R,
+    format: ConfigFormat,
+    dir: &Path,
+    resolver: impl TemplateResolver,
+    mode: Option<&str>,
+) -> ConfigResult<ConfigFile> {
     let reader_string = read_to_string(input)?;
-    let toml_value = toml::from_str(&reader_string)?;
+    let toml_value = format.parse(&reader_string)?;
+    read_config_value(toml_value, dir, resolver, mode)
+}
+
+/// Reads an already parsed config in `dir`, optionally forcing it through the
+/// operation called `mode`
+pub(crate) fn read_config_value(
+    mut toml_value: Value,
+    dir: &Path,
+    resolver: impl TemplateResolver,
+    mode: Option<&str>,
+) -> ConfigResult<ConfigFile> {
+    let input_path = take_input(&mut toml_value)?;
+    let included = resolve_includes(&mut toml_value, dir)?;
+
+    let (mut result_value, mut sources) =
+        resolve_value(toml_value, &resolver, &mut vec![], &KeySource::Config)?;
+    sources.merge(included);
+    let layers = take_layers(&mut result_value)?;
+    let damage = take_damage(&mut result_value)?;
+    let post_process = take_post_process(&mut result_value)?;
+    let recolors = take_recolors(&mut result_value)?;
+    if let Value::Table(table) = &mut result_value {
+        // provenance only describes where a generated config came from
+        table.remove(PROVENANCE_KEY);
+        table.remove(INPUT_KEY);
+    }
+    expand_env_vars(&mut result_value)?;
+    if let Some(mode) = mode {
+        result_value = override_mode(result_value, mode)?;
+    }
+    let (variants, variant_unknown_keys) = take_variants(&mut result_value)?;
+    let hsv = take_hsv(&mut result_value)?;
+    if let Some(recolor) = recolors
+        .iter()
+        .find(|recolor| variants.iter().any(|variant| variant.name == recolor.name))
+    {
+        return Err(ConfigError::Config(format!(
+            "`{}` is the name of both a variant and a recolor, so their outputs would overwrite \
+             each other",
+            recolor.name
+        )));
+    }
+
+    let out_icon_mode: IconOperation = IconOperation::deserialize(result_value.clone())?;
+    debug!(config = ?out_icon_mode, input = ?input_path, variants = variants.len(), "Deserialized");
+    let mut unknown_keys = find_unknown_keys(&result_value, &sources, &out_icon_mode);
+    unknown_keys.extend(variant_unknown_keys);
+    Ok(ConfigFile {
+        operation: out_icon_mode,
+        input: input_path,
+        layers,
+        damage,
+        hsv,
+        post_process,
+        recolors,
+        variants,
+        unknown_keys,
+    })
+}
+
+/// Resolves a config's includes and templates like [`read_config_file`],
+/// listing every value of the result with the template, include (or the
+/// config itself) that set it, for tracking down where a value in a long
+/// template chain comes from
+/// # Errors
+/// Errors if the config can't be read or its includes or templates can't be
+/// resolved
+#[tracing::instrument(skip(resolver, input))]
+pub fn read_config_sources<R: Read>(
+    input: &mut R,
+    format: ConfigFormat,
+    dir: &Path,
+    resolver: impl TemplateResolver,
+) -> ConfigResult<Vec<ResolvedKey>> {
+    let mut toml_value = format.parse(&read_to_string(input)?)?;
+    take_input(&mut toml_value)?;
+    let included = resolve_includes(&mut toml_value, dir)?;
+
+    let (mut result_value, mut sources) =
+        resolve_value(toml_value, &resolver, &mut vec![], &KeySource::Config)?;
+    sources.merge(included);
+    take_layers(&mut result_value)?;
+    take_damage(&mut result_value)?;
+    take_post_process(&mut result_value)?;
+    take_recolors(&mut result_value)?;
+    if let Value::Table(table) = &mut result_value {
+        for key in [PROVENANCE_KEY, INPUT_KEY] {
+            table.remove(key);
+            sources.remove(key);
+        }
+    }
+    expand_env_vars(&mut result_value)?;
+    Ok(sources.resolved_keys(&result_value))
+}
+
+/// Reads just the input image a config names, without resolving templates or
+/// checking the rest of the config, for cheaply finding which config uses an
+/// image
+/// # Errors
+/// Errors if the config isn't valid in `format`, or its input isn't a string
+pub fn read_config_input<R: Read>(
+    input: &mut R,
+    format: ConfigFormat,
+) -> ConfigResult<Option<String>> {
+    let mut toml_value = format.parse(&read_to_string(input)?)?;
+    take_input(&mut toml_value)
+}
 
-    let result_value = resolve_templates(toml_value, resolver)?;
+/// Removes [`INPUT_KEY`] from a config, returning it with any environment
+/// variables it uses expanded
+fn take_input(value: &mut Value) -> ConfigResult<Option<String>> {
+    let Value::Table(table) = value else {
+        return Ok(None);
+    };
+    match table.remove(INPUT_KEY) {
+        Some(Value::String(path)) => Ok(Some(expand_env_string(&path, INPUT_KEY)?)),
+        Some(_) => {
+            Err(ConfigError::Config(format!(
+                "`{INPUT_KEY}` must be the path of the input image, relative to the config"
+            )))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Layers a resolved config over the default settings of the operation called
+/// `mode`, and switches it to that operation
+fn override_mode(value: Value, mode: &str) -> ConfigResult<Value> {
+    if !IconOperation::MODES.contains(&mode) {
+        return Err(ConfigError::Config(format!(
+            "there's no operation called `{mode}`, expected one of {}",
+            IconOperation::MODES.join(", ")
+        )));
+    }
+    let mut out = match IconOperation::default_for(mode) {
+        Some(defaults) => {
+            Value::try_from(defaults).map_err(|err| ConfigError::Config(err.to_string()))?
+        }
+        None => Value::Table(Map::new()),
+    };
+    deep_merge_toml(&mut out, value);
+    if let Value::Table(table) = &mut out {
+        table.insert("mode".to_string(), Value::String(mode.to_string()));
+    }
+    Ok(out)
+}
+
+/// Most templates a config can chain through before it's an error
+const MAX_TEMPLATE_DEPTH: usize = 100;
+
+/// A template a config inherits from, along with the args to substitute into it
+#[derive(Clone, PartialEq, Debug)]
+struct TemplateRef {
+    name: String,
+    args: Map<String, Value>,
+}
+
+impl TemplateRef {
+    /// Reads either a bare template name, or a table like
+    /// `{ name = "wall", args = { size = 48 } }`
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::String(name) => {
+                Some(Self {
+                    name,
+                    args: Map::new(),
+                })
+            }
+            Value::Table(mut table) => {
+                let Some(Value::String(name)) = table.remove("name") else {
+                    return None;
+                };
+                let args = match table.remove("args") {
+                    Some(Value::Table(args)) => args,
+                    _ => Map::new(),
+                };
+                Some(Self { name, args })
+            }
+            _ => None,
+        }
+    }
+}
 
-    let out_icon_mode: IconOperation = IconOperation::deserialize(result_value)?;
-    debug!(config = ?out_icon_mode, "Deserialized");
-    Ok(out_icon_mode)
+/// Seeks out the templates from a value, either a single template or an
+/// array of them. Returns an empty vec if not found
+/// SIDE EFFECT: removes them from the `Value` if it finds them!
+fn extract_templates(value: &mut Value) -> Vec<TemplateRef> {
+    let Value::Table(table) = value else {
+        return vec![];
+    };
+    match table.remove("template") {
+        Some(Value::Array(array)) => {
+            array
+                .into_iter()
+                .filter_map(TemplateRef::from_value)
+                .collect()
+        }
+        Some(template) => TemplateRef::from_value(template).into_iter().collect(),
+        None => vec![],
+    }
 }
 
-/// Seeks out template string from a value and returns it as a `Some(String)`
-/// If not found, returns `None`
-/// SIDE EFFECT: removes it from the `Value` if it finds it!
-fn extract_template_string(value: &mut Value) -> Option<String> {
+/// Takes the merge strategies out of a value
+fn extract_merge_strategies(
+    value: &mut Value,
+) -> Result<BTreeMap<String, MergeStrategy>, TemplateError> {
+    let Value::Table(table) = value else {
+        return Ok(BTreeMap::new());
+    };
+    match table.remove(MERGE_KEY) {
+        Some(strategies) => Ok(strategies.try_into()?),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+/// Replaces `{{arg}}` placeholders in every string inside `value` with the
+/// matching entry of `args`. A string that's only a placeholder is replaced
+/// with the arg itself, so it keeps its type. Placeholders without a matching
+/// arg are left alone, for templates further up to fill in
+fn substitute_args(value: &mut Value, args: &Map<String, Value>) {
     match value {
+        Value::String(string) => {
+            let trimmed = string.trim();
+            if let Some(arg) = trimmed
+                .strip_prefix("{{")
+                .and_then(|rest| rest.strip_suffix("}}"))
+                .filter(|key| !key.contains("{{") && !key.contains("}}"))
+                .and_then(|key| args.get(key.trim()))
+            {
+                *value = arg.clone();
+                return;
+            }
+            let mut out = String::with_capacity(string.len());
+            let mut rest = string.as_str();
+            while let Some(start) = rest.find("{{") {
+                let Some(length) = rest[start..].find("}}") else {
+                    break;
+                };
+                let placeholder = &rest[start..start + length + 2];
+                out.push_str(&rest[..start]);
+                match args.get(placeholder[2..placeholder.len() - 2].trim()) {
+                    Some(Value::String(arg)) => out.push_str(arg),
+                    Some(arg) => out.push_str(&arg.to_string()),
+                    None => out.push_str(placeholder),
+                }
+                rest = &rest[start + length + 2..];
+            }
+            out.push_str(rest);
+            *string = out;
+        }
+        Value::Array(array) => {
+            for item in array {
+                substitute_args(item, args);
+            }
+        }
         Value::Table(table) => {
-            if let Some(Value::String(string)) = table.remove("template") {
-                Some(string)
-            } else {
-                None
+            for (_, item) in table.iter_mut() {
+                substitute_args(item, args);
             }
         }
-        _ => None,
+        _ => {}
     }
 }
 
 #[tracing::instrument(skip(resolver))]
 pub fn resolve_templates(first: Value, resolver: impl TemplateResolver) -> TemplateResult {
     debug!(first = ?first, "Started resolving templates");
-    let mut current = first;
-    let mut stack: Vec<Value> = vec![];
-
-    let mut extracted_template = extract_template_string(&mut current);
-    trace!(extracted = ?extracted_template, "extracted first template");
-
-    // push the first on to the stack to be resolved
-    stack.push(current.clone());
-    let mut recursion_cap = 0;
-    // Drill in to templates and resolve until no new ones found
-    while recursion_cap < 100 {
-        if let Some(template) = &extracted_template {
-            current = resolver.resolve(template.as_str())?;
-            extracted_template = extract_template_string(&mut current);
-            trace!(value = ?current, "Resolved config");
-            stack.push(current.clone());
-            recursion_cap += 1;
-        } else {
-            break;
-        }
-    }
-    trace!(num_in_chain = ?stack.len(), stack = ?stack, "Finished resolving templates");
-    // merge stack in to one hashmap
-    let mut out: Value = Value::Table(Map::new());
-    for conf in stack.iter_mut().rev() {
-        deep_merge_toml(&mut out, conf.clone());
-        trace!(current = ?out, collapsing = ?conf, "Collapsing value step");
-    }
-
+    let (out, _) = resolve_value(first, &resolver, &mut vec![], &KeySource::Config)?;
     debug!(collapsed = ?out, "Collapsed value");
     Ok(out)
 }
 
+/// Resolves each of `value`'s templates (and their own templates) in turn,
+/// substituting in the args passed to them and merging them left to right,
+/// then merges `value` on top so its own keys take priority, using its merge
+/// strategies. Also returns
+/// what set each value, with `value`'s own attributed to `source`. `stack` is
+/// the templates already being resolved, innermost last
+fn resolve_value<R: TemplateResolver>(
+    mut value: Value,
+    resolver: &R,
+    stack: &mut Vec<String>,
+    source: &KeySource,
+) -> Result<(Value, KeySources), TemplateError> {
+    let templates = extract_templates(&mut value);
+    let strategies = extract_merge_strategies(&mut value)?;
+    trace!(templates = ?templates, strategies = ?strategies, depth = stack.len(), "Extracted templates");
+    let mut out = Value::Table(Map::new());
+    let mut sources = KeySources::default();
+    if !templates.is_empty() && stack.len() >= MAX_TEMPLATE_DEPTH {
+        return Err(TemplateError::TooDeep(MAX_TEMPLATE_DEPTH));
+    }
+    for template in templates {
+        if stack.contains(&template.name) {
+            let mut cycle = stack.clone();
+            cycle.push(template.name);
+            return Err(TemplateError::Cycle(cycle));
+        }
+        stack.push(template.name.clone());
+        let (mut template_value, template_sources) = resolve_value(
+            resolver.resolve(&template.name)?,
+            resolver,
+            stack,
+            &KeySource::Template(template.name.clone()),
+        )?;
+        stack.pop();
+        substitute_args(&mut template_value, &template.args);
+        trace!(template = template.name, resolved = ?template_value, "Resolved template");
+        deep_merge_toml(&mut out, template_value);
+        sources.merge(template_sources);
+    }
+    sources.merge(KeySources::of(&value, source));
+    deep_merge_toml_with(&mut out, value, &strategies);
+    Ok((out, sources))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -97,11 +510,15 @@ mod test {
 
         let mut toml_value: Value = toml::from_str(mapping).unwrap();
 
-        let extracted = extract_template_string(&mut toml_value).unwrap();
-
-        let expected = "found".to_string();
+        let extracted = extract_templates(&mut toml_value);
 
-        assert_eq!(extracted, expected);
+        assert_eq!(
+            extracted,
+            vec![TemplateRef {
+                name: "found".to_string(),
+                args: Map::new(),
+            }]
+        );
 
         let expected_mapping = r#"still_there = "junk""#;
         let expected_value: Value = toml::from_str(expected_mapping).unwrap();
@@ -146,7 +563,49 @@ mod test {
             inner_3 = 4
             ";
 
+            let sized_string = r#"
+            first = "{{size}}"
+            second = "{{ size }}px, {{missing}}"
+            [inner]
+            inner_1 = ["{{name}}-{{size}}"]
+            "#;
+
+            let outlined_string = r##"
+            [post_process]
+            filters = [{ filter = "outline", color = "#000000" }]
+            "##;
+
+            let decaled_string = r#"
+            layers = ["decals.png"]
+            [damage]
+            levels = [["cracks.png"]]
+            "#;
+
+            let red_string = r##"
+            [recolors.red]
+            "#808080" = "#A02020"
+            "##;
+
+            let looping_string = r#"
+            template = ["looping", "looping"]
+            "#;
+
+            let left_string = r#"
+            template = "right"
+            "#;
+
+            let right_string = r#"
+            template = "left"
+            "#;
+
             Ok(toml::from_str(match input {
+                "sized" => sized_string,
+                "outlined" => outlined_string,
+                "red" => red_string,
+                "decaled" => decaled_string,
+                "looping" => looping_string,
+                "left" => left_string,
+                "right" => right_string,
                 "first" => first_string,
                 "second" => second_string,
                 "third" => third_string,
@@ -209,12 +668,364 @@ mod test {
             let expected_value: Value = toml::from_str(expected_string).unwrap();
             assert_eq!(result, expected_value);
         }
+
+        #[test]
+        fn flattening_multiple() {
+            let input_string = r#"
+            template = ["first", "third"]
+            first = 10
+            "#;
+
+            let input: Value = toml::from_str(input_string).unwrap();
+
+            let result = resolve_templates(input, TestResolver).unwrap();
+
+            // "third" is merged over "first", and the config over both
+            let expected_string = r"
+            first = 10
+            second = 3
+            third = 3
+            fourth = 2
+            [inner]
+            inner_1 = 3
+            inner_2 = 3
+            inner_3 = 4
+            ";
+            let expected_value: Value = toml::from_str(expected_string).unwrap();
+            assert_eq!(result, expected_value);
+        }
+
+        #[test]
+        fn merge_strategies() {
+            let input_string = r#"
+            template = "third"
+            merge = { inner = "replace" }
+            [inner]
+            inner_1 = 10
+            "#;
+
+            let input: Value = toml::from_str(input_string).unwrap();
+
+            let result = resolve_templates(input, TestResolver).unwrap();
+
+            let expected_string = r"
+            first = 3
+            second = 3
+            third = 3
+            [inner]
+            inner_1 = 10
+            ";
+            let expected_value: Value = toml::from_str(expected_string).unwrap();
+            assert_eq!(result, expected_value);
+
+            let input: Value = toml::from_str(r#"merge = { inner = "sideways" }"#).unwrap();
+            assert!(resolve_templates(input, TestResolver).is_err());
+        }
+
+        #[test]
+        fn template_cycles() {
+            let cycle = |first: &str| {
+                let input: Value = toml::from_str(&format!("template = \"{first}\"")).unwrap();
+                match resolve_templates(input, TestResolver) {
+                    Err(TemplateError::Cycle(cycle)) => cycle,
+                    other => panic!("Expected a cycle, got {other:?}"),
+                }
+            };
+            assert_eq!(cycle("looping"), ["looping", "looping"]);
+            assert_eq!(cycle("left"), ["left", "right", "left"]);
+        }
+
+        #[test]
+        fn template_args() {
+            let input_string = r#"
+            template = { name = "sized", args = { size = 48, name = "wall" } }
+            "#;
+
+            let input: Value = toml::from_str(input_string).unwrap();
+
+            let result = resolve_templates(input, TestResolver).unwrap();
+
+            let expected_string = r#"
+            first = 48
+            second = "48px, {{missing}}"
+            [inner]
+            inner_1 = ["wall-48"]
+            "#;
+            let expected_value: Value = toml::from_str(expected_string).unwrap();
+            assert_eq!(result, expected_value);
+        }
+    }
+
+    #[test]
+    fn key_sources() {
+        let input_string = r#"
+        template = "third"
+        first = 10
+        [inner]
+        inner_1 = 10
+        "#;
+
+        let keys = read_config_sources(
+            &mut input_string.as_bytes(),
+            ConfigFormat::Toml,
+            Path::new(""),
+            TestResolver,
+        )
+        .unwrap();
+        let sources: Vec<(&str, KeySource)> = keys
+            .iter()
+            .map(|key| (key.path.as_str(), key.source.clone()))
+            .collect();
+        let template = |name: &str| KeySource::Template(name.to_string());
+        assert_eq!(
+            sources,
+            vec![
+                ("first", KeySource::Config),
+                ("inner.inner_1", KeySource::Config),
+                ("inner.inner_2", template("third")),
+                ("inner.inner_3", template("fourth")),
+                ("second", template("third")),
+                ("third", template("third")),
+            ]
+        );
     }
 
     mod config {
+        use std::io::Cursor;
+
         use super::*;
         use crate::operations::cutters::bitmask_slice::BitmaskSlice;
 
+        #[test]
+        fn input_key() {
+            let operation: IconOperation = BitmaskSlice::default().into();
+            let text = format!(
+                "input = \"sheet.png\"\n{}",
+                toml::to_string(&operation).unwrap()
+            );
+
+            let config = read_config_file(
+                &mut Cursor::new(&text),
+                ConfigFormat::Toml,
+                Path::new(""),
+                TestResolver,
+            )
+            .unwrap();
+            assert_eq!(config.input.as_deref(), Some("sheet.png"));
+            assert_eq!(config.operation, operation);
+
+            let input = read_config_input(&mut Cursor::new(&text), ConfigFormat::Toml).unwrap();
+            assert_eq!(input.as_deref(), Some("sheet.png"));
+        }
+
+        #[test]
+        fn resolved_toml() {
+            let operation: IconOperation = BitmaskSlice::default().into();
+            let text = format!(
+                "template = \"second\"\ninput = \"sheet.png\"\nlayers = \
+                 [\"decals.png\"]\n{}\n[variants.dirs]\nproduce_dirs = true\n",
+                toml::to_string(&operation).unwrap()
+            );
+            let config = read_config_file(
+                &mut Cursor::new(&text),
+                ConfigFormat::Toml,
+                Path::new(""),
+                TestResolver,
+            )
+            .unwrap();
+
+            // read back without templates, it's the same config
+            let resolved = config.to_resolved_toml().unwrap();
+            let reread = read_config_file(
+                &mut Cursor::new(&resolved),
+                ConfigFormat::Toml,
+                Path::new(""),
+                TestResolver,
+            )
+            .unwrap();
+            assert_eq!(reread.operation, config.operation);
+            assert_eq!(reread.input, config.input);
+            assert_eq!(reread.layers, config.layers);
+            assert_eq!(reread.variants, config.variants);
+            assert!(reread.unknown_keys.is_empty());
+        }
+
+        #[test]
+        fn unknown_keys() {
+            let operation: IconOperation = BitmaskSlice::default().into();
+            let text = format!(
+                "template = \"second\"\n{}\n[anmation]\ndelays = [1]\n",
+                toml::to_string(&operation).unwrap()
+            );
+
+            let config = read_config_file(
+                &mut Cursor::new(&text),
+                ConfigFormat::Toml,
+                Path::new(""),
+                TestResolver,
+            )
+            .unwrap();
+            let unknown: Vec<(&str, &KeySource, Option<&str>)> = config
+                .unknown_keys
+                .iter()
+                .map(|key| (key.path.as_str(), &key.source, key.suggestion.as_deref()))
+                .collect();
+            let second = KeySource::Template("second".to_string());
+            assert_eq!(
+                unknown,
+                vec![
+                    ("anmation", &KeySource::Config, Some("animation")),
+                    ("first", &second, None),
+                    ("fourth", &second, None),
+                    ("second", &second, None),
+                    ("third", &second, None),
+                ]
+            );
+        }
+
+        #[test]
+        fn template_post_process() {
+            use crate::operations::post_process::{Filter, PostProcess};
+            use crate::util::color::Color;
+
+            let operation: IconOperation = BitmaskSlice::default().into();
+            let text = format!(
+                "template = \"outlined\"\n{}",
+                toml::to_string(&operation).unwrap()
+            );
+            let config = read_config_file(
+                &mut Cursor::new(&text),
+                ConfigFormat::Toml,
+                Path::new(""),
+                TestResolver,
+            )
+            .unwrap();
+            assert_eq!(
+                config.post_process,
+                PostProcess {
+                    filters: vec![Filter::Outline {
+                        color: Color::new(0, 0, 0, 255),
+                        diagonals: false,
+                    }],
+                }
+            );
+            assert!(config.unknown_keys.is_empty());
+        }
+
+        #[test]
+        fn template_recolors() {
+            use crate::util::color::Color;
+
+            let operation: IconOperation = BitmaskSlice::default().into();
+            let text = format!(
+                "template = \"red\"\n{}\n[recolors.blue]\n\"#808080\" = \"#2020A0\"\n",
+                toml::to_string(&operation).unwrap()
+            );
+            let config = read_config_file(
+                &mut Cursor::new(&text),
+                ConfigFormat::Toml,
+                Path::new(""),
+                TestResolver,
+            )
+            .unwrap();
+            let grey = Color::new_rgb(128, 128, 128);
+            assert_eq!(
+                config.recolors,
+                vec![
+                    Recolor {
+                        name: "blue".to_string(),
+                        palette: vec![(grey, Color::new_rgb(32, 32, 160))],
+                    },
+                    Recolor {
+                        name: "red".to_string(),
+                        palette: vec![(grey, Color::new_rgb(160, 32, 32))],
+                    },
+                ]
+            );
+            assert!(config.unknown_keys.is_empty());
+        }
+
+        #[test]
+        fn template_layers() {
+            let operation: IconOperation = BitmaskSlice::default().into();
+            let text = format!(
+                "template = \"decaled\"\n{}",
+                toml::to_string(&operation).unwrap()
+            );
+            let config = read_config_file(
+                &mut Cursor::new(&text),
+                ConfigFormat::Toml,
+                Path::new(""),
+                TestResolver,
+            )
+            .unwrap();
+            let paths: Vec<&str> = config
+                .layers
+                .iter()
+                .map(|layer| layer.path.as_str())
+                .collect();
+            assert_eq!(paths, vec!["decals.png"]);
+            assert!(config.unknown_keys.is_empty());
+        }
+
+        #[test]
+        fn template_damage() {
+            let operation: IconOperation = BitmaskSlice::default().into();
+            let text = format!(
+                "template = \"decaled\"\n{}\n[damage]\nsuffix = \"broken\"\n",
+                toml::to_string(&operation).unwrap()
+            );
+            let config = read_config_file(
+                &mut Cursor::new(&text),
+                ConfigFormat::Toml,
+                Path::new(""),
+                TestResolver,
+            )
+            .unwrap();
+            assert_eq!(config.damage.suffix, "broken");
+            assert_eq!(config.damage.levels.len(), 1);
+            assert_eq!(config.damage.levels[0][0].path, "cracks.png");
+            assert!(config.unknown_keys.is_empty());
+        }
+
+        #[test]
+        fn mode_override() {
+            use crate::config::blocks::cutters::IconSize;
+            use crate::operations::cutters::bitmask_windows::BitmaskWindows;
+
+            let text = r#"
+            mode = "BitmaskSlice"
+            produce_dirs = true
+            [icon_size]
+            x = 48
+            y = 48
+            "#;
+            let config = read_config_file_as(
+                &mut Cursor::new(text),
+                ConfigFormat::Toml,
+                Path::new(""),
+                TestResolver,
+                "BitmaskWindows",
+            )
+            .unwrap();
+            assert_eq!(
+                config.operation,
+                BitmaskWindows {
+                    icon_size: IconSize { x: 48, y: 48 },
+                    ..Default::default()
+                }
+                .into()
+            );
+            assert!(read_config_file_as(
+                &mut Cursor::new(text),
+                ConfigFormat::Toml,
+                Path::new(""),
+                TestResolver,
+                "Nonsense"
+            )
+            .is_err());
+        }
+
         #[test]
         fn symmetrical_serialize() {
             let config: IconOperation = BitmaskSlice::default().into();
@@ -225,7 +1036,7 @@ mod test {
             println!("{tomled}");
 
             let test_toml = "
-                operation = \"BitmaskSlice\"
+                mode = \"BitmaskSlice\"
                 produce_dirs = false
                 smooth_diagonally = false
 
@@ -247,7 +1058,7 @@ mod test {
                 horizontal = 2
                 vertical = 3
 
-                [cut_position]
+                [cut_pos]
                 x = 16
                 y = 16
             ";
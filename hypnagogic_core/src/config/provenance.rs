@@ -0,0 +1,129 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// Key of the table generated configs record their provenance under. It's
+/// stripped out by `read_config`, so it never affects the operation
+pub const PROVENANCE_KEY: &str = "provenance";
+
+/// Where a generated config came from, written as a `[provenance]` table at
+/// the end of the config
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub tool_version: String,
+    /// Path of the file the config was generated from, if known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub source: Option<String>,
+    /// UTC timestamp, in RFC 3339 format
+    pub generated_at: String,
+    /// Every icon state in the source, in order
+    pub states: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ProvenanceTable<'a> {
+    provenance: &'a Provenance,
+}
+
+impl Provenance {
+    /// Provenance for a config generated right now by this version of
+    /// hypnagogic
+    #[must_use]
+    pub fn now(states: Vec<String>) -> Self {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        Self {
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            source: None,
+            generated_at: format_utc(seconds),
+            states,
+        }
+    }
+
+    /// Renders the provenance as a toml table. Tables have to come after any
+    /// top level keys, so this should go at the end of a config
+    /// # Panics
+    /// Shouldn't panic, all fields serialize to toml
+    #[must_use]
+    pub fn render(&self) -> String {
+        let table = toml::to_string(&ProvenanceTable { provenance: self })
+            .expect("Provenance should always serialize");
+        format!("# Written by hypnagogic, ignored when the config is read\n{table}")
+    }
+
+    /// Adds a `source` to a generated config's provenance table, for
+    /// frontends that know where the input came from when the operation
+    /// itself doesn't. Configs without a provenance table are returned as is
+    #[must_use]
+    pub fn stamp_source(config: &str, source: &str) -> String {
+        let header = format!("[{PROVENANCE_KEY}]\n");
+        let Some(position) = config.find(&header) else {
+            return config.to_string();
+        };
+        let line = format!("source = {}\n", toml::Value::String(source.to_string()));
+        let insert_at = position + header.len();
+        format!("{}{line}{}", &config[..insert_at], &config[insert_at..])
+    }
+}
+
+/// Formats seconds since the unix epoch as an RFC 3339 UTC timestamp
+fn format_utc(seconds: u64) -> String {
+    let days = seconds / 86_400;
+    let time = seconds % 86_400;
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        time / 3_600,
+        time % 3_600 / 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format_utc_test() {
+        assert_eq!(format_utc(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_utc(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_utc(1_791_979_199), "2026-10-14T11:59:59Z");
+    }
+
+    #[derive(Deserialize)]
+    struct Stamped {
+        provenance: Provenance,
+    }
+
+    #[test]
+    fn stamp_source_test() {
+        let provenance = Provenance {
+            tool_version: "1.0.0".to_string(),
+            source: None,
+            generated_at: format_utc(0),
+            states: vec!["wall-0".to_string()],
+        };
+        let config = format!("x = 1\n\n{}", provenance.render());
+        let stamped = Provenance::stamp_source(&config, "icons/wall.dmi");
+
+        let read: Stamped = toml::from_str(&stamped).unwrap();
+        assert_eq!(read.provenance.source.as_deref(), Some("icons/wall.dmi"));
+        assert_eq!(read.provenance.states, provenance.states);
+    }
+}
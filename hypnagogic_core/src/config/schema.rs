@@ -0,0 +1,303 @@
+//! A JSON schema of everything configs can set, so editors can offer
+//! completion and validation while writing them. Keys aren't required by the
+//! schema, since templates and includes can set them
+
+use schemars::generate::SchemaSettings;
+use schemars::SchemaGenerator;
+use serde_json::{json, Map, Value};
+
+use crate::config::damage::DAMAGE_KEY;
+use crate::config::hsv::{HsvShift, HSV_KEY};
+use crate::config::include::INCLUDE_KEY;
+use crate::config::layers::{Layer, LAYERS_KEY};
+use crate::config::provenance::PROVENANCE_KEY;
+use crate::config::variants::VARIANTS_KEY;
+use crate::config::{INPUT_KEY, MERGE_KEY};
+use crate::operations::post_process::{PostProcess, POST_PROCESS_KEY};
+use crate::operations::recolors::RECOLORS_KEY;
+use crate::operations::IconOperation;
+use crate::util::color::Color;
+use crate::util::MergeStrategy;
+
+/// Schema of a whole config: the keys any config can set, and those of the
+/// operation its `mode` picks, as derived from the types they're read in to
+#[must_use]
+pub fn config_schema() -> Value {
+    let mut generator = SchemaSettings::draft07().into_generator();
+    let mut operation = subschema::<IconOperation>(&mut generator);
+    remove_required(&mut operation);
+    let layer = json!({ "anyOf": [string(), subschema::<Layer>(&mut generator)] });
+    let color = subschema::<Color>(&mut generator);
+    let hsv = subschema::<HsvShift>(&mut generator);
+    let post_process = subschema::<PostProcess>(&mut generator);
+    let merge = subschema::<MergeStrategy>(&mut generator);
+    let mut definitions = generator.take_definitions(true);
+    for (name, definition) in &mut definitions {
+        remove_required(definition);
+        // operations share their table with the keys every config can set,
+        // but anything else only has the keys its type reads
+        if !IconOperation::MODES.contains(&name.as_str()) && definition.get("properties").is_some()
+        {
+            definition
+                .as_object_mut()
+                .unwrap()
+                .entry("additionalProperties")
+                .or_insert(Value::Bool(false));
+        }
+    }
+
+    let template = json!({
+        "description": "A template name, or a table with its `name` and `args` to substitute in",
+        "anyOf": [
+            string(),
+            object(&[("name", string()), ("args", json!({ "type": "object" }))]),
+        ],
+    });
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "Hypnagogic config",
+        "type": "object",
+        "properties": {
+            "mode": described(string_enum(IconOperation::MODES), "The operation to run"),
+            "template": {
+                "description": "Templates to start from, merged left to right",
+                "anyOf": [template.clone(), array(template)],
+            },
+            INCLUDE_KEY: described(
+                json!({ "anyOf": [string(), array(string())] }),
+                "Config fragments to merge under this one, relative to it",
+            ),
+            INPUT_KEY: described(string(), "The input image, relative to the config"),
+            LAYERS_KEY: described(array(layer.clone()), "Images drawn over the input before it's cut"),
+            DAMAGE_KEY: described(
+                object(&[("suffix", string()), ("levels", array(array(layer)))]),
+                "Damaged copies of every state, each level's images drawn over the input",
+            ),
+            HSV_KEY: described(hsv, "Shifts the input's colors before it's cut, in variants too"),
+            POST_PROCESS_KEY: described(
+                post_process,
+                "Filters run over every generated frame, in order",
+            ),
+            RECOLORS_KEY: described(
+                map(map(color)),
+                "Recolors output alongside the config, each a table of colors to swap",
+            ),
+            MERGE_KEY: described(
+                map(merge),
+                "How values are merged over the templates', by dotted path",
+            ),
+            VARIANTS_KEY: described(
+                map(json!({ "type": "object" })),
+                "Variants output alongside the config, each a table of keys to change",
+            ),
+            PROVENANCE_KEY: json!({ "type": "object" }),
+        },
+        // the operation's keys are only checked once there's a `mode` to pick it
+        "if": { "required": ["mode"] },
+        "then": operation,
+        "definitions": definitions,
+    })
+}
+
+/// Schema of `T`, with its definitions left in `generator`
+fn subschema<T: schemars::JsonSchema>(generator: &mut SchemaGenerator) -> Value {
+    generator.subschema_for::<T>().to_value()
+}
+
+/// Strips every list of required keys from `schema`
+fn remove_required(schema: &mut Value) {
+    match schema {
+        Value::Object(object) => {
+            object.remove("required");
+            object.values_mut().for_each(remove_required);
+        }
+        Value::Array(array) => array.iter_mut().for_each(remove_required),
+        _ => {}
+    }
+}
+
+fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+fn string_enum(values: &[&str]) -> Value {
+    json!({ "type": "string", "enum": values })
+}
+
+fn array(items: Value) -> Value {
+    let mut schema = json!({ "type": "array" });
+    schema["items"] = items;
+    schema
+}
+
+/// A table of any keys, each holding `values`
+fn map(values: Value) -> Value {
+    let mut schema = json!({ "type": "object" });
+    schema["additionalProperties"] = values;
+    schema
+}
+
+fn described(mut schema: Value, description: &str) -> Value {
+    schema["description"] = Value::String(description.to_string());
+    schema
+}
+
+/// A table of exactly `properties`
+fn object(properties: &[(&str, Value)]) -> Value {
+    let properties: Map<String, Value> = properties
+        .iter()
+        .map(|(key, value)| ((*key).to_string(), value.clone()))
+        .collect();
+    json!({ "type": "object", "properties": properties, "additionalProperties": false })
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::iter;
+
+    use strum::IntoEnumIterator;
+
+    use super::*;
+    use crate::config::blocks::cutters::GroupPositions;
+    use crate::config::unknown_keys::field_names;
+    use crate::operations::cutters::bitmask_dir_visibility::BitmaskDirectionalVis;
+    use crate::operations::cutters::bitmask_slice::BitmaskSlice;
+    use crate::operations::cutters::bitmask_slice_greyscale::BitmaskSliceGreyscale;
+    use crate::operations::cutters::bitmask_slice_groups::BitmaskSliceGroups;
+    use crate::operations::cutters::bitmask_texture_mask::BitmaskTextureMask;
+    use crate::operations::cutters::bitmask_wall_tops::{BitmaskWallTops, TopFace};
+    use crate::operations::format_converter::dmi_import::DmiImport;
+    use crate::operations::format_converter::dmi_merge::DmiMerge;
+    use crate::operations::format_converter::dmi_optimize::DmiOptimize;
+    use crate::operations::format_converter::dmi_rename::{DmiRename, Substitution};
+    use crate::operations::format_converter::dmi_split::DmiSplit;
+    use crate::operations::format_converter::dmi_validate::DmiValidate;
+    use crate::operations::OperationKind;
+
+    #[test]
+    fn covers_every_field() {
+        let groups: IconOperation = BitmaskSliceGroups {
+            bitmask_slice_config: BitmaskSlice::default(),
+            group_positions: GroupPositions {
+                horizontal: 0,
+                vertical: 0,
+                concave: 0,
+                flat: None,
+                concave_horizontal: None,
+                concave_vertical: None,
+                flat_horizontal: None,
+                flat_vertical: None,
+            },
+        }
+        .into();
+        let schema = config_schema();
+        let definitions = &schema["definitions"];
+        for kind in OperationKind::iter() {
+            let mode: &str = kind.into();
+            let mut operation = match kind {
+                OperationKind::BitmaskSliceGroups => groups.clone(),
+                OperationKind::BitmaskSliceGreyscale => {
+                    BitmaskSliceGreyscale {
+                        bitmask_slice_config: BitmaskSlice::default(),
+                        mask: String::new(),
+                        mask_image: None,
+                    }
+                    .into()
+                }
+                OperationKind::BitmaskTextureMask => {
+                    BitmaskTextureMask {
+                        bitmask_slice_config: BitmaskSlice::default(),
+                        texture: String::new(),
+                        shade: false,
+                        texture_image: None,
+                    }
+                    .into()
+                }
+                OperationKind::BitmaskWallTops => {
+                    BitmaskWallTops {
+                        bitmask_slice_config: BitmaskSlice::default(),
+                        top_face: TopFace::default(),
+                    }
+                    .into()
+                }
+                OperationKind::DmiOptimize => DmiOptimize { dedupe: Some(true) }.into(),
+                OperationKind::DmiImport => DmiImport::default().into(),
+                OperationKind::DmiValidate => {
+                    DmiValidate {
+                        frames: Some(1),
+                        ..Default::default()
+                    }
+                    .into()
+                }
+                OperationKind::DmiRename => {
+                    DmiRename {
+                        renames: BTreeMap::from([(String::new(), String::new())]),
+                        substitutions: vec![Substitution::default()],
+                    }
+                    .into()
+                }
+                OperationKind::DmiSplit => {
+                    DmiSplit {
+                        unmatched: Some(String::new()),
+                        ..Default::default()
+                    }
+                    .into()
+                }
+                OperationKind::DmiMerge => {
+                    DmiMerge {
+                        rename_suffix: Some(String::new()),
+                        ..Default::default()
+                    }
+                    .into()
+                }
+                // flattened structs don't record their skipped fields
+                OperationKind::BitmaskDirectionalVis => {
+                    BitmaskDirectionalVis {
+                        mask_color: Some("#FF00FF".to_string()),
+                        ..Default::default()
+                    }
+                    .into()
+                }
+                _ => IconOperation::default_for(mode).unwrap(),
+            };
+            let mut fields = field_names(&operation);
+            if operation.bitmask_slice_mut().is_some() {
+                fields.extend(field_names(&BitmaskSlice::default().into()));
+            }
+            // the tag is described alongside the operation rather than in it
+            let properties: BTreeSet<String> = definitions[mode]["properties"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .chain(iter::once("mode".to_string()))
+                .collect();
+            assert_eq!(properties, fields, "{mode}");
+        }
+    }
+
+    #[test]
+    fn picks_operation_by_mode() {
+        let schema = config_schema();
+        let modes: Vec<&str> = schema["definitions"]["IconOperation"]["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|operation| operation["properties"]["mode"]["const"].as_str().unwrap())
+            .collect();
+        assert_eq!(modes, IconOperation::MODES);
+
+        // set by templates as often as not
+        assert!(schema["definitions"]["BitmaskSliceGreyscale"]
+            .get("required")
+            .is_none());
+        assert_eq!(
+            schema["definitions"]["Animation"]["additionalProperties"],
+            false
+        );
+        assert!(schema["definitions"]["BitmaskSlice"]
+            .get("additionalProperties")
+            .is_none());
+    }
+}
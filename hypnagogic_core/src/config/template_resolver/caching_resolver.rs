@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError, RwLock};
+
+use toml::Value;
+use tracing::trace;
+
+use crate::config::template_resolver::error::TemplateResult;
+use crate::config::template_resolver::TemplateResolver;
+
+/// Remembers every template `inner` resolves, so a run over many configs only
+/// reads and parses each template once. Clones share the same cache, and it's
+/// safe to resolve through from several threads at once. Failures aren't
+/// remembered, so they're retried for the next config
+#[derive(Clone, Debug)]
+pub struct CachingResolver<R> {
+    inner: R,
+    resolved: Arc<RwLock<HashMap<String, Value>>>,
+}
+
+impl<R: TemplateResolver> CachingResolver<R> {
+    #[must_use]
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            resolved: Arc::default(),
+        }
+    }
+
+    /// The resolver being cached
+    #[must_use]
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// Forgets every template resolved so far, for every clone, so edits to
+    /// them are picked up by long running consumers
+    pub fn clear(&self) {
+        self.resolved
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+}
+
+impl<R: TemplateResolver> TemplateResolver for CachingResolver<R> {
+    fn resolve(&self, input: &str) -> TemplateResult {
+        let cached = self
+            .resolved
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(input)
+            .cloned();
+        if let Some(value) = cached {
+            trace!(template = input, "Using cached template");
+            return Ok(value);
+        }
+        // resolved without holding the lock, so a slow template doesn't hold up
+        // every other one. Two threads missing at once both resolve it, which
+        // is harmless
+        let value = self.inner.resolve(input)?;
+        self.resolved
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(input.to_string(), value.clone());
+        Ok(value)
+    }
+
+    fn available(&self) -> Vec<String> {
+        self.inner.available()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use toml::map::Map;
+
+    use super::*;
+    use crate::config::template_resolver::error::TemplateError;
+
+    #[derive(Default)]
+    struct CountingResolver {
+        calls: AtomicUsize,
+    }
+
+    impl TemplateResolver for CountingResolver {
+        fn resolve(&self, input: &str) -> TemplateResult {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if input == "missing" {
+                return Err(TemplateError::NotEmbedded(input.to_string()));
+            }
+            Ok(Value::Table(Map::new()))
+        }
+    }
+
+    #[test]
+    fn resolves_once() {
+        let counting = CountingResolver::default();
+        let resolver = CachingResolver::new(&counting);
+        let shared = resolver.clone();
+        resolver.resolve("wall").unwrap();
+        shared.resolve("wall").unwrap();
+        assert_eq!(counting.calls.load(Ordering::SeqCst), 1);
+
+        assert!(resolver.resolve("missing").is_err());
+        assert!(resolver.resolve("missing").is_err());
+        assert_eq!(counting.calls.load(Ordering::SeqCst), 3);
+
+        shared.clear();
+        resolver.resolve("wall").unwrap();
+        assert_eq!(counting.calls.load(Ordering::SeqCst), 4);
+    }
+}
@@ -0,0 +1,63 @@
+use tracing::debug;
+
+use crate::config::template_resolver::error::{TemplateError, TemplateResult};
+use crate::config::template_resolver::{parse_template, TemplateResolver};
+
+/// The standard templates, built in to the binary so configs using them work
+/// without a templates folder. Keyed by the same name they'd be referenced by
+/// in the templates folder
+pub const EMBEDDED_TEMPLATES: &[(&str, &str)] = &[
+    (
+        "bitmask/slice-32x32",
+        include_str!("../../../../templates/bitmask/slice-32x32.toml"),
+    ),
+    (
+        "bitmask/slice-32x32-diagonals",
+        include_str!("../../../../templates/bitmask/slice-32x32-diagonals.toml"),
+    ),
+    (
+        "bitmask/slice-tallwalls",
+        include_str!("../../../../templates/bitmask/slice-tallwalls.toml"),
+    ),
+    (
+        "bitmask/slice-tallwalls-directionalvis",
+        include_str!("../../../../templates/bitmask/slice-tallwalls-directionalvis.toml"),
+    ),
+];
+
+/// Serves the templates in [`EMBEDDED_TEMPLATES`]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct EmbeddedResolver;
+
+impl TemplateResolver for EmbeddedResolver {
+    fn resolve(&self, input: &str) -> TemplateResult {
+        let Some((_, text)) = EMBEDDED_TEMPLATES.iter().find(|(name, _)| *name == input) else {
+            return Err(TemplateError::NotEmbedded(input.to_string()));
+        };
+        debug!(template = input, "Using embedded template");
+        parse_template(input, text)
+    }
+
+    fn available(&self) -> Vec<String> {
+        EMBEDDED_TEMPLATES
+            .iter()
+            .map(|(name, _)| (*name).to_string())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn embedded_templates_parse() {
+        for (name, _) in EMBEDDED_TEMPLATES {
+            EmbeddedResolver.resolve(name).unwrap();
+        }
+        assert!(matches!(
+            EmbeddedResolver.resolve("bitmask/nonexistent"),
+            Err(TemplateError::NotEmbedded(_))
+        ));
+    }
+}
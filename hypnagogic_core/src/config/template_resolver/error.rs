@@ -3,12 +3,37 @@ use std::path::PathBuf;
 use thiserror::Error;
 use toml::Value;
 
+use crate::config::error::ParseError;
+
 #[derive(Debug, Error)]
 pub enum TemplateError {
     #[error("Template dir not found while creating FileResolver {0}")]
     NoTemplateDir(PathBuf),
     #[error("Failed to find template: `{0}`, expected `{1}`")]
     FailedToFindTemplate(String, PathBuf),
+    #[error("`{0}` isn't a built in template")]
+    NotEmbedded(String),
+    #[error("Template `{0}` isn't cached at `{1}`, and network access is disabled")]
+    NotCached(String, PathBuf),
+    #[error("Failed to fetch template `{0}`: {1}")]
+    FetchFailed(String, String),
+    #[error("Template `{0}` would be read from outside its template pack")]
+    OutsidePack(String),
+    #[error("Templates use each other in a loop: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+    #[error("Templates are nested more than {0} deep")]
+    TooDeep(usize),
+    #[error(
+        "Template `{template}` doesn't match its pinned checksum, expected {expected} but got \
+         {actual}"
+    )]
+    ChecksumMismatch {
+        template: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("Error while parsing template `{template}` {error}")]
+    Parse { template: String, error: ParseError },
     #[error("Generic toml parse error while resolving template: {0}")]
     TOMLError(#[from] toml::de::Error),
     #[error("Generic IO Error when attempting to resolve template: {0}")]
@@ -3,11 +3,10 @@ use core::result::Result::{Err, Ok};
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use toml::Value;
 use tracing::{debug, trace};
 
 use crate::config::template_resolver::error::{TemplateError, TemplateResult};
-use crate::config::template_resolver::TemplateResolver;
+use crate::config::template_resolver::{parse_template, TemplateResolver};
 use crate::config::DEFAULT_TEMPLATE_LOCATION;
 
 /// Loads templates from a folder on the filesystem.
@@ -56,8 +55,32 @@ impl TemplateResolver for FileResolver {
         trace!("Found template at {:?}", pathbuf);
 
         let toml_string = fs::read_to_string(pathbuf.as_path())?;
-        let deserialized: Value = toml::from_str(&toml_string)?;
+        let deserialized = parse_template(input, &toml_string)?;
         debug!(deserialized = ?deserialized, "Deserialized template");
         Ok(deserialized)
     }
+
+    fn available(&self) -> Vec<String> {
+        let mut available = vec![];
+        list_templates(&self.path, "", &mut available);
+        available.sort();
+        available
+    }
+}
+
+/// Adds the name of every template under `dir` to `out`, prefixed with
+/// `prefix`
+pub(crate) fn list_templates(dir: &Path, prefix: &str, out: &mut Vec<String>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if path.is_dir() {
+            list_templates(&path, &format!("{prefix}{name}/"), out);
+        } else if let Some(stem) = name.strip_suffix(".toml") {
+            out.push(format!("{prefix}{stem}"));
+        }
+    }
 }
@@ -1,10 +1,18 @@
 use toml::map::Map;
 use toml::Value;
 
-use crate::config::template_resolver::error::TemplateResult;
+use crate::config::error::parse_toml;
+use crate::config::template_resolver::error::{TemplateError, TemplateResult};
+use crate::util::edit_distance;
 
+pub mod caching_resolver;
+pub mod embedded_resolver;
 pub mod error;
 pub mod file_resolver;
+#[cfg(feature = "remote")]
+pub mod network;
+#[cfg(feature = "remote")]
+pub mod remote_resolver;
 
 pub trait TemplateResolver {
     /// Determines how exactly to resolve template strings. Primarily for the
@@ -12,6 +20,61 @@ pub trait TemplateResolver {
     /// # Errors
     /// Throws an error if resolution fails
     fn resolve(&self, input: &str) -> TemplateResult;
+
+    /// Names of every template this can resolve, for suggesting alternatives
+    /// when one isn't found. Resolvers that can't list their templates have
+    /// none
+    fn available(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+impl<T: TemplateResolver + ?Sized> TemplateResolver for &T {
+    fn resolve(&self, input: &str) -> TemplateResult {
+        (**self).resolve(input)
+    }
+
+    fn available(&self) -> Vec<String> {
+        (**self).available()
+    }
+}
+
+/// Parses the text of the template called `template`, locating any error in
+/// it
+/// # Errors
+/// Errors if `text` isn't valid toml
+pub(crate) fn parse_template(template: &str, text: &str) -> TemplateResult {
+    parse_toml(text).map_err(|error| {
+        TemplateError::Parse {
+            template: template.to_string(),
+            error,
+        }
+    })
+}
+
+/// Most suggestions [`closest_templates`] makes
+const MAX_SUGGESTIONS: usize = 3;
+
+/// The names in `available` closest to `name`, closest first. Names match on
+/// their last segment too, so `slice-32x32` suggests `bitmask/slice-32x32`.
+/// Anything too different to plausibly be a typo is left out
+#[must_use]
+pub fn closest_templates(name: &str, available: &[String]) -> Vec<String> {
+    let threshold = (name.chars().count() / 4).max(2);
+    let mut scored: Vec<(usize, &String)> = available
+        .iter()
+        .filter_map(|candidate| {
+            let last_segment = candidate.rsplit('/').next().unwrap_or(candidate);
+            let distance = edit_distance(name, candidate).min(edit_distance(name, last_segment));
+            (distance <= threshold).then_some((distance, candidate))
+        })
+        .collect();
+    scored.sort();
+    scored
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate.clone())
+        .collect()
 }
 
 /// Simple resolver that always returns default templatedconfig
@@ -25,3 +88,63 @@ impl TemplateResolver for NullResolver {
         Ok(Value::Table(Map::new()))
     }
 }
+
+/// Tries `primary` first, falling back to `fallback` for templates `primary`
+/// can't find. If neither has it, `primary`'s error is returned
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct FallbackResolver<P, F> {
+    pub primary: P,
+    pub fallback: F,
+}
+
+impl<P: TemplateResolver, F: TemplateResolver> TemplateResolver for FallbackResolver<P, F> {
+    fn resolve(&self, input: &str) -> TemplateResult {
+        match self.primary.resolve(input) {
+            Err(
+                error @ (TemplateError::FailedToFindTemplate(..) | TemplateError::NotEmbedded(_)),
+            ) => {
+                self.fallback.resolve(input).map_err(|fallback_error| {
+                    match fallback_error {
+                        TemplateError::FailedToFindTemplate(..) | TemplateError::NotEmbedded(_) => {
+                            error
+                        }
+                        other => other,
+                    }
+                })
+            }
+            result => result,
+        }
+    }
+
+    fn available(&self) -> Vec<String> {
+        let mut available = self.primary.available();
+        available.extend(self.fallback.available());
+        available.sort();
+        available.dedup();
+        available
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn suggestions() {
+        let available = [
+            "bitmask/slice-32x32",
+            "bitmask/slice-32x32-diagonals",
+            "bitmask/slice-tallwalls",
+        ]
+        .map(String::from);
+        assert_eq!(
+            closest_templates("bitmask/slice-23x32", &available),
+            vec!["bitmask/slice-32x32"]
+        );
+        assert_eq!(
+            closest_templates("slice-tallwall", &available),
+            vec!["bitmask/slice-tallwalls"]
+        );
+        assert!(closest_templates("window", &available).is_empty());
+    }
+}
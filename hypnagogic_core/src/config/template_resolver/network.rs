@@ -0,0 +1,196 @@
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+use toml::Value;
+use tracing::{debug, warn};
+
+use crate::config::template_resolver::error::{TemplateError, TemplateResult};
+use crate::util::file_safe_name;
+
+/// Environment variable that turns on offline mode when set to anything but
+/// `0` or `false`
+pub const OFFLINE_ENV: &str = "HYPNAGOGIC_OFFLINE";
+
+/// How resolvers that reach over the network are allowed to. Anything network
+/// capable should take one of these rather than reading the environment
+/// itself, so frontends can override it
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct NetworkSettings {
+    /// Never touch the network, only use what's already cached
+    pub offline: bool,
+    /// Proxy url to send requests through
+    pub proxy: Option<String>,
+    /// Hosts that skip the proxy, from `NO_PROXY`. A leading `.` matches
+    /// every subdomain, and `*` matches everything
+    pub no_proxy: Vec<String>,
+}
+
+impl NetworkSettings {
+    /// Reads settings from the environment, following the usual proxy
+    /// variable conventions (`HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY` and
+    /// `NO_PROXY`, in either case) along with [`OFFLINE_ENV`]
+    #[must_use]
+    pub fn from_env() -> Self {
+        let var = |name: &str| {
+            env::var(name)
+                .or_else(|_| env::var(name.to_lowercase()))
+                .ok()
+                .filter(|value| !value.is_empty())
+        };
+        let offline = var(OFFLINE_ENV).is_some_and(|value| value != "0" && value != "false");
+        let proxy = var("HTTPS_PROXY")
+            .or_else(|| var("HTTP_PROXY"))
+            .or_else(|| var("ALL_PROXY"));
+        let no_proxy = var("NO_PROXY")
+            .map(|hosts| {
+                hosts
+                    .split(',')
+                    .map(|host| host.trim().to_string())
+                    .filter(|host| !host.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            offline,
+            proxy,
+            no_proxy,
+        }
+    }
+
+    /// The proxy to use for requests to `host`, if any
+    #[must_use]
+    pub fn proxy_for(&self, host: &str) -> Option<&str> {
+        let bypassed = self.no_proxy.iter().any(|pattern| {
+            pattern == "*"
+                || pattern == host
+                || pattern
+                    .strip_prefix('.')
+                    .is_some_and(|domain| host == domain || host.ends_with(pattern.as_str()))
+        });
+        if bypassed {
+            None
+        } else {
+            self.proxy.as_deref()
+        }
+    }
+}
+
+/// On disk cache of templates fetched over the network, so they can still be
+/// resolved offline
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TemplateCache {
+    dir: PathBuf,
+}
+
+impl TemplateCache {
+    #[must_use]
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// Where the template cached under `key` lives
+    #[must_use]
+    pub fn path_for(&self, key: &str) -> PathBuf {
+        self.dir.join(file_safe_name(key)).with_extension("toml")
+    }
+
+    /// Resolves the template cached under `key`. Online, `fetch` is called
+    /// with the proxy to use and its result is cached, falling back to the
+    /// cache if it fails. Offline, only the cache is used
+    /// # Errors
+    /// Errors if the template isn't cached when offline, or can't be fetched
+    /// or read from the cache when online
+    pub fn resolve(
+        &self,
+        key: &str,
+        host: &str,
+        settings: &NetworkSettings,
+        fetch: impl FnOnce(Option<&str>) -> Result<String, TemplateError>,
+    ) -> TemplateResult {
+        Ok(toml::from_str(
+            &self.resolve_text(key, host, settings, fetch)?,
+        )?)
+    }
+
+    /// Same as [`TemplateCache::resolve`], returning the template's text
+    /// instead of parsing it. Fetched text is checked to be valid toml before
+    /// it's cached
+    /// # Errors
+    /// Errors if the template isn't cached when offline, or can't be fetched
+    /// or read from the cache when online
+    pub fn resolve_text(
+        &self,
+        key: &str,
+        host: &str,
+        settings: &NetworkSettings,
+        fetch: impl FnOnce(Option<&str>) -> Result<String, TemplateError>,
+    ) -> Result<String, TemplateError> {
+        let path = self.path_for(key);
+        if settings.offline {
+            if !path.exists() {
+                return Err(TemplateError::NotCached(key.to_string(), path));
+            }
+            debug!(key, path = ?path, "Offline, using cached template");
+            return Ok(fs::read_to_string(&path)?);
+        }
+        match fetch(settings.proxy_for(host)) {
+            Ok(text) => {
+                toml::from_str::<Value>(&text)?;
+                fs::create_dir_all(&self.dir)?;
+                fs::write(&path, &text)?;
+                Ok(text)
+            }
+            Err(error) if path.exists() => {
+                warn!(key, error = %error, "Failed to fetch template, using the cached copy");
+                Ok(fs::read_to_string(&path)?)
+            }
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn no_proxy_hosts() {
+        let settings = NetworkSettings {
+            offline: false,
+            proxy: Some("http://proxy:3128".to_string()),
+            no_proxy: vec!["localhost".to_string(), ".internal".to_string()],
+        };
+        assert_eq!(settings.proxy_for("example.com"), Some("http://proxy:3128"));
+        assert_eq!(settings.proxy_for("localhost"), None);
+        assert_eq!(settings.proxy_for("templates.internal"), None);
+    }
+
+    #[test]
+    fn offline_needs_cache() {
+        let dir = env::temp_dir().join(format!("hypnagogic-cache-test-{}", std::process::id()));
+        let cache = TemplateCache::new(&dir);
+        let offline = NetworkSettings {
+            offline: true,
+            ..Default::default()
+        };
+        let fetch = |_: Option<&str>| -> Result<String, TemplateError> {
+            panic!("Offline resolution shouldn't fetch")
+        };
+        assert!(matches!(
+            cache.resolve("walls", "example.com", &offline, fetch),
+            Err(TemplateError::NotCached(..))
+        ));
+
+        let online = NetworkSettings::default();
+        cache
+            .resolve("walls", "example.com", &online, |_| Ok("x = 1".to_string()))
+            .unwrap();
+        let cached = cache
+            .resolve("walls", "example.com", &offline, fetch)
+            .unwrap();
+        assert_eq!(cached.get("x").and_then(Value::as_integer), Some(1));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+
+use crate::config::template_resolver::error::{TemplateError, TemplateResult};
+use crate::config::template_resolver::file_resolver::list_templates;
+use crate::config::template_resolver::network::{NetworkSettings, TemplateCache};
+use crate::config::template_resolver::{parse_template, TemplateResolver};
+use crate::config::workspace::{RemoteTemplates, TemplateSource};
+use crate::util::file_safe_name;
+
+/// Resolves templates from a [`RemoteTemplates`] pack, caching them under
+/// `cache_dir` so they still resolve offline. Each template is only fetched
+/// once per resolver, however many configs use it
+#[derive(Clone, Debug)]
+pub struct RemoteResolver {
+    remote: RemoteTemplates,
+    cache_dir: PathBuf,
+    settings: NetworkSettings,
+    fetched: Arc<Mutex<HashMap<String, String>>>,
+    checkout: Arc<Mutex<Option<PathBuf>>>,
+}
+
+impl RemoteResolver {
+    #[must_use]
+    pub fn new(remote: RemoteTemplates, cache_dir: &Path, settings: NetworkSettings) -> Self {
+        Self {
+            remote,
+            cache_dir: cache_dir.to_path_buf(),
+            settings,
+            fetched: Arc::default(),
+            checkout: Arc::default(),
+        }
+    }
+
+    fn resolve_http(&self, url: &str, name: &str) -> Result<String, TemplateError> {
+        let mut fetched = self.fetched.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(text) = fetched.get(name) {
+            return Ok(text.clone());
+        }
+        let url = url.trim_end_matches('/');
+        let template_url = format!("{url}/{name}.toml");
+        let cache = TemplateCache::new(&self.cache_dir.join("http"));
+        // templates the pack doesn't have are remembered, so they can fall
+        // through to other resolvers without the network too
+        let missing = cache.path_for(&template_url).with_extension("missing");
+        let resolved = cache.resolve_text(&template_url, host_of(url), &self.settings, |proxy| {
+            fetch_http(&template_url, proxy, name)
+        });
+        let text = match resolved {
+            Ok(text) => text,
+            Err(error @ TemplateError::FailedToFindTemplate(..)) => {
+                fs::create_dir_all(self.cache_dir.join("http"))?;
+                fs::write(&missing, "")?;
+                return Err(error);
+            }
+            Err(TemplateError::FetchFailed(..) | TemplateError::NotCached(..))
+                if missing.exists() =>
+            {
+                return Err(TemplateError::FailedToFindTemplate(
+                    name.to_string(),
+                    PathBuf::from(template_url),
+                ));
+            }
+            Err(error) => return Err(error),
+        };
+        if missing.exists() {
+            fs::remove_file(&missing)?;
+        }
+        fetched.insert(name.to_string(), text.clone());
+        Ok(text)
+    }
+
+    fn resolve_git(
+        &self,
+        repository: &str,
+        rev: Option<&str>,
+        dir: Option<&str>,
+        name: &str,
+    ) -> Result<String, TemplateError> {
+        // names are joined on to the checkout, so one like `../secrets` could
+        // read anything
+        if !Path::new(name)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(TemplateError::OutsidePack(name.to_string()));
+        }
+        let checkout = self.checkout(repository, rev)?;
+        let path = checkout
+            .join(dir.unwrap_or(""))
+            .join(name)
+            .with_extension("toml");
+        if !path.exists() {
+            return Err(TemplateError::FailedToFindTemplate(name.to_string(), path));
+        }
+        Ok(fs::read_to_string(path)?)
+    }
+
+    /// Clones `repository` in to the cache if it isn't already, and checks out
+    /// `rev`. Unpinned checkouts are updated once per resolver when online
+    fn checkout(&self, repository: &str, rev: Option<&str>) -> Result<PathBuf, TemplateError> {
+        let mut checkout = self.checkout.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(path) = checkout.as_ref() {
+            return Ok(path.clone());
+        }
+        let key = format!("{repository}@{}", rev.unwrap_or("HEAD"));
+        // git would take these as options rather than what to fetch
+        if repository.starts_with('-') || rev.is_some_and(|rev| rev.starts_with('-')) {
+            return Err(TemplateError::FetchFailed(
+                key,
+                "the repository and rev can't start with `-`".to_string(),
+            ));
+        }
+        let path = self.cache_dir.join("git").join(file_safe_name(&key));
+        let proxy = self.settings.proxy_for(host_of(repository));
+        let exists = path.join(".git").exists();
+        if self.settings.offline {
+            if !exists {
+                return Err(TemplateError::NotCached(key, path));
+            }
+        } else if !exists {
+            debug!(repository, rev, "Cloning template pack");
+            fs::create_dir_all(self.cache_dir.join("git"))?;
+            run_git(
+                &key,
+                proxy,
+                None,
+                &[
+                    "clone",
+                    "--quiet",
+                    "--",
+                    repository,
+                    &path.display().to_string(),
+                ],
+            )?;
+            if let Some(rev) = rev {
+                checkout_rev(&key, &path, rev)?;
+            }
+        } else if !rev.is_some_and(is_commit_hash) {
+            // fetching the rev itself rather than checking out `origin/<rev>`
+            // works for tags as well as branches
+            let updated = run_git(
+                &key,
+                proxy,
+                Some(&path),
+                &["fetch", "--quiet", "origin", rev.unwrap_or("HEAD")],
+            )
+            .and_then(|()| checkout_rev(&key, &path, "FETCH_HEAD"));
+            if let Err(error) = updated {
+                warn!(repository, error = %error, "Failed to update template pack, using the cached copy");
+            }
+        }
+        *checkout = Some(path.clone());
+        Ok(path)
+    }
+}
+
+impl TemplateResolver for RemoteResolver {
+    #[tracing::instrument(skip(self))]
+    fn resolve(&self, input: &str) -> TemplateResult {
+        let text = match &self.remote.source {
+            TemplateSource::Http { url } => self.resolve_http(url, input)?,
+            TemplateSource::Git { git, rev, dir } => {
+                self.resolve_git(git, rev.as_deref(), dir.as_deref(), input)?
+            }
+        };
+        if let Some(expected) = self.remote.checksums.get(input) {
+            let actual = sha256_hex(&text);
+            if !actual.eq_ignore_ascii_case(expected) {
+                return Err(TemplateError::ChecksumMismatch {
+                    template: input.to_string(),
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+        }
+        let deserialized = parse_template(input, &text)?;
+        debug!(deserialized = ?deserialized, "Deserialized remote template");
+        Ok(deserialized)
+    }
+
+    /// Pinned templates, and everything in the checkout of a git pack once
+    /// it's been checked out. Http packs can't be listed beyond what's pinned
+    fn available(&self) -> Vec<String> {
+        let mut available: Vec<String> = self.remote.checksums.keys().cloned().collect();
+        if let TemplateSource::Git { dir, .. } = &self.remote.source {
+            let checkout = self.checkout.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(path) = checkout.as_ref() {
+                list_templates(&path.join(dir.as_deref().unwrap_or("")), "", &mut available);
+            }
+        }
+        available.sort();
+        available.dedup();
+        available
+    }
+}
+
+/// Lowercase hex sha256 of `text`, the format checksums are pinned in
+#[must_use]
+pub fn sha256_hex(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// Host part of a url, for working out whether to skip the proxy
+fn host_of(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split('/').next().unwrap_or(rest);
+    let authority = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    authority.split(':').next().unwrap_or(authority)
+}
+
+fn is_commit_hash(rev: &str) -> bool {
+    rev.len() == 40 && rev.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn fetch_http(url: &str, proxy: Option<&str>, name: &str) -> Result<String, TemplateError> {
+    let failed = |reason: String| TemplateError::FetchFailed(name.to_string(), reason);
+    let mut builder = ureq::AgentBuilder::new().timeout(Duration::from_secs(30));
+    if let Some(proxy) = proxy {
+        builder =
+            builder.proxy(ureq::Proxy::new(proxy).map_err(|error| failed(error.to_string()))?);
+    }
+    match builder.build().get(url).call() {
+        Ok(response) => Ok(response.into_string()?),
+        Err(ureq::Error::Status(404, _)) => {
+            Err(TemplateError::FailedToFindTemplate(
+                name.to_string(),
+                PathBuf::from(url),
+            ))
+        }
+        Err(error) => Err(failed(error.to_string())),
+    }
+}
+
+/// Checks out `rev` in the checkout at `path`. The `--` keeps a rev that's
+/// also the name of a file from being taken as one
+fn checkout_rev(key: &str, path: &Path, rev: &str) -> Result<(), TemplateError> {
+    run_git(key, None, Some(path), &["checkout", "--quiet", rev, "--"])
+}
+
+fn run_git(
+    key: &str,
+    proxy: Option<&str>,
+    dir: Option<&Path>,
+    args: &[&str],
+) -> Result<(), TemplateError> {
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.arg("-C").arg(dir);
+    }
+    if let Some(proxy) = proxy {
+        command.arg("-c").arg(format!("http.proxy={proxy}"));
+    }
+    let output = command.args(args).output().map_err(|error| {
+        TemplateError::FetchFailed(key.to_string(), format!("couldn't run git: {error}"))
+    })?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(TemplateError::FetchFailed(
+            key.to_string(),
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use toml::Value;
+
+    use super::*;
+
+    #[test]
+    fn source_from_toml() {
+        let remote: RemoteTemplates = toml::from_str(
+            r#"
+            git = "https://example.com/templates.git"
+            rev = "v1"
+            [checksums]
+            "bitmask/slice-32x32" = "abc"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            remote.source,
+            TemplateSource::Git {
+                git: "https://example.com/templates.git".to_string(),
+                rev: Some("v1".to_string()),
+                dir: None,
+            }
+        );
+        assert_eq!(host_of("https://user@example.com:8080/a/b"), "example.com");
+    }
+
+    #[test]
+    fn checksum_pinning() {
+        let dir =
+            std::env::temp_dir().join(format!("hypnagogic-remote-test-{}", std::process::id()));
+        let repository = dir.join("pack");
+        fs::create_dir_all(repository.join("bitmask")).unwrap();
+        fs::write(repository.join("bitmask/wall.toml"), "x = 1\n").unwrap();
+        let git = |args: &[&str]| run_git("test", None, Some(&repository), args).unwrap();
+        git(&["init", "--quiet"]);
+        git(&["add", "."]);
+        git(&[
+            "-c",
+            "user.name=test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "--quiet",
+            "-m",
+            "templates",
+        ]);
+
+        let mut remote = RemoteTemplates {
+            source: TemplateSource::Git {
+                git: repository.display().to_string(),
+                rev: None,
+                dir: None,
+            },
+            checksums: BTreeMap::new(),
+        };
+        remote
+            .checksums
+            .insert("bitmask/wall".to_string(), sha256_hex("x = 1\n"));
+        let resolver = RemoteResolver::new(
+            remote.clone(),
+            &dir.join("cache"),
+            NetworkSettings::default(),
+        );
+        let value = resolver.resolve("bitmask/wall").unwrap();
+        assert_eq!(value.get("x").and_then(Value::as_integer), Some(1));
+        assert!(matches!(
+            resolver.resolve("bitmask/missing"),
+            Err(TemplateError::FailedToFindTemplate(..))
+        ));
+
+        remote
+            .checksums
+            .insert("bitmask/wall".to_string(), sha256_hex("x = 2\n"));
+        let offline = NetworkSettings {
+            offline: true,
+            ..Default::default()
+        };
+        let resolver = RemoteResolver::new(remote, &dir.join("cache"), offline);
+        assert!(matches!(
+            resolver.resolve("bitmask/wall"),
+            Err(TemplateError::ChecksumMismatch { .. })
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn updates_tags() {
+        let dir = std::env::temp_dir().join(format!("hypnagogic-tag-test-{}", std::process::id()));
+        let repository = dir.join("pack");
+        fs::create_dir_all(&repository).unwrap();
+        let git = |args: &[&str]| run_git("test", None, Some(&repository), args).unwrap();
+        let commit = |text: &str| {
+            fs::write(repository.join("wall.toml"), text).unwrap();
+            git(&["add", "."]);
+            git(&[
+                "-c",
+                "user.name=test",
+                "-c",
+                "user.email=test@example.com",
+                "commit",
+                "--quiet",
+                "-m",
+                "templates",
+            ]);
+            git(&["tag", "--force", "v1"]);
+        };
+        git(&["init", "--quiet"]);
+        commit("x = 1\n");
+
+        let remote = |git: &str, rev: &str| {
+            RemoteTemplates {
+                source: TemplateSource::Git {
+                    git: git.to_string(),
+                    rev: Some(rev.to_string()),
+                    dir: None,
+                },
+                checksums: BTreeMap::new(),
+            }
+        };
+        let pack = remote(&repository.display().to_string(), "v1");
+        let resolve = |pack: RemoteTemplates, name: &str| {
+            RemoteResolver::new(pack, &dir.join("cache"), NetworkSettings::default()).resolve(name)
+        };
+        let x = |value: Value| value.get("x").and_then(Value::as_integer);
+        assert_eq!(x(resolve(pack.clone(), "wall").unwrap()), Some(1));
+        // the tag moving is picked up by the next run
+        commit("x = 2\n");
+        assert_eq!(x(resolve(pack.clone(), "wall").unwrap()), Some(2));
+
+        assert!(matches!(
+            resolve(pack, "../pack/wall"),
+            Err(TemplateError::OutsidePack(..))
+        ));
+        assert!(matches!(
+            resolve(remote("--upload-pack=touch", "v1"), "wall"),
+            Err(TemplateError::FetchFailed(..))
+        ));
+        assert!(matches!(
+            resolve(
+                remote(&repository.display().to_string(), "--orphan"),
+                "wall"
+            ),
+            Err(TemplateError::FetchFailed(..))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,347 @@
+//! Finding keys a config sets that nothing reads, so typos like `anmation`
+//! can be reported instead of silently ignored
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::ser::{self, Impossible, Serialize};
+use toml::Value;
+
+use crate::config::key_sources::{for_each_value, KeySource, KeySources};
+use crate::operations::IconOperation;
+use crate::util::edit_distance;
+
+/// A key that was set but isn't read by the operation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownKey {
+    /// Dotted path of the key, like `animation.delay`
+    pub path: String,
+    /// The config or template that set it
+    pub source: KeySource,
+    /// The closest key that would have been read, as a full dotted path
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for UnknownKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}`, set by {}, isn't a known key",
+            self.path, self.source
+        )?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, ", did you mean `{suggestion}`?")?;
+        }
+        Ok(())
+    }
+}
+
+/// Every value of the resolved `config` that `operation`, deserialized from
+/// it, doesn't read
+pub(crate) fn find_unknown_keys(
+    config: &Value,
+    sources: &KeySources,
+    operation: &IconOperation,
+) -> Vec<UnknownKey> {
+    let Ok(read) = Value::try_from(operation) else {
+        return vec![];
+    };
+    let fields = operation.serialize(FieldRecorder).unwrap_or_default();
+
+    let mut unknown: Vec<UnknownKey> = vec![];
+    for_each_value(config, String::new(), &mut |path, _| {
+        // an unknown table is reported once, rather than once for every
+        // value in it
+        let mut value = &read;
+        let mut length = 0;
+        for key in path.split('.') {
+            length += key.len();
+            match value.get(key) {
+                Some(inner) => value = inner,
+                None => break,
+            }
+            length += 1;
+        }
+        if length > path.len() {
+            return;
+        }
+        let path = path[..length].to_string();
+        if unknown.iter().any(|key| key.path == path) {
+            return;
+        }
+        unknown.push(UnknownKey {
+            suggestion: fields.suggest(&path),
+            source: sources.get(&path).cloned().unwrap_or(KeySource::Config),
+            path,
+        });
+    });
+    unknown
+}
+
+/// Top level keys `operation` reads, including ones it doesn't set
+#[cfg(test)]
+pub(crate) fn field_names(operation: &IconOperation) -> std::collections::BTreeSet<String> {
+    operation
+        .serialize(FieldRecorder)
+        .unwrap_or_default()
+        .0
+        .into_keys()
+        .collect()
+}
+
+/// Every field an operation has, including ones that are skipped when
+/// serializing because they aren't set
+#[derive(Clone, Debug, Default)]
+struct Fields(BTreeMap<String, Fields>);
+
+impl Fields {
+    /// The closest known key to `path`, keeping every part of the path that
+    /// is known and correcting the first that isn't
+    fn suggest(&self, path: &str) -> Option<String> {
+        let mut fields = self;
+        let mut known = vec![];
+        for key in path.split('.') {
+            if let Some(inner) = fields.0.get(key) {
+                known.push(key.to_string());
+                fields = inner;
+                continue;
+            }
+            let threshold = (key.chars().count() / 3).max(2);
+            let (distance, closest) = fields
+                .0
+                .keys()
+                .map(|candidate| (edit_distance(key, candidate), candidate))
+                .min()?;
+            if distance > threshold {
+                return None;
+            }
+            known.push(closest.clone());
+            return Some(known.join("."));
+        }
+        None
+    }
+}
+
+/// Records the name of every struct field and map key serialized, along with
+/// the fields of whatever they hold. Values themselves are thrown away
+struct FieldRecorder;
+
+type RecordResult = Result<Fields, toml::ser::Error>;
+
+impl ser::Serializer for FieldRecorder {
+    type Error = toml::ser::Error;
+    type Ok = Fields;
+    type SerializeMap = FieldMap;
+    type SerializeSeq = Impossible<Fields, toml::ser::Error>;
+    type SerializeStruct = FieldMap;
+    type SerializeStructVariant = Impossible<Fields, toml::ser::Error>;
+    type SerializeTuple = Impossible<Fields, toml::ser::Error>;
+    type SerializeTupleStruct = Impossible<Fields, toml::ser::Error>;
+    type SerializeTupleVariant = Impossible<Fields, toml::ser::Error>;
+
+    fn serialize_bool(self, _: bool) -> RecordResult {
+        Ok(Fields::default())
+    }
+
+    fn serialize_i8(self, _: i8) -> RecordResult {
+        Ok(Fields::default())
+    }
+
+    fn serialize_i16(self, _: i16) -> RecordResult {
+        Ok(Fields::default())
+    }
+
+    fn serialize_i32(self, _: i32) -> RecordResult {
+        Ok(Fields::default())
+    }
+
+    fn serialize_i64(self, _: i64) -> RecordResult {
+        Ok(Fields::default())
+    }
+
+    fn serialize_u8(self, _: u8) -> RecordResult {
+        Ok(Fields::default())
+    }
+
+    fn serialize_u16(self, _: u16) -> RecordResult {
+        Ok(Fields::default())
+    }
+
+    fn serialize_u32(self, _: u32) -> RecordResult {
+        Ok(Fields::default())
+    }
+
+    fn serialize_u64(self, _: u64) -> RecordResult {
+        Ok(Fields::default())
+    }
+
+    fn serialize_f32(self, _: f32) -> RecordResult {
+        Ok(Fields::default())
+    }
+
+    fn serialize_f64(self, _: f64) -> RecordResult {
+        Ok(Fields::default())
+    }
+
+    fn serialize_char(self, _: char) -> RecordResult {
+        Ok(Fields::default())
+    }
+
+    fn serialize_str(self, _: &str) -> RecordResult {
+        Ok(Fields::default())
+    }
+
+    fn serialize_bytes(self, _: &[u8]) -> RecordResult {
+        Ok(Fields::default())
+    }
+
+    fn serialize_none(self) -> RecordResult {
+        Ok(Fields::default())
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> RecordResult {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> RecordResult {
+        Ok(Fields::default())
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> RecordResult {
+        Ok(Fields::default())
+    }
+
+    fn serialize_unit_variant(self, _: &'static str, _: u32, _: &'static str) -> RecordResult {
+        Ok(Fields::default())
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> RecordResult {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        value: &T,
+    ) -> RecordResult {
+        value.serialize(self)
+    }
+
+    // arrays are values as far as config keys go, so whatever they hold
+    // doesn't matter
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Err(ser::Error::custom("array"))
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Err(ser::Error::custom("array"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Err(ser::Error::custom("array"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(ser::Error::custom("array"))
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Ok(FieldMap::default())
+    }
+
+    fn serialize_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Ok(FieldMap::default())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(ser::Error::custom("struct variant"))
+    }
+}
+
+#[derive(Default)]
+struct FieldMap {
+    fields: Fields,
+    key: Option<String>,
+}
+
+impl FieldMap {
+    fn record<T: ?Sized + Serialize>(&mut self, key: String, value: &T) {
+        let inner = value.serialize(FieldRecorder).unwrap_or_default();
+        self.fields.0.insert(key, inner);
+    }
+}
+
+impl ser::SerializeMap for FieldMap {
+    type Error = toml::ser::Error;
+    type Ok = Fields;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Self::Error> {
+        self.key = Some(match Value::try_from(key)? {
+            Value::String(key) => key,
+            key => key.to_string(),
+        });
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| ser::Error::custom("map value without a key"))?;
+        self.record(key, value);
+        Ok(())
+    }
+
+    fn end(self) -> RecordResult {
+        Ok(self.fields)
+    }
+}
+
+impl ser::SerializeStruct for FieldMap {
+    type Error = toml::ser::Error;
+    type Ok = Fields;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.record(key.to_string(), value);
+        Ok(())
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), Self::Error> {
+        self.fields.0.insert(key.to_string(), Fields::default());
+        Ok(())
+    }
+
+    fn end(self) -> RecordResult {
+        Ok(self.fields)
+    }
+}
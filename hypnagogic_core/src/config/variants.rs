@@ -0,0 +1,162 @@
+//! Variants of a config, each cut from the same input with some of its keys
+//! changed, like a recolored wall set that only needs a different map icon
+
+use serde::Deserialize;
+use toml::Value;
+
+use crate::config::error::{ConfigError, ConfigResult};
+use crate::config::hsv::{take_hsv, HsvShift, HSV_KEY};
+use crate::config::key_sources::{KeySource, KeySources};
+use crate::config::unknown_keys::{find_unknown_keys, UnknownKey};
+use crate::operations::IconOperation;
+use crate::util::deep_merge_toml;
+
+/// Key of the table a config declares its variants in, each a table of keys
+/// merged over the rest of the config, like
+/// `[variants.red]` with `map_icon.base_color = "#FF0000"` under it
+pub const VARIANTS_KEY: &str = "variants";
+
+/// A variant of a config, output alongside it named `<output>-<name>`
+#[derive(Clone, PartialEq, Debug)]
+pub struct Variant {
+    pub name: String,
+    pub operation: IconOperation,
+    /// Shift made to the input before it's cut, with the variant's own
+    /// [`HSV_KEY`] merged over the config's
+    pub hsv: Option<HsvShift>,
+}
+
+/// Takes the variants out of a resolved config, building each by merging its
+/// keys over what's left. Also returns the keys variants set that their
+/// operation doesn't read
+pub(crate) fn take_variants(value: &mut Value) -> ConfigResult<(Vec<Variant>, Vec<UnknownKey>)> {
+    let Value::Table(table) = value else {
+        return Ok((vec![], vec![]));
+    };
+    let variants = match table.remove(VARIANTS_KEY) {
+        Some(Value::Table(variants)) => variants,
+        Some(_) => {
+            return Err(ConfigError::Config(format!(
+                "`{VARIANTS_KEY}` must be a table of variants, each a table of keys to change"
+            )))
+        }
+        None => return Ok((vec![], vec![])),
+    };
+
+    let mut out = vec![];
+    let mut unknown_keys = vec![];
+    for (name, mut overrides) in variants {
+        if name.is_empty() || name.contains(['/', '\\']) {
+            return Err(ConfigError::Config(format!(
+                "variant `{name}` needs a name that can go in a file name"
+            )));
+        }
+        if !overrides.is_table() {
+            return Err(ConfigError::Config(format!(
+                "variant `{name}` must be a table of keys to change"
+            )));
+        }
+        let mut variant_value = value.clone();
+        deep_merge_toml(&mut variant_value, overrides.clone());
+        let hsv = take_hsv(&mut variant_value)
+            .map_err(|err| ConfigError::Config(format!("in variant `{name}`: {err}")))?;
+        let operation = IconOperation::deserialize(variant_value)
+            .map_err(|err| ConfigError::Config(format!("in variant `{name}`: {err}")))?;
+        if let Value::Table(overrides) = &mut overrides {
+            overrides.remove(HSV_KEY);
+        }
+        let sources = KeySources::of(&overrides, &KeySource::Config);
+        unknown_keys.extend(
+            find_unknown_keys(&overrides, &sources, &operation)
+                .into_iter()
+                .map(|key| {
+                    UnknownKey {
+                        path: format!("{VARIANTS_KEY}.{name}.{}", key.path),
+                        ..key
+                    }
+                }),
+        );
+        out.push(Variant {
+            name,
+            operation,
+            hsv,
+        });
+    }
+    Ok((out, unknown_keys))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::operations::cutters::bitmask_slice::BitmaskSlice;
+
+    #[test]
+    fn builds_variants() {
+        let operation: IconOperation = BitmaskSlice::default().into();
+        let mut value = Value::try_from(&operation).unwrap();
+        let variants: Value = toml::from_str(
+            r"
+            [variants.dirs]
+            produce_dirs = true
+            [variants.typo]
+            smooth_diagonaly = true
+            ",
+        )
+        .unwrap();
+        deep_merge_toml(&mut value, variants);
+
+        let (variants, unknown_keys) = take_variants(&mut value).unwrap();
+        assert_eq!(value, Value::try_from(&operation).unwrap());
+        assert_eq!(
+            variants,
+            vec![
+                Variant {
+                    name: "dirs".to_string(),
+                    operation: BitmaskSlice {
+                        produce_dirs: true,
+                        ..Default::default()
+                    }
+                    .into(),
+                    hsv: None,
+                },
+                Variant {
+                    name: "typo".to_string(),
+                    operation,
+                    hsv: None,
+                },
+            ]
+        );
+        assert_eq!(unknown_keys.len(), 1);
+        assert_eq!(unknown_keys[0].path, "variants.typo.smooth_diagonaly");
+
+        let mut bad: Value = toml::from_str("[variants.\"a/b\"]\nproduce_dirs = true").unwrap();
+        assert!(take_variants(&mut bad).is_err());
+    }
+
+    #[test]
+    fn variant_hsv() {
+        let mut value: Value = toml::from_str(
+            r#"
+            mode = "BitmaskSlice"
+            hsv = { hue = 90, value = -0.1 }
+            [variants.dark]
+            hsv = { value = -0.4 }
+            "#,
+        )
+        .unwrap();
+        deep_merge_toml(
+            &mut value,
+            Value::try_from(IconOperation::from(BitmaskSlice::default())).unwrap(),
+        );
+        let (variants, unknown_keys) = take_variants(&mut value).unwrap();
+        assert!(unknown_keys.is_empty());
+        assert_eq!(
+            variants[0].hsv,
+            Some(HsvShift {
+                hue: 90.0,
+                saturation: 0.0,
+                value: -0.4,
+            })
+        );
+    }
+}
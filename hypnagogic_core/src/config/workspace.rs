@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::error::ConfigResult;
+
+/// Name of the workspace config, which applies to every config in the folder
+/// it's in and below. It's never processed as a config itself
+pub const WORKSPACE_CONFIG_NAME: &str = "hypnagogic.toml";
+
+/// Where cached remote templates go by default, relative to the workspace
+/// config
+pub const DEFAULT_CACHE_DIR: &str = ".hypnagogic-cache";
+
+/// Settings shared by every config in a workspace
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct WorkspaceConfig {
+    /// Template pack to resolve templates missing from the templates folder
+    /// from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub remote_templates: Option<RemoteTemplates>,
+    /// Where to cache remote templates, relative to the workspace config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl WorkspaceConfig {
+    /// Finds the closest workspace config in `start` or any of its parents
+    #[must_use]
+    pub fn find(start: &Path) -> Option<PathBuf> {
+        start
+            .ancestors()
+            .map(|dir| dir.join(WORKSPACE_CONFIG_NAME))
+            .find(|path| path.is_file())
+    }
+
+    /// Reads the workspace config at `path`
+    /// # Errors
+    /// Errors if it can't be read or isn't a valid workspace config
+    pub fn load(path: &Path) -> ConfigResult<Self> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Where remote templates are cached, for a workspace config at `path`
+    #[must_use]
+    pub fn cache_dir(&self, path: &Path) -> PathBuf {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        dir.join(
+            self.cache_dir
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_CACHE_DIR)),
+        )
+    }
+}
+
+/// Where a remote pack of templates is fetched from
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TemplateSource {
+    /// Templates are fetched one at a time from `<url>/<name>.toml`
+    Http { url: String },
+    /// The repository is cloned, and templates read from it
+    Git {
+        git: String,
+        /// Branch, tag or commit to check out. A commit pins the pack, anything
+        /// else is updated every run when online
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default)]
+        rev: Option<String>,
+        /// Folder in the repository the templates are in
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default)]
+        dir: Option<String>,
+    },
+}
+
+/// A remote template pack, as declared in a workspace config
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct RemoteTemplates {
+    #[serde(flatten)]
+    pub source: TemplateSource,
+    /// Sha256 of templates by name, checked every time they're used
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    #[serde(default)]
+    pub checksums: BTreeMap<String, String>,
+}
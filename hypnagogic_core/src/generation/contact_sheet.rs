@@ -0,0 +1,109 @@
+use dmi::icon::{Icon, IconState};
+use image::{imageops, DynamicImage};
+
+use crate::generation::rect::draw_rect;
+use crate::generation::text::generate_label;
+use crate::util::color::Color;
+
+/// Enough to fit a full set of cardinal junctions on one row
+pub const DEFAULT_COLUMNS: u32 = 16;
+
+/// Space around each cell of the sheet
+const PADDING: u32 = 4;
+/// Height of a label, plus a pixel of spacing from the icon above it
+const LABEL_HEIGHT: u32 = 6;
+
+/// Renders the first frame of the first dir of every state in `icon` into a
+/// grid, `columns` wide, with each state's name printed under it
+/// # Panics
+/// Panics if `columns` is 0
+#[must_use]
+pub fn generate_contact_sheet(icon: &Icon, columns: u32) -> DynamicImage {
+    generate_contact_sheet_with(icon, columns, |_, state| state.images.first())
+}
+
+/// Same as [`generate_contact_sheet`], but `pick` chooses which image to draw
+/// for each state, given its index and the state. States it returns `None`
+/// for are left blank
+/// # Panics
+/// Panics if `columns` is 0
+#[must_use]
+pub fn generate_contact_sheet_with<'a>(
+    icon: &'a Icon,
+    columns: u32,
+    pick: impl Fn(usize, &'a IconState) -> Option<&'a DynamicImage>,
+) -> DynamicImage {
+    assert!(columns > 0, "Contact sheets need at least one column");
+    let text_color = Color::new(238, 238, 238, 255);
+    let labels: Vec<Option<DynamicImage>> = icon
+        .states
+        .iter()
+        .map(|state| generate_label(&state.name, text_color))
+        .collect();
+    let cell_width = labels
+        .iter()
+        .flatten()
+        .map(DynamicImage::width)
+        .max()
+        .unwrap_or(0)
+        .max(icon.width)
+        + PADDING;
+    let cell_height = icon.height + LABEL_HEIGHT + PADDING;
+
+    let count = icon.states.len() as u32;
+    let columns = columns.min(count).max(1);
+    let rows = count.div_ceil(columns);
+    let width = PADDING + cell_width * columns;
+    let height = PADDING + cell_height * rows;
+    let mut sheet = DynamicImage::new_rgba8(width, height);
+    draw_rect(&mut sheet, 0, 0, width, height, Color::new(60, 60, 60, 255));
+
+    for (index, (state, label)) in icon.states.iter().zip(&labels).enumerate() {
+        let index = index as u32;
+        let x = PADDING + (index % columns) * cell_width;
+        let y = PADDING + (index / columns) * cell_height;
+        // center the icon over its label
+        let icon_x = x + (cell_width - PADDING - icon.width) / 2;
+        if let Some(image) = pick(index as usize, state) {
+            imageops::overlay(&mut sheet, image, icon_x as i64, y as i64);
+        }
+        if let Some(label) = label {
+            let label_x = x + (cell_width - PADDING - label.width()) / 2;
+            imageops::overlay(
+                &mut sheet,
+                label,
+                label_x as i64,
+                (y + icon.height + 1) as i64,
+            );
+        }
+    }
+    sheet
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn contact_sheet_layout() {
+        let state = |name: &str| {
+            IconState {
+                name: name.to_string(),
+                dirs: 1,
+                frames: 1,
+                images: vec![DynamicImage::new_rgba8(32, 32)],
+                ..Default::default()
+            }
+        };
+        let icon = Icon {
+            version: dmi::icon::DmiVersion::default(),
+            width: 32,
+            height: 32,
+            states: vec![state("0"), state("1"), state("2")],
+        };
+        let sheet = generate_contact_sheet(&icon, 2);
+        // 2 columns and 2 rows of 32px icons, each with padding and a label
+        assert_eq!(sheet.width(), PADDING + (32 + PADDING) * 2);
+        assert_eq!(sheet.height(), PADDING + (32 + LABEL_HEIGHT + PADDING) * 2);
+    }
+}
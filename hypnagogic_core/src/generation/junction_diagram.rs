@@ -0,0 +1,118 @@
+use enum_iterator::all;
+use image::DynamicImage;
+
+use crate::generation::rect::draw_rect;
+use crate::util::adjacency::Adjacency;
+use crate::util::color::Color;
+use crate::util::corners::{Corner, CornerType};
+
+/// Size of each tile in a junction diagram
+const TILE_SIZE: u32 = 8;
+/// Space between tiles
+const GAP: u32 = 1;
+
+/// Color each corner type is drawn with in junction diagrams
+#[must_use]
+pub fn corner_type_color(corner_type: CornerType) -> Color {
+    match corner_type {
+        CornerType::Convex => Color::new(220, 60, 60, 255),
+        CornerType::Concave => Color::new(60, 180, 60, 255),
+        CornerType::Horizontal => Color::new(60, 110, 230, 255),
+        CornerType::Vertical => Color::new(230, 200, 40, 255),
+        CornerType::Flat => Color::new(200, 200, 200, 255),
+    }
+}
+
+/// Draws a 3x3 grid of tiles, with the neighbours set in `adjacency` filled
+/// in, and the middle tile split into its four corners, each colored by the
+/// [`CornerType`] it resolves to (see [`corner_type_color`]). Diagonal
+/// neighbours are ignored unless `smooth_diagonally` is set, like the cutters
+/// do
+#[must_use]
+pub fn generate_junction_diagram(adjacency: Adjacency, smooth_diagonally: bool) -> DynamicImage {
+    let adjacency = if smooth_diagonally {
+        adjacency
+    } else {
+        adjacency & Adjacency::CARDINALS
+    };
+    let size = GAP + (TILE_SIZE + GAP) * 3;
+    let mut diagram = DynamicImage::new_rgba8(size, size);
+    draw_rect(&mut diagram, 0, 0, size, size, Color::new(30, 30, 30, 255));
+
+    let tile_start = |index: u32| GAP + index * (TILE_SIZE + GAP);
+    let neighbours = [
+        (Adjacency::NW, 0, 0),
+        (Adjacency::N, 1, 0),
+        (Adjacency::NE, 2, 0),
+        (Adjacency::W, 0, 1),
+        (Adjacency::E, 2, 1),
+        (Adjacency::SW, 0, 2),
+        (Adjacency::S, 1, 2),
+        (Adjacency::SE, 2, 2),
+    ];
+    for (direction, x, y) in neighbours {
+        let color = if adjacency.contains(direction) {
+            Color::new(150, 150, 150, 255)
+        } else {
+            Color::new(70, 70, 70, 255)
+        };
+        draw_rect(
+            &mut diagram,
+            tile_start(x),
+            tile_start(y),
+            TILE_SIZE,
+            TILE_SIZE,
+            color,
+        );
+    }
+
+    let half = TILE_SIZE / 2;
+    for corner in all::<Corner>() {
+        let (x, y) = match corner {
+            Corner::NorthEast => (half, 0),
+            Corner::SouthEast => (half, half),
+            Corner::SouthWest => (0, half),
+            Corner::NorthWest => (0, 0),
+        };
+        draw_rect(
+            &mut diagram,
+            tile_start(1) + x,
+            tile_start(1) + y,
+            half,
+            half,
+            corner_type_color(adjacency.get_corner_type(corner)),
+        );
+    }
+    diagram
+}
+
+#[cfg(test)]
+mod test {
+    use image::GenericImageView;
+
+    use super::*;
+
+    #[test]
+    fn diagram_corners() {
+        let diagram = generate_junction_diagram(Adjacency::N | Adjacency::E | Adjacency::NE, false);
+        let color = |x, y| Color::from(diagram.get_pixel(x, y).0);
+        let center = GAP + TILE_SIZE + GAP + TILE_SIZE / 2;
+        // diagonals are masked off when not smoothing diagonally, so north
+        // east is concave rather than flat
+        assert_eq!(
+            color(center + 1, center - 1),
+            corner_type_color(CornerType::Concave)
+        );
+        assert_eq!(
+            color(center - 1, center + 1),
+            corner_type_color(CornerType::Convex)
+        );
+
+        let diagram = generate_junction_diagram(Adjacency::N | Adjacency::E | Adjacency::NE, true);
+        let color = |x, y| Color::from(diagram.get_pixel(x, y).0);
+        assert_eq!(
+            color(center + 1, center - 1),
+            corner_type_color(CornerType::Flat)
+        );
+    }
+}
@@ -1,4 +1,6 @@
+pub mod contact_sheet;
 pub mod error;
 pub mod icon;
+pub mod junction_diagram;
 pub mod rect;
 pub mod text;
@@ -1,4 +1,5 @@
 use image::{DynamicImage, GenericImage};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::util::color::Color;
@@ -11,14 +12,14 @@ pub fn draw_rect(image: &mut DynamicImage, x: u32, y: u32, width: u32, height: u
     }
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum BorderStyle {
     Solid,
     Dotted,
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Border {
     pub style: BorderStyle,
     pub color: Color,
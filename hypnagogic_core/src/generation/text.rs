@@ -1,8 +1,12 @@
+use std::sync::LazyLock;
+
 use image::{DynamicImage, GenericImage};
-use once_cell::sync::Lazy;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::util::color::{fill_image_color, Color};
+
 // all printable ascii characters
 const VALID_CHARS: [char; 95] = [
     ' ', '!', '"', '#', '$', '%', '&', '\'', '(', ')', '*', '+', ',', '-', '.', '/', '0', '1', '2',
@@ -13,8 +17,8 @@ const VALID_CHARS: [char; 95] = [
 ];
 
 const CHARACTER_RAW_BYTES: &[u8; 371] = include_bytes!("characters.png");
-static CHARACTER_IMAGE: Lazy<DynamicImage> =
-    Lazy::new(|| image::load_from_memory(CHARACTER_RAW_BYTES).unwrap());
+static CHARACTER_IMAGE: LazyLock<DynamicImage> =
+    LazyLock::new(|| image::load_from_memory(CHARACTER_RAW_BYTES).unwrap());
 
 const CHARACTER_WIDTH: u32 = 3;
 const CHARACTER_HEIGHT: u32 = 5;
@@ -55,7 +59,30 @@ pub fn generate_text_line(text_to_gen: &str) -> DynamicImage {
     image.crop_imm(0, 0, pos - 1, CHARACTER_HEIGHT)
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+/// Generates a single line of text in the given color, for labelling debug
+/// images. Characters the font doesn't have are swapped for `?`, so arbitrary
+/// icon state names can be passed in. Returns `None` for empty text
+#[must_use]
+pub fn generate_label(text: &str, color: Color) -> Option<DynamicImage> {
+    if text.is_empty() {
+        return None;
+    }
+    let sanitized: String = text
+        .chars()
+        .map(|char| {
+            if const_contains(&VALID_CHARS, char) {
+                char
+            } else {
+                '?'
+            }
+        })
+        .collect();
+    let mut image = generate_text_line(&sanitized);
+    fill_image_color(&mut image, color);
+    Some(image)
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum Alignment {
     Left,
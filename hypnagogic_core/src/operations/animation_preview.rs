@@ -0,0 +1,318 @@
+use std::collections::BTreeSet;
+
+use dmi::icon::{Icon, IconState};
+use image::{imageops, DynamicImage};
+
+use crate::config::blocks::cutters::{AnimationPreview, PreviewFormat, PreviewLayout};
+use crate::generation::contact_sheet::{generate_contact_sheet_with, DEFAULT_COLUMNS};
+use crate::operations::error::ProcessorResult;
+use crate::operations::{IconOperation, NamedIcon, OutputImage, ProcessorPayload};
+use crate::util::animation::{
+    encode_apng_sequence,
+    encode_gif_sequence,
+    frames_for_dir,
+    playback_sequence,
+};
+use crate::util::file_safe_name;
+
+impl IconOperation {
+    /// How the operation's `animation` asks for its states to be previewed,
+    /// if it does
+    #[must_use]
+    pub fn animation_preview(&self) -> Option<AnimationPreview> {
+        let animation = match self {
+            IconOperation::BitmaskWindows(config) => config.animation.as_ref(),
+            IconOperation::BitmaskSlice(config) => config.animation.as_ref(),
+            IconOperation::BitmaskSliceGroups(config) => {
+                config.bitmask_slice_config.animation.as_ref()
+            }
+            IconOperation::BitmaskSliceGreyscale(config) => {
+                config.bitmask_slice_config.animation.as_ref()
+            }
+            IconOperation::BitmaskDirectionalVis(config) => {
+                config.bitmask_slice_config.animation.as_ref()
+            }
+            IconOperation::BitmaskTextureMask(config) => {
+                config.bitmask_slice_config.animation.as_ref()
+            }
+            IconOperation::BitmaskWallTops(config) => {
+                config.bitmask_slice_config.animation.as_ref()
+            }
+            IconOperation::BitmaskSliceReconstruct(_)
+            | IconOperation::BitmaskPipes(_)
+            | IconOperation::BitmaskLattice(_)
+            | IconOperation::BitmaskCornerOverlays(_)
+            | IconOperation::TurfEdges(_)
+            | IconOperation::DirectionalRotation(_)
+            | IconOperation::GridSlice(_)
+            | IconOperation::GlyphSheet(_)
+            | IconOperation::DmiMerge(_)
+            | IconOperation::DmiSplit(_)
+            | IconOperation::DmiRename(_)
+            | IconOperation::DmiOptimize(_)
+            | IconOperation::DmiValidate(_)
+            | IconOperation::DmiExport(_)
+            | IconOperation::DmiImport(_)
+            | IconOperation::DmiAseprite(_) => None,
+        };
+        animation.and_then(|animation| animation.preview)
+    }
+
+    /// Adds the previews the operation asks for to `payload`, built from its
+    /// main icon. Meant to run once nothing else will change the icon, so
+    /// the previews show it with its damage states, recolors and filters
+    /// # Errors
+    /// Errors if a preview fails to encode
+    pub fn add_animation_previews(
+        &self,
+        payload: ProcessorPayload,
+    ) -> ProcessorResult<ProcessorPayload> {
+        let previews = match (self.animation_preview(), main_icon(&payload)) {
+            (Some(preview), Some(icon)) => animation_previews(icon, preview)?,
+            _ => vec![],
+        };
+        Ok(payload.with_named(previews))
+    }
+}
+
+/// The icon in `payload` without a name or path of its own, which the rest
+/// are named after
+fn main_icon(payload: &ProcessorPayload) -> Option<&Icon> {
+    match payload {
+        ProcessorPayload::Single(image) => {
+            match image.as_ref() {
+                OutputImage::Dmi(icon) => Some(icon),
+                _ => None,
+            }
+        }
+        ProcessorPayload::SingleNamed(named) => unnamed_icon(named),
+        ProcessorPayload::MultipleNamed(icons) => icons.iter().find_map(unnamed_icon),
+        ProcessorPayload::ConfigWrapped(payload, _) | ProcessorPayload::Warned(payload, _) => {
+            main_icon(payload)
+        }
+    }
+}
+
+fn unnamed_icon(named: &NamedIcon) -> Option<&Icon> {
+    match &named.image {
+        OutputImage::Dmi(icon) if named.path_hint.is_none() && named.name_hint.is_none() => {
+            Some(icon)
+        }
+        _ => None,
+    }
+}
+
+/// Builds the animated previews configured by `preview` for `icon`. Icons
+/// without any animated states don't get any previews
+/// # Errors
+/// Errors if a preview fails to encode
+pub fn animation_previews(
+    icon: &Icon,
+    preview: AnimationPreview,
+) -> ProcessorResult<Vec<NamedIcon>> {
+    match preview.layout {
+        PreviewLayout::PerState => {
+            icon.states
+                .iter()
+                .filter(|state| state.frames > 1)
+                .map(|state| {
+                    let image = encode(&state_sequence(icon, state), preview.format)?;
+                    Ok(NamedIcon::new(
+                        "PREVIEWS",
+                        &file_safe_name(&state.name),
+                        image,
+                    ))
+                })
+                .collect()
+        }
+        PreviewLayout::PerDmi => {
+            let Some(sequence) = dmi_sequence(icon) else {
+                return Ok(vec![]);
+            };
+            Ok(vec![NamedIcon {
+                path_hint: None,
+                name_hint: Some("preview".to_string()),
+                image: encode(&sequence, preview.format)?,
+            }])
+        }
+    }
+}
+
+fn encode(sequence: &[(DynamicImage, f32)], format: PreviewFormat) -> ProcessorResult<OutputImage> {
+    Ok(match format {
+        PreviewFormat::Apng => OutputImage::Apng(encode_apng_sequence(sequence)?),
+        PreviewFormat::Gif => OutputImage::Gif(encode_gif_sequence(sequence)?),
+    })
+}
+
+/// Every frame of a state with all of its dirs side by side
+fn state_sequence(icon: &Icon, state: &IconState) -> Vec<(DynamicImage, f32)> {
+    let dirs: Vec<Vec<&DynamicImage>> = (0..state.dirs)
+        .map(|dir| frames_for_dir(state, dir))
+        .collect();
+    let frames: Vec<DynamicImage> = (0..state.frames as usize)
+        .map(|frame| {
+            let mut combined = DynamicImage::new_rgba8(icon.width * dirs.len() as u32, icon.height);
+            for (dir, images) in dirs.iter().enumerate() {
+                if let Some(image) = images.get(frame) {
+                    imageops::replace(&mut combined, *image, (dir as u32 * icon.width) as i64, 0);
+                }
+            }
+            combined
+        })
+        .collect();
+    let frame_refs: Vec<&DynamicImage> = frames.iter().collect();
+    playback_sequence(&frame_refs, state.delay.as_deref(), state.rewind)
+        .into_iter()
+        .map(|(frame, delay)| (frame.clone(), delay))
+        .collect()
+}
+
+/// The south dir of every state laid out in a grid, each playing its own
+/// animation. States animate with different delays (deduping frames changes
+/// them per state), so the preview gets a new frame whenever any state changes
+/// frame. Returns `None` if no state is animated
+fn dmi_sequence(icon: &Icon) -> Option<Vec<(DynamicImage, f32)>> {
+    // frame indexes and delays in centiseconds, to keep the timing math exact
+    let timelines: Vec<Vec<(usize, u32)>> = icon
+        .states
+        .iter()
+        .map(|state| {
+            let indexes: Vec<usize> = (0..state.frames as usize).collect();
+            playback_sequence(&indexes, state.delay.as_deref(), state.rewind)
+                .into_iter()
+                .map(|(index, delay)| (index, ((delay * 10.0).round() as u32).max(1)))
+                .collect()
+        })
+        .collect();
+    let total = |timeline: &[(usize, u32)]| timeline.iter().map(|(_, delay)| delay).sum::<u32>();
+    let loop_length = timelines
+        .iter()
+        .filter(|timeline| timeline.len() > 1)
+        .map(|timeline| total(timeline))
+        .max()?;
+
+    let mut changes = BTreeSet::from([0]);
+    for timeline in timelines.iter().filter(|timeline| timeline.len() > 1) {
+        let mut time = 0;
+        'outer: loop {
+            for (_, delay) in timeline {
+                time += delay;
+                if time >= loop_length {
+                    break 'outer;
+                }
+                changes.insert(time);
+            }
+        }
+    }
+
+    let frame_at = |timeline: &[(usize, u32)], time: u32| {
+        let mut time = time % total(timeline).max(1);
+        for (index, delay) in timeline {
+            if time < *delay {
+                return *index;
+            }
+            time -= delay;
+        }
+        0
+    };
+    let changes: Vec<u32> = changes.into_iter().collect();
+    let sequence = changes
+        .iter()
+        .enumerate()
+        .map(|(position, start)| {
+            let end = changes.get(position + 1).copied().unwrap_or(loop_length);
+            let sheet = generate_contact_sheet_with(icon, DEFAULT_COLUMNS, |index, state| {
+                let frame = frame_at(&timelines[index], *start);
+                state.images.get(frame * state.dirs.max(1) as usize)
+            });
+            (sheet, (end - start) as f32 / 10.0)
+        })
+        .collect();
+    Some(sequence)
+}
+
+#[cfg(test)]
+mod test {
+    use image::Rgba;
+
+    use super::*;
+    use crate::config::blocks::cutters::Animation;
+    use crate::operations::cutters::bitmask_slice::BitmaskSlice;
+
+    fn animated_state(name: &str, delays: Vec<f32>) -> IconState {
+        let frame = |shade: u8| {
+            DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(2, 2, Rgba([shade, 0, 0, 255])))
+        };
+        IconState {
+            name: name.to_string(),
+            dirs: 1,
+            frames: 2,
+            images: vec![frame(0), frame(1)],
+            delay: Some(delays),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn dmi_sequence_merges_timelines() {
+        let icon = Icon {
+            version: dmi::icon::DmiVersion::default(),
+            width: 2,
+            height: 2,
+            states: vec![
+                animated_state("fast", vec![1.0, 1.0]),
+                animated_state("slow", vec![2.0, 2.0]),
+            ],
+        };
+        let sequence = dmi_sequence(&icon).unwrap();
+        // fast changes every decisecond across the 4 decisecond loop of slow
+        let delays: Vec<f32> = sequence.iter().map(|(_, delay)| *delay).collect();
+        assert_eq!(delays, vec![1.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn previews_the_main_icon() {
+        let icon = |state: &str| {
+            Icon {
+                version: dmi::icon::DmiVersion::default(),
+                width: 2,
+                height: 2,
+                states: vec![animated_state(state, vec![1.0, 1.0])],
+            }
+        };
+        let payload = || {
+            ProcessorPayload::MultipleNamed(vec![
+                NamedIcon::new("DEBUGOUT", "CORNERS", OutputImage::Dmi(icon("corners"))),
+                NamedIcon::from_icon(icon("wall")),
+            ])
+        };
+        let operation: IconOperation = BitmaskSlice {
+            animation: Some(Animation {
+                preview: Some(AnimationPreview::default()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+        .into();
+        let ProcessorPayload::MultipleNamed(icons) =
+            operation.add_animation_previews(payload()).unwrap()
+        else {
+            panic!("Expected named icons");
+        };
+        let previews: Vec<(Option<&str>, Option<&str>)> = icons[2..]
+            .iter()
+            .map(|named| (named.path_hint.as_deref(), named.name_hint.as_deref()))
+            .collect();
+        assert_eq!(previews, vec![(Some("PREVIEWS"), Some("wall"))]);
+        assert!(matches!(icons[2].image, OutputImage::Apng(_)));
+
+        let ProcessorPayload::MultipleNamed(icons) = IconOperation::from(BitmaskSlice::default())
+            .add_animation_previews(payload())
+            .unwrap()
+        else {
+            panic!("Expected named icons");
+        };
+        assert_eq!(icons.len(), 2);
+    }
+}
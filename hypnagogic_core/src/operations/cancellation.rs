@@ -0,0 +1,59 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::operations::error::{ProcessorError, ProcessorResult};
+
+/// Shared flag used to abort an icon operation that's in progress.
+///
+/// Clones share the same flag, so a host (GUI, file watcher, etc) can keep one
+/// and hand another to the operation, then call [`CancellationToken::cancel`]
+/// when the result is no longer wanted. Operations check the token in their
+/// longer loops and bail with `ProcessorError::Cancelled`.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests that any operation holding this token stops as soon as it can
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Helper for use inside operations, so cancellation can be checked with
+    /// `?`
+    /// # Errors
+    /// Returns `ProcessorError::Cancelled` if the token has been cancelled
+    pub fn check(&self) -> ProcessorResult<()> {
+        if self.is_cancelled() {
+            Err(ProcessorError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clones_share_cancellation() {
+        let token = CancellationToken::new();
+        let handed_out = token.clone();
+        assert!(handed_out.check().is_ok());
+        token.cancel();
+        assert!(handed_out.is_cancelled());
+        assert!(matches!(handed_out.check(), Err(ProcessorError::Cancelled)));
+    }
+}
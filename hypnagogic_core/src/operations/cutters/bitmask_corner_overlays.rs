@@ -0,0 +1,256 @@
+use dmi::icon::{Icon, IconState, Looping};
+use image::{imageops, DynamicImage};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::config::blocks::cutters::{Animation, CutPosition, IconSize, Positions};
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::cutters::bitmask_slice::BitmaskSlice;
+use crate::operations::error::{ProcessorError, ProcessorResult, ProcessorWarning};
+use crate::operations::{IconOperationConfig, InputIcon, OperationMode, ProcessorPayload};
+use crate::util::corners::{Corner, CornerType};
+use crate::util::icon_ops::dedupe_frames;
+use crate::util::repeat_for;
+
+/// Cuts the corners of a bitmask sheet in to overlays, one state for each
+/// corner of the tile and each way it can be smoothed, rather than assembling
+/// whole junctions. Overlay based smoothing draws the four states matching a
+/// tile's neighbours over it, and names them like tg's old `1-i` and `4-se`
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct BitmaskCornerOverlays {
+    pub icon_size: IconSize,
+    /// Put before each state name, like `wall-1-i`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub output_name: Option<String>,
+    pub cut_pos: CutPosition,
+    pub positions: Positions,
+    /// Also cut flat corners, for corners whose diagonal is connected too
+    #[serde(default)]
+    pub smooth_diagonally: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub animation: Option<Animation>,
+}
+
+/// Number overlay smoothing gives `corner`
+const fn corner_number(corner: Corner) -> u8 {
+    match corner {
+        Corner::NorthWest => 1,
+        Corner::NorthEast => 2,
+        Corner::SouthWest => 3,
+        Corner::SouthEast => 4,
+    }
+}
+
+/// What overlay smoothing calls `corner_type` in `corner`: `i` for no
+/// connections, the side for a single connection, the corner for both sides
+/// and `f` for both sides and the diagonal
+const fn corner_suffix(corner: Corner, corner_type: CornerType) -> &'static str {
+    match (corner_type, corner) {
+        (CornerType::Convex, _) => "i",
+        (CornerType::Flat, _) => "f",
+        (CornerType::Vertical, Corner::NorthEast | Corner::NorthWest) => "n",
+        (CornerType::Vertical, Corner::SouthEast | Corner::SouthWest) => "s",
+        (CornerType::Horizontal, Corner::NorthEast | Corner::SouthEast) => "e",
+        (CornerType::Horizontal, Corner::NorthWest | Corner::SouthWest) => "w",
+        (CornerType::Concave, Corner::NorthEast) => "ne",
+        (CornerType::Concave, Corner::SouthEast) => "se",
+        (CornerType::Concave, Corner::SouthWest) => "sw",
+        (CornerType::Concave, Corner::NorthWest) => "nw",
+    }
+}
+
+impl BitmaskCornerOverlays {
+    /// The bitmask slice the corners are cut with
+    fn slice_config(&self) -> BitmaskSlice {
+        BitmaskSlice {
+            icon_size: self.icon_size,
+            cut_pos: self.cut_pos,
+            positions: self.positions.clone(),
+            smooth_diagonally: self.smooth_diagonally,
+            animation: self.animation.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Name of the overlay for `corner_type` in `corner`
+    #[must_use]
+    pub fn state_name(&self, corner: Corner, corner_type: CornerType) -> String {
+        let name = format!(
+            "{}-{}",
+            corner_number(corner),
+            corner_suffix(corner, corner_type)
+        );
+        match &self.output_name {
+            Some(prefix) => format!("{prefix}-{name}"),
+            None => name,
+        }
+    }
+}
+
+impl IconOperationConfig for BitmaskCornerOverlays {
+    #[tracing::instrument(skip(input, _mode))]
+    fn perform_operation(
+        &self,
+        input: &InputIcon,
+        _mode: OperationMode,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ProcessorPayload> {
+        let InputIcon::DynamicImage(img) = input else {
+            return Err(ProcessorError::ImageNotFound);
+        };
+        let config = self.slice_config();
+        let (corners, _) = config.generate_corners(img)?;
+        let num_frames = config.frame_count(img)?;
+        let warnings: Vec<ProcessorWarning> = config.leftover_rows(img).into_iter().collect();
+
+        let delay = self
+            .animation
+            .as_ref()
+            .filter(|_| num_frames > 1)
+            .map(|animation| repeat_for(&animation.delays_in_deciseconds(), num_frames as usize));
+        let rewind = self
+            .animation
+            .as_ref()
+            .and_then(|animation| animation.rewind)
+            .unwrap_or(false);
+        let loop_flag = self
+            .animation
+            .as_ref()
+            .map_or(Looping::Indefinitely, Animation::looping);
+
+        let mut states = vec![];
+        // the same order as the corners are numbered in
+        let corner_order = [
+            Corner::NorthWest,
+            Corner::NorthEast,
+            Corner::SouthWest,
+            Corner::SouthEast,
+        ];
+        for corner in corner_order {
+            cancel.check()?;
+            let placement = config.corner_placement(corner);
+            for corner_type in CornerType::diagonal() {
+                // empty slots and flat corners without diagonal smoothing
+                // aren't cut
+                let Some(frames) = corners.get(corner_type).and_then(|block| block.get(corner))
+                else {
+                    continue;
+                };
+                let images = frames
+                    .iter()
+                    .map(|frame| {
+                        let mut canvas =
+                            DynamicImage::new_rgba8(self.icon_size.x, self.icon_size.y);
+                        imageops::overlay(
+                            &mut canvas,
+                            frame,
+                            i64::from(placement.x),
+                            i64::from(placement.y),
+                        );
+                        canvas
+                    })
+                    .collect();
+                states.push(dedupe_frames(
+                    IconState {
+                        name: self.state_name(corner, corner_type),
+                        dirs: 1,
+                        frames: num_frames,
+                        images,
+                        delay: delay.clone(),
+                        rewind,
+                        loop_flag,
+                        ..Default::default()
+                    },
+                    self.animation.as_ref(),
+                ));
+            }
+        }
+        Ok(ProcessorPayload::from_icon(Icon {
+            width: self.icon_size.x,
+            height: self.icon_size.y,
+            states,
+            ..Default::default()
+        })
+        .with_warnings(warnings))
+    }
+
+    fn verify_config(&self) -> ProcessorResult<()> {
+        ProcessorError::check_all(self.slice_config().config_problems())
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        match input {
+            InputIcon::DynamicImage(img) => self.slice_config().image_problems(img, []),
+            InputIcon::Dmi(_) | InputIcon::Aseprite(_) => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{GenericImageView, Rgba};
+
+    use super::*;
+    use crate::operations::{single_dmi, tile_sheet};
+
+    /// Blocks in one color each, so every overlay shows which block it's from
+    fn sheet(blocks: u32) -> DynamicImage {
+        tile_sheet((8, 8), (blocks, 1), |block, _, _| {
+            Rgba([block as u8 * 40 + 40, 0, 0, 255])
+        })
+    }
+
+    fn states(config: &BitmaskCornerOverlays, blocks: u32) -> Vec<IconState> {
+        let payload = config
+            .do_operation(
+                &InputIcon::DynamicImage(sheet(blocks)),
+                OperationMode::Standard,
+            )
+            .unwrap();
+        single_dmi(payload).states
+    }
+
+    #[test]
+    fn cuts_overlays() {
+        let config = BitmaskCornerOverlays {
+            icon_size: IconSize { x: 8, y: 8 },
+            cut_pos: CutPosition { x: 4, y: 4 },
+            positions: Positions::default(),
+            ..Default::default()
+        };
+        let states = states(&config, 4);
+        let names: Vec<&str> = states.iter().map(|state| state.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "1-i", "1-nw", "1-w", "1-n", "2-i", "2-ne", "2-e", "2-n", "3-i", "3-sw", "3-w",
+                "3-s", "4-i", "4-se", "4-e", "4-s"
+            ]
+        );
+        // the north east concave overlay only covers the north east quarter,
+        // cut from the concave block
+        let overlay = &states[5].images[0];
+        assert_eq!(overlay.get_pixel(6, 1), Rgba([80, 0, 0, 255]));
+        assert_eq!(overlay.get_pixel(1, 1)[3], 0);
+        assert_eq!(overlay.get_pixel(6, 6)[3], 0);
+    }
+
+    #[test]
+    fn flat_overlays() {
+        let mut positions = Positions::default();
+        positions.0.insert(CornerType::Flat, Some(4));
+        let config = BitmaskCornerOverlays {
+            icon_size: IconSize { x: 8, y: 8 },
+            output_name: Some("wall".to_string()),
+            cut_pos: CutPosition { x: 4, y: 4 },
+            positions,
+            smooth_diagonally: true,
+            ..Default::default()
+        };
+        let states = states(&config, 5);
+        assert_eq!(states.len(), 20);
+        assert!(states.iter().any(|state| state.name == "wall-4-f"));
+    }
+}
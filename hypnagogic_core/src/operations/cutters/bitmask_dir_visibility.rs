@@ -1,17 +1,18 @@
-use dmi::icon::{Icon, IconState};
+use dmi::icon::{Icon, IconState, Looping};
 use enum_iterator::all;
-use image::{imageops, DynamicImage, GenericImageView};
+use image::{imageops, DynamicImage};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::config::blocks::cutters::SlicePoint;
+use crate::config::blocks::cutters::{Animation, IconHotspot, SlicePoint};
 use crate::generation::icon::generate_map_icon;
+use crate::operations::cancellation::CancellationToken;
 use crate::operations::cutters::bitmask_slice::{
     BitmaskSlice,
-    SideSpacing,
     SIZE_OF_CARDINALS,
     SIZE_OF_DIAGONALS,
 };
-use crate::operations::error::{ProcessorError, ProcessorResult};
+use crate::operations::error::{ProcessorError, ProcessorResult, ProcessorWarning};
 use crate::operations::{
     IconOperationConfig,
     InputIcon,
@@ -22,9 +23,10 @@ use crate::operations::{
 use crate::util::adjacency::Adjacency;
 use crate::util::corners::{Corner, Side};
 use crate::util::icon_ops::dedupe_frames;
+use crate::util::layout::SideSpacing;
 use crate::util::repeat_for;
 
-#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
 pub struct BitmaskDirectionalVis {
     #[serde(flatten)]
     pub bitmask_slice_config: BitmaskSlice,
@@ -39,14 +41,20 @@ impl IconOperationConfig for BitmaskDirectionalVis {
         &self,
         input: &InputIcon,
         mode: OperationMode,
+        cancel: &CancellationToken,
     ) -> ProcessorResult<ProcessorPayload> {
         let InputIcon::DynamicImage(img) = input else {
             return Err(ProcessorError::ImageNotFound);
         };
         let (corners, prefabs) = self.bitmask_slice_config.generate_corners(img)?;
+        let variants = self.bitmask_slice_config.generate_corner_variants(img)?;
 
-        let (_in_x, in_y) = img.dimensions();
-        let num_frames = in_y / self.bitmask_slice_config.icon_size.y;
+        let num_frames = self.bitmask_slice_config.frame_count(img)?;
+        let mut warnings: Vec<ProcessorWarning> = self
+            .bitmask_slice_config
+            .leftover_rows(img)
+            .into_iter()
+            .collect();
 
         let possible_states = if self.bitmask_slice_config.smooth_diagonally {
             SIZE_OF_DIAGONALS
@@ -54,29 +62,64 @@ impl IconOperationConfig for BitmaskDirectionalVis {
             SIZE_OF_CARDINALS
         };
 
-        let assembled = self.bitmask_slice_config.generate_icons(
+        let mut assembled = self.bitmask_slice_config.generate_icons(
             &corners,
+            &variants,
             &prefabs,
             num_frames,
             possible_states,
+            cancel,
+        )?;
+        self.bitmask_slice_config
+            .apply_prefab_overlays(img, &mut assembled)?;
+        warnings.extend(
+            self.bitmask_slice_config
+                .skipped_junctions(&assembled, possible_states),
         );
 
         let delay: Option<Vec<f32>> = self
             .bitmask_slice_config
             .animation
             .clone()
-            .map(|x| repeat_for(&x.delays, num_frames as usize));
+            .map(|x| repeat_for(&x.delays_in_deciseconds(), num_frames as usize));
         let rewind = self
             .bitmask_slice_config
             .animation
             .as_ref()
             .and_then(|animation| animation.rewind)
             .unwrap_or(false);
+        let hotspot = self
+            .bitmask_slice_config
+            .hotspot
+            .map(IconHotspot::dmi_hotspot);
+        let loop_flag = self
+            .bitmask_slice_config
+            .animation
+            .as_ref()
+            .map_or(Looping::Indefinitely, Animation::looping);
+        let movement = self
+            .bitmask_slice_config
+            .animation
+            .as_ref()
+            .and_then(|animation| animation.movement)
+            .unwrap_or(false);
+
+        // these states have never had the output name in them, so only follow
+        // the slice's naming when a format is set
+        let state_name = |adjacency: Adjacency| {
+            match &self.bitmask_slice_config.state_name_format {
+                Some(_) => self.bitmask_slice_config.state_name(None, adjacency),
+                None => adjacency.bits().to_string(),
+            }
+        };
 
         let mut icon_states = vec![];
 
         for (adjacency, images) in &assembled {
-            if !adjacency.has_no_orphaned_corner() {
+            cancel.check()?;
+            if !self.bitmask_slice_config.keeps_junction(*adjacency)
+                || !self.bitmask_slice_config.emits_state(*adjacency)
+            {
                 continue;
             }
             for side in Side::dmi_cardinals() {
@@ -110,66 +153,81 @@ impl IconOperationConfig for BitmaskDirectionalVis {
                     imageops::overlay(&mut cut_img, &crop, x as i64, y as i64);
                     icon_state_frames.push(cut_img);
                 }
-                icon_states.push(dedupe_frames(IconState {
-                    name: format!("{}-{}", adjacency.bits(), side.byond_dir()),
-
-                    dirs: 1,
-                    frames: num_frames,
-                    images: icon_state_frames,
-                    delay: delay.clone(),
-                    rewind,
-                    ..Default::default()
-                }));
+                icon_states.push(dedupe_frames(
+                    IconState {
+                        name: format!("{}-{}", state_name(*adjacency), side.byond_dir()),
+
+                        dirs: 1,
+                        frames: num_frames,
+                        images: icon_state_frames,
+                        delay: delay.clone(),
+                        rewind,
+                        loop_flag,
+                        movement,
+                        hotspot,
+                        ..Default::default()
+                    },
+                    self.bitmask_slice_config.animation.as_ref(),
+                ));
             }
         }
 
-        let convex_images = assembled.get(&Adjacency::CARDINALS).unwrap();
-        for corner in all::<Corner>() {
-            let mut icon_state_frames = vec![];
+        // inner corners are cut from the fully connected junction, which is
+        // skipped if it needs an empty position slot
+        if let Some(convex_images) = assembled.get(&Adjacency::CARDINALS) {
+            for corner in all::<Corner>() {
+                let mut icon_state_frames = vec![];
 
-            let (horizontal, vertical) = corner.sides_of_corner();
+                let (horizontal, vertical) = corner.sides_of_corner();
 
-            let horizontal_side_info = self.bitmask_slice_config.get_side_info(horizontal);
-            let x = horizontal_side_info.start;
-            let width = horizontal_side_info.step();
+                let horizontal_side_info = self.bitmask_slice_config.get_side_info(horizontal);
+                let x = horizontal_side_info.start;
+                let width = horizontal_side_info.step();
 
-            // todo: This is awful, maybe a better way to do this?
-            let (y, height) = if vertical == Side::North {
-                (0, self.slice_point.get(vertical).unwrap())
-            } else {
-                let slice_point = self.slice_point.get(vertical).unwrap();
-                let end = self.bitmask_slice_config.icon_size.y;
-                (slice_point, end - slice_point)
-            };
+                // todo: This is awful, maybe a better way to do this?
+                let (y, height) = if vertical == Side::North {
+                    (0, self.slice_point.get(vertical).unwrap())
+                } else {
+                    let slice_point = self.slice_point.get(vertical).unwrap();
+                    let end = self.bitmask_slice_config.icon_size.y;
+                    (slice_point, end - slice_point)
+                };
 
-            for image in convex_images {
-                let mut cut_img = DynamicImage::new_rgba8(
-                    self.bitmask_slice_config.icon_size.x,
-                    self.bitmask_slice_config.icon_size.y,
-                );
+                for image in convex_images {
+                    let mut cut_img = DynamicImage::new_rgba8(
+                        self.bitmask_slice_config.icon_size.x,
+                        self.bitmask_slice_config.icon_size.y,
+                    );
 
-                let crop_img = image.crop_imm(x, y, width, height);
+                    let crop_img = image.crop_imm(x, y, width, height);
 
-                imageops::overlay(&mut cut_img, &crop_img, x as i64, y as i64);
-                icon_state_frames.push(cut_img);
-            }
+                    imageops::overlay(&mut cut_img, &crop_img, x as i64, y as i64);
+                    icon_state_frames.push(cut_img);
+                }
 
-            icon_states.push(dedupe_frames(IconState {
-                name: format!("innercorner-{}", corner.byond_dir()),
-                dirs: 1,
-                frames: num_frames,
-                images: icon_state_frames,
-                delay: delay.clone(),
-                rewind,
+                icon_states.push(dedupe_frames(
+                    IconState {
+                        name: format!("innercorner-{}", corner.byond_dir()),
+                        dirs: 1,
+                        frames: num_frames,
+                        images: icon_state_frames,
+                        delay: delay.clone(),
+                        rewind,
+                        loop_flag,
+                        movement,
+                        hotspot,
 
-                ..Default::default()
-            }));
+                        ..Default::default()
+                    },
+                    self.bitmask_slice_config.animation.as_ref(),
+                ));
+            }
         }
 
         if let Some(map_icon) = &self.bitmask_slice_config.map_icon {
             let icon = generate_map_icon(
-                self.bitmask_slice_config.output_icon_size.x,
-                self.bitmask_slice_config.output_icon_size.y,
+                self.bitmask_slice_config.output_size().x,
+                self.bitmask_slice_config.output_size().y,
                 map_icon,
             )?;
             icon_states.push(IconState {
@@ -183,24 +241,32 @@ impl IconOperationConfig for BitmaskDirectionalVis {
 
         let out_icon = Icon {
             version: dmi::icon::DmiVersion::default(),
-            width: self.bitmask_slice_config.output_icon_size.x,
-            height: self.bitmask_slice_config.output_icon_size.y,
+            width: self.bitmask_slice_config.output_size().x,
+            height: self.bitmask_slice_config.output_size().y,
             states: icon_states,
         };
 
-        if mode == OperationMode::Debug {
+        let payload = if mode == OperationMode::Debug {
             let mut out = self.bitmask_slice_config.generate_debug_icons(&corners);
 
             out.push(NamedIcon::from_icon(out_icon));
-            Ok(ProcessorPayload::MultipleNamed(out))
+            ProcessorPayload::MultipleNamed(out)
         } else {
-            Ok(ProcessorPayload::from_icon(out_icon))
-        }
+            ProcessorPayload::from_icon(out_icon)
+        };
+        Ok(payload.with_warnings(warnings))
     }
 
     fn verify_config(&self) -> ProcessorResult<()> {
-        // TODO: actually verify config
-        Ok(())
+        self.bitmask_slice_config.verify_config()
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        self.bitmask_slice_config.input_problems(input)
+    }
+
+    fn config_warnings(&self) -> Vec<ProcessorWarning> {
+        self.bitmask_slice_config.config_warnings()
     }
 }
 
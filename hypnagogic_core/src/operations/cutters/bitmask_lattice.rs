@@ -0,0 +1,278 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::config::blocks::cutters::{Animation, IconSize};
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::cutters::pieces::{
+    overlay_pieces,
+    piece_sheet_problems,
+    repeated_name_problem,
+};
+use crate::operations::error::{ProcessorError, ProcessorResult};
+use crate::operations::{IconOperationConfig, InputIcon, OperationMode, ProcessorPayload};
+use crate::util::adjacency::Adjacency;
+use crate::util::state_names::StateNameFormat;
+
+/// Cuts lattices, catwalks and the like, which smooth by reaching a strut
+/// towards each neighbour. Every state is a hub with an arm drawn for each of
+/// its connections
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct BitmaskLattice {
+    pub icon_size: IconSize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub output_name: Option<String>,
+    pub positions: LatticePositions,
+    /// Also connect diagonally, which needs an arm for each diagonal
+    #[serde(default)]
+    pub smooth_diagonally: bool,
+    /// How junction states are named, like `{prefix}-{bits}`. Defaults to
+    /// `{prefix}-{tag}-{bits}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub state_name_format: Option<StateNameFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub animation: Option<Animation>,
+}
+
+/// Where each piece is in the input, counted in pieces from the left. Arms are
+/// drawn on the whole tile, reaching from the hub to the edge they connect to
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct LatticePositions {
+    /// Drawn in every state, over the arms
+    pub hub: u32,
+    pub n: u32,
+    pub s: u32,
+    pub e: u32,
+    pub w: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub ne: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub se: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub sw: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub nw: Option<u32>,
+}
+
+impl Default for LatticePositions {
+    fn default() -> Self {
+        Self {
+            hub: 0,
+            n: 1,
+            s: 2,
+            e: 3,
+            w: 4,
+            ne: None,
+            se: None,
+            sw: None,
+            nw: None,
+        }
+    }
+}
+
+impl LatticePositions {
+    /// Each direction with the position of its arm, diagonals first so the
+    /// cardinal arms are drawn over them
+    fn arms(&self) -> [(Adjacency, &'static str, Option<u32>); 8] {
+        [
+            (Adjacency::NE, "ne", self.ne),
+            (Adjacency::SE, "se", self.se),
+            (Adjacency::SW, "sw", self.sw),
+            (Adjacency::NW, "nw", self.nw),
+            (Adjacency::N, "n", Some(self.n)),
+            (Adjacency::S, "s", Some(self.s)),
+            (Adjacency::E, "e", Some(self.e)),
+            (Adjacency::W, "w", Some(self.w)),
+        ]
+    }
+
+    fn last(&self) -> u32 {
+        self.arms()
+            .into_iter()
+            .filter_map(|(_, _, position)| position)
+            .fold(self.hub, u32::max)
+    }
+}
+
+impl BitmaskLattice {
+    /// The standard set of junctions, leaving out any with a diagonal whose
+    /// sides aren't both connected
+    fn junctions(&self) -> impl Iterator<Item = Adjacency> {
+        let last = if self.smooth_diagonally {
+            u8::MAX
+        } else {
+            Adjacency::CARDINALS.bits()
+        };
+        (0..=last)
+            .filter_map(Adjacency::from_bits)
+            .filter(|junction| junction.has_no_orphaned_corner())
+    }
+
+    fn state_name(&self, junction: Adjacency) -> String {
+        let prefix = self.output_name.as_deref();
+        match &self.state_name_format {
+            Some(format) => format.name(prefix, None, junction),
+            None => StateNameFormat::default().name(prefix, None, junction),
+        }
+    }
+}
+
+impl IconOperationConfig for BitmaskLattice {
+    #[tracing::instrument(skip(input, _mode))]
+    fn perform_operation(
+        &self,
+        input: &InputIcon,
+        _mode: OperationMode,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ProcessorPayload> {
+        let InputIcon::DynamicImage(img) = input else {
+            return Err(ProcessorError::ImageNotFound);
+        };
+        let arms = self.positions.arms();
+        let states = self.junctions().map(|junction| {
+            let pieces = arms
+                .iter()
+                .filter(|(side, ..)| junction.contains(*side))
+                .filter_map(|(_, _, position)| *position)
+                .chain([self.positions.hub])
+                .map(|position| (position, 0))
+                .collect();
+            (self.state_name(junction), pieces)
+        });
+        let icon = overlay_pieces(img, self.icon_size, self.animation.as_ref(), states, cancel)?;
+        Ok(ProcessorPayload::from_icon(icon))
+    }
+
+    fn verify_config(&self) -> ProcessorResult<()> {
+        let mut problems = vec![];
+        if self.icon_size.x == 0 || self.icon_size.y == 0 {
+            problems.push(ProcessorError::ConfigError(
+                "icon_size can't be zero".to_string(),
+            ));
+        }
+        for (_, name, position) in &self.positions.arms()[..4] {
+            if self.smooth_diagonally && position.is_none() {
+                problems.push(ProcessorError::ConfigError(format!(
+                    "positions.{name} is missing, smooth_diagonally needs an arm for every \
+                     diagonal"
+                )));
+            } else if !self.smooth_diagonally && position.is_some() {
+                problems.push(ProcessorError::ConfigError(format!(
+                    "positions.{name} is only used with smooth_diagonally"
+                )));
+            }
+        }
+        problems.extend(repeated_name_problem(
+            self.junctions().map(|junction| self.state_name(junction)),
+        ));
+        ProcessorError::check_all(problems)
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        let InputIcon::DynamicImage(img) = input else {
+            return vec![];
+        };
+        piece_sheet_problems(
+            img,
+            self.icon_size,
+            self.positions.last() + 1,
+            self.animation.as_ref(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{DynamicImage, GenericImageView, Rgba};
+
+    use super::*;
+    use crate::operations::{single_dmi, tile_sheet};
+
+    /// 3x3 pieces: a hub in the middle, then an arm to each edge and corner,
+    /// each its own color
+    fn input() -> DynamicImage {
+        let spots = [
+            (1, 1),
+            (1, 0),
+            (1, 2),
+            (2, 1),
+            (0, 1),
+            (2, 0),
+            (2, 2),
+            (0, 2),
+            (0, 0),
+        ];
+        tile_sheet((3, 3), (9, 1), |piece, x, y| {
+            if spots[piece as usize] == (x, y) {
+                Rgba([piece as u8 * 20, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        })
+    }
+
+    fn states(config: &BitmaskLattice) -> Vec<dmi::icon::IconState> {
+        config.verify_config().unwrap();
+        let payload = config
+            .do_operation(&InputIcon::DynamicImage(input()), OperationMode::Standard)
+            .unwrap();
+        single_dmi(payload).states
+    }
+
+    #[test]
+    fn draws_arms() {
+        let config = BitmaskLattice {
+            icon_size: IconSize { x: 3, y: 3 },
+            ..Default::default()
+        };
+        let states = states(&config);
+        assert_eq!(states.len(), 16);
+        // north and east
+        let state = states.iter().find(|state| state.name == "5").unwrap();
+        let filled = |x, y| state.images[0].get_pixel(x, y)[3] != 0;
+        assert!(filled(1, 1) && filled(1, 0) && filled(2, 1));
+        assert!(!filled(1, 2) && !filled(0, 1));
+    }
+
+    #[test]
+    fn diagonals() {
+        let config = BitmaskLattice {
+            icon_size: IconSize { x: 3, y: 3 },
+            smooth_diagonally: true,
+            positions: LatticePositions {
+                ne: Some(5),
+                se: Some(6),
+                sw: Some(7),
+                nw: Some(8),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let states = states(&config);
+        assert_eq!(states.len(), 47);
+        // north, east and north east
+        let state = states.iter().find(|state| state.name == "21").unwrap();
+        assert_eq!(state.images[0].get_pixel(2, 0), Rgba([100, 0, 0, 255]));
+        assert!(states.iter().all(|state| state.name != "16"));
+
+        let missing = BitmaskLattice {
+            positions: LatticePositions {
+                nw: None,
+                ..config.positions.clone()
+            },
+            ..config.clone()
+        };
+        assert!(missing.verify_config().is_err());
+        let unused = BitmaskLattice {
+            smooth_diagonally: false,
+            ..config
+        };
+        assert!(unused.verify_config().is_err());
+    }
+}
@@ -0,0 +1,347 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::config::blocks::cutters::{Animation, IconSize};
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::cutters::pieces::{
+    overlay_pieces,
+    piece_sheet_problems,
+    repeated_name_problem,
+};
+use crate::operations::error::{ProcessorError, ProcessorResult};
+use crate::operations::{IconOperationConfig, InputIcon, OperationMode, ProcessorPayload};
+use crate::util::adjacency::Adjacency;
+use crate::util::state_names::StateNameFormat;
+
+/// Cuts pipes and cables, which connect from the middle of their tile rather
+/// than smoothing at its corners. Every state is put together from segments
+/// drawn facing north, turned to face each connection
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct BitmaskPipes {
+    /// Size of each segment, which has to be square for them to be turned
+    pub icon_size: IconSize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub output_name: Option<String>,
+    pub positions: PipePositions,
+    /// How connection states are named, like `{prefix}-{bits}`. Defaults to
+    /// `{prefix}-{tag}-{bits}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub state_name_format: Option<StateNameFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub animation: Option<Animation>,
+}
+
+/// Where each segment is in the input, counted in segments from the left
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct PipePositions {
+    /// Runs from the middle to the north edge, for a single connection
+    pub end: u32,
+    /// Runs from the north edge to the south edge
+    pub straight: u32,
+    /// Bends from the north edge to the east edge
+    pub corner: u32,
+    /// Runs from the middle to the north east corner. Diagonal connections
+    /// are only cut when it's set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub diagonal: Option<u32>,
+    /// Drawn when nothing is connected, leaving that state out when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub isolated: Option<u32>,
+}
+
+impl Default for PipePositions {
+    fn default() -> Self {
+        Self {
+            end: 0,
+            straight: 1,
+            corner: 2,
+            diagonal: None,
+            isolated: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    End,
+    Straight,
+    Corner,
+    Diagonal,
+    Isolated,
+}
+
+impl PipePositions {
+    fn position(&self, segment: Segment) -> Option<u32> {
+        match segment {
+            Segment::End => Some(self.end),
+            Segment::Straight => Some(self.straight),
+            Segment::Corner => Some(self.corner),
+            Segment::Diagonal => self.diagonal,
+            Segment::Isolated => self.isolated,
+        }
+    }
+
+    fn last(&self) -> u32 {
+        [self.end, self.straight, self.corner]
+            .into_iter()
+            .chain(self.diagonal)
+            .chain(self.isolated)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+/// How far clockwise a segment drawn facing north is turned to face `side`
+fn turn_to(side: Adjacency) -> u32 {
+    match side {
+        Adjacency::E | Adjacency::SE => 90,
+        Adjacency::S | Adjacency::SW => 180,
+        Adjacency::W | Adjacency::NW => 270,
+        _ => 0,
+    }
+}
+
+/// The segments `junction` is drawn from, each with how far clockwise it's
+/// turned. Two adjacent connections bend with a corner, anything else is made
+/// of straights through connected opposite sides and ends for the rest
+fn segments(junction: Adjacency) -> Vec<(Segment, u32)> {
+    let cardinals = junction & Adjacency::CARDINALS;
+    let mut segments = vec![];
+    if junction.is_empty() {
+        segments.push((Segment::Isolated, 0));
+    } else if cardinals.bits().count_ones() == 2
+        && cardinals != Adjacency::N_S
+        && cardinals != Adjacency::E_W
+    {
+        let turn = match cardinals {
+            corner if corner == Adjacency::S | Adjacency::E => 90,
+            corner if corner == Adjacency::S | Adjacency::W => 180,
+            corner if corner == Adjacency::N | Adjacency::W => 270,
+            _ => 0,
+        };
+        segments.push((Segment::Corner, turn));
+    } else {
+        for (pair, turn) in [(Adjacency::N_S, 0), (Adjacency::E_W, 90)] {
+            if cardinals.contains(pair) {
+                segments.push((Segment::Straight, turn));
+            } else {
+                segments.extend(
+                    (cardinals & pair)
+                        .set_flags_vec()
+                        .into_iter()
+                        .map(|side| (Segment::End, turn_to(side))),
+                );
+            }
+        }
+    }
+    segments.extend(
+        Adjacency::diagonals()
+            .into_iter()
+            .filter(|diagonal| junction.contains(*diagonal))
+            .map(|diagonal| (Segment::Diagonal, turn_to(diagonal))),
+    );
+    segments
+}
+
+impl BitmaskPipes {
+    /// Every junction a state is cut for
+    fn junctions(&self) -> impl Iterator<Item = Adjacency> + '_ {
+        let last = if self.positions.diagonal.is_some() {
+            u8::MAX
+        } else {
+            Adjacency::CARDINALS.bits()
+        };
+        (0..=last)
+            .filter(|bits| *bits != 0 || self.positions.isolated.is_some())
+            .filter_map(Adjacency::from_bits)
+    }
+
+    fn state_name(&self, junction: Adjacency) -> String {
+        let prefix = self.output_name.as_deref();
+        match &self.state_name_format {
+            Some(format) => format.name(prefix, None, junction),
+            None => StateNameFormat::default().name(prefix, None, junction),
+        }
+    }
+}
+
+impl IconOperationConfig for BitmaskPipes {
+    #[tracing::instrument(skip(input, _mode))]
+    fn perform_operation(
+        &self,
+        input: &InputIcon,
+        _mode: OperationMode,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ProcessorPayload> {
+        let InputIcon::DynamicImage(img) = input else {
+            return Err(ProcessorError::ImageNotFound);
+        };
+        let states = self.junctions().map(|junction| {
+            let pieces = segments(junction)
+                .into_iter()
+                .filter_map(|(segment, turn)| {
+                    self.positions
+                        .position(segment)
+                        .map(|position| (position, turn))
+                })
+                .collect();
+            (self.state_name(junction), pieces)
+        });
+        let icon = overlay_pieces(img, self.icon_size, self.animation.as_ref(), states, cancel)?;
+        Ok(ProcessorPayload::from_icon(icon))
+    }
+
+    fn verify_config(&self) -> ProcessorResult<()> {
+        let mut problems = vec![];
+        if self.icon_size.x == 0 || self.icon_size.y == 0 {
+            problems.push(ProcessorError::ConfigError(
+                "icon_size can't be zero".to_string(),
+            ));
+        } else if self.icon_size.x != self.icon_size.y {
+            problems.push(ProcessorError::ConfigError(format!(
+                "icon_size has to be square for segments to be turned, but it's {}x{}",
+                self.icon_size.x, self.icon_size.y
+            )));
+        }
+        problems.extend(repeated_name_problem(
+            self.junctions().map(|junction| self.state_name(junction)),
+        ));
+        ProcessorError::check_all(problems)
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        let InputIcon::DynamicImage(img) = input else {
+            return vec![];
+        };
+        piece_sheet_problems(
+            img,
+            self.icon_size,
+            self.positions.last() + 1,
+            self.animation.as_ref(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use dmi::icon::IconState;
+    use image::{DynamicImage, GenericImageView, Rgba};
+
+    use super::*;
+    use crate::operations::{single_dmi, tile_sheet};
+
+    const GREEN: Rgba<u8> = Rgba([0, 255, 0, 255]);
+
+    /// 5x5 segments, each a line of pixels: an end from the middle to the
+    /// north, a straight through, a corner from north to east and a diagonal
+    /// arm to the north east
+    fn input() -> DynamicImage {
+        let segments: [&[(u32, u32)]; 4] = [
+            &[(2, 0), (2, 1), (2, 2)],
+            &[(2, 0), (2, 1), (2, 2), (2, 3), (2, 4)],
+            &[(2, 0), (2, 1), (2, 2), (3, 2), (4, 2)],
+            &[(2, 2), (3, 1), (4, 0)],
+        ];
+        tile_sheet((5, 5), (4, 1), |segment, x, y| {
+            if segments[segment as usize].contains(&(x, y)) {
+                GREEN
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        })
+    }
+
+    fn states(config: &BitmaskPipes) -> Vec<IconState> {
+        let payload = config
+            .do_operation(&InputIcon::DynamicImage(input()), OperationMode::Standard)
+            .unwrap();
+        single_dmi(payload).states
+    }
+
+    #[test]
+    fn assembles_connections() {
+        let config = BitmaskPipes {
+            icon_size: IconSize { x: 5, y: 5 },
+            output_name: Some("pipe".to_string()),
+            ..Default::default()
+        };
+        let states = states(&config);
+        assert_eq!(states.len(), 15);
+        let filled = |bits: u8, x, y| {
+            let name = format!("pipe-{bits}");
+            let state = states.iter().find(|state| state.name == name).unwrap();
+            state.images[0].get_pixel(x, y)[3] != 0
+        };
+        // south only, an end turned to run down from the middle
+        assert!(filled(2, 2, 4) && filled(2, 2, 2) && !filled(2, 2, 0));
+        // east and west, a straight turned sideways
+        assert!(filled(12, 0, 2) && filled(12, 4, 2) && !filled(12, 2, 0));
+        // south and west, a corner turned half way round
+        assert!(filled(10, 2, 4) && filled(10, 0, 2) && !filled(10, 4, 2));
+        // all but west, a straight with an end to the east
+        assert!(filled(7, 2, 0) && filled(7, 2, 4) && filled(7, 4, 2) && !filled(7, 0, 2));
+    }
+
+    #[test]
+    fn diagonals() {
+        let config = BitmaskPipes {
+            icon_size: IconSize { x: 5, y: 5 },
+            output_name: Some("cable".to_string()),
+            positions: PipePositions {
+                diagonal: Some(3),
+                isolated: Some(3),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let states = states(&config);
+        assert_eq!(states.len(), 256);
+        // south west only
+        let state = states
+            .iter()
+            .find(|state| state.name == "cable-64")
+            .unwrap();
+        assert_eq!(state.images[0].get_pixel(0, 4), GREEN);
+        assert_eq!(state.images[0].get_pixel(4, 0)[3], 0);
+    }
+
+    #[test]
+    fn problems() {
+        let config = BitmaskPipes {
+            icon_size: IconSize { x: 5, y: 4 },
+            ..Default::default()
+        };
+        assert!(config.verify_config().is_err());
+
+        let config = BitmaskPipes {
+            icon_size: IconSize { x: 5, y: 5 },
+            state_name_format: Some("{prefix}-{cardinal_letters}".parse().unwrap()),
+            positions: PipePositions {
+                diagonal: Some(3),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(config.verify_config().is_err());
+
+        let config = BitmaskPipes {
+            icon_size: IconSize { x: 5, y: 5 },
+            positions: PipePositions {
+                isolated: Some(4),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            config
+                .input_problems(&InputIcon::DynamicImage(input()))
+                .len(),
+            1
+        );
+    }
+}
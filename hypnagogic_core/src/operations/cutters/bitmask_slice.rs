@@ -1,25 +1,39 @@
-use std::collections::{BTreeMap, HashMap};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Range;
 
-use dmi::icon::{Icon, IconState};
+use dmi::icon::{Icon, IconState, Looping};
 use enum_iterator::all;
 use fixed_map::Map;
-use image::{imageops, DynamicImage, GenericImageView};
+use image::{imageops, DynamicImage};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, trace};
+use tracing::{debug, trace, warn};
 
 use crate::config::blocks::cutters::{
     Animation,
+    CornerCutPositions,
+    CornerVariants,
     CutPosition,
+    DiagonalWalls,
+    DirOutputPositions,
+    FrameRange,
+    IconHotspot,
     IconSize,
+    InputLayout,
     OutputIconPosition,
     OutputIconSize,
     Positions,
+    Prefab,
     PrefabOverlays,
     Prefabs,
+    SizeOverride,
+    TileBounds,
 };
 use crate::config::blocks::generators::MapIcon;
 use crate::generation::icon::generate_map_icon;
-use crate::operations::error::{ProcessorError, ProcessorResult};
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::error::{ProcessorError, ProcessorResult, ProcessorWarning};
 use crate::operations::{
     IconOperationConfig,
     InputIcon,
@@ -29,47 +43,201 @@ use crate::operations::{
     ProcessorPayload,
 };
 use crate::util::adjacency::Adjacency;
+use crate::util::blend::blend_onto_faded;
 use crate::util::corners::{Corner, CornerType, Side};
-use crate::util::icon_ops::dedupe_frames;
+use crate::util::icon_ops::{dedupe_frames, pad_to_canvas};
+use crate::util::layout::{
+    centered_corner_rect,
+    corner_rect,
+    cut_rect,
+    side_spacing,
+    tile_rect,
+    InputGrid,
+    OutputLayout,
+    Rect,
+    SheetLayout,
+    SideSpacing,
+    SlotLayout,
+};
 use crate::util::repeat_for;
+use crate::util::state_inventory::StateOrigin;
+use crate::util::state_names::StateNameFormat;
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
-pub struct SideSpacing {
-    pub start: u32,
-    pub end: u32,
+/// Where to put the cardinal only set of states produced alongside a diagonal
+/// set
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CardinalSetOutput {
+    /// Emitted into the same DMI, with `cardinal` inserted into the state names
+    SameIcon,
+    /// Emitted into a second DMI, named with a `cardinal` suffix, padded to the
+    /// same size as the first when size overrides make it larger
+    SeparateIcon,
 }
 
-impl SideSpacing {
-    #[must_use]
-    pub fn step(self) -> u32 {
-        self.end - self.start
-    }
+/// How junctions with an orphaned corner, a diagonal without both of the
+/// cardinals next to it, are generated
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrphanedCorners {
+    /// The orphaned diagonal is ignored, so the state looks the same as the
+    /// junction without it
+    Ignore,
+    /// The orphaned corner is drawn concave
+    Concave,
 }
 
-#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[allow(clippy::struct_excessive_bools)] // each is its own config key
 pub struct BitmaskSlice {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
     pub output_name: Option<String>,
     pub produce_dirs: bool,
+    /// When producing dirs, emit only the south facing dir if every other dir
+    /// is an exact rotation of it, along with a note on how to rotate at
+    /// runtime
+    #[serde(default)]
+    pub collapse_rotations: bool,
+    /// Also emit a `.dm` file listing the icon states produced and the
+    /// smoothing flags and junction bits they're meant to be used with
+    #[serde(default)]
+    pub dm_snippet: bool,
     pub smooth_diagonally: bool,
+    /// Cut flat corners from the middle of the concave block, where there
+    /// are no edges, instead of reading them from `positions.flat`
+    #[serde(default)]
+    pub derive_flat: bool,
+    /// The second half of the input's frames is movement art, laid out the
+    /// same as the first, emitted as movement states named the same as the
+    /// regular ones
+    #[serde(default)]
+    pub movement_states: bool,
     pub icon_size: IconSize,
-    pub output_icon_pos: OutputIconPosition,
-    pub output_icon_size: OutputIconSize,
+    /// Where generated icons are placed on each output state. Anchored to
+    /// the bottom left of the canvas if unset, where byond draws the tile
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub output_icon_pos: Option<OutputIconPosition>,
+    /// Output positions for single dirs, overriding `output_icon_pos` for
+    /// them, like nudging a window pane towards the edge it faces
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub dir_output_icon_pos: Option<DirOutputPositions>,
+    /// Size of each output state, `icon_size` if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub output_icon_size: Option<OutputIconSize>,
+    /// The logical tile within each icon, for icons bigger than a tile that
+    /// overhang on to their neighbours
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub tile_bounds: Option<TileBounds>,
     pub positions: Positions,
+    /// Wraps positions on to a new row every this many tiles, rather than
+    /// reading them from one long strip. Each animation frame is then a whole
+    /// grid, stacked below the last
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub input_columns: Option<u32>,
+    /// Which way positions run in the input. Vertical sheets have positions
+    /// running down and animation frames running right, with `input_columns`
+    /// wrapping positions in to new columns instead of rows
+    #[serde(default)]
+    pub layout: InputLayout,
+    /// Alternative positions for each corner type. Every junction picks
+    /// between them and the one in `positions` for each of its corners, so
+    /// junctions vary from each other
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub corner_variants: Option<CornerVariants>,
+    /// Png, relative to the config, that corners are read from instead of
+    /// the input, for edge art shared between sheets. The input then only
+    /// supplies prefabs and prefab overlays
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub corner_atlas: Option<String>,
+    /// The image `corner_atlas` points to, loaded in by
+    /// `IconOperation::load_side_files`
+    #[serde(skip)]
+    pub corner_atlas_image: Option<DynamicImage>,
+    /// Seed `corner_variants` are picked with, the same seed always picks
+    /// the same ones. 0 if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub variant_seed: Option<u32>,
     pub cut_pos: CutPosition,
+    /// Cut positions for single corners, overriding `cut_pos` for them, for
+    /// asymmetric tiles like tables with a thick south rim. Each corner is
+    /// placed in the output at the same spot it's cut from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub corner_cut_pos: Option<CornerCutPositions>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
     pub animation: Option<Animation>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
     pub prefabs: Option<Prefabs>,
+    /// Images of the prefabs read from their own `file`, by file, loaded in
+    /// by `IconOperation::load_side_files`
+    #[serde(skip)]
+    pub prefab_images: HashMap<String, DynamicImage>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
     pub prefab_overlays: Option<PrefabOverlays>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
     pub map_icon: Option<MapIcon>,
+    /// Additionally emit a cardinal only set of states (diagonal bits ignored)
+    /// when smoothing diagonally
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub cardinal_set: Option<CardinalSetOutput>,
+    /// Generate states for junctions with an orphaned corner instead of
+    /// leaving them out, for codebases that set diagonal bits on their own
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub orphaned_corners: Option<OrphanedCorners>,
+    /// Small text grid of filled (any character) and empty (`.` or space)
+    /// tiles, rendered as a smoothed preview in debug mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub preview_map: Option<String>,
+    /// Hotspot set on every generated state
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub hotspot: Option<IconHotspot>,
+    /// Junctions to emit again as extra states on a different sized canvas.
+    /// If any canvas is larger than `output_icon_size`, every state is padded
+    /// up to it, since a dmi only has one icon size
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub size_overrides: Option<Vec<SizeOverride>>,
+    /// 45 degree diagonal walls emitted alongside the regular states, for
+    /// tiles connected on two sides that meet at a corner
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub diagonal_walls: Option<DiagonalWalls>,
+    /// Junctions whose states only use some of the input's frames. A junction
+    /// listed in more than one uses the first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub frame_ranges: Option<Vec<FrameRange>>,
+    /// Junctions to emit, leaving out every other state. Prefabs and size
+    /// overrides still apply to the ones that are emitted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub only_states: Option<Vec<u8>>,
+    /// Junctions to leave out, for combinations a codebase never uses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub skip_states: Option<Vec<u8>>,
+    /// How junction states are named, like `{prefix}-{bits}` or
+    /// `{prefix}_{cardinal_letters}`. Defaults to `{prefix}-{tag}-{bits}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub state_name_format: Option<StateNameFormat>,
 }
 
 impl IconOperationConfig for BitmaskSlice {
@@ -78,15 +246,18 @@ impl IconOperationConfig for BitmaskSlice {
         &self,
         input: &InputIcon,
         mode: OperationMode,
+        cancel: &CancellationToken,
     ) -> ProcessorResult<ProcessorPayload> {
         debug!("Starting bitmask slice icon op");
         let InputIcon::DynamicImage(img) = input else {
             return Err(ProcessorError::ImageNotFound);
         };
+        let (img, movement) = self.split_movement(img)?;
+        let img = img.as_ref();
         let (corners, prefabs) = self.generate_corners(img)?;
-
-        let (_in_x, in_y) = img.dimensions();
-        let num_frames = in_y / self.icon_size.y;
+        let variants = self.generate_corner_variants(img)?;
+        let num_frames = self.frame_count(img)?;
+        let mut warnings: Vec<ProcessorWarning> = self.leftover_rows(img).into_iter().collect();
 
         let possible_states = if self.smooth_diagonally {
             SIZE_OF_DIAGONALS
@@ -94,98 +265,394 @@ impl IconOperationConfig for BitmaskSlice {
             SIZE_OF_CARDINALS
         };
 
-        let icon_directions = if self.produce_dirs {
-            Adjacency::dmi_cardinals().to_vec()
-        } else {
-            vec![Adjacency::S]
-        };
-
         // First phase: generate icons
-        let assembled = self.generate_icons(&corners, &prefabs, num_frames, possible_states);
+        let mut assembled = self.generate_icons(
+            &corners,
+            &variants,
+            &prefabs,
+            num_frames,
+            possible_states,
+            cancel,
+        )?;
+        self.apply_prefab_overlays(img, &mut assembled)?;
+        warnings.extend(self.skipped_junctions(&assembled, possible_states));
 
         // Second phase: map to byond icon states and produce dirs if need
         // Even though this is the same loop as what happens in generate_icons,
         // all states need to be generated first for the
         // Rotation to work correctly, so it must be done as a second loop.
-        let mut icon_states = vec![];
+        let dir_rotations = if self.produce_dirs && self.collapse_rotations {
+            let rotations = self.find_dir_rotations(&assembled, possible_states);
+            if rotations.is_none() {
+                warnings.push(ProcessorWarning::new(
+                    "Directions are not exact rotations of south, leaving them uncollapsed",
+                ));
+            }
+            rotations
+        } else {
+            None
+        };
+        let collapsed_config;
+        let state_config = if dir_rotations.is_some() {
+            collapsed_config = BitmaskSlice {
+                produce_dirs: false,
+                ..self.clone()
+            };
+            &collapsed_config
+        } else {
+            self
+        };
 
-        let delay = self
-            .animation
-            .clone()
-            .map(|x| repeat_for(&x.delays, num_frames as usize));
-        let rewind = self
-            .animation
-            .as_ref()
-            .and_then(|animation| animation.rewind)
-            .unwrap_or(false);
+        let mut icon_states =
+            state_config.build_icon_states(&assembled, possible_states, num_frames, None);
 
-        let states_to_gen = (0..possible_states)
-            .map(|x| Adjacency::from_bits(x as u8).unwrap())
-            .filter(Adjacency::ref_has_no_orphaned_corner);
-        for adjacency in states_to_gen {
-            let mut icon_state_frames = vec![];
+        if let Some(movement) = &movement {
+            let (corners, prefabs) = self.generate_corners(movement)?;
+            let variants = self.generate_corner_variants(movement)?;
+            let mut moving = self.generate_icons(
+                &corners,
+                &variants,
+                &prefabs,
+                num_frames,
+                possible_states,
+                cancel,
+            )?;
+            self.apply_prefab_overlays(movement, &mut moving)?;
+            icon_states.extend(
+                state_config
+                    .build_icon_states(&moving, possible_states, num_frames, None)
+                    .into_iter()
+                    .map(|state| {
+                        IconState {
+                            movement: true,
+                            ..state
+                        }
+                    }),
+            );
+        }
 
-            for icon_state_dir in &icon_directions {
-                let rotated_sig = adjacency.rotate_to(*icon_state_dir);
-                trace!(sig = ?icon_state_dir, rotated_sig = ?rotated_sig, "Rotated");
-                icon_state_frames.extend(assembled[&rotated_sig].clone());
+        // Cardinal states are a subset of the diagonal ones, since a junction
+        // without diagonal bits resolves to the same corners either way
+        let mut cardinal_states = match self.cardinal_set {
+            Some(CardinalSetOutput::SameIcon) => {
+                icon_states.extend(state_config.build_icon_states(
+                    &assembled,
+                    SIZE_OF_CARDINALS,
+                    num_frames,
+                    Some("cardinal"),
+                ));
+                None
             }
-
-            let signature = adjacency.bits();
-            let name = if let Some(prefix_name) = &self.output_name {
-                format!("{prefix_name}-{signature}")
-            } else {
-                format!("{signature}")
-            };
-            icon_states.push(dedupe_frames(IconState {
-                name,
-                dirs: icon_directions.len() as u8,
-                frames: num_frames,
-                images: icon_state_frames,
-                delay: delay.clone(),
-                rewind,
-                ..Default::default()
-            }));
-        }
+            Some(CardinalSetOutput::SeparateIcon) => {
+                Some(state_config.build_icon_states(
+                    &assembled,
+                    SIZE_OF_CARDINALS,
+                    num_frames,
+                    None,
+                ))
+            }
+            None => None,
+        };
 
         if let Some(map_icon) = &self.map_icon {
-            let icon =
-                generate_map_icon(self.output_icon_size.x, self.output_icon_size.y, map_icon)?;
-            icon_states.push(IconState {
+            let icon = generate_map_icon(self.output_size().x, self.output_size().y, map_icon)?;
+            let map_state = IconState {
                 name: map_icon.icon_state_name.clone(),
                 dirs: 1,
                 frames: 1,
                 images: vec![icon],
                 ..Default::default()
-            });
+            };
+            if let Some(cardinal_states) = &mut cardinal_states {
+                cardinal_states.push(map_state.clone());
+            }
+            icon_states.push(map_state);
         }
 
+        icon_states.extend(state_config.diagonal_wall_states(img, num_frames));
+
+        let (width, height) =
+            state_config.add_size_overrides(&assembled, &mut icon_states, num_frames);
+
         let output_icon = Icon {
             version: dmi::icon::DmiVersion::default(),
-            width: self.output_icon_size.x,
-            height: self.output_icon_size.y,
+            width,
+            height,
             states: icon_states,
         };
+        let snippet = self
+            .dm_snippet
+            .then(|| self.smoothing_snippet(&output_icon));
+
+        // the same size as the main icon whatever the size overrides make it,
+        // so either can be used in place of the other
+        let cardinal_icon = cardinal_states.map(|mut states| {
+            for image in states.iter_mut().flat_map(|state| &mut state.images) {
+                if image.width() != width || image.height() != height {
+                    *image = pad_to_canvas(image, width, height);
+                }
+            }
+            NamedIcon {
+                path_hint: None,
+                name_hint: Some("cardinal".to_string()),
+                image: OutputImage::Dmi(Icon {
+                    version: dmi::icon::DmiVersion::default(),
+                    width,
+                    height,
+                    states,
+                }),
+            }
+        });
 
-        if mode == OperationMode::Debug {
+        let payload = if mode == OperationMode::Debug {
             debug!("Starting debug output");
             let mut out = self.generate_debug_icons(&corners);
+            if let Some(map) = &self.preview_map {
+                out.push(NamedIcon::new(
+                    "DEBUGOUT",
+                    "PREVIEW",
+                    OutputImage::Png(self.generate_map_preview(&assembled, map)),
+                ));
+            }
 
             out.push(NamedIcon::from_icon(output_icon));
-            Ok(ProcessorPayload::MultipleNamed(out))
+            out.extend(cardinal_icon);
+            ProcessorPayload::MultipleNamed(out)
+        } else if let Some(cardinal_icon) = cardinal_icon {
+            ProcessorPayload::MultipleNamed(vec![NamedIcon::from_icon(output_icon), cardinal_icon])
         } else {
-            Ok(ProcessorPayload::from_icon(output_icon))
-        }
+            ProcessorPayload::from_icon(output_icon)
+        };
+
+        let dm_code: Vec<String> = dir_rotations
+            .map(|rotations| rotation_note(&rotations))
+            .into_iter()
+            .chain(snippet)
+            .collect();
+        let payload = if dm_code.is_empty() {
+            payload
+        } else {
+            ProcessorPayload::wrap_dm_code(payload, dm_code.join("\n"))
+        };
+        Ok(payload.with_warnings(warnings))
     }
 
     fn verify_config(&self) -> ProcessorResult<()> {
-        // TODO: Actual verification
-        Ok(())
+        ProcessorError::check_all(self.config_problems())
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        match input {
+            InputIcon::DynamicImage(img) => self.image_problems(img, []),
+            InputIcon::Dmi(_) | InputIcon::Aseprite(_) => vec![],
+        }
+    }
+
+    fn config_warnings(&self) -> Vec<ProcessorWarning> {
+        let unused = self.unused_prefabs().into_iter().map(|junction| {
+            ProcessorWarning::new(format!(
+                "Prefab {junction} is for a junction that's never generated, so it's never used. \
+                 Junctions with a diagonal whose sides aren't both connected, or with any \
+                 diagonal without smooth_diagonally, don't exist"
+            ))
+        });
+        let overlapping = self
+            .overlapping_prefabs()
+            .into_iter()
+            .map(|(junction, position)| {
+                ProcessorWarning::new(format!(
+                    "Prefab {junction} shares its position {position} with a corner block in \
+                     positions, so it copies that block instead of its own art"
+                ))
+            });
+        unused.chain(overlapping).collect()
+    }
+
+    fn state_origin(&self, state_name: &str) -> StateOrigin {
+        if self
+            .map_icon
+            .as_ref()
+            .is_some_and(|map_icon| map_icon.icon_state_name == state_name)
+        {
+            return StateOrigin::MapIcon;
+        }
+        // names can be in any format, so check against every name that could
+        // have been given instead of picking them apart
+        if let Some(diagonal_walls) = &self.diagonal_walls {
+            let junction = self
+                .diagonal_wall_junctions()
+                .into_iter()
+                .map(|(junction, _)| junction)
+                .find(|junction| {
+                    self.state_name(Some(&diagonal_walls.name), *junction) == state_name
+                });
+            if let Some(junction) = junction {
+                return StateOrigin::DiagonalWall {
+                    junction: junction.bits(),
+                };
+            }
+        }
+        let tags = [None, Some("cardinal")].into_iter().chain(
+            self.size_overrides
+                .iter()
+                .flatten()
+                .map(|size_override| Some(size_override.name.as_str())),
+        );
+        for tag in tags {
+            let Some(junction) = (0..=u8::MAX).find(|bits| {
+                self.state_name(tag, Adjacency::from_bits(*bits).unwrap()) == state_name
+            }) else {
+                continue;
+            };
+            return match tag {
+                None | Some("cardinal") => {
+                    if self
+                        .prefabs
+                        .as_ref()
+                        .and_then(|prefabs| prefabs.0.get(&junction))
+                        .is_some_and(|prefab| prefab.corners.is_none())
+                    {
+                        StateOrigin::Prefab { junction }
+                    } else {
+                        StateOrigin::Junction { junction }
+                    }
+                }
+                Some(name) => {
+                    StateOrigin::Override {
+                        name: name.to_string(),
+                        junction,
+                    }
+                }
+            };
+        }
+        StateOrigin::Unknown
+    }
+}
+
+fn rotate_clockwise(image: &DynamicImage, angle: u32) -> DynamicImage {
+    match angle {
+        90 => image.rotate90(),
+        180 => image.rotate180(),
+        270 => image.rotate270(),
+        _ => image.clone(),
+    }
+}
+
+fn rotation_note(rotations: &[(Adjacency, u32)]) -> String {
+    let mut note = vec![
+        "// Generated by hypnagogic".to_string(),
+        "// Every direction of this icon was an exact rotation of the south direction, so only \
+         south was kept."
+            .to_string(),
+        "// Rotate at runtime to get the other directions, e.g. `transform = matrix().Turn(angle)`"
+            .to_string(),
+        "// Angles are in degrees clockwise:".to_string(),
+    ];
+    for (direction, angle) in rotations {
+        let dir_name = match *direction {
+            Adjacency::N => "NORTH",
+            Adjacency::S => "SOUTH",
+            Adjacency::E => "EAST",
+            _ => "WEST",
+        };
+        note.push(format!("// {dir_name} = {angle}"));
+    }
+    note.push(String::new());
+    note.join("\n")
+}
+
+/// Mixes `seed`, `adjacency` and `corner` in to a well spread number, the
+/// same on every platform so picked variants don't change between machines
+fn variant_roll(seed: u32, adjacency: Adjacency, corner: Corner) -> u64 {
+    let input = (u64::from(seed) << 32) | (u64::from(adjacency.bits()) << 8) | corner as u64;
+    // splitmix64
+    let mut mixed = input.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    mixed = (mixed ^ (mixed >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    mixed = (mixed ^ (mixed >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    mixed ^ (mixed >> 31)
+}
+
+/// Splits a preview map into rows of filled/empty tiles, ignoring blank lines
+/// at the start and end so multiline toml strings can be used
+fn parse_preview_map(map: &str) -> Vec<Vec<bool>> {
+    map.trim_matches(|c| c == '\n' || c == '\r')
+        .lines()
+        .map(|line| line.chars().map(|c| !matches!(c, '.' | ' ')).collect())
+        .collect()
+}
+
+/// `name` as a dm identifier for defines, in capitals with anything that
+/// can't be in one swapped for `_`, and a `_` in front of a leading digit
+fn define_name(name: &str) -> String {
+    let mut define: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if define.is_empty() || define.starts_with(|c: char| c.is_ascii_digit()) {
+        define.insert(0, '_');
+    }
+    define
+}
+
+/// Sizes along one axis of an input sheet, with how to describe them
+#[derive(Copy, Clone, Debug)]
+struct Axis {
+    /// Size of the input
+    extent: u32,
+    /// Size of a tile
+    tile: u32,
+    /// `height` or `width`
+    dimension: &'static str,
+    /// `tall` or `wide`
+    described: &'static str,
+    /// The config key setting the size of a tile
+    key: &'static str,
+    /// What lines of tiles across the axis are called, `rows` or `columns`
+    lines: &'static str,
+}
+
+impl Axis {
+    fn height(img: &DynamicImage, icon_size: IconSize) -> Self {
+        Self {
+            extent: img.height(),
+            tile: icon_size.y,
+            dimension: "height",
+            described: "tall",
+            key: "icon_size.y",
+            lines: "rows",
+        }
+    }
+
+    fn width(img: &DynamicImage, icon_size: IconSize) -> Self {
+        Self {
+            extent: img.width(),
+            tile: icon_size.x,
+            dimension: "width",
+            described: "wide",
+            key: "icon_size.x",
+            lines: "columns",
+        }
     }
 }
 
 type CornerPayload = Map<CornerType, Map<Corner, Vec<DynamicImage>>>;
-type PrefabPayload = HashMap<Adjacency, Vec<DynamicImage>>;
+type PrefabPayload = HashMap<Adjacency, PrefabBlock>;
+
+/// Frames of a prefab, cut into the corners it replaces if it doesn't
+/// replace the whole state
+#[derive(Clone, Debug)]
+pub enum PrefabBlock {
+    Whole(Vec<DynamicImage>),
+    Corners(Map<Corner, Vec<DynamicImage>>),
+}
+/// The blocks cut from each corner type's `corner_variants`, in order
+pub type VariantPayload = Map<CornerType, Vec<Map<Corner, Vec<DynamicImage>>>>;
 
 // possible icon set is the powerset of the possible directions
 // the size of a powerset is always 2^n where n is number of discrete elements
@@ -200,6 +667,7 @@ impl BitmaskSlice {
         position: u32,
         num_frames: u32,
     ) -> Map<Corner, Vec<DynamicImage>> {
+        let grid = self.input_grid(img);
         let mut out = Map::new();
 
         for corner in all::<Corner>() {
@@ -207,33 +675,193 @@ impl BitmaskSlice {
             for frame_num in 0..num_frames {
                 let frame_vec = out.get_mut(corner).unwrap();
 
-                let (x_side, y_side) = corner.sides_of_corner();
-
-                let x_spacing = self.get_side_info(x_side);
-                let y_spacing = self.get_side_info(y_side);
-                let x_offset = x_spacing.start;
-                let y_offset = y_spacing.start;
-
-                let x = (position * self.icon_size.x) + x_offset;
-                let y = (frame_num * self.icon_size.y) + y_offset;
-
-                let width = x_spacing.step();
-                let height = y_spacing.step();
-                trace!(
-                    corner = ?corner,
-                    x = ?x,
-                    y = ?y,
-                    width = ?width,
-                    height = ?height,
-                    "Ready to generate image"
+                let rect = cut_rect(
+                    self.icon_size,
+                    self.corner_cut(corner),
+                    grid,
+                    corner,
+                    position,
+                    frame_num,
                 );
-                let corner_img = img.crop_imm(x, y, width, height);
+                trace!(corner = ?corner, rect = ?rect, "Ready to generate image");
+                let corner_img = img.crop_imm(rect.x, rect.y, rect.width, rect.height);
                 frame_vec.push(corner_img);
             }
         }
         out
     }
 
+    /// Builds flat corners from the middle of the concave block at `position`,
+    /// for `derive_flat`
+    #[must_use]
+    pub fn build_derived_flat(
+        &self,
+        img: &DynamicImage,
+        position: u32,
+        num_frames: u32,
+    ) -> Map<Corner, Vec<DynamicImage>> {
+        let grid = self.input_grid(img);
+        let mut out = Map::new();
+        for corner in all::<Corner>() {
+            let frames = (0..num_frames)
+                .map(|frame| {
+                    let tile = tile_rect(self.icon_size, grid, position, frame);
+                    let rect =
+                        centered_corner_rect(self.icon_size, self.corner_cut(corner), corner)
+                            .offset(tile.x, tile.y);
+                    img.crop_imm(rect.x, rect.y, rect.width, rect.height)
+                })
+                .collect();
+            out.insert(corner, frames);
+        }
+        out
+    }
+
+    /// Sizes of `img` along the axis frames are stacked on
+    fn frame_axis(&self, img: &DynamicImage) -> Axis {
+        match self.layout {
+            InputLayout::Horizontal => Axis::height(img, self.icon_size),
+            InputLayout::Vertical => Axis::width(img, self.icon_size),
+        }
+    }
+
+    /// Sizes of `img` along the axis positions run on
+    fn position_axis(&self, img: &DynamicImage) -> Axis {
+        match self.layout {
+            InputLayout::Horizontal => Axis::width(img, self.icon_size),
+            InputLayout::Vertical => Axis::height(img, self.icon_size),
+        }
+    }
+
+    /// Splits `img` in to its regular and movement halves along the axis
+    /// frames are stacked on, if `movement_states` is set
+    /// # Errors
+    /// Errors if the input doesn't split in to two halves of whole tiles
+    pub fn split_movement<'a>(
+        &self,
+        img: &'a DynamicImage,
+    ) -> ProcessorResult<(Cow<'a, DynamicImage>, Option<DynamicImage>)> {
+        if !self.movement_states {
+            return Ok((Cow::Borrowed(img), None));
+        }
+        let axis = self.frame_axis(img);
+        if axis.tile == 0 || !axis.extent.is_multiple_of(2 * axis.tile) {
+            return Err(ProcessorError::ConfigError(format!(
+                "The input is {}px {}, which doesn't split in to regular and movement halves of \
+                 whole {}px {}",
+                axis.extent, axis.described, axis.tile, axis.lines
+            )));
+        }
+        let half = axis.extent / 2;
+        let (regular, movement) = match self.layout {
+            InputLayout::Horizontal => {
+                (
+                    img.crop_imm(0, 0, img.width(), half),
+                    img.crop_imm(0, half, img.width(), half),
+                )
+            }
+            InputLayout::Vertical => {
+                (
+                    img.crop_imm(0, 0, half, img.height()),
+                    img.crop_imm(half, 0, half, img.height()),
+                )
+            }
+        };
+        Ok((Cow::Owned(regular), Some(movement)))
+    }
+
+    /// How the tiles of `img` are arranged. Wrapped inputs are split evenly
+    /// between their frames
+    #[must_use]
+    pub fn input_grid(&self, img: &DynamicImage) -> InputGrid {
+        let Some(columns) = self.input_columns else {
+            return InputGrid {
+                layout: self.layout,
+                ..InputGrid::default()
+            };
+        };
+        let frames = self
+            .animation
+            .as_ref()
+            .and_then(|animation| animation.frames)
+            .unwrap_or(1)
+            .max(1);
+        let axis = self.frame_axis(img);
+        InputGrid {
+            layout: self.layout,
+            columns: Some(columns),
+            rows: (axis.extent / axis.tile / frames).max(1),
+        }
+    }
+
+    /// Works out how many frames the input has. If `animation.frames` is set
+    /// the input height (or width, laid out vertically) has to match it
+    /// exactly, otherwise it's inferred from it, ignoring any leftover rows.
+    /// Wrapped inputs have a single frame unless `animation.frames` says
+    /// otherwise
+    /// # Errors
+    /// Errors if the input size doesn't match `animation.frames`
+    pub fn frame_count(&self, img: &DynamicImage) -> ProcessorResult<u32> {
+        let axis = self.frame_axis(img);
+        let expected = self
+            .animation
+            .as_ref()
+            .and_then(|animation| animation.frames);
+        if self.input_columns.is_some() {
+            let frames = expected.unwrap_or(1);
+            let frame_size = frames * axis.tile;
+            if frame_size == 0
+                || axis.extent < frame_size
+                || !axis.extent.is_multiple_of(frame_size)
+            {
+                return Err(ProcessorError::ConfigError(format!(
+                    "The input is {}px {}, which doesn't split in to {frames} frames of whole \
+                     {}px {}",
+                    axis.extent, axis.described, axis.tile, axis.lines
+                )));
+            }
+            return Ok(frames);
+        }
+        if let Some(expected) = expected {
+            if axis.extent != expected * axis.tile {
+                return Err(match self.layout {
+                    InputLayout::Horizontal => {
+                        ProcessorError::FrameCountMismatch {
+                            expected,
+                            image_height: axis.extent,
+                            icon_height: axis.tile,
+                        }
+                    }
+                    InputLayout::Vertical => {
+                        ProcessorError::ConfigError(format!(
+                            "Expected {expected} frames of {}px each, but the input is {}px wide, \
+                             which would need to be {}px",
+                            axis.tile,
+                            axis.extent,
+                            expected * axis.tile
+                        ))
+                    }
+                });
+            }
+            return Ok(expected);
+        }
+        Ok(axis.extent / axis.tile)
+    }
+
+    /// Warns about rows at the bottom of the input (or columns at the right,
+    /// laid out vertically) that [`Self::frame_count`] ignores, left over
+    /// after its last whole frame
+    #[must_use]
+    pub fn leftover_rows(&self, img: &DynamicImage) -> Option<ProcessorWarning> {
+        let axis = self.frame_axis(img);
+        (!axis.extent.is_multiple_of(axis.tile)).then(|| {
+            ProcessorWarning::new(format!(
+                "Input {} {} isn't a multiple of {} {}, ignoring the leftover {}",
+                axis.dimension, axis.extent, axis.key, axis.tile, axis.lines
+            ))
+        })
+    }
+
     /// Generates corners
     /// # Errors
     /// Errors on malformed image
@@ -244,9 +872,7 @@ impl BitmaskSlice {
         &self,
         img: &DynamicImage,
     ) -> ProcessorResult<(CornerPayload, PrefabPayload)> {
-        let (_width, height) = img.dimensions();
-
-        let num_frames = height / self.icon_size.y;
+        let num_frames = self.frame_count(img)?;
 
         let corner_types = if self.smooth_diagonally {
             CornerType::diagonal()
@@ -255,11 +881,24 @@ impl BitmaskSlice {
         };
 
         let mut corner_map: CornerPayload = Map::new();
+        let source = self.corner_source(img)?;
 
         for corner_type in &corner_types[..] {
-            let position = self.positions.get(*corner_type).unwrap();
+            if *corner_type == CornerType::Flat && self.derive_flat {
+                if let Some(concave) = self.positions.get(CornerType::Concave) {
+                    corner_map.insert(
+                        CornerType::Flat,
+                        self.build_derived_flat(source, concave, num_frames),
+                    );
+                }
+                continue;
+            }
+            // empty slots get no corners, junctions that need them are skipped
+            let Some(position) = self.positions.get(*corner_type) else {
+                continue;
+            };
 
-            let corners = self.build_corner(img, position, num_frames);
+            let corners = self.build_corner(source, position, num_frames);
 
             corner_map.insert(*corner_type, corners);
         }
@@ -267,76 +906,241 @@ impl BitmaskSlice {
         let mut prefabs: PrefabPayload = HashMap::new();
 
         if let Some(prefabs_config) = &self.prefabs {
-            for (adjacency_bits, position) in &prefabs_config.0 {
+            for (adjacency_bits, prefab) in &prefabs_config.0 {
+                let source = self.prefab_source(img, prefab)?;
+                let adjacency = Adjacency::from_bits(*adjacency_bits).unwrap();
+                if prefab.corners.is_some() {
+                    let mut block = self.build_corner(source, prefab.position, num_frames);
+                    block.retain(|corner, _| prefab.covers(corner));
+                    prefabs.insert(adjacency, PrefabBlock::Corners(block));
+                    continue;
+                }
+                let grid = self.input_grid(source);
                 let mut frame_vector = vec![];
                 for frame in 0..num_frames {
-                    let x = position * self.icon_size.x;
-                    let y = frame * self.icon_size.y;
-                    let img = img.crop_imm(x, y, self.icon_size.x, self.icon_size.y);
+                    let rect = tile_rect(self.icon_size, grid, prefab.position, frame);
+                    let img = source.crop_imm(rect.x, rect.y, rect.width, rect.height);
 
                     frame_vector.push(img);
                 }
-                prefabs.insert(Adjacency::from_bits(*adjacency_bits).unwrap(), frame_vector);
+                prefabs.insert(adjacency, PrefabBlock::Whole(frame_vector));
             }
         }
 
         Ok((corner_map, prefabs))
     }
 
-    /// Blah
-    /// # Panics
-    /// Whatever
+    /// Draws the `prefab_overlays` of each junction in `assembled` over it,
+    /// reading them from `img`
+    /// # Errors
+    /// Errors on malformed image
+    pub fn apply_prefab_overlays(
+        &self,
+        img: &DynamicImage,
+        assembled: &mut BTreeMap<Adjacency, Vec<DynamicImage>>,
+    ) -> ProcessorResult<()> {
+        for (adjacency, frames) in assembled.iter_mut() {
+            self.overlay_junction(img, *adjacency, frames)?;
+        }
+        Ok(())
+    }
+
+    /// Draws the `prefab_overlays` of `adjacency` over its `frames`, reading
+    /// them from `img`
+    /// # Errors
+    /// Errors on malformed image
+    pub fn overlay_junction(
+        &self,
+        img: &DynamicImage,
+        adjacency: Adjacency,
+        frames: &mut [DynamicImage],
+    ) -> ProcessorResult<()> {
+        let Some(overlays) = self
+            .prefab_overlays
+            .as_ref()
+            .and_then(|prefab_overlays| prefab_overlays.0.get(&adjacency.bits()))
+        else {
+            return Ok(());
+        };
+        let num_frames = self.frame_count(img)?;
+        let grid = self.input_grid(img);
+        let position = self.output_pos();
+        for (frame, image) in (0..num_frames).zip(frames.iter_mut()) {
+            for overlay in overlays {
+                let rect = tile_rect(self.icon_size, grid, overlay.position, frame);
+                let tile = img.crop_imm(rect.x, rect.y, rect.width, rect.height);
+                blend_onto_faded(
+                    image,
+                    &tile,
+                    i64::from(position.x),
+                    i64::from(position.y),
+                    overlay.blend,
+                    overlay.opacity,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The image corners are cut from, `corner_atlas` if it's set and the
+    /// input otherwise
+    /// # Errors
+    /// Errors if `corner_atlas` is set but its image hasn't been loaded
+    pub fn corner_source<'a>(&'a self, img: &'a DynamicImage) -> ProcessorResult<&'a DynamicImage> {
+        match (&self.corner_atlas, &self.corner_atlas_image) {
+            (None, _) => Ok(img),
+            (Some(_), Some(atlas)) => Ok(atlas),
+            (Some(path), None) => {
+                Err(ProcessorError::ConfigError(format!(
+                    "corner_atlas \"{path}\" hasn't been loaded"
+                )))
+            }
+        }
+    }
+
+    /// The image `prefab` is read from, its `file` if it has one and the
+    /// input otherwise
+    /// # Errors
+    /// Errors if the prefab's file hasn't been loaded
+    pub fn prefab_source<'a>(
+        &'a self,
+        img: &'a DynamicImage,
+        prefab: &Prefab,
+    ) -> ProcessorResult<&'a DynamicImage> {
+        let Some(file) = &prefab.file else {
+            return Ok(img);
+        };
+        self.prefab_images.get(file).ok_or_else(|| {
+            ProcessorError::ConfigError(format!("Prefab file \"{file}\" hasn't been loaded"))
+        })
+    }
+
+    /// Cuts the blocks of every corner type's `corner_variants`
+    /// # Errors
+    /// Errors on malformed image
+    pub fn generate_corner_variants(&self, img: &DynamicImage) -> ProcessorResult<VariantPayload> {
+        let mut variants = Map::new();
+        let Some(corner_variants) = &self.corner_variants else {
+            return Ok(variants);
+        };
+        let num_frames = self.frame_count(img)?;
+        let source = self.corner_source(img)?;
+        for (corner_type, positions) in &corner_variants.0 {
+            let blocks = positions
+                .iter()
+                .map(|position| self.build_corner(source, *position, num_frames))
+                .collect();
+            variants.insert(*corner_type, blocks);
+        }
+        Ok(variants)
+    }
+
+    /// Whether `adjacency` is generated, rather than left out for having an
+    /// orphaned corner
+    #[must_use]
+    pub fn keeps_junction(&self, adjacency: Adjacency) -> bool {
+        self.orphaned_corners.is_some() || adjacency.has_no_orphaned_corner()
+    }
+
+    /// The type of corner used for `corner` of `adjacency`, going by
+    /// `orphaned_corners`
     #[must_use]
+    pub fn corner_type(&self, adjacency: Adjacency, corner: Corner) -> CornerType {
+        if self.orphaned_corners == Some(OrphanedCorners::Concave) && adjacency.is_orphaned(corner)
+        {
+            return CornerType::Concave;
+        }
+        adjacency.get_corner_type(corner)
+    }
+
+    /// Which of `corner_variants` the `corner` of `adjacency` uses, 0 for the
+    /// block in `positions` and 1 onwards for the variants in order
+    #[must_use]
+    pub fn corner_variant(&self, adjacency: Adjacency, corner: Corner) -> usize {
+        let count = self
+            .corner_variants
+            .as_ref()
+            .and_then(|variants| variants.0.get(&self.corner_type(adjacency, corner)))
+            .map_or(0, Vec::len);
+        if count == 0 {
+            return 0;
+        }
+        let roll = variant_roll(self.variant_seed.unwrap_or(0), adjacency, corner);
+        (roll % (count as u64 + 1)) as usize
+    }
+
+    /// Assembles the frames of every junction below `possible_states`, from
+    /// prefabs where available and corners otherwise. Junctions needing a
+    /// corner type missing from `corners` (an empty position slot) are left
+    /// out
+    /// # Errors
+    /// Errors if `cancel` is cancelled part way through
+    /// # Panics
+    /// Shouldn't panic
     pub fn generate_icons(
         &self,
         corners: &CornerPayload,
+        variants: &VariantPayload,
         prefabs: &PrefabPayload,
         num_frames: u32,
         possible_states: usize,
-    ) -> BTreeMap<Adjacency, Vec<DynamicImage>> {
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<BTreeMap<Adjacency, Vec<DynamicImage>>> {
         let mut assembled: BTreeMap<Adjacency, Vec<DynamicImage>> = BTreeMap::new();
+        let (size, position) = (self.output_size(), self.output_pos());
         for signature in 0..possible_states {
+            cancel.check()?;
             let adjacency = Adjacency::from_bits(signature as u8).unwrap();
+            let prefab = prefabs.get(&adjacency);
+            let corner_prefab = |corner: Corner| {
+                match prefab {
+                    Some(PrefabBlock::Corners(blocks)) => blocks.get(corner),
+                    _ => None,
+                }
+            };
+            if !matches!(prefab, Some(PrefabBlock::Whole(_)))
+                && all::<Corner>().any(|corner| {
+                    corner_prefab(corner).is_none()
+                        && !corners.contains_key(self.corner_type(adjacency, corner))
+                })
+            {
+                continue;
+            }
             let mut icon_state_images = vec![];
             for frame in 0..num_frames {
-                if prefabs.contains_key(&adjacency) {
-                    let mut frame_image =
-                        DynamicImage::new_rgba8(self.output_icon_size.x, self.output_icon_size.y);
+                if let Some(PrefabBlock::Whole(prefab_frames)) = prefab {
+                    let mut frame_image = DynamicImage::new_rgba8(size.x, size.y);
                     imageops::replace(
                         &mut frame_image,
-                        prefabs
-                            .get(&adjacency)
-                            .unwrap()
-                            .get(frame as usize)
-                            .unwrap(),
-                        self.output_icon_pos.x as i64,
-                        self.output_icon_pos.y as i64,
+                        prefab_frames.get(frame as usize).unwrap(),
+                        i64::from(position.x),
+                        i64::from(position.y),
                     );
 
                     icon_state_images.push(frame_image);
                 } else {
-                    let mut frame_image =
-                        DynamicImage::new_rgba8(self.output_icon_size.x, self.output_icon_size.y);
+                    let mut frame_image = DynamicImage::new_rgba8(size.x, size.y);
 
                     for corner in all::<Corner>() {
-                        let corner_type = adjacency.get_corner_type(corner);
-                        let corner_img = &corners
-                            .get(corner_type)
-                            .unwrap()
-                            .get(corner)
-                            .unwrap()
-                            .get(frame as usize)
-                            .unwrap();
-
-                        let (horizontal, vertical) = corner.sides_of_corner();
-                        let horizontal = self.get_side_info(horizontal);
-                        let vertical = self.get_side_info(vertical);
+                        let corner_type = self.corner_type(adjacency, corner);
+                        let block = match self.corner_variant(adjacency, corner) {
+                            0 => corners.get(corner_type),
+                            variant => {
+                                variants
+                                    .get(corner_type)
+                                    .and_then(|blocks| blocks.get(variant - 1))
+                            }
+                        };
+                        let corner_frames = corner_prefab(corner)
+                            .unwrap_or_else(|| block.unwrap().get(corner).unwrap());
+                        let corner_img = &corner_frames.get(frame as usize).unwrap();
 
+                        let placement = self.corner_placement(corner);
                         imageops::overlay(
                             &mut frame_image,
                             *corner_img,
-                            horizontal.start as i64,
-                            vertical.start as i64,
+                            i64::from(position.x + placement.x),
+                            i64::from(position.y + placement.y),
                         );
                     }
                     icon_state_images.push(frame_image);
@@ -344,11 +1148,1056 @@ impl BitmaskSlice {
             }
             assembled.insert(adjacency, icon_state_images);
         }
-        assembled
+        Ok(assembled)
     }
 
-    /// Generates debug outputs for bitmask slice
-    /// # Panics
+    /// Maps assembled icons to byond icon states for every junction below
+    /// `possible_states` that isn't left out for an orphaned corner, producing
+    /// dirs if configured to. If a `name_tag` is passed it's inserted
+    /// between the output name and junction in each state name. Junctions
+    /// missing from `assembled` in any dir are skipped
+    #[must_use]
+    pub fn build_icon_states(
+        &self,
+        assembled: &BTreeMap<Adjacency, Vec<DynamicImage>>,
+        possible_states: usize,
+        num_frames: u32,
+        name_tag: Option<&str>,
+    ) -> Vec<IconState> {
+        let states_to_gen = (0..possible_states)
+            .map(|x| Adjacency::from_bits(x as u8).unwrap())
+            .filter(|adjacency| self.keeps_junction(*adjacency))
+            .filter(|adjacency| self.emits_state(*adjacency));
+        self.build_states_for(assembled, states_to_gen, num_frames, name_tag)
+    }
+
+    /// Same as [`BitmaskSlice::build_icon_states`], for a specific set of
+    /// junctions
+    fn build_states_for(
+        &self,
+        assembled: &BTreeMap<Adjacency, Vec<DynamicImage>>,
+        junctions: impl Iterator<Item = Adjacency>,
+        num_frames: u32,
+        name_tag: Option<&str>,
+    ) -> Vec<IconState> {
+        let icon_directions = if self.produce_dirs {
+            Side::dmi_cardinals().to_vec()
+        } else {
+            vec![Side::South]
+        };
+
+        let delay = self
+            .animation
+            .clone()
+            .map(|x| repeat_for(&x.delays_in_deciseconds(), num_frames as usize));
+        let rewind = self
+            .animation
+            .as_ref()
+            .and_then(|animation| animation.rewind)
+            .unwrap_or(false);
+        let hotspot = self.hotspot.map(IconHotspot::dmi_hotspot);
+        let loop_flag = self
+            .animation
+            .as_ref()
+            .map_or(Looping::Indefinitely, Animation::looping);
+        let movement = self
+            .animation
+            .as_ref()
+            .and_then(|animation| animation.movement)
+            .unwrap_or(false);
+
+        let mut icon_states = vec![];
+
+        for adjacency in junctions.filter(|adjacency| self.is_assembled(assembled, *adjacency)) {
+            let mut icon_state_frames = vec![];
+            let frames = self.frame_range(adjacency, num_frames);
+            let selected = frames.start as usize..frames.end as usize;
+
+            for icon_state_dir in &icon_directions {
+                let rotated_sig = adjacency.rotate_to(Adjacency::from(*icon_state_dir));
+                trace!(sig = ?icon_state_dir, rotated_sig = ?rotated_sig, "Rotated");
+                icon_state_frames.extend(
+                    self.place_for_dir(*icon_state_dir, &assembled[&rotated_sig][selected.clone()]),
+                );
+            }
+
+            icon_states.push(dedupe_frames(
+                IconState {
+                    name: self.state_name(name_tag, adjacency),
+                    dirs: icon_directions.len() as u8,
+                    frames: frames.len() as u32,
+                    images: icon_state_frames,
+                    delay: delay
+                        .as_ref()
+                        .filter(|_| frames.len() > 1)
+                        .map(|delay| delay[selected].to_vec()),
+                    rewind,
+                    loop_flag,
+                    movement,
+                    hotspot,
+                    ..Default::default()
+                },
+                self.animation.as_ref(),
+            ));
+        }
+        icon_states
+    }
+
+    /// Frames of the input used by the state for `adjacency`, going by
+    /// `frame_ranges`
+    #[must_use]
+    pub fn frame_range(&self, adjacency: Adjacency, num_frames: u32) -> Range<u32> {
+        let Some(frame_range) = self
+            .frame_ranges
+            .iter()
+            .flatten()
+            .find(|frame_range| frame_range.junctions.contains(&adjacency.bits()))
+        else {
+            return 0..num_frames;
+        };
+        let end = frame_range.end.map_or(num_frames, |end| end + 1);
+        frame_range.start.min(num_frames)..end.min(num_frames)
+    }
+
+    /// Adds the states of every size override to `icon_states`. If any
+    /// override's canvas is larger than `output_icon_size` every state is
+    /// padded up to the largest, since a dmi only has one icon size. Returns
+    /// the resulting icon size
+    pub fn add_size_overrides(
+        &self,
+        assembled: &BTreeMap<Adjacency, Vec<DynamicImage>>,
+        icon_states: &mut Vec<IconState>,
+        num_frames: u32,
+    ) -> (u32, u32) {
+        let size = (self.output_size().x, self.output_size().y);
+        let Some(size_overrides) = &self.size_overrides else {
+            return size;
+        };
+        for size_override in size_overrides {
+            let canvas_size = size_override.output_icon_size;
+            let position = size_override.output_icon_pos;
+            let resized: BTreeMap<Adjacency, Vec<DynamicImage>> = assembled
+                .iter()
+                .map(|(adjacency, frames)| {
+                    let frames = frames
+                        .iter()
+                        .map(|frame| {
+                            let mut canvas = DynamicImage::new_rgba8(canvas_size.x, canvas_size.y);
+                            imageops::overlay(
+                                &mut canvas,
+                                frame,
+                                i64::from(position.x),
+                                i64::from(position.y),
+                            );
+                            canvas
+                        })
+                        .collect();
+                    (*adjacency, frames)
+                })
+                .collect();
+            let junctions = size_override
+                .junctions
+                .iter()
+                .filter_map(|bits| Adjacency::from_bits(*bits));
+            icon_states.extend(self.build_states_for(
+                &resized,
+                junctions,
+                num_frames,
+                Some(&size_override.name),
+            ));
+        }
+
+        let width = size_overrides
+            .iter()
+            .map(|size_override| size_override.output_icon_size.x)
+            .fold(size.0, u32::max);
+        let height = size_overrides
+            .iter()
+            .map(|size_override| size_override.output_icon_size.y)
+            .fold(size.1, u32::max);
+        for state in icon_states.iter_mut() {
+            for image in &mut state.images {
+                if image.width() != width || image.height() != height {
+                    *image = pad_to_canvas(image, width, height);
+                }
+            }
+        }
+        (width, height)
+    }
+
+    /// Junctions a diagonal wall is emitted for, each with the diagonal its
+    /// connections meet at. Only the junction with that diagonal connected
+    /// too is used when smoothing diagonally, since the one without it would
+    /// have an open corner
+    #[must_use]
+    pub fn diagonal_wall_junctions(&self) -> Vec<(Adjacency, Adjacency)> {
+        if self.diagonal_walls.is_none() {
+            return vec![];
+        }
+        Adjacency::diagonals()
+            .into_iter()
+            .map(|diagonal| {
+                let (vertical, horizontal) = diagonal.corner_sides();
+                let sides = vertical | horizontal;
+                if self.smooth_diagonally {
+                    (sides | diagonal, diagonal)
+                } else {
+                    (sides, diagonal)
+                }
+            })
+            .collect()
+    }
+
+    /// States for `diagonal_walls`, each wall copied whole from `img`
+    #[must_use]
+    pub fn diagonal_wall_states(&self, img: &DynamicImage, num_frames: u32) -> Vec<IconState> {
+        let Some(diagonal_walls) = &self.diagonal_walls else {
+            return vec![];
+        };
+        let grid = self.input_grid(img);
+        let positions = diagonal_walls.positions();
+        let walls: BTreeMap<Adjacency, Vec<DynamicImage>> = self
+            .diagonal_wall_junctions()
+            .into_iter()
+            .filter_map(|(junction, diagonal)| {
+                let (_, position) = positions.iter().find(|(wall, _)| *wall == diagonal)?;
+                let frames = (0..num_frames)
+                    .map(|frame| {
+                        let rect = tile_rect(self.icon_size, grid, *position, frame);
+                        img.crop_imm(rect.x, rect.y, rect.width, rect.height)
+                    })
+                    .collect();
+                Some((junction, frames))
+            })
+            .collect();
+        self.build_states_for(
+            &walls,
+            walls.keys().copied(),
+            num_frames,
+            Some(&diagonal_walls.name),
+        )
+    }
+
+    /// Whether a junction was assembled in every dir it's output in
+    fn is_assembled(
+        &self,
+        assembled: &BTreeMap<Adjacency, Vec<DynamicImage>>,
+        adjacency: Adjacency,
+    ) -> bool {
+        if self.produce_dirs {
+            Adjacency::dmi_cardinals()
+                .into_iter()
+                .all(|direction| assembled.contains_key(&adjacency.rotate_to(direction)))
+        } else {
+            assembled.contains_key(&adjacency)
+        }
+    }
+
+    /// Corner types whose positions are cut from
+    fn used_corner_types(&self) -> Vec<CornerType> {
+        if self.smooth_diagonally {
+            CornerType::diagonal()
+        } else {
+            CornerType::cardinal()
+        }
+    }
+
+    /// Everything wrong with the config, so they can all be reported at once
+    #[must_use]
+    pub fn config_problems(&self) -> Vec<ProcessorError> {
+        let mut problems = vec![];
+        let mut problem = |message: String| problems.push(ProcessorError::ConfigError(message));
+        if self.collapse_rotations && !self.produce_dirs {
+            problem("collapse_rotations can only be used when produce_dirs is enabled".to_string());
+        }
+        if let Some(positions) = &self.dir_output_icon_pos {
+            let output_pos = self.output_pos();
+            if self.collapse_rotations
+                && positions.0.values().any(|position| *position != output_pos)
+            {
+                problem(
+                    "collapse_rotations can't be used with dir_output_icon_pos, since dirs placed \
+                     differently aren't rotations of each other"
+                        .to_string(),
+                );
+            }
+            let unused = positions.0.keys().find(|dir| **dir != Side::South);
+            if let Some(dir) = unused.filter(|_| !self.produce_dirs) {
+                problem(format!(
+                    "dir_output_icon_pos.{dir} is set, but only south is output without \
+                     produce_dirs"
+                ));
+            }
+        }
+        if self.cardinal_set.is_some() && !self.smooth_diagonally {
+            problem("cardinal_set can only be used when smooth_diagonally is enabled".to_string());
+        }
+        if self.orphaned_corners.is_some() && !self.smooth_diagonally {
+            problem(
+                "orphaned_corners can only be used when smooth_diagonally is enabled".to_string(),
+            );
+        }
+        if self
+            .animation
+            .as_ref()
+            .is_some_and(|animation| animation.frames == Some(0))
+        {
+            problem("animation.frames has to be at least 1".to_string());
+        }
+        let bad_opacity = self
+            .prefab_overlays
+            .iter()
+            .flat_map(|overlays| &overlays.0)
+            .find(|(_, overlays)| {
+                overlays
+                    .iter()
+                    .any(|overlay| !(0.0..=1.0).contains(&overlay.opacity))
+            });
+        if let Some((junction, _)) = bad_opacity {
+            problem(format!(
+                "prefab_overlays for junction {junction} has an opacity outside of 0 to 1"
+            ));
+        }
+        if let Some(hotspot) = self.hotspot {
+            if hotspot.frame.is_some_and(|frame| frame != 1) {
+                problem(
+                    "hotspot.frame can only be 1, dmis only hold a hotspot for the first frame"
+                        .to_string(),
+                );
+            }
+            let size = self.output_size();
+            if hotspot.x > size.x || hotspot.y > size.y {
+                problem(format!(
+                    "hotspot is at {}, {}, outside the {}x{} output icon",
+                    hotspot.x, hotspot.y, size.x, size.y
+                ));
+            }
+        }
+        if self
+            .animation
+            .as_ref()
+            .is_some_and(|animation| animation.loop_count == Some(0))
+        {
+            problem(
+                "animation.loop has to be at least 1, leave it unset to loop forever".to_string(),
+            );
+        }
+        if self.movement_states
+            && self
+                .animation
+                .as_ref()
+                .is_some_and(|animation| animation.movement.is_some())
+        {
+            problem(
+                "animation.movement marks every state, so it can't be used with movement_states"
+                    .to_string(),
+            );
+        }
+        if self.movement_states && self.corner_atlas.is_some() {
+            problem("movement_states can't be used with a corner_atlas".to_string());
+        }
+        let no_corners = self
+            .prefabs
+            .iter()
+            .flat_map(|prefabs| prefabs.0.iter())
+            .find(|(_, prefab)| prefab.corners.as_ref().is_some_and(Vec::is_empty));
+        if let Some((junction, _)) = no_corners {
+            problem(format!(
+                "Prefab {junction} replaces no corners, leave corners unset to replace the whole \
+                 state"
+            ));
+        }
+        if self.movement_states
+            && self
+                .prefabs
+                .as_ref()
+                .is_some_and(|prefabs| !prefabs.files().is_empty())
+        {
+            problem("movement_states can't be used with prefabs read from a file".to_string());
+        }
+        if self.derive_flat && !self.smooth_diagonally {
+            problem("derive_flat can only be used when smooth_diagonally is enabled".to_string());
+        }
+        if self.derive_flat && self.positions.0.contains_key(CornerType::Flat) {
+            problem("positions.flat is set, but derive_flat replaces it, remove one".to_string());
+        }
+        for corner_type in self.used_corner_types() {
+            if corner_type == CornerType::Flat && self.derive_flat {
+                continue;
+            }
+            if self.positions.get(corner_type).is_none()
+                && !self.positions.is_empty_slot(corner_type)
+            {
+                problem(format!(
+                    "positions.{corner_type} is missing, set it to a position or to \"none\" to \
+                     leave it empty"
+                ));
+            }
+        }
+        for size_override in self.size_overrides.iter().flatten() {
+            if size_override.name.is_empty() {
+                problem("size_overrides need a name to tell their states apart".to_string());
+            }
+            let invalid = size_override
+                .junctions
+                .iter()
+                .find(|bits| !self.generates_junction(**bits));
+            if let Some(invalid) = invalid {
+                problem(format!(
+                    "size_overrides \"{}\" lists junction {invalid}, which is never generated",
+                    size_override.name
+                ));
+            }
+        }
+        if let Some(diagonal_walls) = &self.diagonal_walls {
+            if diagonal_walls.name.is_empty() {
+                problem("diagonal_walls needs a name to tell its states apart".to_string());
+            }
+        }
+        for frame_range in self.frame_ranges.iter().flatten() {
+            let invalid = frame_range
+                .junctions
+                .iter()
+                .find(|bits| !self.generates_junction(**bits));
+            if let Some(invalid) = invalid {
+                problem(format!(
+                    "frame_ranges lists junction {invalid}, which is never generated"
+                ));
+            }
+            if frame_range.end.is_some_and(|end| end < frame_range.start) {
+                problem(format!(
+                    "A frame range ends before it starts, at frame {}",
+                    frame_range.start
+                ));
+            }
+        }
+        for (corner, cut) in self.corner_cut_pos.iter().flat_map(|cuts| &cuts.0) {
+            if cut.x > self.icon_size.x || cut.y > self.icon_size.y {
+                problem(format!(
+                    "corner_cut_pos.{corner} is at {}, {}, outside the {}x{} icon",
+                    cut.x, cut.y, self.icon_size.x, self.icon_size.y
+                ));
+            }
+        }
+        if let Some(bounds) = self.tile_bounds {
+            if bounds.width == 0 || bounds.height == 0 {
+                problem("tile_bounds needs a width and height of at least 1".to_string());
+            }
+            if bounds.x + bounds.width > self.icon_size.x
+                || bounds.y + bounds.height > self.icon_size.y
+            {
+                problem(format!(
+                    "tile_bounds is a {}x{} tile at {}, {}, which goes past the {}x{} icon",
+                    bounds.width,
+                    bounds.height,
+                    bounds.x,
+                    bounds.y,
+                    self.icon_size.x,
+                    self.icon_size.y
+                ));
+            }
+            let (size, position) = (self.output_size(), self.output_pos());
+            if position.x + self.icon_size.x > size.x || position.y + self.icon_size.y > size.y {
+                problem(format!(
+                    "The {}x{} icon placed at {}, {} doesn't fit in the {}x{} output, so its \
+                     overhang would be cut off",
+                    self.icon_size.x, self.icon_size.y, position.x, position.y, size.x, size.y
+                ));
+            }
+        }
+        let used_corner_types = self.used_corner_types();
+        for corner_type in self
+            .corner_variants
+            .iter()
+            .flat_map(|variants| variants.0.keys())
+        {
+            if !used_corner_types.contains(corner_type) {
+                problem(format!(
+                    "corner_variants.{corner_type} is set, but no junction uses {corner_type} \
+                     corners"
+                ));
+            }
+        }
+        if self.input_columns == Some(0) {
+            problem("input_columns has to be at least 1".to_string());
+        }
+        if self.input_columns.is_some()
+            && self
+                .animation
+                .as_ref()
+                .is_some_and(|animation| animation.frames.is_none())
+        {
+            problem(
+                "input_columns needs animation.frames to be set when animated, to tell where each \
+                 frame's grid starts"
+                    .to_string(),
+            );
+        }
+        if self.only_states.as_ref().is_some_and(Vec::is_empty) {
+            problem("only_states is empty, so no states would be emitted".to_string());
+        }
+        for (name, states) in [
+            ("only_states", &self.only_states),
+            ("skip_states", &self.skip_states),
+        ] {
+            let invalid = states
+                .iter()
+                .flatten()
+                .find(|bits| !self.generates_junction(**bits));
+            if let Some(invalid) = invalid {
+                problem(format!(
+                    "{name} lists junction {invalid}, which is never generated"
+                ));
+            }
+        }
+        if let Some(collision) = self.state_name_collision() {
+            problem(collision);
+        }
+        if let Some(map) = &self.preview_map {
+            if !parse_preview_map(map)
+                .iter()
+                .flatten()
+                .any(|filled| *filled)
+            {
+                problem("preview_map needs at least one filled tile".to_string());
+            }
+        }
+        problems
+    }
+
+    /// Everything about `img` that doesn't fit the config, like positions past
+    /// its right edge or animated frames without delays. `extra_positions`
+    /// are corner positions the operation reads on top of the config's own.
+    /// Corner positions are checked against `corner_atlas` instead if it's set
+    #[must_use]
+    pub fn image_problems(
+        &self,
+        img: &DynamicImage,
+        extra_positions: impl IntoIterator<Item = u32>,
+    ) -> Vec<ProcessorError> {
+        let img = match self.split_movement(img) {
+            Ok((regular, _)) => regular,
+            Err(error) => return vec![error],
+        };
+        let img = img.as_ref();
+        let mut problems = vec![];
+        let num_frames = match self.frame_count(img) {
+            Ok(num_frames) => num_frames,
+            Err(error) => {
+                problems.push(error);
+                0
+            }
+        };
+        let corner_positions: Vec<u32> = self
+            .used_corner_types()
+            .iter()
+            .filter_map(|corner_type| self.positions.get(*corner_type))
+            .chain(
+                self.corner_variants
+                    .iter()
+                    .flat_map(|variants| variants.0.values().flatten().copied()),
+            )
+            .chain(extra_positions)
+            .collect();
+        let sheet_positions: Vec<u32> = self
+            .prefabs
+            .iter()
+            .flat_map(|prefabs| prefabs.sheet_positions().map(|(_, position)| position))
+            .chain(
+                self.prefab_overlays
+                    .iter()
+                    .flat_map(PrefabOverlays::positions),
+            )
+            .chain(self.diagonal_walls.iter().flat_map(|diagonal_walls| {
+                diagonal_walls.positions().map(|(_, position)| position)
+            }))
+            .collect();
+        match (&self.corner_atlas, &self.corner_atlas_image) {
+            (None, _) => {
+                problems.extend(self.position_problems(
+                    img,
+                    "input",
+                    corner_positions.into_iter().chain(sheet_positions),
+                ));
+            }
+            (Some(_), Some(atlas)) => {
+                match self.frame_count(atlas) {
+                    Ok(atlas_frames) if atlas_frames != num_frames && num_frames > 0 => {
+                        problems.push(ProcessorError::ConfigError(format!(
+                            "The corner_atlas has {atlas_frames} frames, but the input has \
+                             {num_frames}"
+                        )));
+                    }
+                    Ok(_) => {}
+                    Err(error) => problems.push(error),
+                }
+                problems.extend(self.position_problems(atlas, "corner_atlas", corner_positions));
+                problems.extend(self.position_problems(img, "input", sheet_positions));
+            }
+            (Some(_), None) => {
+                problems.extend(self.corner_source(img).err());
+                problems.extend(self.position_problems(img, "input", sheet_positions));
+            }
+        }
+        for (file, positions) in self.prefabs.iter().flat_map(Prefabs::files) {
+            let Some(image) = self.prefab_images.get(file) else {
+                problems.push(ProcessorError::ConfigError(format!(
+                    "Prefab file \"{file}\" hasn't been loaded"
+                )));
+                continue;
+            };
+            match self.frame_count(image) {
+                Ok(file_frames) if file_frames != num_frames && num_frames > 0 => {
+                    problems.push(ProcessorError::ConfigError(format!(
+                        "Prefab file \"{file}\" has {file_frames} frames, but the input has \
+                         {num_frames}"
+                    )));
+                }
+                Ok(_) => {}
+                Err(error) => problems.push(error),
+            }
+            problems.extend(self.position_problems(
+                image,
+                &format!("prefab file \"{file}\""),
+                positions,
+            ));
+        }
+        let past_end = self
+            .frame_ranges
+            .iter()
+            .flatten()
+            .map(|frame_range| frame_range.end.unwrap_or(frame_range.start))
+            .find(|last| *last >= num_frames);
+        if let Some(last) = past_end.filter(|_| num_frames > 0) {
+            problems.push(ProcessorError::ConfigError(format!(
+                "frame_ranges uses frame {last}, but the input only has {num_frames} frames"
+            )));
+        }
+        let has_delays = self
+            .animation
+            .as_ref()
+            .is_some_and(|animation| !animation.delays.is_empty());
+        if num_frames > 1 && !has_delays {
+            problems.push(ProcessorError::ConfigError(format!(
+                "The input has {num_frames} frames, but animation.delays isn't set, which \
+                 animated states need"
+            )));
+        }
+        problems
+    }
+
+    /// Positions past the edge of `img`, called `name` in messages
+    fn position_problems(
+        &self,
+        img: &DynamicImage,
+        name: &str,
+        positions: impl IntoIterator<Item = u32>,
+    ) -> Vec<ProcessorError> {
+        let mut problems = vec![];
+        let Some(furthest) = positions.into_iter().max() else {
+            return problems;
+        };
+        let grid = self.input_grid(img);
+        let axis = self.position_axis(img);
+        let columns = grid
+            .columns
+            .map_or(furthest + 1, |columns| columns.min(furthest + 1));
+        let needed = columns * axis.tile;
+        if axis.extent < needed {
+            problems.push(ProcessorError::ConfigError(format!(
+                "The {name} is {}px {}, but position {furthest} needs it to be at least \
+                 {needed}px {}",
+                axis.extent, axis.described, axis.described
+            )));
+        }
+        let (_, row) = grid.cell(furthest);
+        if row >= grid.rows {
+            let lines = self.frame_axis(img).lines;
+            problems.push(ProcessorError::ConfigError(format!(
+                "Each frame of the {name} has {} {lines} of tiles, but position {furthest} needs \
+                 {}",
+                grid.rows,
+                row + 1
+            )));
+        }
+        problems
+    }
+
+    /// Whether the junction `bits` is one that's generated, rather than one
+    /// with an orphaned corner or a diagonal while only cardinals are smoothed
+    fn generates_junction(&self, bits: u8) -> bool {
+        Adjacency::from_bits(bits).is_some_and(|adjacency| {
+            self.keeps_junction(adjacency)
+                && (self.smooth_diagonally || Adjacency::CARDINALS.contains(adjacency))
+        })
+    }
+
+    /// DM listing the states in `icon`, along with the smoothing flags and
+    /// junction bits to use them with, so code can be kept in sync with the
+    /// art by regenerating it
+    fn smoothing_snippet(&self, icon: &Icon) -> String {
+        let define_prefix = define_name(self.output_name.as_deref().unwrap_or("ICON"));
+        let flags = if self.smooth_diagonally {
+            "SMOOTH_BITMASK"
+        } else {
+            "(SMOOTH_BITMASK|SMOOTH_BITMASK_SKIP_CORNERS)"
+        };
+        let mut junctions = vec![
+            "// NORTH_JUNCTION = 1, SOUTH_JUNCTION = 2, EAST_JUNCTION = 4, WEST_JUNCTION = 8"
+                .to_string(),
+        ];
+        if self.smooth_diagonally {
+            junctions.push(
+                "// NORTHEAST_JUNCTION = 16, SOUTHEAST_JUNCTION = 32, SOUTHWEST_JUNCTION = 64, \
+                 NORTHWEST_JUNCTION = 128"
+                    .to_string(),
+            );
+        }
+        let states = icon
+            .states
+            .iter()
+            .map(|state| format!("\t\"{}\"", state.name))
+            .collect::<Vec<_>>()
+            .join(",\\\n");
+        // cardinal and diagonal wall states can have other dirs than the rest
+        let dirs: BTreeSet<u8> = icon.states.iter().map(|state| state.dirs).collect();
+        let dirs: Vec<String> = dirs.iter().map(ToString::to_string).collect();
+        let mut snippet = vec![
+            "// Generated by hypnagogic, regenerate it rather than editing by hand".to_string(),
+            format!(
+                "// {}x{} icon with {} icon states, {} dirs each",
+                icon.width,
+                icon.height,
+                icon.states.len(),
+                dirs.join(" or ")
+            ),
+            "// Junction bits used in state names:".to_string(),
+        ];
+        snippet.extend(junctions);
+        snippet.push(format!("#define {define_prefix}_SMOOTHING_FLAGS {flags}"));
+        if let Some(output_name) = &self.output_name {
+            snippet.push(format!(
+                "#define {define_prefix}_BASE_ICON_STATE \"{output_name}\""
+            ));
+        }
+        snippet.push(format!(
+            "#define {define_prefix}_ICON_STATES list(\\\n{states}\\\n)"
+        ));
+        if let Some((pixel_x, pixel_y)) = self.tile_pixel_offset() {
+            snippet.push(
+                "// Offsets that line the tile up with the map, the rest overhangs".to_string(),
+            );
+            snippet.push(format!("#define {define_prefix}_PIXEL_X {pixel_x}"));
+            snippet.push(format!("#define {define_prefix}_PIXEL_Y {pixel_y}"));
+        }
+        snippet.push(String::new());
+        snippet.join("\n")
+    }
+
+    /// Name of the state for `adjacency`, using `state_name_format`. `tag`
+    /// sets apart extra states for the same junction, like cardinal ones
+    #[must_use]
+    pub fn state_name(&self, tag: Option<&str>, adjacency: Adjacency) -> String {
+        let prefix = self.output_name.as_deref();
+        match &self.state_name_format {
+            Some(format) => format.name(prefix, tag, adjacency),
+            None => StateNameFormat::default().name(prefix, tag, adjacency),
+        }
+    }
+
+    /// Describes the first two states `state_name_format` gives the same name,
+    /// if it's set and there are any
+    fn state_name_collision(&self) -> Option<String> {
+        self.state_name_format.as_ref()?;
+        let junctions = |tag: Option<&'static str>| {
+            (0..=u8::MAX)
+                .filter(|bits| self.generates_junction(*bits))
+                .map(move |bits| (tag, bits))
+        };
+        let mut states: Vec<(Option<&str>, u8)> = junctions(None)
+            .filter(|(_, bits)| self.emits_state(Adjacency::from_bits(*bits).unwrap()))
+            .collect();
+        if self.cardinal_set == Some(CardinalSetOutput::SameIcon) {
+            states.extend(junctions(Some("cardinal")).filter(|(_, bits)| {
+                Adjacency::CARDINALS.contains(Adjacency::from_bits(*bits).unwrap())
+            }));
+        }
+        for size_override in self.size_overrides.iter().flatten() {
+            states.extend(
+                size_override
+                    .junctions
+                    .iter()
+                    .map(|bits| (Some(size_override.name.as_str()), *bits)),
+            );
+        }
+        if let Some(diagonal_walls) = &self.diagonal_walls {
+            states.extend(
+                self.diagonal_wall_junctions()
+                    .into_iter()
+                    .map(|(junction, _)| (Some(diagonal_walls.name.as_str()), junction.bits())),
+            );
+        }
+        let describe = |(tag, bits): (Option<&str>, u8)| {
+            match tag {
+                Some(tag) => format!("{tag} junction {bits}"),
+                None => format!("junction {bits}"),
+            }
+        };
+        let mut seen: HashMap<String, (Option<&str>, u8)> = HashMap::new();
+        for (tag, bits) in states {
+            let name = self.state_name(tag, Adjacency::from_bits(bits).unwrap());
+            if let Some(first) = seen.get(&name) {
+                return Some(format!(
+                    "state_name_format gives {} and {} the same state name \"{name}\"",
+                    describe(*first),
+                    describe((tag, bits))
+                ));
+            }
+            seen.insert(name, (tag, bits));
+        }
+        None
+    }
+
+    /// Whether the state for `adjacency` is emitted, going by `only_states`
+    /// and `skip_states`
+    #[must_use]
+    pub fn emits_state(&self, adjacency: Adjacency) -> bool {
+        let bits = adjacency.bits();
+        self.only_states
+            .as_ref()
+            .is_none_or(|only| only.contains(&bits))
+            && !self
+                .skip_states
+                .as_ref()
+                .is_some_and(|skip| skip.contains(&bits))
+    }
+
+    /// Junctions of prefabs that are never generated or never emitted, so the
+    /// prefab is never used, in junction order
+    #[must_use]
+    pub fn unused_prefabs(&self) -> Vec<u8> {
+        self.prefabs
+            .iter()
+            .flat_map(|prefabs| prefabs.0.keys())
+            .copied()
+            .filter(|junction| {
+                !self.generates_junction(*junction)
+                    || !self.emits_state(Adjacency::from_bits(*junction).unwrap())
+            })
+            .collect()
+    }
+
+    /// Prefabs whose position is also one of `positions`, as junction and
+    /// position, in junction order. None can overlap with corners read from a
+    /// `corner_atlas`, or be prefabs read from their own file
+    #[must_use]
+    pub fn overlapping_prefabs(&self) -> Vec<(u8, u32)> {
+        let Some(prefabs) = self
+            .prefabs
+            .as_ref()
+            .filter(|_| self.corner_atlas.is_none())
+        else {
+            return vec![];
+        };
+        let corner_positions: Vec<u32> = self
+            .used_corner_types()
+            .iter()
+            .filter_map(|corner_type| self.positions.get(*corner_type))
+            .collect();
+        prefabs
+            .sheet_positions()
+            .filter(|(_, position)| corner_positions.contains(position))
+            .collect()
+    }
+
+    /// New positions for every prefab in [`BitmaskSlice::overlapping_prefabs`],
+    /// moving each in junction order to the lowest slot nothing else uses
+    #[must_use]
+    pub fn prefab_overlap_fixes(&self) -> BTreeMap<u8, u32> {
+        let overlapping = self.overlapping_prefabs();
+        if overlapping.is_empty() {
+            return BTreeMap::new();
+        }
+        let mut used: Vec<u32> = self
+            .used_corner_types()
+            .iter()
+            .filter_map(|corner_type| self.positions.get(*corner_type))
+            .collect();
+        used.extend(
+            self.prefabs
+                .iter()
+                .flat_map(Prefabs::sheet_positions)
+                .filter(|(junction, _)| !overlapping.iter().any(|(other, _)| other == junction))
+                .map(|(_, position)| position),
+        );
+        used.extend(
+            self.prefab_overlays
+                .iter()
+                .flat_map(PrefabOverlays::positions),
+        );
+        let mut free = (0..).filter(|slot| !used.contains(slot));
+        overlapping
+            .into_iter()
+            .map(|(junction, _)| (junction, free.next().unwrap()))
+            .collect()
+    }
+
+    /// Warns about every junction that won't be output because it needs a
+    /// corner from an empty position slot
+    #[must_use]
+    pub fn skipped_junctions(
+        &self,
+        assembled: &BTreeMap<Adjacency, Vec<DynamicImage>>,
+        possible_states: usize,
+    ) -> Option<ProcessorWarning> {
+        let skipped: Vec<String> = (0..possible_states)
+            .map(|x| Adjacency::from_bits(x as u8).unwrap())
+            .filter(|adjacency| self.keeps_junction(*adjacency))
+            .filter(|adjacency| self.emits_state(*adjacency))
+            .filter(|adjacency| !self.is_assembled(assembled, *adjacency))
+            .map(|adjacency| adjacency.bits().to_string())
+            .collect();
+        if skipped.is_empty() {
+            return None;
+        }
+        let empty_slots: Vec<String> = self
+            .positions
+            .0
+            .iter()
+            .filter(|(_, position)| position.is_none())
+            .map(|(corner_type, _)| corner_type.to_string())
+            .collect();
+        Some(ProcessorWarning::new(format!(
+            "Skipping {} junctions that need a corner from an empty position slot ({}): {}",
+            skipped.len(),
+            empty_slots.join(", "),
+            skipped.join(", ")
+        )))
+    }
+
+    /// Works out which clockwise rotation (in degrees) of the south facing
+    /// icons produces each of the other dirs. Returns `None` if any junction
+    /// in any dir isn't an exact rotation. Junctions missing from `assembled`
+    /// are ignored
+    #[must_use]
+    pub fn find_dir_rotations(
+        &self,
+        assembled: &BTreeMap<Adjacency, Vec<DynamicImage>>,
+        possible_states: usize,
+    ) -> Option<Vec<(Adjacency, u32)>> {
+        let junctions: Vec<Adjacency> = (0..possible_states)
+            .map(|x| Adjacency::from_bits(x as u8).unwrap())
+            .filter(|adjacency| self.keeps_junction(*adjacency))
+            .filter(|adjacency| self.is_assembled(assembled, *adjacency))
+            .collect();
+
+        let mut rotations = vec![];
+        for direction in Adjacency::dmi_cardinals() {
+            let found = [0, 90, 180, 270].into_iter().find(|angle| {
+                junctions.iter().all(|adjacency| {
+                    let south = &assembled[adjacency];
+                    let rotated = &assembled[&adjacency.rotate_to(direction)];
+                    south
+                        .iter()
+                        .zip(rotated)
+                        .all(|(south, rotated)| rotate_clockwise(south, *angle) == *rotated)
+                })
+            })?;
+            rotations.push((direction, found));
+        }
+        Some(rotations)
+    }
+
+    /// Renders the first frame of each junction laid out as described by
+    /// `map`, the same way they'd connect in game. Tiles are spaced by
+    /// `tile_bounds`, or by `icon_size` with outputs anchored to their bottom
+    /// left like byond does, so oversized outputs overlap their neighbours.
+    /// Tiles with a junction missing from `assembled` are left blank
+    #[must_use]
+    pub fn generate_map_preview(
+        &self,
+        assembled: &BTreeMap<Adjacency, Vec<DynamicImage>>,
+        map: &str,
+    ) -> DynamicImage {
+        let tiles = parse_preview_map(map);
+        let filled = |x: i64, y: i64| {
+            usize::try_from(y)
+                .ok()
+                .zip(usize::try_from(x).ok())
+                .and_then(|(y, x)| tiles.get(y)?.get(x).copied())
+                .unwrap_or(false)
+        };
+        let neighbours = [
+            (Adjacency::N, 0, -1),
+            (Adjacency::S, 0, 1),
+            (Adjacency::E, 1, 0),
+            (Adjacency::W, -1, 0),
+            (Adjacency::NE, 1, -1),
+            (Adjacency::SE, 1, 1),
+            (Adjacency::SW, -1, 1),
+            (Adjacency::NW, -1, -1),
+        ];
+
+        let columns = tiles.iter().map(Vec::len).max().unwrap_or(0) as u32;
+        let rows = tiles.len() as u32;
+        let size = self.output_size();
+        let (step_x, step_y) = match self.tile_bounds {
+            Some(bounds) => (bounds.width, bounds.height),
+            None => (self.icon_size.x, self.icon_size.y),
+        };
+        // where each output is drawn relative to its tile, outputs shorter
+        // than a tile still sit on the bottom of it
+        let (offset_x, offset_y) = match self.tile_bounds {
+            Some(bounds) => {
+                let position = self.output_pos();
+                (
+                    -i64::from(position.x + bounds.x),
+                    -i64::from(position.y + bounds.y),
+                )
+            }
+            None => (0, i64::from(self.icon_size.y) - i64::from(size.y)),
+        };
+        // room for overhang past the top left tile
+        let (margin_x, margin_y) = (offset_x.min(0), offset_y.min(0));
+        let extent = |count: u32, step: u32, offset: i64, margin: i64, size: u32| {
+            let far_edge = i64::from(count.saturating_sub(1) * step) + offset + i64::from(size);
+            (far_edge - margin).max(0) as u32
+        };
+        let mut preview = DynamicImage::new_rgba8(
+            extent(columns, step_x, offset_x, margin_x, size.x),
+            extent(rows, step_y, offset_y, margin_y, size.y),
+        );
+
+        // drawn top to bottom so lower tiles overlap the ones above, matching
+        // byond's layering
+        for (y, row) in tiles.iter().enumerate() {
+            for (x, _) in row.iter().enumerate().filter(|(_, filled)| **filled) {
+                let (x, y) = (i64::from(x as u32), i64::from(y as u32));
+                let mut adjacency = Adjacency::empty();
+                for (direction, x_offset, y_offset) in neighbours {
+                    if filled(x + x_offset, y + y_offset) {
+                        adjacency |= direction;
+                    }
+                }
+                if !self.smooth_diagonally {
+                    adjacency &= Adjacency::CARDINALS;
+                }
+                let Some(tile) = assembled.get(&adjacency).and_then(|frames| frames.first()) else {
+                    continue;
+                };
+                imageops::overlay(
+                    &mut preview,
+                    tile,
+                    x * i64::from(step_x) + offset_x - margin_x,
+                    y * i64::from(step_y) + offset_y - margin_y,
+                );
+            }
+        }
+        preview
+    }
+
+    /// Generates debug outputs for bitmask slice
+    /// # Panics
     /// Shouldn't panic, unless the passed in corners are malformed
     #[must_use]
     pub fn generate_debug_icons(&self, corners: &CornerPayload) -> Vec<NamedIcon> {
@@ -366,15 +2215,13 @@ impl BitmaskSlice {
                     OutputImage::Png(vec.first().unwrap().clone()),
                 ));
                 // Reassemble the input image from corners (minus prefabs and frames)
-                let (horizontal, vertical) = corner.sides_of_corner();
-                let horizontal = self.get_side_info(horizontal);
-                let vertical = self.get_side_info(vertical);
+                let placement = self.corner_placement(corner);
                 let frame = vec.first().unwrap();
                 imageops::replace(
                     &mut corners_image,
                     frame,
-                    ((position * self.icon_size.x) + horizontal.start) as i64,
-                    vertical.start as i64,
+                    ((position * self.icon_size.x) + placement.x) as i64,
+                    placement.y as i64,
                 );
             }
         }
@@ -388,31 +2235,1452 @@ impl BitmaskSlice {
 
     #[must_use]
     pub fn get_side_info(&self, side: Side) -> SideSpacing {
-        match side {
-            Side::North => {
-                SideSpacing {
-                    start: 0,
-                    end: self.cut_pos.y,
-                }
+        side_spacing(self.icon_size, self.cut_pos, side)
+    }
+
+    /// Size of each output state, `output_icon_size` if it's set and
+    /// `icon_size` otherwise
+    #[must_use]
+    pub fn output_size(&self) -> OutputIconSize {
+        self.output_icon_size.unwrap_or(OutputIconSize {
+            x: self.icon_size.x,
+            y: self.icon_size.y,
+        })
+    }
+
+    /// Where generated icons are placed on each output state,
+    /// `output_icon_pos` if it's set. Otherwise they're anchored to the bottom
+    /// left, so the tile is where byond draws it
+    #[must_use]
+    pub fn output_pos(&self) -> OutputIconPosition {
+        self.output_icon_pos.unwrap_or_else(|| {
+            OutputIconPosition {
+                x: 0,
+                y: self.output_size().y.saturating_sub(self.icon_size.y),
             }
-            Side::South => {
-                SideSpacing {
-                    start: self.cut_pos.y,
-                    end: self.icon_size.y,
-                }
-            }
-            Side::East => {
-                SideSpacing {
-                    start: self.cut_pos.x,
-                    end: self.icon_size.x,
-                }
+        })
+    }
+
+    /// Where generated icons are placed on output states facing `dir`,
+    /// `dir_output_icon_pos` if it's set for the dir and `output_pos`
+    /// otherwise
+    #[must_use]
+    pub fn dir_output_pos(&self, dir: Side) -> OutputIconPosition {
+        self.dir_output_icon_pos
+            .as_ref()
+            .and_then(|positions| positions.0.get(&dir))
+            .copied()
+            .unwrap_or_else(|| self.output_pos())
+    }
+
+    /// Moves `frames`, assembled at `output_pos`, to where states facing `dir`
+    /// are placed
+    #[must_use]
+    pub fn place_for_dir(&self, dir: Side, frames: &[DynamicImage]) -> Vec<DynamicImage> {
+        let (from, to) = (self.output_pos(), self.dir_output_pos(dir));
+        if from == to {
+            return frames.to_vec();
+        }
+        frames
+            .iter()
+            .map(|frame| {
+                let mut placed = DynamicImage::new_rgba8(frame.width(), frame.height());
+                imageops::overlay(
+                    &mut placed,
+                    frame,
+                    i64::from(to.x) - i64::from(from.x),
+                    i64::from(to.y) - i64::from(from.y),
+                );
+                placed
+            })
+            .collect()
+    }
+
+    /// The `pixel_x` and `pixel_y` that line the logical tile of each output
+    /// state up with the map, when `tile_bounds` is set. Byond draws icons
+    /// from their bottom left, so overhang to the left or below needs shifting
+    #[must_use]
+    pub fn tile_pixel_offset(&self) -> Option<(i64, i64)> {
+        let bounds = self.tile_bounds?;
+        let (size, position) = (self.output_size(), self.output_pos());
+        let left = i64::from(position.x + bounds.x);
+        let bottom = i64::from(size.y) - i64::from(position.y + bounds.y + bounds.height);
+        Some((-left, -bottom))
+    }
+
+    /// Where `corner` is cut, `corner_cut_pos` if it's set for the corner and
+    /// `cut_pos` otherwise
+    #[must_use]
+    pub fn corner_cut(&self, corner: Corner) -> CutPosition {
+        self.corner_cut_pos
+            .as_ref()
+            .and_then(|cuts| cuts.0.get(&corner))
+            .copied()
+            .unwrap_or(self.cut_pos)
+    }
+
+    /// Where `corner` is cut from within a tile, and placed in the output
+    #[must_use]
+    pub fn corner_placement(&self, corner: Corner) -> Rect {
+        corner_rect(self.icon_size, self.corner_cut(corner), corner)
+    }
+
+    /// Where every corner and prefab is read from in an input laid out in
+    /// `grid` with `num_frames` frames, and where they're placed in the output.
+    /// Empty position slots are left out
+    #[must_use]
+    pub fn layout(&self, grid: InputGrid, num_frames: u32) -> SheetLayout {
+        let mut slots: Vec<SlotLayout> = self
+            .used_corner_types()
+            .iter()
+            .filter_map(|corner_type| {
+                let position = self.positions.get(*corner_type)?;
+                Some(SlotLayout::corners(
+                    self.icon_size,
+                    |corner| self.corner_cut(corner),
+                    grid,
+                    *corner_type,
+                    position,
+                    num_frames,
+                ))
+            })
+            .collect();
+        if let Some(prefabs) = &self.prefabs {
+            slots.extend(prefabs.sheet_positions().map(|(junction, position)| {
+                SlotLayout::prefab(self.icon_size, grid, junction, position, num_frames)
+            }));
+        }
+        slots.sort_by_key(|slot| slot.position);
+        SheetLayout {
+            icon_size: self.icon_size,
+            cut_pos: self.cut_pos,
+            grid,
+            frames: num_frames,
+            slots,
+            output: OutputLayout::new(
+                self.icon_size,
+                |corner| self.corner_cut(corner),
+                self.output_size(),
+                self.output_pos(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use dmi::icon::Hotspot;
+    use image::{GenericImageView, Rgba};
+
+    use super::*;
+    use crate::config::blocks::cutters::{DelayUnit, PrefabOverlay};
+    use crate::operations::{single_dmi, tile_sheet, OutputText};
+    use crate::util::blend::BlendMode;
+
+    /// Each corner block is a solid color with horizontal and vertical sharing
+    /// one, so every junction is an exact rotation of the others
+    fn symmetric_sheet() -> DynamicImage {
+        let colors = [
+            Rgba([255, 0, 0, 255]),
+            Rgba([0, 255, 0, 255]),
+            Rgba([0, 0, 255, 255]),
+            Rgba([0, 0, 255, 255]),
+        ];
+        tile_sheet((32, 32), (4, 1), |block, _, _| colors[block as usize])
+    }
+
+    #[test]
+    fn collapse_rotations() {
+        let config = BitmaskSlice {
+            produce_dirs: true,
+            collapse_rotations: true,
+            ..Default::default()
+        };
+        let input = InputIcon::DynamicImage(symmetric_sheet());
+        let payload = config
+            .do_operation(&input, OperationMode::Standard)
+            .unwrap();
+
+        let ProcessorPayload::ConfigWrapped(inner, text) = payload else {
+            panic!("Expected a rotation note to be produced");
+        };
+        let OutputText::DmCode(note) = *text else {
+            panic!("Expected the note to be dm code");
+        };
+        assert!(note.contains("// SOUTH = 0"));
+        let icon = single_dmi(*inner);
+        assert!(icon.states.iter().all(|state| state.dirs == 1));
+    }
+
+    #[test]
+    fn dm_snippet() {
+        let config = BitmaskSlice {
+            output_name: Some("wall".to_string()),
+            dm_snippet: true,
+            only_states: Some(vec![0, 15]),
+            ..Default::default()
+        };
+        let input = InputIcon::DynamicImage(symmetric_sheet());
+        let payload = config
+            .do_operation(&input, OperationMode::Standard)
+            .unwrap();
+
+        let ProcessorPayload::ConfigWrapped(_, text) = payload else {
+            panic!("Expected a dm snippet to be produced");
+        };
+        let OutputText::DmCode(snippet) = *text else {
+            panic!("Expected the snippet to be dm code");
+        };
+        assert!(snippet
+            .contains("#define WALL_SMOOTHING_FLAGS (SMOOTH_BITMASK|SMOOTH_BITMASK_SKIP_CORNERS)"));
+        assert!(snippet.contains("#define WALL_BASE_ICON_STATE \"wall\""));
+        assert!(snippet.contains("list(\\\n\t\"wall-0\",\\\n\t\"wall-15\"\\\n)"));
+        assert!(!snippet.contains("NORTHEAST_JUNCTION"));
+        assert!(snippet.contains("with 2 icon states, 1 dirs each"));
+
+        let config = BitmaskSlice {
+            output_name: Some("2x-wall".to_string()),
+            produce_dirs: true,
+            ..config
+        };
+        let payload = config
+            .do_operation(&input, OperationMode::Standard)
+            .unwrap();
+        let ProcessorPayload::ConfigWrapped(_, text) = payload else {
+            panic!("Expected a dm snippet to be produced");
+        };
+        let OutputText::DmCode(snippet) = *text else {
+            panic!("Expected the snippet to be dm code");
+        };
+        assert!(snippet.contains("#define _2X_WALL_BASE_ICON_STATE \"2x-wall\""));
+        assert!(snippet.contains("with 2 icon states, 4 dirs each"));
+        assert_eq!(define_name(""), "_");
+    }
+
+    #[test]
+    fn wrapped_input() {
+        let strip = symmetric_sheet();
+        // the same four positions, two to a row
+        let mut wrapped = DynamicImage::new_rgba8(64, 64);
+        for position in 0..4 {
+            let tile = strip.crop_imm(position * 32, 0, 32, 32);
+            let (x, y) = (position % 2 * 32, position / 2 * 32);
+            imageops::overlay(&mut wrapped, &tile, i64::from(x), i64::from(y));
+        }
+        let states = |config: &BitmaskSlice, img: DynamicImage| {
+            let payload = config
+                .do_operation(&InputIcon::DynamicImage(img), OperationMode::Standard)
+                .unwrap();
+            single_dmi(payload).states
+        };
+        let config = BitmaskSlice {
+            input_columns: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            states(&config, wrapped.clone()),
+            states(&BitmaskSlice::default(), strip)
+        );
+
+        let too_short = BitmaskSlice {
+            prefabs: Some(Prefabs(BTreeMap::from([(15, Prefab::sheet(5))]))),
+            ..config
+        };
+        let problems = too_short.input_problems(&InputIcon::DynamicImage(wrapped));
+        let expected = "position 5 needs 3";
+        assert!(problems.iter().any(|problem| {
+            matches!(problem, ProcessorError::ConfigError(message) if message.contains(expected))
+        }));
+    }
+
+    #[test]
+    fn vertical_input() {
+        // two frames, the second a different color, of the symmetric sheet
+        let mut strip = DynamicImage::new_rgba8(128, 64);
+        imageops::overlay(&mut strip, &symmetric_sheet(), 0, 0);
+        imageops::overlay(&mut strip, &symmetric_sheet().fliph(), 0, 32);
+        let mut vertical = DynamicImage::new_rgba8(64, 128);
+        for position in 0..4 {
+            for frame in 0..2 {
+                let tile = strip.crop_imm(position * 32, frame * 32, 32, 32);
+                let (x, y) = (frame * 32, position * 32);
+                imageops::overlay(&mut vertical, &tile, i64::from(x), i64::from(y));
             }
-            Side::West => {
-                SideSpacing {
+        }
+        let states = |config: &BitmaskSlice, img: DynamicImage| {
+            let payload = config
+                .do_operation(&InputIcon::DynamicImage(img), OperationMode::Standard)
+                .unwrap();
+            single_dmi(payload).states
+        };
+        let animation = Some(Animation {
+            delays: vec![1.0],
+            ..Default::default()
+        });
+        let horizontal = BitmaskSlice {
+            animation: animation.clone(),
+            ..Default::default()
+        };
+        let config = BitmaskSlice {
+            layout: InputLayout::Vertical,
+            animation,
+            ..Default::default()
+        };
+        let expected = states(&horizontal, strip);
+        assert_eq!(expected[0].frames, 2);
+        assert_eq!(states(&config, vertical), expected);
+    }
+
+    #[test]
+    fn corner_cut_overrides() {
+        let overrides: CornerCutPositions =
+            toml::from_str("south_east = { x = 20, y = 24 }").unwrap();
+        let config = BitmaskSlice {
+            corner_cut_pos: Some(overrides),
+            ..Default::default()
+        };
+        assert_eq!(config.corner_cut(Corner::NorthWest), config.cut_pos);
+        assert_eq!(
+            config.corner_cut(Corner::SouthEast),
+            CutPosition { x: 20, y: 24 }
+        );
+        let placement = config.corner_placement(Corner::SouthEast);
+        assert_eq!((placement.x, placement.y), (20, 24));
+        assert_eq!((placement.width, placement.height), (12, 8));
+        let placement = config.corner_placement(Corner::NorthWest);
+        assert_eq!((placement.width, placement.height), (16, 16));
+
+        let config = BitmaskSlice {
+            corner_cut_pos: Some(CornerCutPositions(BTreeMap::from([(
+                Corner::NorthEast,
+                CutPosition { x: 40, y: 8 },
+            )]))),
+            ..Default::default()
+        };
+        let expected = "corner_cut_pos.north_east is at 40, 8, outside the 32x32 icon";
+        assert!(config.config_problems().iter().any(
+            |problem| matches!(problem, ProcessorError::ConfigError(message) if message == expected)
+        ));
+    }
+
+    #[test]
+    fn frame_count_mismatch() {
+        let config = BitmaskSlice {
+            animation: Some(Animation {
+                delays: vec![1.0],
+                frames: Some(2),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let one_frame = symmetric_sheet();
+        assert!(matches!(
+            config.frame_count(&one_frame),
+            Err(ProcessorError::FrameCountMismatch { expected: 2, .. })
+        ));
+
+        let mut two_frames = DynamicImage::new_rgba8(one_frame.width(), 64);
+        imageops::replace(&mut two_frames, &one_frame, 0, 0);
+        assert_eq!(config.frame_count(&two_frames).unwrap(), 2);
+    }
+
+    #[test]
+    fn frame_ranges() {
+        let colors = [
+            Rgba([255, 0, 0, 255]),
+            Rgba([0, 255, 0, 255]),
+            Rgba([0, 0, 255, 255]),
+        ];
+        let mut sheet = image::RgbaImage::new(4 * 32, 3 * 32);
+        for (_x, y, pixel) in sheet.enumerate_pixels_mut() {
+            *pixel = colors[(y / 32) as usize];
+        }
+        let sheet = DynamicImage::ImageRgba8(sheet);
+        let config = BitmaskSlice {
+            animation: Some(Animation {
+                delays: vec![1.0, 2.0, 3.0],
+                ..Default::default()
+            }),
+            frame_ranges: Some(vec![
+                FrameRange {
+                    junctions: vec![0],
                     start: 0,
-                    end: self.cut_pos.x,
+                    end: Some(0),
+                },
+                FrameRange {
+                    junctions: vec![15],
+                    start: 1,
+                    end: None,
+                },
+            ]),
+            ..Default::default()
+        };
+        assert!(config.config_problems().is_empty());
+        assert!(config.image_problems(&sheet, []).is_empty());
+
+        let payload = config
+            .do_operation(
+                &InputIcon::DynamicImage(sheet.clone()),
+                OperationMode::Standard,
+            )
+            .unwrap();
+        let icon = single_dmi(payload);
+        let find = |name: &str| icon.states.iter().find(|state| state.name == name).unwrap();
+        let still = find("0");
+        assert_eq!((still.frames, still.delay.as_ref()), (1, None));
+        let enclosed = find("15");
+        assert_eq!(enclosed.frames, 2);
+        assert_eq!(enclosed.delay, Some(vec![2.0, 3.0]));
+        assert_eq!(enclosed.images[0].get_pixel(0, 0), colors[1]);
+        assert_eq!(find("5").frames, 3);
+
+        let past_end = BitmaskSlice {
+            frame_ranges: Some(vec![FrameRange {
+                junctions: vec![15],
+                start: 1,
+                end: Some(3),
+            }]),
+            ..config.clone()
+        };
+        assert_eq!(past_end.image_problems(&sheet, []).len(), 1);
+        let backwards = BitmaskSlice {
+            frame_ranges: Some(vec![FrameRange {
+                junctions: vec![16],
+                start: 2,
+                end: Some(1),
+            }]),
+            ..config
+        };
+        assert_eq!(backwards.config_problems().len(), 2);
+    }
+
+    #[test]
+    fn delay_units() {
+        let mut sheet = image::RgbaImage::new(4 * 32, 2 * 32);
+        for (_x, y, pixel) in sheet.enumerate_pixels_mut() {
+            *pixel = Rgba([0, 0, (y / 32) as u8 * 255, 255]);
+        }
+        let input = InputIcon::DynamicImage(DynamicImage::ImageRgba8(sheet));
+        for (delay_unit, delays) in [
+            (None, vec![4.0, 2.0]),
+            (Some(DelayUnit::Ticks), vec![8.0, 4.0]),
+            (Some(DelayUnit::Milliseconds), vec![400.0, 200.0]),
+        ] {
+            let config = BitmaskSlice {
+                animation: Some(Animation {
+                    delays,
+                    delay_unit,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let payload = config
+                .do_operation(&input, OperationMode::Standard)
+                .unwrap();
+            let icon = single_dmi(payload);
+            assert_eq!(icon.states[0].delay, Some(vec![4.0, 2.0]), "{delay_unit:?}");
+        }
+    }
+
+    #[test]
+    fn frame_dedupe() {
+        let blues = [0, 0, 2];
+        let mut sheet = image::RgbaImage::new(4 * 32, 3 * 32);
+        for (_x, y, pixel) in sheet.enumerate_pixels_mut() {
+            *pixel = Rgba([0, 0, blues[(y / 32) as usize], 255]);
+        }
+        let input = InputIcon::DynamicImage(DynamicImage::ImageRgba8(sheet));
+        for (dedupe, dedupe_tolerance, delays) in [
+            (None, None, vec![2.0, 1.0]),
+            (None, Some(2), vec![3.0]),
+            (Some(false), Some(2), vec![1.0, 1.0, 1.0]),
+        ] {
+            let config = BitmaskSlice {
+                animation: Some(Animation {
+                    delays: vec![1.0],
+                    dedupe,
+                    dedupe_tolerance,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let payload = config
+                .do_operation(&input, OperationMode::Standard)
+                .unwrap();
+            let icon = single_dmi(payload);
+            let state = &icon.states[0];
+            assert_eq!(state.frames as usize, delays.len());
+            assert_eq!(state.delay, Some(delays));
+        }
+    }
+
+    #[test]
+    fn movement_states() {
+        let colors = [Rgba([255, 0, 0, 255]), Rgba([0, 255, 0, 255])];
+        let mut sheet = image::RgbaImage::new(4 * 32, 2 * 32);
+        for (_x, y, pixel) in sheet.enumerate_pixels_mut() {
+            *pixel = colors[(y / 32) as usize];
+        }
+        let sheet = DynamicImage::ImageRgba8(sheet);
+        let config = BitmaskSlice {
+            movement_states: true,
+            ..Default::default()
+        };
+        assert!(config.image_problems(&sheet, []).is_empty());
+
+        let payload = config
+            .do_operation(
+                &InputIcon::DynamicImage(sheet.clone()),
+                OperationMode::Standard,
+            )
+            .unwrap();
+        let icon = single_dmi(payload);
+        assert_eq!(icon.states.len(), 2 * SIZE_OF_CARDINALS);
+        for state in icon.states.iter().filter(|state| state.name == "15") {
+            assert_eq!(state.frames, 1);
+            let expected = colors[usize::from(state.movement)];
+            assert_eq!(state.images[0].get_pixel(0, 0), expected);
+        }
+
+        let odd = sheet.crop_imm(0, 0, 4 * 32, 48);
+        assert_eq!(config.image_problems(&odd, []).len(), 1);
+    }
+
+    #[test]
+    fn animation_flags() {
+        let config = BitmaskSlice {
+            animation: Some(Animation {
+                delays: vec![1.0],
+                loop_count: Some(2),
+                movement: Some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(config.config_problems().is_empty());
+        let payload = config
+            .do_operation(
+                &InputIcon::DynamicImage(symmetric_sheet()),
+                OperationMode::Standard,
+            )
+            .unwrap();
+        let icon = single_dmi(payload);
+        assert!(icon
+            .states
+            .iter()
+            .all(|state| state.loop_flag == Looping::new(2) && state.movement));
+
+        let never = BitmaskSlice {
+            animation: Some(Animation {
+                loop_count: Some(0),
+                movement: Some(true),
+                ..Default::default()
+            }),
+            movement_states: true,
+            ..config
+        };
+        assert_eq!(never.config_problems().len(), 2);
+    }
+
+    #[test]
+    fn hotspots() {
+        let config = BitmaskSlice {
+            hotspot: Some(IconHotspot {
+                x: 4,
+                y: 28,
+                frame: None,
+            }),
+            ..Default::default()
+        };
+        assert!(config.config_problems().is_empty());
+        let payload = config
+            .do_operation(
+                &InputIcon::DynamicImage(symmetric_sheet()),
+                OperationMode::Standard,
+            )
+            .unwrap();
+        let icon = single_dmi(payload);
+        assert!(icon
+            .states
+            .iter()
+            .all(|state| state.hotspot == Some(Hotspot { x: 4, y: 28 })));
+
+        let outside = BitmaskSlice {
+            hotspot: Some(IconHotspot {
+                x: 40,
+                y: 0,
+                frame: Some(2),
+            }),
+            ..config
+        };
+        assert_eq!(outside.config_problems().len(), 2);
+    }
+
+    #[test]
+    fn prefab_overlays() {
+        // the symmetric sheet with a black block at 4 and a white one at 5
+        let mut sheet = image::RgbaImage::new(32 * 6, 32);
+        imageops::replace(&mut sheet, &symmetric_sheet().to_rgba8(), 0, 0);
+        for (x, _y, pixel) in sheet.enumerate_pixels_mut().filter(|(x, ..)| *x >= 32 * 4) {
+            *pixel = if x < 32 * 5 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            };
+        }
+
+        let overlays: PrefabOverlays =
+            toml::from_str("15 = [4, { position = 5, blend = \"multiply\", opacity = 0.5 }]")
+                .unwrap();
+        assert_eq!(
+            overlays.0[&15],
+            vec![
+                PrefabOverlay::normal(4),
+                PrefabOverlay {
+                    position: 5,
+                    blend: BlendMode::Multiply,
+                    opacity: 0.5,
+                },
+            ]
+        );
+
+        let config = BitmaskSlice {
+            prefab_overlays: Some(PrefabOverlays(BTreeMap::from([(
+                15,
+                vec![
+                    PrefabOverlay {
+                        position: 4,
+                        blend: BlendMode::Multiply,
+                        opacity: 1.0,
+                    },
+                    PrefabOverlay {
+                        position: 5,
+                        blend: BlendMode::Normal,
+                        opacity: 0.5,
+                    },
+                ],
+            )]))),
+            ..Default::default()
+        };
+        assert!(config.config_problems().is_empty());
+        let payload = config
+            .do_operation(
+                &InputIcon::DynamicImage(DynamicImage::ImageRgba8(sheet)),
+                OperationMode::Standard,
+            )
+            .unwrap();
+        let icon = single_dmi(payload);
+        let pixel = |name: &str| {
+            let state = icon.states.iter().find(|state| state.name == name).unwrap();
+            state.images[0].get_pixel(8, 8)
+        };
+        // multiplied to black, then half covered in white
+        assert_eq!(pixel("15"), Rgba([128, 128, 128, 255]));
+        assert_ne!(pixel("0"), Rgba([128, 128, 128, 255]));
+
+        let faded = BitmaskSlice {
+            prefab_overlays: Some(PrefabOverlays(BTreeMap::from([(
+                15,
+                vec![PrefabOverlay {
+                    position: 4,
+                    blend: BlendMode::Screen,
+                    opacity: 1.5,
+                }],
+            )]))),
+            ..Default::default()
+        };
+        assert_eq!(faded.config_problems().len(), 1);
+    }
+
+    #[test]
+    fn map_preview() {
+        let config = BitmaskSlice::default();
+        let (corners, prefabs) = config.generate_corners(&symmetric_sheet()).unwrap();
+        let assembled = config
+            .generate_icons(
+                &corners,
+                &VariantPayload::new(),
+                &prefabs,
+                1,
+                SIZE_OF_CARDINALS,
+                &CancellationToken::new(),
+            )
+            .unwrap();
+
+        let preview = config.generate_map_preview(&assembled, "\nXX.\nXXX\n");
+        assert_eq!(preview.dimensions(), (96, 64));
+        // empty tiles are left transparent, filled ones get their junction
+        assert_eq!(preview.get_pixel(80, 16)[3], 0);
+        assert_eq!(
+            preview.get_pixel(0, 0),
+            assembled[&Adjacency::E.union(Adjacency::S)][0].get_pixel(0, 0)
+        );
+    }
+
+    #[test]
+    fn oversized_icons() {
+        let config = BitmaskSlice {
+            icon_size: IconSize { x: 48, y: 48 },
+            cut_pos: CutPosition { x: 24, y: 32 },
+            tile_bounds: Some(TileBounds {
+                x: 8,
+                y: 16,
+                width: 32,
+                height: 32,
+            }),
+            ..Default::default()
+        };
+        assert_eq!(config.output_size(), OutputIconSize { x: 48, y: 48 });
+        assert_eq!(config.output_pos(), OutputIconPosition { x: 0, y: 0 });
+        assert_eq!(config.tile_pixel_offset(), Some((-8, 0)));
+        assert!(config.config_problems().is_empty());
+
+        // neighbours are a tile apart, overlapping where they overhang
+        let assembled: BTreeMap<Adjacency, Vec<DynamicImage>> = [Adjacency::E, Adjacency::W]
+            .into_iter()
+            .map(|adjacency| (adjacency, vec![DynamicImage::new_rgba8(48, 48)]))
+            .collect();
+        let preview = config.generate_map_preview(&assembled, "XX");
+        assert_eq!(preview.dimensions(), (80, 48));
+
+        let taller = BitmaskSlice {
+            output_icon_size: Some(OutputIconSize { x: 48, y: 64 }),
+            ..config.clone()
+        };
+        assert_eq!(taller.output_pos(), OutputIconPosition { x: 0, y: 16 });
+        assert_eq!(taller.tile_pixel_offset(), Some((-8, 0)));
+
+        let clipped = BitmaskSlice {
+            output_icon_size: Some(OutputIconSize { x: 32, y: 48 }),
+            ..config
+        };
+        let expected = "The 48x48 icon placed at 0, 0 doesn't fit in the 32x48 output, so its \
+                        overhang would be cut off";
+        assert!(clipped.config_problems().iter().any(
+            |problem| matches!(problem, ProcessorError::ConfigError(message) if message == expected)
+        ));
+    }
+
+    #[test]
+    fn dir_output_positions() {
+        let config = BitmaskSlice {
+            produce_dirs: true,
+            dir_output_icon_pos: Some(DirOutputPositions(BTreeMap::from([(
+                Side::North,
+                OutputIconPosition { x: 0, y: 4 },
+            )]))),
+            ..Default::default()
+        };
+        assert!(config.config_problems().is_empty());
+        let payload = config
+            .do_operation(
+                &InputIcon::DynamicImage(symmetric_sheet()),
+                OperationMode::Standard,
+            )
+            .unwrap();
+        let icon = single_dmi(payload);
+        // dirs are south, north, east then west, and junction 0 looks the
+        // same from every dir
+        let state = icon.states.iter().find(|state| state.name == "0").unwrap();
+        let (south, north) = (&state.images[0], &state.images[1]);
+        assert_eq!(north.get_pixel(8, 2)[3], 0);
+        assert_eq!(north.get_pixel(8, 20), south.get_pixel(8, 16));
+        assert_eq!(state.images[2], *south);
+
+        let collapsed = BitmaskSlice {
+            collapse_rotations: true,
+            ..config.clone()
+        };
+        assert_eq!(collapsed.config_problems().len(), 1);
+        let single_dir = BitmaskSlice {
+            produce_dirs: false,
+            ..config
+        };
+        assert_eq!(single_dir.config_problems().len(), 1);
+    }
+
+    #[test]
+    fn corner_variants() {
+        let mut sheet = DynamicImage::new_rgba8(5 * 32, 32);
+        imageops::overlay(&mut sheet, &symmetric_sheet(), 0, 0);
+        let white = Rgba([255, 255, 255, 255]);
+        for x in 4 * 32..5 * 32 {
+            for y in 0..32 {
+                sheet.as_mut_rgba8().unwrap().put_pixel(x, y, white);
+            }
+        }
+        let variants: CornerVariants = toml::from_str("convex = [4]").unwrap();
+        let config = BitmaskSlice {
+            corner_variants: Some(variants),
+            ..Default::default()
+        };
+        assert!(config.config_problems().is_empty());
+
+        let picks = |config: &BitmaskSlice| {
+            (0..SIZE_OF_CARDINALS as u8)
+                .filter_map(Adjacency::from_bits)
+                .flat_map(|adjacency| all::<Corner>().map(move |corner| (adjacency, corner)))
+                .map(|(adjacency, corner)| config.corner_variant(adjacency, corner))
+                .collect::<Vec<_>>()
+        };
+        let seeded = picks(&config);
+        assert_eq!(picks(&config), seeded);
+        assert!(seeded.contains(&0) && seeded.contains(&1));
+        let reseeded = BitmaskSlice {
+            variant_seed: Some(7),
+            ..config.clone()
+        };
+        assert_ne!(picks(&reseeded), seeded);
+
+        let (corners, prefabs) = config.generate_corners(&sheet).unwrap();
+        let variant_blocks = config.generate_corner_variants(&sheet).unwrap();
+        let assembled = config
+            .generate_icons(
+                &corners,
+                &variant_blocks,
+                &prefabs,
+                1,
+                SIZE_OF_CARDINALS,
+                &CancellationToken::new(),
+            )
+            .unwrap();
+        // junction 0 is all convex corners, each from whichever block it picked
+        let isolated = &assembled[&Adjacency::empty()][0];
+        for corner in all::<Corner>() {
+            let placement = config.corner_placement(corner);
+            let expected = match config.corner_variant(Adjacency::empty(), corner) {
+                0 => Rgba([255, 0, 0, 255]),
+                _ => white,
+            };
+            assert_eq!(isolated.get_pixel(placement.x, placement.y), expected);
+        }
+
+        let unused: CornerVariants = toml::from_str("flat = [4]").unwrap();
+        let unused = BitmaskSlice {
+            corner_variants: Some(unused),
+            ..Default::default()
+        };
+        assert_eq!(unused.config_problems().len(), 1);
+    }
+
+    #[test]
+    fn corner_atlas() {
+        let prefab_sheet = symmetric_sheet().crop_imm(0, 0, 32, 32);
+        let config = BitmaskSlice {
+            corner_atlas: Some("edges.png".to_string()),
+            prefabs: Some(Prefabs(BTreeMap::from([(15, Prefab::sheet(0))]))),
+            ..Default::default()
+        };
+        // read from the atlas, so positions past the input's edge are fine
+        let unloaded = config.image_problems(&prefab_sheet, []);
+        assert_eq!(unloaded.len(), 1);
+        assert!(config.overlapping_prefabs().is_empty());
+
+        let loaded = BitmaskSlice {
+            corner_atlas_image: Some(symmetric_sheet()),
+            ..config
+        };
+        assert!(loaded.image_problems(&prefab_sheet, []).is_empty());
+        let (corners, prefabs) = loaded.generate_corners(&prefab_sheet).unwrap();
+        let concave = &corners
+            .get(CornerType::Concave)
+            .and_then(|block| block.get(Corner::NorthEast))
+            .unwrap()[0];
+        assert_eq!(concave.get_pixel(0, 0), Rgba([0, 255, 0, 255]));
+        let PrefabBlock::Whole(prefab) = &prefabs[&Adjacency::CARDINALS] else {
+            panic!("Expected a whole prefab");
+        };
+        assert_eq!(prefab[0].get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+
+        let short_atlas = BitmaskSlice {
+            corner_atlas_image: Some(prefab_sheet.clone()),
+            ..loaded
+        };
+        assert_eq!(short_atlas.image_problems(&prefab_sheet, []).len(), 1);
+    }
+
+    #[test]
+    fn prefab_files() {
+        let prefabs: Prefabs =
+            toml::from_str("15 = 4\n12 = { file = \"prefabs.png\", position = 1 }").unwrap();
+        assert_eq!(prefabs.0[&15], Prefab::sheet(4));
+        assert_eq!(prefabs.files(), BTreeMap::from([("prefabs.png", vec![1])]));
+
+        let config = BitmaskSlice {
+            prefabs: Some(Prefabs(BTreeMap::from([(
+                15,
+                Prefab {
+                    position: 1,
+                    file: Some("prefabs.png".to_string()),
+                    corners: None,
+                },
+            )]))),
+            ..Default::default()
+        };
+        // never overlaps the input's positions, only unloaded
+        assert!(config.overlapping_prefabs().is_empty());
+        assert_eq!(config.image_problems(&symmetric_sheet(), []).len(), 1);
+
+        let mut prefab_file = image::RgbaImage::new(64, 32);
+        for (x, _y, pixel) in prefab_file.enumerate_pixels_mut() {
+            *pixel = Rgba([x as u8, 0, 255, 255]);
+        }
+        let loaded = BitmaskSlice {
+            prefab_images: HashMap::from([(
+                "prefabs.png".to_string(),
+                DynamicImage::ImageRgba8(prefab_file),
+            )]),
+            ..config
+        };
+        assert!(loaded.image_problems(&symmetric_sheet(), []).is_empty());
+        let (_, prefabs) = loaded.generate_corners(&symmetric_sheet()).unwrap();
+        let PrefabBlock::Whole(prefab) = &prefabs[&Adjacency::CARDINALS] else {
+            panic!("Expected a whole prefab");
+        };
+        assert_eq!(prefab[0].get_pixel(0, 0), Rgba([32, 0, 255, 255]));
+
+        let narrow = BitmaskSlice {
+            prefab_images: HashMap::from([(
+                "prefabs.png".to_string(),
+                symmetric_sheet().crop_imm(0, 0, 32, 32),
+            )]),
+            ..loaded
+        };
+        assert_eq!(narrow.image_problems(&symmetric_sheet(), []).len(), 1);
+    }
+
+    #[test]
+    fn corner_prefabs() {
+        let prefabs: Prefabs =
+            toml::from_str("15 = { position = 4, corners = [\"north_east\"] }").unwrap();
+        assert_eq!(prefabs.0[&15].corners, Some(vec![Corner::NorthEast]));
+        let written = toml::to_string(&prefabs).unwrap();
+        assert_eq!(toml::from_str::<Prefabs>(&written).unwrap(), prefabs);
+
+        let mut sheet = image::RgbaImage::from_pixel(32 * 5, 32, Rgba([255, 255, 255, 255]));
+        imageops::replace(&mut sheet, &symmetric_sheet().to_rgba8(), 0, 0);
+        let config = BitmaskSlice {
+            prefabs: Some(prefabs),
+            ..Default::default()
+        };
+        assert!(config.config_problems().is_empty());
+        let payload = config
+            .do_operation(
+                &InputIcon::DynamicImage(DynamicImage::ImageRgba8(sheet)),
+                OperationMode::Standard,
+            )
+            .unwrap();
+        let icon = single_dmi(payload);
+        let state = icon.states.iter().find(|state| state.name == "15").unwrap();
+        // only the north east corner comes from the prefab
+        assert_eq!(state.images[0].get_pixel(24, 8), Rgba([255, 255, 255, 255]));
+        assert_ne!(state.images[0].get_pixel(8, 24), Rgba([255, 255, 255, 255]));
+        assert_eq!(
+            config.state_origin("15"),
+            StateOrigin::Junction { junction: 15 }
+        );
+
+        let no_corners = BitmaskSlice {
+            prefabs: Some(Prefabs(BTreeMap::from([(
+                15,
+                Prefab {
+                    corners: Some(vec![]),
+                    ..Prefab::sheet(4)
+                },
+            )]))),
+            ..Default::default()
+        };
+        assert_eq!(no_corners.config_problems().len(), 1);
+    }
+
+    #[test]
+    fn orphaned_corners() {
+        let config = BitmaskSlice {
+            smooth_diagonally: true,
+            derive_flat: true,
+            ..Default::default()
+        };
+        let states = |config: &BitmaskSlice| {
+            let input = InputIcon::DynamicImage(symmetric_sheet());
+            let payload = config
+                .do_operation(&input, OperationMode::Standard)
+                .unwrap();
+            single_dmi(payload).states
+        };
+        assert_eq!(states(&config).len(), 47);
+
+        let north_east = Adjacency::NE.bits().to_string();
+        let placement = config.corner_placement(Corner::NorthEast);
+        for (orphaned_corners, expected) in [
+            (OrphanedCorners::Ignore, Rgba([255, 0, 0, 255])),
+            (OrphanedCorners::Concave, Rgba([0, 255, 0, 255])),
+        ] {
+            let config = BitmaskSlice {
+                orphaned_corners: Some(orphaned_corners),
+                ..config.clone()
+            };
+            let states = states(&config);
+            assert_eq!(states.len(), 256);
+            let orphan = states
+                .iter()
+                .find(|state| state.name == north_east)
+                .unwrap();
+            assert_eq!(
+                orphan.images[0].get_pixel(placement.x, placement.y),
+                expected
+            );
+        }
+
+        let cardinal = BitmaskSlice {
+            orphaned_corners: Some(OrphanedCorners::Ignore),
+            ..Default::default()
+        };
+        assert_eq!(cardinal.config_problems().len(), 1);
+    }
+
+    #[test]
+    fn derive_flat() {
+        let mut sheet = symmetric_sheet();
+        let white = Rgba([255, 255, 255, 255]);
+        sheet.as_mut_rgba8().unwrap().put_pixel(32 + 16, 16, white);
+        let config = BitmaskSlice {
+            smooth_diagonally: true,
+            derive_flat: true,
+            ..Default::default()
+        };
+        assert!(config.config_problems().is_empty());
+
+        let (corners, _) = config.generate_corners(&sheet).unwrap();
+        let flat = corners.get(CornerType::Flat).unwrap();
+        // each corner is centered on the cut, so it's clear of the concave block's
+        // edges
+        for corner in all::<Corner>() {
+            let frame = &flat.get(corner).unwrap()[0];
+            assert_eq!(frame.dimensions(), (16, 16));
+            assert_eq!(frame.get_pixel(8, 8), white);
+            assert_eq!(frame.get_pixel(0, 0), Rgba([0, 255, 0, 255]));
+        }
+
+        let cardinal = BitmaskSlice {
+            smooth_diagonally: false,
+            ..config.clone()
+        };
+        assert_eq!(cardinal.config_problems().len(), 1);
+        let mut positions = Positions::default();
+        positions.0.insert(CornerType::Flat, Some(4));
+        let both = BitmaskSlice {
+            positions,
+            ..config
+        };
+        assert_eq!(both.config_problems().len(), 1);
+    }
+
+    #[test]
+    fn empty_position_slot() {
+        let positions: Positions = toml::from_str(
+            r#"
+            convex = 0
+            concave = 1
+            horizontal = 2
+            vertical = "none"
+            "#,
+        )
+        .unwrap();
+        assert!(positions.is_empty_slot(CornerType::Vertical));
+        assert!(toml::from_str::<Positions>("convex = \"nothing\"").is_err());
+
+        let config = BitmaskSlice {
+            positions,
+            ..Default::default()
+        };
+        config.verify_config().unwrap();
+        let payload = config
+            .do_operation(
+                &InputIcon::DynamicImage(symmetric_sheet()),
+                OperationMode::Standard,
+            )
+            .unwrap();
+        let (payload, warnings) = payload.take_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].message.starts_with("Skipping 9 junctions"));
+        let icon = single_dmi(payload);
+        // vertical corners are only needed next to a north or south neighbour
+        // without an east or west one
+        let names: Vec<&str> = icon
+            .states
+            .iter()
+            .map(|state| state.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["0", "4", "8", "12", "13", "14", "15"]);
+
+        let missing_flat = BitmaskSlice {
+            smooth_diagonally: true,
+            ..Default::default()
+        };
+        assert!(missing_flat.verify_config().is_err());
+    }
+
+    #[test]
+    fn size_overrides() {
+        let config = BitmaskSlice {
+            size_overrides: Some(vec![SizeOverride {
+                name: "large".to_string(),
+                junctions: vec![15],
+                output_icon_size: OutputIconSize { x: 64, y: 48 },
+                output_icon_pos: OutputIconPosition { x: 16, y: 16 },
+            }]),
+            ..Default::default()
+        };
+        config.verify_config().unwrap();
+        let payload = config
+            .do_operation(
+                &InputIcon::DynamicImage(symmetric_sheet()),
+                OperationMode::Standard,
+            )
+            .unwrap();
+        let icon = single_dmi(payload);
+        assert_eq!((icon.width, icon.height), (64, 48));
+        let find = |name: &str| icon.states.iter().find(|state| state.name == name).unwrap();
+        // regular states are anchored to the bottom left of the larger canvas
+        let regular = &find("15").images[0];
+        assert_eq!(regular.get_pixel(0, 16)[3], 255);
+        assert_eq!(regular.get_pixel(0, 15)[3], 0);
+        let large = &find("large-15").images[0];
+        assert_eq!(large.get_pixel(16, 16), regular.get_pixel(0, 16));
+        assert_eq!(large.get_pixel(15, 16)[3], 0);
+
+        let orphaned = BitmaskSlice {
+            size_overrides: Some(vec![SizeOverride {
+                junctions: vec![Adjacency::NE.bits()],
+                ..config.size_overrides.unwrap()[0].clone()
+            }]),
+            ..Default::default()
+        };
+        assert!(orphaned.verify_config().is_err());
+    }
+
+    #[test]
+    fn cardinal_sets() {
+        let config = BitmaskSlice {
+            smooth_diagonally: true,
+            derive_flat: true,
+            cardinal_set: Some(CardinalSetOutput::SameIcon),
+            ..Default::default()
+        };
+        let input = InputIcon::DynamicImage(symmetric_sheet());
+        let payload = config
+            .do_operation(&input, OperationMode::Standard)
+            .unwrap();
+        let icon = single_dmi(payload);
+        let find = |icon: &Icon, name: &str| {
+            icon.states
+                .iter()
+                .find(|state| state.name == name)
+                .unwrap()
+                .images
+                .clone()
+        };
+        assert_eq!(icon.states.len(), 47 + SIZE_OF_CARDINALS);
+        // junctions without diagonals look the same either way
+        for bits in 0..SIZE_OF_CARDINALS {
+            assert_eq!(
+                find(&icon, &format!("cardinal-{bits}")),
+                find(&icon, &bits.to_string())
+            );
+        }
+
+        // a separate icon is padded along with the main one
+        let config = BitmaskSlice {
+            cardinal_set: Some(CardinalSetOutput::SeparateIcon),
+            size_overrides: Some(vec![SizeOverride {
+                name: "large".to_string(),
+                junctions: vec![15],
+                output_icon_size: OutputIconSize { x: 64, y: 48 },
+                output_icon_pos: OutputIconPosition { x: 16, y: 16 },
+            }]),
+            ..config
+        };
+        let payload = config
+            .do_operation(&input, OperationMode::Standard)
+            .unwrap();
+        let ProcessorPayload::MultipleNamed(icons) = payload else {
+            panic!("Expected the main and cardinal icons");
+        };
+        let [main, cardinal] = icons.as_slice() else {
+            panic!("Expected two icons, got {}", icons.len());
+        };
+        assert_eq!(cardinal.name_hint.as_deref(), Some("cardinal"));
+        let (OutputImage::Dmi(main), OutputImage::Dmi(cardinal)) = (&main.image, &cardinal.image)
+        else {
+            panic!("Expected dmis");
+        };
+        assert_eq!(main.states.len(), 47 + 1);
+        assert_eq!(cardinal.states.len(), SIZE_OF_CARDINALS);
+        assert_eq!((cardinal.width, cardinal.height), (64, 48));
+        assert_eq!((main.width, main.height), (64, 48));
+        for bits in 0..SIZE_OF_CARDINALS {
+            let name = bits.to_string();
+            assert_eq!(find(cardinal, &name), find(main, &name));
+        }
+    }
+
+    #[test]
+    fn diagonal_walls() {
+        let mut sheet = DynamicImage::new_rgba8(32 * 8, 32);
+        imageops::overlay(&mut sheet, &symmetric_sheet(), 0, 0);
+        for position in 4..8 {
+            let shade = position as u8 * 10;
+            for x in 0..32 {
+                for y in 0..32 {
+                    sheet.as_mut_rgba8().unwrap().put_pixel(
+                        position * 32 + x,
+                        y,
+                        Rgba([shade, shade, shade, 255]),
+                    );
                 }
             }
         }
+        let config = BitmaskSlice {
+            output_name: Some("wall".to_string()),
+            diagonal_walls: Some(DiagonalWalls {
+                name: "d".to_string(),
+                ne: 4,
+                se: 5,
+                sw: 6,
+                nw: 7,
+            }),
+            ..Default::default()
+        };
+        config.verify_config().unwrap();
+        let payload = config
+            .do_operation(&InputIcon::DynamicImage(sheet), OperationMode::Standard)
+            .unwrap();
+        let icon = single_dmi(payload);
+        let shade = |name: &str| {
+            let state = icon.states.iter().find(|state| state.name == name).unwrap();
+            state.images[0].get_pixel(16, 16)[0]
+        };
+        assert_eq!(shade("wall-d-5"), 40);
+        assert_eq!(shade("wall-d-6"), 50);
+        assert_eq!(shade("wall-d-10"), 60);
+        assert_eq!(shade("wall-d-9"), 70);
+        assert_eq!(icon.states.len(), 16 + 4);
+        assert_eq!(
+            config.state_origin("wall-d-5"),
+            StateOrigin::DiagonalWall { junction: 5 }
+        );
+
+        // with diagonal smoothing the walls are for the diagonal being
+        // connected too
+        let diagonal = BitmaskSlice {
+            smooth_diagonally: true,
+            ..config
+        };
+        let junctions: Vec<u8> = diagonal
+            .diagonal_wall_junctions()
+            .iter()
+            .map(|(junction, _)| junction.bits())
+            .collect();
+        assert_eq!(junctions, [21, 38, 74, 137]);
+    }
+
+    #[test]
+    fn state_origins() {
+        let config = BitmaskSlice {
+            output_name: Some("wall".to_string()),
+            prefabs: Some(Prefabs(BTreeMap::from([(15, Prefab::sheet(4))]))),
+            size_overrides: Some(vec![SizeOverride {
+                name: "large".to_string(),
+                junctions: vec![3],
+                output_icon_size: OutputIconSize { x: 64, y: 64 },
+                output_icon_pos: OutputIconPosition::default(),
+            }]),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.state_origin("wall-3"),
+            StateOrigin::Junction { junction: 3 }
+        );
+        assert_eq!(
+            config.state_origin("wall-15"),
+            StateOrigin::Prefab { junction: 15 }
+        );
+        assert_eq!(
+            config.state_origin("wall-large-3"),
+            StateOrigin::Override {
+                name: "large".to_string(),
+                junction: 3
+            }
+        );
+        assert_eq!(config.state_origin("3"), StateOrigin::Unknown);
+    }
+
+    #[test]
+    fn prefab_overlaps() {
+        // default positions use 0 to 3
+        let config = BitmaskSlice {
+            prefabs: Some(Prefabs(BTreeMap::from([
+                (15, Prefab::sheet(3)),
+                (12, Prefab::sheet(4)),
+                (3, Prefab::sheet(0)),
+            ]))),
+            ..Default::default()
+        };
+        assert_eq!(config.overlapping_prefabs(), vec![(3, 0), (15, 3)]);
+        assert_eq!(
+            config.prefab_overlap_fixes(),
+            BTreeMap::from([(3, 5), (15, 6)])
+        );
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let config = BitmaskSlice {
+            collapse_rotations: true,
+            cardinal_set: Some(CardinalSetOutput::SameIcon),
+            prefabs: Some(Prefabs(BTreeMap::from([(15, Prefab::sheet(6))]))),
+            ..Default::default()
+        };
+        // four positions wide and two frames tall, with no delays
+        let input = InputIcon::DynamicImage(DynamicImage::new_rgba8(128, 64));
+        let Err(ProcessorError::Multiple(problems)) =
+            config.do_operation(&input, OperationMode::Standard)
+        else {
+            panic!("Expected every problem at once");
+        };
+        let messages: Vec<String> = problems
+            .iter()
+            .map(|problem| {
+                let ProcessorError::ConfigError(message) = problem else {
+                    panic!("Expected config errors, got {problem:?}");
+                };
+                message.clone()
+            })
+            .collect();
+        assert_eq!(messages.len(), 4);
+        assert!(messages[0].starts_with("collapse_rotations"));
+        assert!(messages[1].starts_with("cardinal_set"));
+        assert!(messages[2].contains("position 6 needs it to be at least 224px wide"));
+        assert!(messages[3].contains("animation.delays isn't set"));
+    }
+
+    #[test]
+    fn state_subsets() {
+        let names = |config: &BitmaskSlice| -> Vec<String> {
+            let payload = config
+                .do_operation(
+                    &InputIcon::DynamicImage(symmetric_sheet()),
+                    OperationMode::Standard,
+                )
+                .unwrap();
+            let icon = single_dmi(payload);
+            icon.states.into_iter().map(|state| state.name).collect()
+        };
+        let only = BitmaskSlice {
+            only_states: Some(vec![0, 15, 5]),
+            ..Default::default()
+        };
+        assert_eq!(names(&only), vec!["0", "5", "15"]);
+        let skip = BitmaskSlice {
+            skip_states: Some((1..15).collect()),
+            ..Default::default()
+        };
+        assert_eq!(names(&skip), vec!["0", "15"]);
+
+        // a prefab for a junction that's left out is never used
+        let config = BitmaskSlice {
+            prefabs: Some(Prefabs(BTreeMap::from([(15, Prefab::sheet(4))]))),
+            ..only.clone()
+        };
+        assert!(config.unused_prefabs().is_empty());
+        let config = BitmaskSlice {
+            only_states: Some(vec![0]),
+            ..config
+        };
+        assert_eq!(config.unused_prefabs(), vec![15]);
+
+        let diagonal = BitmaskSlice {
+            only_states: Some(vec![255]),
+            ..Default::default()
+        };
+        assert!(diagonal.verify_config().is_err());
+        let empty = BitmaskSlice {
+            only_states: Some(vec![]),
+            ..Default::default()
+        };
+        assert!(empty.verify_config().is_err());
+    }
+
+    #[test]
+    fn state_name_formats() {
+        let config = BitmaskSlice {
+            output_name: Some("wall".to_string()),
+            state_name_format: Some("{prefix}_{cardinal_letters}".parse().unwrap()),
+            only_states: Some(vec![0, 5, 15]),
+            ..Default::default()
+        };
+        config.verify_config().unwrap();
+        let payload = config
+            .do_operation(
+                &InputIcon::DynamicImage(symmetric_sheet()),
+                OperationMode::Standard,
+            )
+            .unwrap();
+        let icon = single_dmi(payload);
+        let names: Vec<&str> = icon
+            .states
+            .iter()
+            .map(|state| state.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["wall", "wall_NE", "wall_NSEW"]);
+        assert_eq!(
+            config.state_origin("wall_NE"),
+            StateOrigin::Junction { junction: 5 }
+        );
+
+        // letters for cardinals alone can't tell diagonal junctions apart
+        let colliding = BitmaskSlice {
+            smooth_diagonally: true,
+            only_states: None,
+            ..config
+        };
+        let expected = "junction 5 and junction 21 the same state name \"wall_NE\"";
+        assert!(colliding.config_problems().iter().any(|problem| {
+            matches!(problem, ProcessorError::ConfigError(message) if message.contains(expected))
+        }));
+
+        // nor can goonstation's, which only go by the cardinals
+        let goonstation = BitmaskSlice {
+            state_name_format: Some("goonstation".parse().unwrap()),
+            ..colliding
+        };
+        let expected = "junction 5 and junction 21 the same state name \"wall5\"";
+        assert!(goonstation.config_problems().iter().any(|problem| {
+            matches!(problem, ProcessorError::ConfigError(message) if message.contains(expected))
+        }));
+    }
+
+    #[test]
+    fn unused_prefabs() {
+        let prefabs: Prefabs = toml::from_str("15 = 4\n46 = 5\n64 = 6\n255 = 7").unwrap();
+        let config = BitmaskSlice {
+            prefabs: Some(prefabs),
+            ..Default::default()
+        };
+        assert_eq!(config.unused_prefabs(), vec![46, 64, 255]);
+        assert_eq!(config.config_warnings().len(), 3);
+        // 64 is only the south west corner, without either of its sides
+        let config = BitmaskSlice {
+            smooth_diagonally: true,
+            ..config
+        };
+        assert_eq!(config.unused_prefabs(), vec![64]);
+
+        // written differently, but the same junction
+        let duplicate = toml::from_str::<Prefabs>("4 = 4\n04 = 5").unwrap_err();
+        assert!(duplicate
+            .to_string()
+            .contains("prefab keys `04` and `4` are both junction 4"));
+        assert!(toml::from_str::<Prefabs>("north = 4").is_err());
     }
 }
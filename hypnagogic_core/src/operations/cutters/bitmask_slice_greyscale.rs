@@ -0,0 +1,236 @@
+use image::{DynamicImage, GenericImageView};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::cutters::bitmask_slice::BitmaskSlice;
+use crate::operations::error::{ProcessorError, ProcessorResult, ProcessorWarning};
+use crate::operations::post_process::{Filter, PostProcess};
+use crate::operations::{
+    IconOperationConfig,
+    InputIcon,
+    NamedIcon,
+    OperationMode,
+    OutputImage,
+    ProcessorPayload,
+    StateOrigin,
+};
+
+/// Name hint of the icon cut from the mask
+const MASK_HINT: &str = "mask";
+
+/// A bitmask slice that outputs a greyscale copy of its icon for tinting at
+/// runtime, along with the same states cut from a mask marking which regions
+/// take which color
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct BitmaskSliceGreyscale {
+    #[serde(flatten)]
+    pub bitmask_slice_config: BitmaskSlice,
+    /// Png next to the config with the color regions, laid out the same as the
+    /// input
+    pub mask: String,
+    /// The loaded `mask`, filled in by `IconOperation::load_side_files`
+    #[serde(skip)]
+    pub mask_image: Option<DynamicImage>,
+}
+
+impl IconOperationConfig for BitmaskSliceGreyscale {
+    fn perform_operation(
+        &self,
+        input: &InputIcon,
+        mode: OperationMode,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ProcessorPayload> {
+        let Some(mask) = &self.mask_image else {
+            return Err(ProcessorError::ConfigError(format!(
+                "mask `{}` hasn't been loaded",
+                self.mask
+            )));
+        };
+        let mut base = self
+            .bitmask_slice_config
+            .perform_operation(input, mode, cancel)?;
+        PostProcess {
+            filters: vec![Filter::Desaturate { amount: 1.0 }],
+        }
+        .apply(&mut base)?;
+
+        // previews and the like only make sense for the base, so the mask is
+        // always cut as standard and only its icons are kept
+        let (mask_payload, _) = self
+            .bitmask_slice_config
+            .perform_operation(
+                &InputIcon::DynamicImage(mask.clone()),
+                OperationMode::Standard,
+                cancel,
+            )?
+            .take_warnings();
+        let mut mask_icons = vec![];
+        collect_icons(mask_payload, &mut mask_icons);
+        for icon in &mut mask_icons {
+            icon.name_hint = Some(match &icon.name_hint {
+                Some(hint) => format!("{hint}-{MASK_HINT}"),
+                None => MASK_HINT.to_string(),
+            });
+        }
+        Ok(base.with_named(mask_icons))
+    }
+
+    fn verify_config(&self) -> ProcessorResult<()> {
+        let config = &self.bitmask_slice_config;
+        let mut problems = config.config_problems();
+        if config.corner_atlas.is_some() {
+            problems.push(ProcessorError::ConfigError(
+                "corner_atlas isn't supported by BitmaskSliceGreyscale, the mask would be cut \
+                 from it too"
+                    .to_string(),
+            ));
+        }
+        if config
+            .prefabs
+            .as_ref()
+            .is_some_and(|prefabs| !prefabs.files().is_empty())
+        {
+            problems.push(ProcessorError::ConfigError(
+                "prefabs from a separate file aren't supported by BitmaskSliceGreyscale, the mask \
+                 would use them too"
+                    .to_string(),
+            ));
+        }
+        ProcessorError::check_all(problems)
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        let mut problems = self.bitmask_slice_config.input_problems(input);
+        if let (InputIcon::DynamicImage(img), Some(mask)) = (input, &self.mask_image) {
+            if mask.dimensions() != img.dimensions() {
+                problems.push(ProcessorError::ConfigError(format!(
+                    "mask `{}` is {}x{}, but the input is {}x{}",
+                    self.mask,
+                    mask.width(),
+                    mask.height(),
+                    img.width(),
+                    img.height()
+                )));
+            }
+        }
+        problems
+    }
+
+    fn config_warnings(&self) -> Vec<ProcessorWarning> {
+        self.bitmask_slice_config.config_warnings()
+    }
+
+    fn state_origin(&self, state_name: &str) -> StateOrigin {
+        self.bitmask_slice_config.state_origin(state_name)
+    }
+}
+
+/// Adds every dmi in `payload` to `icons`, dropping everything else
+fn collect_icons(payload: ProcessorPayload, icons: &mut Vec<NamedIcon>) {
+    match payload {
+        ProcessorPayload::Single(image) => {
+            if let OutputImage::Dmi(icon) = *image {
+                icons.push(NamedIcon::from_icon(icon));
+            }
+        }
+        ProcessorPayload::SingleNamed(named) => {
+            if matches!(named.image, OutputImage::Dmi(_)) {
+                icons.push(*named);
+            }
+        }
+        ProcessorPayload::MultipleNamed(named) => {
+            icons.extend(
+                named
+                    .into_iter()
+                    .filter(|named| matches!(named.image, OutputImage::Dmi(_))),
+            );
+        }
+        ProcessorPayload::ConfigWrapped(payload, _) | ProcessorPayload::Warned(payload, _) => {
+            collect_icons(*payload, icons);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::Rgba;
+
+    use super::*;
+    use crate::config::blocks::cutters::{CutPosition, IconSize, OutputIconSize, Positions};
+    use crate::operations::tile_sheet;
+
+    /// Opaque blocks, each in its own color
+    fn sheet(colors: [[u8; 3]; 5]) -> DynamicImage {
+        tile_sheet((8, 8), (5, 1), |block, _, _| {
+            let [r, g, b] = colors[block as usize];
+            Rgba([r, g, b, 255])
+        })
+    }
+
+    fn config(mask: Option<DynamicImage>) -> BitmaskSliceGreyscale {
+        BitmaskSliceGreyscale {
+            bitmask_slice_config: BitmaskSlice {
+                icon_size: IconSize { x: 8, y: 8 },
+                output_icon_size: Some(OutputIconSize { x: 8, y: 8 }),
+                cut_pos: CutPosition { x: 4, y: 4 },
+                positions: Positions::default(),
+                ..Default::default()
+            },
+            mask: "mask.png".to_string(),
+            mask_image: mask,
+        }
+    }
+
+    #[test]
+    fn greyscale_and_mask() {
+        let input = sheet([[200, 40, 40]; 5]);
+        let mask = sheet([
+            [255, 0, 0],
+            [0, 255, 0],
+            [0, 0, 255],
+            [255, 0, 0],
+            [0, 255, 0],
+        ]);
+        let payload = config(Some(mask))
+            .do_operation(&InputIcon::DynamicImage(input), OperationMode::Standard)
+            .unwrap();
+        let ProcessorPayload::MultipleNamed(icons) = payload else {
+            panic!("Expected a base and a mask");
+        };
+        let [base, mask] = icons.as_slice() else {
+            panic!("Expected two icons");
+        };
+        assert_eq!(base.name_hint, None);
+        assert_eq!(mask.name_hint.as_deref(), Some("mask"));
+        let (OutputImage::Dmi(base), OutputImage::Dmi(mask)) = (&base.image, &mask.image) else {
+            panic!("Expected dmis");
+        };
+
+        let names = |icon: &dmi::icon::Icon| {
+            icon.states
+                .iter()
+                .map(|state| state.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(base), names(mask));
+
+        let [r, g, b, a] = base.states[0].images[0].get_pixel(1, 1).0;
+        assert_eq!((r, a), (g, 255));
+        assert_eq!(g, b);
+        // junction 0 is all convex corners, cut from the red region
+        assert_eq!(mask.states[0].images[0].get_pixel(1, 1).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn mask_problems() {
+        let input = InputIcon::DynamicImage(sheet([[0; 3]; 5]));
+        assert!(config(None)
+            .do_operation(&input, OperationMode::Standard)
+            .is_err());
+
+        let small = DynamicImage::new_rgba8(8, 8);
+        let problems = config(Some(small)).input_problems(&input);
+        assert_eq!(problems.len(), 1);
+    }
+}
@@ -0,0 +1,358 @@
+use std::collections::{BTreeMap, HashMap};
+
+use dmi::icon::{Icon, IconState, Looping};
+use enum_iterator::all;
+use fixed_map::Map;
+use image::{imageops, DynamicImage};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::config::blocks::cutters::{Animation, GroupPositions, IconHotspot};
+use crate::generation::icon::generate_map_icon;
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::cutters::bitmask_slice::{
+    BitmaskSlice,
+    SIZE_OF_CARDINALS,
+    SIZE_OF_DIAGONALS,
+};
+use crate::operations::error::{ProcessorError, ProcessorResult, ProcessorWarning};
+use crate::operations::{
+    IconOperationConfig,
+    InputIcon,
+    NamedIcon,
+    OperationMode,
+    ProcessorPayload,
+};
+use crate::util::adjacency::Adjacency;
+use crate::util::corners::{Corner, CornerType, Side};
+use crate::util::icon_ops::dedupe_frames;
+use crate::util::repeat_for;
+use crate::util::state_inventory::StateOrigin;
+
+/// Bitmask smoothing with three classes of neighbour per side: the same type,
+/// the same smoothing group, or nothing. Regular `positions` are used for
+/// corners touching the same type, and `group_positions` for corners touching
+/// the group.
+///
+/// Junctions where nothing touches the group are named like regular bitmask
+/// smoothing. The others get the cardinals touching the group appended, so
+/// `14-g4` connects north, east and west, with east being the group
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct BitmaskSliceGroups {
+    #[serde(flatten)]
+    pub bitmask_slice_config: BitmaskSlice,
+    pub group_positions: GroupPositions,
+}
+
+type CornerBlock = Map<Corner, Vec<DynamicImage>>;
+
+impl IconOperationConfig for BitmaskSliceGroups {
+    #[tracing::instrument(skip(input))]
+    fn perform_operation(
+        &self,
+        input: &InputIcon,
+        mode: OperationMode,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ProcessorPayload> {
+        debug!("Starting bitmask slice groups icon op");
+        let InputIcon::DynamicImage(img) = input else {
+            return Err(ProcessorError::ImageNotFound);
+        };
+        let config = &self.bitmask_slice_config;
+        let (corners, prefabs) = config.generate_corners(img)?;
+        let variants = config.generate_corner_variants(img)?;
+        let num_frames = config.frame_count(img)?;
+        let mut warnings: Vec<ProcessorWarning> = config.leftover_rows(img).into_iter().collect();
+
+        let possible_states = if config.smooth_diagonally {
+            SIZE_OF_DIAGONALS
+        } else {
+            SIZE_OF_CARDINALS
+        };
+
+        let mut assembled = config.generate_icons(
+            &corners,
+            &variants,
+            &prefabs,
+            num_frames,
+            possible_states,
+            cancel,
+        )?;
+        config.apply_prefab_overlays(img, &mut assembled)?;
+        warnings.extend(config.skipped_junctions(&assembled, possible_states));
+
+        let source = config.corner_source(img)?;
+        let group_blocks: HashMap<u32, CornerBlock> = self
+            .group_positions
+            .all()
+            .into_iter()
+            .map(|position| (position, config.build_corner(source, position, num_frames)))
+            .collect();
+
+        let icon_directions = if config.produce_dirs {
+            Side::dmi_cardinals().to_vec()
+        } else {
+            vec![Side::South]
+        };
+        let delay = config
+            .animation
+            .clone()
+            .map(|x| repeat_for(&x.delays_in_deciseconds(), num_frames as usize));
+        let rewind = config
+            .animation
+            .as_ref()
+            .and_then(|animation| animation.rewind)
+            .unwrap_or(false);
+        let hotspot = config.hotspot.map(IconHotspot::dmi_hotspot);
+        let loop_flag = config
+            .animation
+            .as_ref()
+            .map_or(Looping::Indefinitely, Animation::looping);
+        let movement = config
+            .animation
+            .as_ref()
+            .and_then(|animation| animation.movement)
+            .unwrap_or(false);
+
+        let mut icon_states =
+            config.build_icon_states(&assembled, possible_states, num_frames, None);
+
+        let junctions = (0..possible_states)
+            .map(|x| Adjacency::from_bits(x as u8).unwrap())
+            .filter(|adjacency| config.keeps_junction(*adjacency));
+        for adjacency in junctions {
+            cancel.check()?;
+            for group in group_subsets(adjacency & Adjacency::CARDINALS) {
+                let frames: Option<Vec<Vec<DynamicImage>>> = icon_directions
+                    .iter()
+                    .map(|direction| {
+                        let rotation = Adjacency::from(*direction);
+                        let Some(mut frames) = self.assemble(
+                            &corners,
+                            &group_blocks,
+                            adjacency.rotate_to(rotation),
+                            group.rotate_to(rotation),
+                            num_frames,
+                        ) else {
+                            return Ok(None);
+                        };
+                        config.overlay_junction(img, adjacency.rotate_to(rotation), &mut frames)?;
+                        Ok(Some(config.place_for_dir(*direction, &frames)))
+                    })
+                    .collect::<ProcessorResult<Vec<_>>>()?
+                    .into_iter()
+                    .collect();
+                // only possible if it needs a corner from an empty position
+                // slot, which has already been warned about
+                let Some(frames) = frames else {
+                    continue;
+                };
+                icon_states.push(dedupe_frames(
+                    IconState {
+                        name: format!("{}-g{}", config.state_name(None, adjacency), group.bits()),
+                        dirs: icon_directions.len() as u8,
+                        frames: num_frames,
+                        images: frames.into_iter().flatten().collect(),
+                        delay: delay.clone(),
+                        rewind,
+                        loop_flag,
+                        movement,
+                        hotspot,
+                        ..Default::default()
+                    },
+                    config.animation.as_ref(),
+                ));
+            }
+        }
+
+        if let Some(map_icon) = &config.map_icon {
+            let icon = generate_map_icon(config.output_size().x, config.output_size().y, map_icon)?;
+            icon_states.push(IconState {
+                name: map_icon.icon_state_name.clone(),
+                dirs: 1,
+                frames: 1,
+                images: vec![icon],
+                ..Default::default()
+            });
+        }
+
+        let out_icon = Icon {
+            version: dmi::icon::DmiVersion::default(),
+            width: config.output_size().x,
+            height: config.output_size().y,
+            states: icon_states,
+        };
+
+        let payload = if mode == OperationMode::Debug {
+            let mut out = config.generate_debug_icons(&corners);
+            out.push(NamedIcon::from_icon(out_icon));
+            ProcessorPayload::MultipleNamed(out)
+        } else {
+            ProcessorPayload::from_icon(out_icon)
+        };
+        Ok(payload.with_warnings(warnings))
+    }
+
+    fn verify_config(&self) -> ProcessorResult<()> {
+        let config = &self.bitmask_slice_config;
+        let mut problems = config.config_problems();
+        let unsupported = [
+            ("collapse_rotations", config.collapse_rotations),
+            ("dm_snippet", config.dm_snippet),
+            ("cardinal_set", config.cardinal_set.is_some()),
+            ("size_overrides", config.size_overrides.is_some()),
+            ("preview_map", config.preview_map.is_some()),
+            ("movement_states", config.movement_states),
+        ];
+        for (name, _) in unsupported.iter().filter(|(_, set)| *set) {
+            problems.push(ProcessorError::ConfigError(format!(
+                "{name} isn't supported by BitmaskSliceGroups"
+            )));
+        }
+        if config.smooth_diagonally && self.group_positions.flat.is_none() {
+            problems.push(ProcessorError::ConfigError(
+                "group_positions.flat is required when smooth_diagonally is enabled".to_string(),
+            ));
+        }
+        ProcessorError::check_all(problems)
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        match input {
+            InputIcon::DynamicImage(img) => {
+                self.bitmask_slice_config
+                    .image_problems(img, self.group_positions.all())
+            }
+            InputIcon::Dmi(_) | InputIcon::Aseprite(_) => vec![],
+        }
+    }
+
+    fn config_warnings(&self) -> Vec<ProcessorWarning> {
+        self.bitmask_slice_config.config_warnings()
+    }
+
+    fn state_origin(&self, state_name: &str) -> StateOrigin {
+        let name = match state_name.rsplit_once("-g") {
+            Some((name, group)) if group.parse::<u8>().is_ok() => name,
+            _ => state_name,
+        };
+        self.bitmask_slice_config.state_origin(name)
+    }
+}
+
+/// Every non empty subset of `cardinals`, in order
+fn group_subsets(cardinals: Adjacency) -> impl Iterator<Item = Adjacency> {
+    (1..=Adjacency::CARDINALS.bits())
+        .filter_map(Adjacency::from_bits)
+        .filter(move |group| cardinals.contains(*group))
+}
+
+impl BitmaskSliceGroups {
+    /// Assembles the frames of a junction where the cardinals in `group`
+    /// touch the smoothing group. `None` if it needs a corner from an empty
+    /// position slot
+    fn assemble(
+        &self,
+        corners: &Map<CornerType, CornerBlock>,
+        group_blocks: &HashMap<u32, CornerBlock>,
+        adjacency: Adjacency,
+        group: Adjacency,
+        num_frames: u32,
+    ) -> Option<Vec<DynamicImage>> {
+        let config = &self.bitmask_slice_config;
+        let mut blocks = BTreeMap::new();
+        for corner in all::<Corner>() {
+            let corner_type = config.corner_type(adjacency, corner);
+            let (vertical, horizontal) = Adjacency::from(corner).corner_sides();
+            let block = match self.group_positions.get(
+                corner_type,
+                group.contains(horizontal),
+                group.contains(vertical),
+            ) {
+                Some(position) => group_blocks.get(&position)?,
+                None => corners.get(corner_type)?,
+            };
+            blocks.insert(corner, block.get(corner)?);
+        }
+
+        let (size, position) = (config.output_size(), config.output_pos());
+        let frames = (0..num_frames as usize)
+            .map(|frame| {
+                let mut frame_image = DynamicImage::new_rgba8(size.x, size.y);
+                for (corner, block) in &blocks {
+                    let placement = config.corner_placement(*corner);
+                    imageops::overlay(
+                        &mut frame_image,
+                        &block[frame],
+                        i64::from(position.x + placement.x),
+                        i64::from(position.y + placement.y),
+                    );
+                }
+                frame_image
+            })
+            .collect();
+        Some(frames)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{GenericImageView, Rgba};
+
+    use super::*;
+    use crate::config::blocks::cutters::{CutPosition, IconSize, OutputIconSize, Positions};
+    use crate::operations::{single_dmi, tile_sheet};
+
+    /// Each block is a solid color matching its position
+    fn input() -> DynamicImage {
+        tile_sheet((8, 8), (5, 1), |block, _, _| {
+            Rgba([block as u8 * 50, 0, 0, 255])
+        })
+    }
+
+    #[test]
+    fn group_corners() {
+        let config = BitmaskSliceGroups {
+            bitmask_slice_config: BitmaskSlice {
+                icon_size: IconSize { x: 8, y: 8 },
+                output_icon_size: Some(OutputIconSize { x: 8, y: 8 }),
+                cut_pos: CutPosition { x: 4, y: 4 },
+                positions: Positions::default(),
+                ..Default::default()
+            },
+            group_positions: GroupPositions {
+                horizontal: 4,
+                vertical: 4,
+                concave: 4,
+                flat: None,
+                concave_horizontal: None,
+                concave_vertical: None,
+                flat_horizontal: None,
+                flat_vertical: None,
+            },
+        };
+        config.verify_config().unwrap();
+        let payload = config
+            .do_operation(&InputIcon::DynamicImage(input()), OperationMode::Standard)
+            .unwrap();
+        let icon = single_dmi(payload);
+        // 16 regular junctions, and 2^n - 1 grouped ones for each junction with
+        // n cardinals
+        assert_eq!(icon.states.len(), 81);
+
+        // north connected to the group, so only the north corners use it
+        let state = icon
+            .states
+            .iter()
+            .find(|state| state.name == "1-g1")
+            .unwrap();
+        let red = |x, y| state.images[0].get_pixel(x, y).0[0];
+        assert_eq!(red(1, 1), 200);
+        assert_eq!(red(6, 1), 200);
+        assert_eq!(red(1, 6), 0);
+
+        let state = icon.states.iter().find(|state| state.name == "1").unwrap();
+        assert_eq!(state.images[0].get_pixel(1, 1).0[0], 150);
+    }
+}
@@ -0,0 +1,334 @@
+use dmi::icon::{Icon, IconState};
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::cutters::bitmask_slice::BitmaskSlice;
+use crate::operations::error::{ProcessorError, ProcessorResult, ProcessorWarning};
+use crate::operations::{
+    IconOperationConfig,
+    InputIcon,
+    OperationMode,
+    OutputImage,
+    ProcessorPayload,
+    StateOrigin,
+};
+
+/// A bitmask slice whose input is only a template of where each junction is
+/// filled in, with a separate seamless texture drawn through it. Every state
+/// shows the same part of the texture, so bricks and rock carry on across
+/// tiles no matter how they smooth
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct BitmaskTextureMask {
+    #[serde(flatten)]
+    pub bitmask_slice_config: BitmaskSlice,
+    /// Png next to the config with the texture, tiling seamlessly. Anything
+    /// bigger than a tile is split in to tile sized windows, each giving its
+    /// own copy of every state
+    pub texture: String,
+    /// Also multiplies the texture by the template's color, so the template
+    /// can shade edges rather than only cutting them out
+    #[serde(default)]
+    pub shade: bool,
+    /// The loaded `texture`, filled in by `IconOperation::load_side_files`
+    #[serde(skip)]
+    pub texture_image: Option<DynamicImage>,
+}
+
+/// `window` of `texture`, whichever tile sized piece of it that is, with the
+/// coverage of `template` and its color too when `shade` is set
+fn masked(
+    texture: &DynamicImage,
+    window: (u32, u32),
+    template: &DynamicImage,
+    shade: bool,
+) -> DynamicImage {
+    let (width, height) = template.dimensions();
+    let mut out = RgbaImage::new(width, height);
+    for (x, y, mask) in template.pixels() {
+        let Rgba([r, g, b, a]) = texture.get_pixel(window.0 * width + x, window.1 * height + y);
+        let scale = |channel: u8, by: u8| (u16::from(channel) * u16::from(by) / 255) as u8;
+        let pixel = if shade {
+            Rgba([
+                scale(r, mask[0]),
+                scale(g, mask[1]),
+                scale(b, mask[2]),
+                scale(a, mask[3]),
+            ])
+        } else {
+            Rgba([r, g, b, scale(a, mask[3])])
+        };
+        out.put_pixel(x, y, pixel);
+    }
+    DynamicImage::ImageRgba8(out)
+}
+
+impl BitmaskTextureMask {
+    /// How many tile sized windows `texture` splits in to, across and down,
+    /// for an icon of `width` by `height`
+    fn windows(texture: &DynamicImage, width: u32, height: u32) -> (u32, u32) {
+        (texture.width() / width, texture.height() / height)
+    }
+
+    /// Draws the texture through every state of the icons in `payload`. With
+    /// more than one window, each state is copied for each of them, named
+    /// with the window's column and row counted from the bottom left like
+    /// byond's coordinates, so a turf at `x`, `y` uses `x % columns` and
+    /// `y % rows`
+    fn texture_payload(
+        &self,
+        texture: &DynamicImage,
+        payload: &mut ProcessorPayload,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<()> {
+        match payload {
+            ProcessorPayload::Single(image) => self.texture_image_output(texture, image, cancel),
+            ProcessorPayload::SingleNamed(named) => {
+                self.texture_image_output(texture, &mut named.image, cancel)
+            }
+            ProcessorPayload::MultipleNamed(icons) => {
+                for named in icons {
+                    self.texture_image_output(texture, &mut named.image, cancel)?;
+                }
+                Ok(())
+            }
+            ProcessorPayload::ConfigWrapped(payload, _) | ProcessorPayload::Warned(payload, _) => {
+                self.texture_payload(texture, payload, cancel)
+            }
+        }
+    }
+
+    fn texture_image_output(
+        &self,
+        texture: &DynamicImage,
+        image: &mut OutputImage,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<()> {
+        let OutputImage::Dmi(icon) = image else {
+            return Ok(());
+        };
+        let (columns, rows) = Self::windows(texture, icon.width, icon.height);
+        if columns == 0 || rows == 0 {
+            return Err(ProcessorError::ConfigError(format!(
+                "texture `{}` is {}x{}, smaller than the {}x{} icons it's drawn in to",
+                self.texture,
+                texture.width(),
+                texture.height(),
+                icon.width,
+                icon.height
+            )));
+        }
+        let mut states = vec![];
+        for state in &icon.states {
+            cancel.check()?;
+            for row in 0..rows {
+                for column in 0..columns {
+                    let name = if columns * rows == 1 {
+                        state.name.clone()
+                    } else {
+                        // rows are counted up from the bottom of the texture
+                        format!("{}-{column}-{}", state.name, rows - 1 - row)
+                    };
+                    states.push(IconState {
+                        name,
+                        images: state
+                            .images
+                            .iter()
+                            .map(|template| masked(texture, (column, row), template, self.shade))
+                            .collect(),
+                        ..state.clone()
+                    });
+                }
+            }
+        }
+        *icon = Icon {
+            states,
+            ..icon.clone()
+        };
+        Ok(())
+    }
+}
+
+impl IconOperationConfig for BitmaskTextureMask {
+    fn perform_operation(
+        &self,
+        input: &InputIcon,
+        mode: OperationMode,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ProcessorPayload> {
+        let Some(texture) = &self.texture_image else {
+            return Err(ProcessorError::ConfigError(format!(
+                "texture `{}` hasn't been loaded",
+                self.texture
+            )));
+        };
+        let mut payload = self
+            .bitmask_slice_config
+            .perform_operation(input, mode, cancel)?;
+        self.texture_payload(texture, &mut payload, cancel)?;
+        Ok(payload)
+    }
+
+    fn verify_config(&self) -> ProcessorResult<()> {
+        self.bitmask_slice_config.verify_config()
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        let mut problems = self.bitmask_slice_config.input_problems(input);
+        let Some(texture) = &self.texture_image else {
+            return problems;
+        };
+        let size = self.bitmask_slice_config.output_size();
+        if size.x == 0 || size.y == 0 {
+            return problems;
+        }
+        if texture.width() < size.x || texture.height() < size.y {
+            problems.push(ProcessorError::ConfigError(format!(
+                "texture `{}` is {}x{}, smaller than a {}x{} tile",
+                self.texture,
+                texture.width(),
+                texture.height(),
+                size.x,
+                size.y
+            )));
+        } else if !texture.width().is_multiple_of(size.x)
+            || !texture.height().is_multiple_of(size.y)
+        {
+            problems.push(ProcessorError::ConfigError(format!(
+                "texture `{}` is {}x{}, which doesn't split in to whole {}x{} tiles",
+                self.texture,
+                texture.width(),
+                texture.height(),
+                size.x,
+                size.y
+            )));
+        }
+        problems
+    }
+
+    fn config_warnings(&self) -> Vec<ProcessorWarning> {
+        self.bitmask_slice_config.config_warnings()
+    }
+
+    fn state_origin(&self, state_name: &str) -> StateOrigin {
+        let origin = self.bitmask_slice_config.state_origin(state_name);
+        if origin != StateOrigin::Unknown {
+            return origin;
+        }
+        // copies for each window of the texture end in its column and row
+        state_name
+            .rsplitn(3, '-')
+            .nth(2)
+            .map_or(origin, |name| self.bitmask_slice_config.state_origin(name))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::blocks::cutters::{CutPosition, IconSize, OutputIconSize, Positions};
+    use crate::operations::{single_dmi, tile_sheet};
+
+    /// Opaque blocks for every corner type, with the concave block's corners
+    /// half transparent and grey
+    fn template() -> DynamicImage {
+        tile_sheet((8, 8), (4, 1), |block, _, _| {
+            if block == 1 {
+                Rgba([128, 128, 128, 128])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        })
+    }
+
+    /// Two tiles side by side, red on the left and blue on the right
+    fn texture() -> DynamicImage {
+        tile_sheet((8, 8), (2, 1), |tile, _, _| {
+            if tile == 0 {
+                Rgba([200, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 200, 255])
+            }
+        })
+    }
+
+    fn config(texture: DynamicImage, shade: bool) -> BitmaskTextureMask {
+        BitmaskTextureMask {
+            bitmask_slice_config: BitmaskSlice {
+                output_name: Some("rock".to_string()),
+                icon_size: IconSize { x: 8, y: 8 },
+                output_icon_size: Some(OutputIconSize { x: 8, y: 8 }),
+                cut_pos: CutPosition { x: 4, y: 4 },
+                positions: Positions::default(),
+                ..Default::default()
+            },
+            texture: "texture.png".to_string(),
+            shade,
+            texture_image: Some(texture),
+        }
+    }
+
+    fn states(config: &BitmaskTextureMask) -> Vec<IconState> {
+        let payload = config
+            .do_operation(
+                &InputIcon::DynamicImage(template()),
+                OperationMode::Standard,
+            )
+            .unwrap();
+        single_dmi(payload).states
+    }
+
+    #[test]
+    fn draws_texture() {
+        let plain = states(&config(texture().crop_imm(0, 0, 8, 8), false));
+        assert_eq!(plain.len(), 16);
+        // every junction is the texture's color with the template's coverage,
+        // so the half transparent concave corners of the middle are too
+        let find = |states: &[IconState], name: &str| {
+            states
+                .iter()
+                .find(|state| state.name == name)
+                .unwrap()
+                .images[0]
+                .get_pixel(3, 3)
+        };
+        assert_eq!(find(&plain, "rock-0"), Rgba([200, 0, 0, 255]));
+        assert_eq!(find(&plain, "rock-15"), Rgba([200, 0, 0, 128]));
+
+        let shaded = states(&config(texture().crop_imm(0, 0, 8, 8), true));
+        assert_eq!(find(&shaded, "rock-15"), Rgba([100, 0, 0, 128]));
+    }
+
+    #[test]
+    fn texture_windows() {
+        let config = config(texture(), false);
+        let states = states(&config);
+        assert_eq!(states.len(), 32);
+        let left = states
+            .iter()
+            .find(|state| state.name == "rock-0-0-0")
+            .unwrap();
+        let right = states
+            .iter()
+            .find(|state| state.name == "rock-0-1-0")
+            .unwrap();
+        assert_eq!(left.images[0].get_pixel(3, 3), Rgba([200, 0, 0, 255]));
+        assert_eq!(right.images[0].get_pixel(3, 3), Rgba([0, 0, 200, 255]));
+        assert_eq!(
+            config.state_origin("rock-5-1-0"),
+            StateOrigin::Junction { junction: 5 }
+        );
+
+        let uneven = BitmaskTextureMask {
+            texture_image: Some(texture().crop_imm(0, 0, 12, 8)),
+            ..config
+        };
+        assert_eq!(
+            uneven
+                .input_problems(&InputIcon::DynamicImage(template()))
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use dmi::icon::IconState;
+use image::{imageops, DynamicImage};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::cutters::bitmask_slice::BitmaskSlice;
+use crate::operations::error::{ProcessorError, ProcessorResult, ProcessorWarning};
+use crate::operations::{
+    IconOperationConfig,
+    InputIcon,
+    OperationMode,
+    OutputImage,
+    ProcessorPayload,
+    StateOrigin,
+};
+use crate::util::adjacency::Adjacency;
+
+/// Added to the names of top states when `top_face` has no suffix, giving
+/// names like `wall-12-top`
+pub const DEFAULT_TOP_SUFFIX: &str = "top";
+
+/// A bitmask slice that also outputs the top of every junction by itself,
+/// for walls seen from the z level above. The top is the strip along the top
+/// of each state, moved down to sit over the wall's footprint
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct BitmaskWallTops {
+    #[serde(flatten)]
+    pub bitmask_slice_config: BitmaskSlice,
+    pub top_face: TopFace,
+}
+
+/// Which part of each state is the top of the wall, and where it goes
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct TopFace {
+    /// Rows along the top of each state that are the top of the wall
+    pub height: u32,
+    /// How far down the top is moved, usually the height of the wall's front
+    #[serde(default)]
+    pub shift: u32,
+    /// How far down the top of junctions connected to the south is moved
+    /// instead, since their top runs on in to the wall below rather than
+    /// ending at a front. `shift` if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub south_shift: Option<u32>,
+    /// Added to the names of top states, `top` if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub suffix: Option<String>,
+}
+
+impl TopFace {
+    fn suffix(&self) -> &str {
+        self.suffix.as_deref().unwrap_or(DEFAULT_TOP_SUFFIX)
+    }
+
+    /// How far down the top of `junction` is moved
+    fn shift_for(&self, junction: u8) -> u32 {
+        if Adjacency::from_bits_truncate(junction).contains(Adjacency::S) {
+            self.south_shift.unwrap_or(self.shift)
+        } else {
+            self.shift
+        }
+    }
+
+    /// The top strip of `image`, moved down by `shift`
+    fn top_of(&self, image: &DynamicImage, shift: u32) -> DynamicImage {
+        let mut canvas = DynamicImage::new_rgba8(image.width(), image.height());
+        let strip = image.crop_imm(0, 0, image.width(), self.height);
+        imageops::overlay(&mut canvas, &strip, 0, i64::from(shift));
+        canvas
+    }
+}
+
+impl BitmaskWallTops {
+    /// Name given to the top of the state named `state_name`
+    #[must_use]
+    pub fn top_name(&self, state_name: &str) -> String {
+        format!("{state_name}-{}", self.top_face.suffix())
+    }
+
+    /// The junction `state_name` was cut for, if it's a junction at all
+    fn junction(&self, state_name: &str) -> Option<u8> {
+        match self.bitmask_slice_config.state_origin(state_name) {
+            StateOrigin::Junction { junction } | StateOrigin::Prefab { junction } => Some(junction),
+            _ => None,
+        }
+    }
+
+    /// Adds the top of every junction state to the icons in `payload`
+    fn add_tops(
+        &self,
+        payload: &mut ProcessorPayload,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<()> {
+        match payload {
+            ProcessorPayload::Single(image) => self.add_tops_to_image(image, cancel),
+            ProcessorPayload::SingleNamed(named) => {
+                self.add_tops_to_image(&mut named.image, cancel)
+            }
+            ProcessorPayload::MultipleNamed(icons) => {
+                for named in icons {
+                    self.add_tops_to_image(&mut named.image, cancel)?;
+                }
+                Ok(())
+            }
+            ProcessorPayload::ConfigWrapped(payload, _) | ProcessorPayload::Warned(payload, _) => {
+                self.add_tops(payload, cancel)
+            }
+        }
+    }
+
+    fn add_tops_to_image(
+        &self,
+        image: &mut OutputImage,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<()> {
+        let OutputImage::Dmi(icon) = image else {
+            return Ok(());
+        };
+        let mut tops = vec![];
+        for state in &icon.states {
+            cancel.check()?;
+            let Some(junction) = self.junction(&state.name) else {
+                continue;
+            };
+            let shift = self.top_face.shift_for(junction);
+            tops.push(IconState {
+                name: self.top_name(&state.name),
+                images: state
+                    .images
+                    .iter()
+                    .map(|image| self.top_face.top_of(image, shift))
+                    .collect(),
+                ..state.clone()
+            });
+        }
+        icon.states.extend(tops);
+        Ok(())
+    }
+}
+
+impl IconOperationConfig for BitmaskWallTops {
+    fn perform_operation(
+        &self,
+        input: &InputIcon,
+        mode: OperationMode,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ProcessorPayload> {
+        let mut payload = self
+            .bitmask_slice_config
+            .perform_operation(input, mode, cancel)?;
+        self.add_tops(&mut payload, cancel)?;
+        Ok(payload)
+    }
+
+    fn verify_config(&self) -> ProcessorResult<()> {
+        let mut problems = self.bitmask_slice_config.config_problems();
+        let top_face = &self.top_face;
+        let height = self.bitmask_slice_config.output_size().y;
+        if top_face.height == 0 {
+            problems.push(ProcessorError::ConfigError(
+                "top_face.height can't be zero".to_string(),
+            ));
+        }
+        for (key, shift) in [
+            ("shift", Some(top_face.shift)),
+            ("south_shift", top_face.south_shift),
+        ] {
+            if let Some(shift) = shift.filter(|shift| shift + top_face.height > height) {
+                problems.push(ProcessorError::ConfigError(format!(
+                    "top_face.{key} moves the top {shift}px down, but a {}px top only fits {}px \
+                     down a {height}px tall state",
+                    top_face.height,
+                    height.saturating_sub(top_face.height)
+                )));
+            }
+        }
+        if top_face.suffix().is_empty() || top_face.suffix().contains(['/', '\\']) {
+            problems.push(ProcessorError::ConfigError(
+                "top_face.suffix must be something that can go in a state name".to_string(),
+            ));
+        }
+        ProcessorError::check_all(problems)
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        self.bitmask_slice_config.input_problems(input)
+    }
+
+    fn config_warnings(&self) -> Vec<ProcessorWarning> {
+        self.bitmask_slice_config.config_warnings()
+    }
+
+    fn state_origin(&self, state_name: &str) -> StateOrigin {
+        let origin = self.bitmask_slice_config.state_origin(state_name);
+        if origin != StateOrigin::Unknown {
+            return origin;
+        }
+        state_name
+            .strip_suffix(self.top_face.suffix())
+            .and_then(|name| name.strip_suffix('-'))
+            .and_then(|name| self.junction(name))
+            .map_or(origin, |junction| StateOrigin::WallTop { junction })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{GenericImageView, Rgba};
+
+    use super::*;
+    use crate::config::blocks::cutters::{CutPosition, IconSize, OutputIconSize, Positions};
+    use crate::operations::{single_dmi, tile_sheet};
+
+    /// Blocks with a green top row over red
+    fn sheet() -> DynamicImage {
+        tile_sheet((8, 8), (4, 1), |_, _, y| {
+            if y < 2 {
+                Rgba([0, 255, 0, 255])
+            } else {
+                Rgba([255, 0, 0, 255])
+            }
+        })
+    }
+
+    fn config(top_face: TopFace) -> BitmaskWallTops {
+        BitmaskWallTops {
+            bitmask_slice_config: BitmaskSlice {
+                output_name: Some("wall".to_string()),
+                icon_size: IconSize { x: 8, y: 8 },
+                output_icon_size: Some(OutputIconSize { x: 8, y: 8 }),
+                cut_pos: CutPosition { x: 4, y: 4 },
+                positions: Positions::default(),
+                ..Default::default()
+            },
+            top_face,
+        }
+    }
+
+    #[test]
+    fn cuts_tops() {
+        let config = config(TopFace {
+            height: 2,
+            shift: 3,
+            south_shift: Some(6),
+            ..Default::default()
+        });
+        let payload = config
+            .do_operation(&InputIcon::DynamicImage(sheet()), OperationMode::Standard)
+            .unwrap();
+        let icon = single_dmi(payload);
+        assert_eq!(icon.states.len(), 32);
+        let top = |name: &str| {
+            &icon
+                .states
+                .iter()
+                .find(|state| state.name == name)
+                .unwrap()
+                .images[0]
+        };
+        // north only, moved down by shift
+        let north = top("wall-1-top");
+        assert_eq!(north.get_pixel(1, 3), Rgba([0, 255, 0, 255]));
+        assert_eq!(north.get_pixel(1, 0)[3], 0);
+        assert_eq!(north.get_pixel(1, 5)[3], 0);
+        // south only, moved down by south_shift
+        let south = top("wall-2-top");
+        assert_eq!(south.get_pixel(1, 7), Rgba([0, 255, 0, 255]));
+        assert_eq!(south.get_pixel(1, 3)[3], 0);
+        assert_eq!(
+            config.state_origin("wall-2-top"),
+            StateOrigin::WallTop { junction: 2 }
+        );
+    }
+
+    #[test]
+    fn problems() {
+        for top_face in [
+            TopFace::default(),
+            TopFace {
+                height: 4,
+                shift: 5,
+                ..Default::default()
+            },
+            TopFace {
+                height: 2,
+                south_shift: Some(7),
+                ..Default::default()
+            },
+            TopFace {
+                height: 2,
+                suffix: Some(String::new()),
+                ..Default::default()
+            },
+        ] {
+            assert!(
+                config(top_face.clone()).verify_config().is_err(),
+                "{top_face:?}"
+            );
+        }
+    }
+}
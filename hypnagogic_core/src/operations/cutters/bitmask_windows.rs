@@ -1,27 +1,30 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 
-use dmi::icon::{Icon, IconState};
+use dmi::icon::{Icon, IconState, Looping};
 use fixed_map::Map;
-use image::{DynamicImage, GenericImageView};
+use image::DynamicImage;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::config::blocks::cutters::{
     Animation,
     CutPosition,
     IconSize,
+    InputLayout,
     OutputIconPosition,
     OutputIconSize,
     Positions,
 };
-use crate::operations::cutters::bitmask_slice::{BitmaskSlice, SIZE_OF_DIAGONALS};
-use crate::operations::error::{ProcessorError, ProcessorResult};
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::cutters::bitmask_slice::{BitmaskSlice, VariantPayload, SIZE_OF_DIAGONALS};
+use crate::operations::error::{ProcessorError, ProcessorResult, ProcessorWarning};
 use crate::operations::{IconOperationConfig, InputIcon, OperationMode, ProcessorPayload};
 use crate::util::adjacency::Adjacency;
 use crate::util::corners::CornerType;
 use crate::util::icon_ops::dedupe_frames;
 use crate::util::repeat_for;
 
-#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
 pub struct BitmaskWindows {
     pub icon_size: IconSize,
     pub output_icon_pos: OutputIconPosition,
@@ -37,25 +40,27 @@ impl IconOperationConfig for BitmaskWindows {
         &self,
         input: &InputIcon,
         mode: OperationMode,
+        cancel: &CancellationToken,
     ) -> ProcessorResult<ProcessorPayload> {
         let InputIcon::DynamicImage(img) = input else {
             return Err(ProcessorError::ImageNotFound);
         };
 
-        let (_in_x, in_y) = img.dimensions();
-        let num_frames = in_y / self.icon_size.y;
-
         let mut positions = Positions::default();
-        positions.0.insert(CornerType::Flat, 4);
+        positions.0.insert(CornerType::Flat, Some(4));
 
         let bitmask_config = BitmaskSlice {
             output_name: None,
             icon_size: self.icon_size,
-            output_icon_pos: self.output_icon_pos,
-            output_icon_size: OutputIconSize {
-                x: self.icon_size.x,
-                y: self.icon_size.y,
-            },
+            output_icon_pos: Some(self.output_icon_pos),
+            dir_output_icon_pos: None,
+            output_icon_size: None,
+            corner_variants: None,
+            corner_atlas: None,
+            corner_atlas_image: None,
+            prefab_images: HashMap::new(),
+            variant_seed: None,
+            tile_bounds: None,
             positions,
             cut_pos: CutPosition {
                 x: self.icon_size.x / 2,
@@ -63,40 +68,81 @@ impl IconOperationConfig for BitmaskWindows {
             },
             animation: self.animation.clone(),
             produce_dirs: false,
+            collapse_rotations: false,
+            dm_snippet: false,
+            input_columns: None,
+            corner_cut_pos: None,
+            layout: InputLayout::Horizontal,
             prefabs: None,
             prefab_overlays: None,
             smooth_diagonally: true,
+            derive_flat: false,
+            movement_states: false,
             map_icon: None,
+            cardinal_set: None,
+            orphaned_corners: None,
+            preview_map: None,
+            size_overrides: None,
+            diagonal_walls: None,
+            frame_ranges: None,
+            hotspot: None,
+            only_states: None,
+            skip_states: None,
+            state_name_format: None,
         };
 
         let (corners, prefabs) = bitmask_config.generate_corners(img)?;
-        let assembled =
-            bitmask_config.generate_icons(&corners, &prefabs, num_frames, SIZE_OF_DIAGONALS);
+        let num_frames = bitmask_config.frame_count(img)?;
+        let warnings: Vec<ProcessorWarning> =
+            bitmask_config.leftover_rows(img).into_iter().collect();
+        let assembled = bitmask_config.generate_icons(
+            &corners,
+            &VariantPayload::new(),
+            &prefabs,
+            num_frames,
+            SIZE_OF_DIAGONALS,
+            cancel,
+        )?;
 
         let mut alt_config = bitmask_config;
 
         let mut positions = Map::new();
-        positions.insert(CornerType::Convex, 5);
-        positions.insert(CornerType::Concave, 6);
-        positions.insert(CornerType::Horizontal, 7);
-        positions.insert(CornerType::Vertical, 8);
-        positions.insert(CornerType::Flat, 9);
+        positions.insert(CornerType::Convex, Some(5));
+        positions.insert(CornerType::Concave, Some(6));
+        positions.insert(CornerType::Horizontal, Some(7));
+        positions.insert(CornerType::Vertical, Some(8));
+        positions.insert(CornerType::Flat, Some(9));
 
         alt_config.positions = Positions(positions);
 
         let (corners_alt, prefabs_alt) = alt_config.generate_corners(img)?;
-        let assembled_alt =
-            alt_config.generate_icons(&corners_alt, &prefabs_alt, num_frames, SIZE_OF_DIAGONALS);
+        let assembled_alt = alt_config.generate_icons(
+            &corners_alt,
+            &VariantPayload::new(),
+            &prefabs_alt,
+            num_frames,
+            SIZE_OF_DIAGONALS,
+            cancel,
+        )?;
 
         let delay = self
             .animation
             .clone()
-            .map(|x| repeat_for(&x.delays, num_frames as usize));
+            .map(|x| repeat_for(&x.delays_in_deciseconds(), num_frames as usize));
         let rewind = self
             .animation
             .as_ref()
             .and_then(|animation| animation.rewind)
             .unwrap_or(false);
+        let loop_flag = self
+            .animation
+            .as_ref()
+            .map_or(Looping::Indefinitely, Animation::looping);
+        let movement = self
+            .animation
+            .as_ref()
+            .and_then(|animation| animation.movement)
+            .unwrap_or(false);
 
         let mut states = vec![];
 
@@ -104,6 +150,7 @@ impl IconOperationConfig for BitmaskWindows {
             .map(|x| Adjacency::from_bits(x as u8).unwrap())
             .filter(Adjacency::ref_has_no_orphaned_corner);
         for adjacency in states_to_gen {
+            cancel.check()?;
             let mut states_from_assembled = |prefix: &str,
                                              assembled_set: &BTreeMap<
                 Adjacency,
@@ -131,24 +178,34 @@ impl IconOperationConfig for BitmaskWindows {
                 }
 
                 let signature = adjacency.bits();
-                states.push(dedupe_frames(IconState {
-                    name: format!("{prefix}{signature}-upper"),
-                    dirs: 1,
-                    frames: num_frames,
-                    images: upper_frames,
-                    delay: delay.clone(),
-                    rewind,
-                    ..Default::default()
-                }));
-                states.push(dedupe_frames(IconState {
-                    name: format!("{prefix}{signature}-lower"),
-                    dirs: 1,
-                    frames: num_frames,
-                    images: lower_frames,
-                    delay: delay.clone(),
-                    rewind,
-                    ..Default::default()
-                }));
+                states.push(dedupe_frames(
+                    IconState {
+                        name: format!("{prefix}{signature}-upper"),
+                        dirs: 1,
+                        frames: num_frames,
+                        images: upper_frames,
+                        delay: delay.clone(),
+                        rewind,
+                        loop_flag,
+                        movement,
+                        ..Default::default()
+                    },
+                    self.animation.as_ref(),
+                ));
+                states.push(dedupe_frames(
+                    IconState {
+                        name: format!("{prefix}{signature}-lower"),
+                        dirs: 1,
+                        frames: num_frames,
+                        images: lower_frames,
+                        delay: delay.clone(),
+                        rewind,
+                        loop_flag,
+                        movement,
+                        ..Default::default()
+                    },
+                    self.animation.as_ref(),
+                ));
             };
             states_from_assembled("", &assembled);
             states_from_assembled("alt-", &assembled_alt);
@@ -160,8 +217,7 @@ impl IconOperationConfig for BitmaskWindows {
             states,
             ..Default::default()
         };
-
-        Ok(ProcessorPayload::from_icon(icon))
+        Ok(ProcessorPayload::from_icon(icon).with_warnings(warnings))
     }
 
     fn verify_config(&self) -> ProcessorResult<()> {
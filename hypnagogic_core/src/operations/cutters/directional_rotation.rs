@@ -0,0 +1,580 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use dmi::icon::{Icon, IconState, Looping};
+use enum_iterator::Sequence;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::config::blocks::cutters::{Animation, IconSize};
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::cutters::pieces::piece_sheet_problems;
+use crate::operations::error::{ProcessorError, ProcessorResult};
+use crate::operations::{IconOperationConfig, InputIcon, OperationMode, ProcessorPayload};
+use crate::util::corners::Side;
+use crate::util::icon_ops::{dedupe_frames, turned_clockwise};
+use crate::util::repeat_for;
+
+/// Turns a sprite drawn facing one way to face every other, giving a single
+/// four or eight directional state. For conveyors, arrows, thrusters and
+/// anything else that only needs drawing once. Rotationally symmetric art
+/// like pipes gets its other cardinal directions from the one drawn this way,
+/// each turned 90, 180 or 270 degrees
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct DirectionalRotation {
+    /// Size of each piece, which has to be square for them to be turned a
+    /// quarter of the way round
+    pub icon_size: IconSize,
+    /// Name of the generated state, byond's unnamed state if it isn't set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub output_name: Option<String>,
+    /// How many directions the state has, 4 or 8. Defaults to 4
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub dirs: Option<u8>,
+    /// Which way the input is drawn facing, south if it isn't set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub facing: Option<Side>,
+    #[serde(default)]
+    pub positions: RotationPositions,
+    /// Builds north by mirroring south top to bottom and east by mirroring
+    /// west left to right, rather than turning the input for them, so
+    /// symmetric sprites only need south and west drawn. West is the piece at
+    /// `positions.west`, or turned from `positions.cardinal` if that isn't set
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    #[serde(default)]
+    pub mirror: bool,
+    /// Directions drawn some other way than turning the input, like from a
+    /// piece of their own or mirrored rather than turned
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    #[serde(default)]
+    pub overrides: BTreeMap<Direction, DirectionOverride>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub animation: Option<Animation>,
+}
+
+/// Where each piece is in the input, counted in pieces from the left
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct RotationPositions {
+    /// Drawn facing `facing`, and turned for the cardinal directions
+    #[serde(default)]
+    pub cardinal: u32,
+    /// Drawn facing 45 degrees clockwise of `facing`, and turned for the
+    /// diagonals of eight directional states
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub diagonal: Option<u32>,
+    /// Drawn facing west, and mirrored for east with `mirror` set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub west: Option<u32>,
+}
+
+/// A direction of a directional state, in byond's order
+#[derive(
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Ord,
+    PartialOrd,
+    Debug,
+    Sequence,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    South,
+    North,
+    East,
+    West,
+    SouthEast,
+    SouthWest,
+    NorthEast,
+    NorthWest,
+}
+
+impl Direction {
+    /// How far clockwise of north it faces, in degrees
+    const fn angle(self) -> u32 {
+        match self {
+            Direction::North => 0,
+            Direction::NorthEast => 45,
+            Direction::East => 90,
+            Direction::SouthEast => 135,
+            Direction::South => 180,
+            Direction::SouthWest => 225,
+            Direction::West => 270,
+            Direction::NorthWest => 315,
+        }
+    }
+
+    const fn is_diagonal(self) -> bool {
+        !self.angle().is_multiple_of(90)
+    }
+}
+
+impl Display for Direction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Direction::South => write!(f, "south"),
+            Direction::North => write!(f, "north"),
+            Direction::East => write!(f, "east"),
+            Direction::West => write!(f, "west"),
+            Direction::SouthEast => write!(f, "south_east"),
+            Direction::SouthWest => write!(f, "south_west"),
+            Direction::NorthEast => write!(f, "north_east"),
+            Direction::NorthWest => write!(f, "north_west"),
+        }
+    }
+}
+
+/// Mirrors a piece, after it's been turned
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Flip {
+    /// Left to right
+    Horizontal,
+    /// Top to bottom
+    Vertical,
+}
+
+/// How one direction is drawn instead of its default. Anything left unset
+/// keeps its default
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct DirectionOverride {
+    /// Piece drawn for the direction, which is taken to be drawn facing it
+    /// unless `turn` says otherwise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub position: Option<u32>,
+    /// How far clockwise the piece is turned, in degrees. Has to be a
+    /// multiple of 90
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub turn: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub flip: Option<Flip>,
+}
+
+impl Flip {
+    /// `piece` mirrored this way on top of whatever it's already mirrored
+    /// by. Mirroring both ways is the same as turning it half way round
+    fn onto(self, (position, turn, flip): (u32, u32, Option<Flip>)) -> (u32, u32, Option<Flip>) {
+        match flip {
+            None => (position, turn, Some(self)),
+            Some(flip) if flip == self => (position, turn, None),
+            Some(_) => (position, (turn + 180) % 360, None),
+        }
+    }
+}
+
+/// How far clockwise of north `side` faces, in degrees
+const fn side_angle(side: Side) -> u32 {
+    match side {
+        Side::North => 0,
+        Side::East => 90,
+        Side::South => 180,
+        Side::West => 270,
+    }
+}
+
+impl DirectionalRotation {
+    fn eight_dirs(&self) -> bool {
+        self.dirs == Some(8)
+    }
+
+    /// The directions of the generated state, in byond's order
+    fn directions(&self) -> impl Iterator<Item = Direction> {
+        let count = if self.eight_dirs() { 8 } else { 4 };
+        enum_iterator::all::<Direction>().take(count)
+    }
+
+    /// The piece drawn for `direction`, with how far clockwise it's turned
+    /// and how it's then mirrored. `None` for a diagonal with no piece
+    fn piece(&self, direction: Direction) -> Option<(u32, u32, Option<Flip>)> {
+        let overridden = self.overrides.contains_key(&direction);
+        match direction {
+            Direction::North if self.mirror && !overridden => {
+                return Some(Flip::Vertical.onto(self.piece(Direction::South)?));
+            }
+            Direction::East if self.mirror && !overridden => {
+                return Some(Flip::Horizontal.onto(self.piece(Direction::West)?));
+            }
+            Direction::West if !overridden => {
+                if let Some(west) = self.positions.west {
+                    return Some((west, 0, None));
+                }
+            }
+            _ => {}
+        }
+        let overrides = self.overrides.get(&direction).cloned().unwrap_or_default();
+        let facing = side_angle(self.facing.unwrap_or(Side::South));
+        let (position, drawn_facing) = match overrides.position {
+            Some(position) => (position, direction.angle()),
+            None if direction.is_diagonal() => (self.positions.diagonal?, facing + 45),
+            None => (self.positions.cardinal, facing),
+        };
+        let turn = overrides
+            .turn
+            .unwrap_or((direction.angle() + 360 - drawn_facing) % 360);
+        Some((position, turn, overrides.flip))
+    }
+
+    fn last_position(&self) -> u32 {
+        self.directions()
+            .filter_map(|direction| self.piece(direction))
+            .map(|(position, ..)| position)
+            .fold(self.positions.cardinal, u32::max)
+    }
+}
+
+impl IconOperationConfig for DirectionalRotation {
+    #[tracing::instrument(skip(input, _mode))]
+    fn perform_operation(
+        &self,
+        input: &InputIcon,
+        _mode: OperationMode,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ProcessorPayload> {
+        let InputIcon::DynamicImage(img) = input else {
+            return Err(ProcessorError::ImageNotFound);
+        };
+        let num_frames = img.height() / self.icon_size.y;
+
+        let mut images = vec![];
+        for direction in self.directions() {
+            cancel.check()?;
+            let (position, turn, flip) = self.piece(direction).ok_or_else(|| {
+                ProcessorError::ConfigError(format!("Nothing is drawn for {direction}"))
+            })?;
+            images.extend((0..num_frames).map(|frame| {
+                let piece = img.crop_imm(
+                    position * self.icon_size.x,
+                    frame * self.icon_size.y,
+                    self.icon_size.x,
+                    self.icon_size.y,
+                );
+                let turned = turned_clockwise(&piece, turn);
+                match flip {
+                    Some(Flip::Horizontal) => turned.fliph(),
+                    Some(Flip::Vertical) => turned.flipv(),
+                    None => turned,
+                }
+            }));
+        }
+
+        let delay = self
+            .animation
+            .as_ref()
+            .filter(|_| num_frames > 1)
+            .map(|animation| repeat_for(&animation.delays_in_deciseconds(), num_frames as usize));
+        let state = IconState {
+            name: self.output_name.clone().unwrap_or_default(),
+            dirs: if self.eight_dirs() { 8 } else { 4 },
+            frames: num_frames,
+            images,
+            delay,
+            rewind: self
+                .animation
+                .as_ref()
+                .and_then(|animation| animation.rewind)
+                .unwrap_or(false),
+            loop_flag: self
+                .animation
+                .as_ref()
+                .map_or(Looping::Indefinitely, Animation::looping),
+            ..Default::default()
+        };
+        Ok(ProcessorPayload::from_icon(Icon {
+            width: self.icon_size.x,
+            height: self.icon_size.y,
+            states: vec![dedupe_frames(state, self.animation.as_ref())],
+            ..Default::default()
+        }))
+    }
+
+    fn verify_config(&self) -> ProcessorResult<()> {
+        let mut problems = vec![];
+        if self.icon_size.x == 0 || self.icon_size.y == 0 {
+            problems.push(ProcessorError::ConfigError(
+                "icon_size can't be zero".to_string(),
+            ));
+        }
+        if let Some(dirs) = self.dirs.filter(|dirs| ![4, 8].contains(dirs)) {
+            problems.push(ProcessorError::ConfigError(format!(
+                "dirs has to be 4 or 8, not {dirs}"
+            )));
+        }
+        if !self.mirror && self.positions.west.is_some() {
+            problems.push(ProcessorError::ConfigError(
+                "positions.west is only used with mirror = true".to_string(),
+            ));
+        }
+        if !self.eight_dirs() && self.positions.diagonal.is_some() {
+            problems.push(ProcessorError::ConfigError(
+                "positions.diagonal is only used with dirs = 8".to_string(),
+            ));
+        }
+        for direction in self.overrides.keys() {
+            if direction.is_diagonal() && !self.eight_dirs() {
+                problems.push(ProcessorError::ConfigError(format!(
+                    "overrides.{direction} is only used with dirs = 8"
+                )));
+            }
+        }
+        let square = self.icon_size.x == self.icon_size.y;
+        for direction in self.directions() {
+            let Some((_, turn, _)) = self.piece(direction) else {
+                problems.push(ProcessorError::ConfigError(format!(
+                    "Nothing is drawn for {direction}, set positions.diagonal or give it a \
+                     position in overrides"
+                )));
+                continue;
+            };
+            if !turn.is_multiple_of(90) {
+                problems.push(ProcessorError::ConfigError(format!(
+                    "{direction} is turned {turn} degrees, but pieces can only be turned in \
+                     multiples of 90"
+                )));
+            } else if !turn.is_multiple_of(180) && !square {
+                problems.push(ProcessorError::ConfigError(format!(
+                    "{direction} is turned {turn} degrees, which needs a square icon_size, but \
+                     it's {}x{}",
+                    self.icon_size.x, self.icon_size.y
+                )));
+            }
+        }
+        ProcessorError::check_all(problems)
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        let InputIcon::DynamicImage(img) = input else {
+            return vec![];
+        };
+        piece_sheet_problems(
+            img,
+            self.icon_size,
+            self.last_position() + 1,
+            self.animation.as_ref(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{DynamicImage, GenericImageView, Rgba};
+
+    use super::*;
+    use crate::operations::{single_dmi, tile_sheet};
+
+    /// Whether `image` has a visible pixel at `x`, `y`
+    fn filled(image: &DynamicImage, x: u32, y: u32) -> bool {
+        image.get_pixel(x, y)[3] != 0
+    }
+
+    /// A 4x4 arrow pointing south, as a line down the middle with its tip in
+    /// the bottom right, then a diagonal one pointing south west as a dot in
+    /// the bottom left
+    fn input() -> DynamicImage {
+        tile_sheet((4, 4), (2, 1), |arrow, x, y| {
+            match (arrow, x, y) {
+                (0, 1, _) | (0, 2, 3) => Rgba([255, 0, 0, 255]),
+                (1, 0, 3) => Rgba([0, 0, 255, 255]),
+                _ => Rgba([0, 0, 0, 0]),
+            }
+        })
+    }
+
+    fn state(config: &DirectionalRotation) -> IconState {
+        let payload = config
+            .do_operation(&InputIcon::DynamicImage(input()), OperationMode::Standard)
+            .unwrap();
+        let icon = single_dmi(payload);
+        let [state] = <[IconState; 1]>::try_from(icon.states).unwrap();
+        state
+    }
+
+    #[test]
+    fn turns_input() {
+        let config = DirectionalRotation {
+            icon_size: IconSize { x: 4, y: 4 },
+            output_name: Some("conveyor".to_string()),
+            ..Default::default()
+        };
+        let state = state(&config);
+        assert_eq!((state.name.as_str(), state.dirs), ("conveyor", 4));
+        // south as drawn, north turned half way round, east with its tip
+        // top right and west bottom left
+        assert!(filled(&state.images[0], 2, 3));
+        assert!(filled(&state.images[1], 1, 0) && filled(&state.images[1], 2, 1));
+        assert!(filled(&state.images[2], 3, 1) && !filled(&state.images[2], 0, 1));
+        assert!(filled(&state.images[3], 0, 2) && filled(&state.images[3], 0, 1));
+    }
+
+    #[test]
+    fn overrides_and_diagonals() {
+        let config = DirectionalRotation {
+            icon_size: IconSize { x: 4, y: 4 },
+            dirs: Some(8),
+            positions: RotationPositions {
+                cardinal: 0,
+                diagonal: Some(1),
+                ..Default::default()
+            },
+            overrides: BTreeMap::from([(
+                Direction::North,
+                DirectionOverride {
+                    turn: Some(0),
+                    flip: Some(Flip::Vertical),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        let state = state(&config);
+        assert_eq!((state.dirs, state.images.len()), (8, 8));
+        // north mirrored rather than turned keeps its tip on the right
+        assert!(filled(&state.images[1], 2, 0) && !filled(&state.images[1], 2, 2));
+        // south east, south west, north east and north west
+        assert!(filled(&state.images[4], 3, 3));
+        assert!(filled(&state.images[5], 0, 3));
+        assert!(filled(&state.images[6], 3, 0));
+        assert!(filled(&state.images[7], 0, 0));
+    }
+
+    #[test]
+    fn rotates_one_direction() {
+        // drawn facing east, every other cardinal is the same pixels turned
+        let config = DirectionalRotation {
+            icon_size: IconSize { x: 4, y: 4 },
+            facing: Some(Side::East),
+            ..Default::default()
+        };
+        let drawn = input().crop_imm(0, 0, 4, 4);
+        let images = state(&config).images;
+        assert_eq!(images[0], drawn.rotate90());
+        assert_eq!(images[1], drawn.rotate270());
+        assert_eq!(images[2], drawn);
+        assert_eq!(images[3], drawn.rotate180());
+    }
+
+    #[test]
+    fn mirrors() {
+        // a south piece, then a west one with a dot in its top left
+        let mut input = input();
+        input
+            .as_mut_rgba8()
+            .unwrap()
+            .put_pixel(4, 0, Rgba([0, 0, 255, 255]));
+        let config = DirectionalRotation {
+            icon_size: IconSize { x: 4, y: 4 },
+            mirror: true,
+            positions: RotationPositions {
+                west: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        config.verify_config().unwrap();
+        let payload = config
+            .do_operation(
+                &InputIcon::DynamicImage(input.clone()),
+                OperationMode::Standard,
+            )
+            .unwrap();
+        let icon = single_dmi(payload);
+        let images = &icon.states[0].images;
+        let south = input.crop_imm(0, 0, 4, 4);
+        let west = input.crop_imm(4, 0, 4, 4);
+        assert_eq!(images[0], south);
+        assert_eq!(images[1], south.flipv());
+        assert_eq!(images[2], west.fliph());
+        assert_eq!(images[3], west);
+
+        // without a west piece, east mirrors south turned to face west
+        let turned = DirectionalRotation {
+            positions: RotationPositions::default(),
+            ..config.clone()
+        };
+        let (position, turn, flip) = turned.piece(Direction::East).unwrap();
+        assert_eq!((position, turn, flip), (0, 90, Some(Flip::Horizontal)));
+
+        let unused = DirectionalRotation {
+            mirror: false,
+            ..config
+        };
+        assert!(unused.verify_config().is_err());
+    }
+
+    #[test]
+    fn problems() {
+        let eight = DirectionalRotation {
+            icon_size: IconSize { x: 4, y: 4 },
+            dirs: Some(8),
+            ..Default::default()
+        };
+        assert!(eight.verify_config().is_err());
+
+        let skewed = DirectionalRotation {
+            icon_size: IconSize { x: 4, y: 4 },
+            overrides: BTreeMap::from([(
+                Direction::East,
+                DirectionOverride {
+                    turn: Some(45),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        assert!(skewed.verify_config().is_err());
+
+        let wide = DirectionalRotation {
+            icon_size: IconSize { x: 4, y: 2 },
+            ..Default::default()
+        };
+        assert!(wide.verify_config().is_err());
+        let flipped = DirectionalRotation {
+            overrides: [Direction::East, Direction::West]
+                .into_iter()
+                .map(|direction| {
+                    (
+                        direction,
+                        DirectionOverride {
+                            turn: Some(0),
+                            flip: Some(Flip::Horizontal),
+                            ..Default::default()
+                        },
+                    )
+                })
+                .collect(),
+            ..wide
+        };
+        flipped.verify_config().unwrap();
+
+        let missing = DirectionalRotation {
+            icon_size: IconSize { x: 4, y: 4 },
+            overrides: BTreeMap::from([(
+                Direction::North,
+                DirectionOverride {
+                    position: Some(2),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        assert_eq!(
+            missing
+                .input_problems(&InputIcon::DynamicImage(input()))
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::config::blocks::cutters::IconSize;
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::cutters::grid_slice::{GridSlice, GridState};
+use crate::operations::cutters::pieces::repeated_name_problem;
+use crate::operations::error::{ProcessorError, ProcessorResult};
+use crate::operations::{IconOperationConfig, InputIcon, OperationMode, ProcessorPayload};
+use crate::util::state_names::fits_in_dmi;
+
+/// Cuts a monospaced font sheet in to one state per character, named after
+/// the character, for the pixel fonts drawn by status displays and the like
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct GlyphSheet {
+    /// Size of each glyph
+    pub icon_size: IconSize,
+    /// Put before each character's state name, like `font-A`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub output_name: Option<String>,
+    /// Every character on the sheet, in order, left to right then top to
+    /// bottom
+    pub charset: String,
+    /// Glyphs across each row of the sheet, as many as fit if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub columns: Option<u32>,
+    /// State names for characters that can't be their own, by character,
+    /// like `"\"" = "quote"`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    #[serde(default)]
+    pub names: BTreeMap<String, String>,
+}
+
+impl GlyphSheet {
+    /// Name of the state cut for `character`
+    #[must_use]
+    pub fn state_name(&self, character: char) -> String {
+        let name = self
+            .names
+            .get(&character.to_string())
+            .cloned()
+            .unwrap_or_else(|| character.to_string());
+        match &self.output_name {
+            Some(prefix) => format!("{prefix}-{name}"),
+            None => name,
+        }
+    }
+
+    /// The grid the glyphs are cut with, one state per character
+    fn grid(&self) -> GridSlice {
+        GridSlice {
+            icon_size: self.icon_size,
+            columns: self.columns,
+            states: self
+                .charset
+                .chars()
+                .map(|character| {
+                    GridState {
+                        name: self.state_name(character),
+                        ..Default::default()
+                    }
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+impl IconOperationConfig for GlyphSheet {
+    #[tracing::instrument(skip(input, mode))]
+    fn perform_operation(
+        &self,
+        input: &InputIcon,
+        mode: OperationMode,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ProcessorPayload> {
+        self.grid().perform_operation(input, mode, cancel)
+    }
+
+    fn verify_config(&self) -> ProcessorResult<()> {
+        let mut problems = vec![];
+        let mut problem = |message: String| problems.push(ProcessorError::ConfigError(message));
+        if self.icon_size.x == 0 || self.icon_size.y == 0 {
+            problem("icon_size can't be zero".to_string());
+        }
+        if self.columns == Some(0) {
+            problem("columns can't be zero".to_string());
+        }
+        if self.charset.is_empty() {
+            problem("charset is empty, it needs every character on the sheet".to_string());
+        }
+        for key in self.names.keys() {
+            if key.chars().count() != 1 {
+                problem(format!(
+                    "names has `{key}`, but it's only for single characters"
+                ));
+            } else if !self.charset.contains(key.as_str()) {
+                problem(format!("names has `{key}`, which isn't in charset"));
+            }
+        }
+        let mut seen = String::new();
+        for character in self.charset.chars() {
+            if seen.contains(character) {
+                problem(format!("charset has `{character}` more than once"));
+                continue;
+            }
+            seen.push(character);
+            let name = self.state_name(character);
+            if !fits_in_dmi(&name) {
+                problem(format!(
+                    "`{}` can't be a state name, give it one in names",
+                    character.escape_default()
+                ));
+            }
+        }
+        problems.extend(repeated_name_problem(
+            self.charset
+                .chars()
+                .map(|character| self.state_name(character)),
+        ));
+        ProcessorError::check_all(problems)
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        self.grid().input_problems(input)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use dmi::icon::IconState;
+    use image::{DynamicImage, GenericImageView, Rgba};
+
+    use super::*;
+    use crate::operations::{single_dmi, tile_sheet};
+
+    /// Three 2x2 glyphs in a row, each with a pixel in a different spot
+    fn sheet() -> DynamicImage {
+        let spots = [(0, 0), (1, 0), (0, 1)];
+        tile_sheet((2, 2), (3, 1), |glyph, x, y| {
+            if spots[glyph as usize] == (x, y) {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        })
+    }
+
+    fn states(config: &GlyphSheet) -> Vec<IconState> {
+        let payload = config
+            .do_operation(&InputIcon::DynamicImage(sheet()), OperationMode::Standard)
+            .unwrap();
+        single_dmi(payload).states
+    }
+
+    #[test]
+    fn cuts_glyphs() {
+        let config = GlyphSheet {
+            icon_size: IconSize { x: 2, y: 2 },
+            output_name: Some("font".to_string()),
+            charset: "A\"1".to_string(),
+            names: BTreeMap::from([("\"".to_string(), "quote".to_string())]),
+            ..Default::default()
+        };
+        let states = states(&config);
+        let names: Vec<&str> = states.iter().map(|state| state.name.as_str()).collect();
+        assert_eq!(names, ["font-A", "font-quote", "font-1"]);
+        assert_eq!(states[1].images[0].get_pixel(1, 0)[3], 255);
+        assert_eq!(states[2].images[0].get_pixel(0, 1)[3], 255);
+    }
+
+    #[test]
+    fn problems() {
+        let config = |charset: &str, names: &[(&str, &str)]| {
+            GlyphSheet {
+                icon_size: IconSize { x: 2, y: 2 },
+                charset: charset.to_string(),
+                names: names
+                    .iter()
+                    .map(|(key, name)| ((*key).to_string(), (*name).to_string()))
+                    .collect(),
+                ..Default::default()
+            }
+        };
+        for config in [
+            config("", &[]),
+            config("AA", &[]),
+            config("A\"", &[]),
+            config("AB", &[("C", "c")]),
+            config("AB", &[("AB", "c")]),
+            config("AB", &[("B", "A")]),
+        ] {
+            assert!(config.verify_config().is_err(), "{config:?}");
+        }
+
+        let config = config("ABCD", &[]);
+        config.verify_config().unwrap();
+        assert_eq!(
+            config
+                .input_problems(&InputIcon::DynamicImage(sheet()))
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,451 @@
+use std::collections::BTreeSet;
+
+use dmi::icon::{Icon, IconState, Looping};
+use image::{DynamicImage, GenericImageView};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::config::blocks::cutters::{Animation, IconSize};
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::cutters::pieces::repeated_name_problem;
+use crate::operations::error::{ProcessorError, ProcessorResult};
+use crate::operations::{IconOperationConfig, InputIcon, OperationMode, ProcessorPayload};
+use crate::util::icon_ops::dedupe_frames;
+use crate::util::repeat_for;
+
+/// Cuts a sheet on a fixed grid with no smoothing at all, naming each state
+/// from the config. For items, effects and anything else drawn as a plain
+/// sheet of sprites
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct GridSlice {
+    /// Size of each cell of the grid
+    pub icon_size: IconSize,
+    /// Cells across each row of the sheet, as many as fit if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub columns: Option<u32>,
+    /// States cut from the sheet, in order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub states: Vec<GridState>,
+    /// Names every cell no state in `states` covers, leaving out blank ones.
+    /// `{index}` is replaced with the cell's number, and `{column}` and
+    /// `{row}` with where it is in the grid
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub name_pattern: Option<String>,
+    /// Delays of animated states that don't set their own, and how every
+    /// state loops
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub animation: Option<Animation>,
+}
+
+/// A state cut from consecutive cells of the grid. Its cells run through
+/// each dir in byond's order (south, north, east, west, then the diagonals),
+/// with all of one dir's frames before the next dir
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct GridState {
+    pub name: String,
+    /// First cell, counted left to right and top to bottom from 0. Follows
+    /// on from the last state if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub cell: Option<u32>,
+    /// 1, 4 or 8, defaulting to 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub dirs: Option<u8>,
+    /// Defaults to 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub frames: Option<u32>,
+    /// Delays of its frames, in the animation's `delay_unit`, for states
+    /// timed differently from the rest
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub delays: Option<Vec<f32>>,
+}
+
+impl GridState {
+    fn dirs(&self) -> u8 {
+        self.dirs.unwrap_or(1)
+    }
+
+    fn frames(&self) -> u32 {
+        self.frames.unwrap_or(1)
+    }
+
+    fn cell_count(&self) -> u32 {
+        u32::from(self.dirs()) * self.frames()
+    }
+}
+
+impl GridSlice {
+    fn columns(&self, img: &DynamicImage) -> u32 {
+        self.columns.unwrap_or(img.width() / self.icon_size.x)
+    }
+
+    /// Every cell of `img` there's room for
+    fn cell_count(&self, img: &DynamicImage) -> u32 {
+        self.columns(img) * (img.height() / self.icon_size.y)
+    }
+
+    /// Each state in `states` with its first cell
+    fn placed_states(&self) -> Vec<(&GridState, u32)> {
+        let mut next = 0;
+        self.states
+            .iter()
+            .map(|state| {
+                let first = state.cell.unwrap_or(next);
+                next = first + state.cell_count();
+                (state, first)
+            })
+            .collect()
+    }
+
+    fn cell_image(&self, img: &DynamicImage, columns: u32, cell: u32) -> DynamicImage {
+        img.crop_imm(
+            cell % columns * self.icon_size.x,
+            cell / columns * self.icon_size.y,
+            self.icon_size.x,
+            self.icon_size.y,
+        )
+    }
+
+    /// Name `name_pattern` gives `cell`
+    fn pattern_name(pattern: &str, columns: u32, cell: u32) -> String {
+        pattern
+            .replace("{index}", &cell.to_string())
+            .replace("{column}", &(cell % columns).to_string())
+            .replace("{row}", &(cell / columns).to_string())
+    }
+
+    /// Delays of `state` in deciseconds, if it's animated
+    fn delays(&self, state: &GridState) -> Option<Vec<f32>> {
+        if state.frames() <= 1 {
+            return None;
+        }
+        let unit = self
+            .animation
+            .as_ref()
+            .and_then(|animation| animation.delay_unit)
+            .unwrap_or_default();
+        let delays = match &state.delays {
+            Some(delays) => {
+                delays
+                    .iter()
+                    .map(|delay| unit.to_deciseconds(*delay))
+                    .collect()
+            }
+            None => self.animation.as_ref()?.delays_in_deciseconds(),
+        };
+        Some(repeat_for(&delays, state.frames() as usize))
+    }
+}
+
+impl IconOperationConfig for GridSlice {
+    #[tracing::instrument(skip(input, _mode))]
+    fn perform_operation(
+        &self,
+        input: &InputIcon,
+        _mode: OperationMode,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ProcessorPayload> {
+        let InputIcon::DynamicImage(img) = input else {
+            return Err(ProcessorError::ImageNotFound);
+        };
+        let columns = self.columns(img);
+        let rewind = self
+            .animation
+            .as_ref()
+            .and_then(|animation| animation.rewind)
+            .unwrap_or(false);
+        let loop_flag = self
+            .animation
+            .as_ref()
+            .map_or(Looping::Indefinitely, Animation::looping);
+
+        let mut states = vec![];
+        let mut taken = BTreeSet::new();
+        for (state, first) in self.placed_states() {
+            cancel.check()?;
+            let cells = first..first + state.cell_count();
+            taken.extend(cells.clone());
+            states.push(dedupe_frames(
+                IconState {
+                    name: state.name.clone(),
+                    dirs: state.dirs(),
+                    frames: state.frames(),
+                    images: cells
+                        .map(|cell| self.cell_image(img, columns, cell))
+                        .collect(),
+                    delay: self.delays(state),
+                    rewind,
+                    loop_flag,
+                    ..Default::default()
+                },
+                self.animation.as_ref(),
+            ));
+        }
+        if let Some(pattern) = &self.name_pattern {
+            for cell in (0..self.cell_count(img)).filter(|cell| !taken.contains(cell)) {
+                cancel.check()?;
+                let image = self.cell_image(img, columns, cell);
+                if image.pixels().all(|(_, _, pixel)| pixel[3] == 0) {
+                    continue;
+                }
+                states.push(IconState {
+                    name: Self::pattern_name(pattern, columns, cell),
+                    images: vec![image],
+                    ..Default::default()
+                });
+            }
+        }
+        Ok(ProcessorPayload::from_icon(Icon {
+            width: self.icon_size.x,
+            height: self.icon_size.y,
+            states,
+            ..Default::default()
+        }))
+    }
+
+    fn verify_config(&self) -> ProcessorResult<()> {
+        let mut problems = vec![];
+        let mut problem = |message: String| problems.push(ProcessorError::ConfigError(message));
+        if self.icon_size.x == 0 || self.icon_size.y == 0 {
+            problem("icon_size can't be zero".to_string());
+        }
+        if self.columns == Some(0) {
+            problem("columns can't be zero".to_string());
+        }
+        if self.states.is_empty() && self.name_pattern.is_none() {
+            problem("Nothing is cut, list some states or set a name_pattern".to_string());
+        }
+        if let Some(pattern) = &self.name_pattern {
+            let by_position = pattern.contains("{column}") && pattern.contains("{row}");
+            if !pattern.contains("{index}") && !by_position {
+                problem(format!(
+                    "name_pattern `{pattern}` would give every cell the same name, it needs \
+                     `{{index}}` or both `{{column}}` and `{{row}}`"
+                ));
+            }
+        }
+        for state in &self.states {
+            if ![1, 4, 8].contains(&state.dirs()) {
+                problem(format!(
+                    "`{}` has {} dirs, but states can only have 1, 4 or 8",
+                    state.name,
+                    state.dirs()
+                ));
+            }
+            if state.frames() == 0 {
+                problem(format!("`{}` has no frames", state.name));
+            }
+            if state.delays.as_ref().is_some_and(Vec::is_empty) {
+                problem(format!("`{}` has an empty list of delays", state.name));
+            }
+            if state.frames() > 1 && state.delays.is_none() && self.animation.is_none() {
+                problem(format!(
+                    "`{}` has {} frames but no delays, set its delays or an animation",
+                    state.name,
+                    state.frames()
+                ));
+            }
+        }
+        let placed = self.placed_states();
+        for (index, (state, first)) in placed.iter().enumerate() {
+            let overlapping = placed[..index].iter().find(|(other, other_first)| {
+                *first < other_first + other.cell_count()
+                    && *other_first < first + state.cell_count()
+            });
+            if let Some((other, _)) = overlapping {
+                problem(format!(
+                    "`{}` is cut from some of the same cells as `{}`",
+                    state.name, other.name
+                ));
+            }
+        }
+        problems.extend(repeated_name_problem(
+            self.states.iter().map(|state| state.name.clone()),
+        ));
+        ProcessorError::check_all(problems)
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        let InputIcon::DynamicImage(img) = input else {
+            return vec![];
+        };
+        if self.icon_size.x == 0 || self.icon_size.y == 0 {
+            return vec![];
+        }
+        let mut problems = vec![];
+        if self.columns(img) * self.icon_size.x > img.width() {
+            problems.push(ProcessorError::ConfigError(format!(
+                "The input is {}px wide, too narrow for {} columns of {}px cells",
+                img.width(),
+                self.columns(img),
+                self.icon_size.x
+            )));
+        }
+        let cells = self.cell_count(img);
+        let needed = self
+            .placed_states()
+            .iter()
+            .map(|(state, first)| first + state.cell_count())
+            .max()
+            .unwrap_or_default();
+        if needed > cells {
+            problems.push(ProcessorError::ConfigError(format!(
+                "The states need {needed} cells, but the input only has room for {cells}"
+            )));
+        }
+        if let Some(pattern) = &self.name_pattern {
+            let named: BTreeSet<&str> = self
+                .states
+                .iter()
+                .map(|state| state.name.as_str())
+                .collect();
+            let columns = self.columns(img);
+            let clash = (0..cells)
+                .map(|cell| Self::pattern_name(pattern, columns, cell))
+                .find(|name| named.contains(name.as_str()));
+            if let Some(name) = clash {
+                problems.push(ProcessorError::ConfigError(format!(
+                    "name_pattern would name a cell `{name}`, which a listed state is already \
+                     called"
+                )));
+            }
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::Rgba;
+
+    use super::*;
+    use crate::operations::{single_dmi, tile_sheet};
+
+    /// A 4x2 grid of 2x2 cells, each filled with its own number as red, the
+    /// last left blank
+    fn sheet() -> DynamicImage {
+        tile_sheet((2, 2), (4, 2), |cell, _, _| {
+            if cell < 7 {
+                Rgba([cell as u8, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        })
+    }
+
+    fn states(config: &GridSlice) -> Vec<IconState> {
+        let payload = config
+            .do_operation(&InputIcon::DynamicImage(sheet()), OperationMode::Standard)
+            .unwrap();
+        single_dmi(payload).states
+    }
+
+    fn red(image: &DynamicImage) -> u8 {
+        image.get_pixel(0, 0)[0]
+    }
+
+    #[test]
+    fn cuts_listed_states() {
+        let config = GridSlice {
+            icon_size: IconSize { x: 2, y: 2 },
+            states: vec![
+                GridState {
+                    name: "idle".to_string(),
+                    ..Default::default()
+                },
+                GridState {
+                    name: "walk".to_string(),
+                    frames: Some(2),
+                    delays: Some(vec![1.0, 3.0]),
+                    ..Default::default()
+                },
+                GridState {
+                    name: "facing".to_string(),
+                    cell: Some(3),
+                    dirs: Some(4),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let states = states(&config);
+        let names: Vec<&str> = states.iter().map(|state| state.name.as_str()).collect();
+        assert_eq!(names, ["idle", "walk", "facing"]);
+        assert_eq!(states[1].delay, Some(vec![1.0, 3.0]));
+        assert_eq!(states[1].images.iter().map(red).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(states[2].dirs, 4);
+        assert_eq!(
+            states[2].images.iter().map(red).collect::<Vec<_>>(),
+            [3, 4, 5, 6]
+        );
+    }
+
+    #[test]
+    fn names_leftover_cells() {
+        let config = GridSlice {
+            icon_size: IconSize { x: 2, y: 2 },
+            states: vec![GridState {
+                name: "first".to_string(),
+                ..Default::default()
+            }],
+            name_pattern: Some("item-{row}-{column}".to_string()),
+            ..Default::default()
+        };
+        let states = states(&config);
+        let names: Vec<&str> = states.iter().map(|state| state.name.as_str()).collect();
+        // the blank last cell is left out
+        assert_eq!(
+            names,
+            ["first", "item-0-1", "item-0-2", "item-0-3", "item-1-0", "item-1-1", "item-1-2"]
+        );
+    }
+
+    #[test]
+    fn problems() {
+        let state = |name: &str, cell: Option<u32>, frames: Option<u32>| {
+            GridState {
+                name: name.to_string(),
+                cell,
+                frames,
+                ..Default::default()
+            }
+        };
+        for states in [
+            vec![],
+            vec![state("a", None, Some(2))],
+            vec![state("a", None, Some(2)), state("b", Some(1), None)],
+            vec![state("a", None, None), state("a", None, None)],
+        ] {
+            let config = GridSlice {
+                icon_size: IconSize { x: 2, y: 2 },
+                states,
+                ..Default::default()
+            };
+            assert!(config.verify_config().is_err(), "{config:?}");
+        }
+
+        let config = GridSlice {
+            icon_size: IconSize { x: 2, y: 2 },
+            states: vec![state("a", Some(7), Some(2))],
+            animation: Some(Animation {
+                delays: vec![1.0],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        config.verify_config().unwrap();
+        assert_eq!(
+            config
+                .input_problems(&InputIcon::DynamicImage(sheet()))
+                .len(),
+            1
+        );
+    }
+}
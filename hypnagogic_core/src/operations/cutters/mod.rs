@@ -1,3 +1,15 @@
+pub mod bitmask_corner_overlays;
 pub mod bitmask_dir_visibility;
+pub mod bitmask_lattice;
+pub mod bitmask_pipes;
 pub mod bitmask_slice;
+pub mod bitmask_slice_greyscale;
+pub mod bitmask_slice_groups;
+pub mod bitmask_texture_mask;
+pub mod bitmask_wall_tops;
 pub mod bitmask_windows;
+pub mod directional_rotation;
+pub mod glyph_sheet;
+pub mod grid_slice;
+pub(crate) mod pieces;
+pub mod turf_edges;
@@ -0,0 +1,122 @@
+//! Helpers for operations that draw their states from a row of whole pieces,
+//! turned and drawn over each other, rather than from tile corners
+
+use dmi::icon::{Icon, IconState, Looping};
+use image::{imageops, DynamicImage};
+
+use crate::config::blocks::cutters::{Animation, IconSize};
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::error::{ProcessorError, ProcessorResult};
+use crate::util::icon_ops::{dedupe_frames, turned_clockwise};
+use crate::util::repeat_for;
+
+/// A piece drawn in to a state, as its position in the sheet and how far
+/// clockwise it's turned
+pub(crate) type Piece = (u32, u32);
+
+/// An icon of `states`, each named and drawn from its pieces in order, cut
+/// from `img` for every row of frames
+pub(crate) fn overlay_pieces(
+    img: &DynamicImage,
+    icon_size: IconSize,
+    animation: Option<&Animation>,
+    states: impl Iterator<Item = (String, Vec<Piece>)>,
+    cancel: &CancellationToken,
+) -> ProcessorResult<Icon> {
+    let num_frames = img.height() / icon_size.y;
+    let delay = animation
+        .filter(|_| num_frames > 1)
+        .map(|animation| repeat_for(&animation.delays_in_deciseconds(), num_frames as usize));
+    let rewind = animation
+        .and_then(|animation| animation.rewind)
+        .unwrap_or(false);
+    let loop_flag = animation.map_or(Looping::Indefinitely, Animation::looping);
+
+    let mut icon_states = vec![];
+    for (name, pieces) in states {
+        cancel.check()?;
+        let images = (0..num_frames)
+            .map(|frame| {
+                let mut canvas = DynamicImage::new_rgba8(icon_size.x, icon_size.y);
+                for (position, turn) in &pieces {
+                    let piece = img.crop_imm(
+                        position * icon_size.x,
+                        frame * icon_size.y,
+                        icon_size.x,
+                        icon_size.y,
+                    );
+                    imageops::overlay(&mut canvas, &turned_clockwise(&piece, *turn), 0, 0);
+                }
+                canvas
+            })
+            .collect();
+        icon_states.push(dedupe_frames(
+            IconState {
+                name,
+                dirs: 1,
+                frames: num_frames,
+                images,
+                delay: delay.clone(),
+                rewind,
+                loop_flag,
+                ..Default::default()
+            },
+            animation,
+        ));
+    }
+    Ok(Icon {
+        width: icon_size.x,
+        height: icon_size.y,
+        states: icon_states,
+        ..Default::default()
+    })
+}
+
+/// A problem for the first name in `names` that's given to more than one state
+pub(crate) fn repeated_name_problem(names: impl Iterator<Item = String>) -> Option<ProcessorError> {
+    let mut names: Vec<String> = names.collect();
+    names.sort();
+    let [name, _] = names.windows(2).find(|pair| pair[0] == pair[1])? else {
+        return None;
+    };
+    Some(ProcessorError::ConfigError(format!(
+        "state_name_format gives more than one state the name `{name}`"
+    )))
+}
+
+/// Problems with a sheet of `pieces` pieces side by side, each row below the
+/// first being another frame of them
+pub(crate) fn piece_sheet_problems(
+    img: &DynamicImage,
+    icon_size: IconSize,
+    pieces: u32,
+    animation: Option<&Animation>,
+) -> Vec<ProcessorError> {
+    if icon_size.x == 0 || icon_size.y == 0 {
+        return vec![];
+    }
+    let mut problems = vec![];
+    let needed = pieces * icon_size.x;
+    if img.width() < needed {
+        problems.push(ProcessorError::ConfigError(format!(
+            "The input is {}px wide, but the pieces need it to be at least {needed}px",
+            img.width()
+        )));
+    }
+    let frames = img.height() / icon_size.y;
+    let expected = animation.and_then(|animation| animation.frames);
+    if frames == 0 || !img.height().is_multiple_of(icon_size.y) {
+        problems.push(ProcessorError::ConfigError(format!(
+            "The input is {}px tall, which doesn't split in to rows of whole {}px frames",
+            img.height(),
+            icon_size.y
+        )));
+    } else if let Some(expected) = expected.filter(|expected| *expected != frames) {
+        problems.push(ProcessorError::FrameCountMismatch {
+            expected,
+            image_height: img.height(),
+            icon_height: icon_size.y,
+        });
+    }
+    problems
+}
@@ -0,0 +1,265 @@
+use dmi::icon::{Icon, IconState, Looping};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::config::blocks::cutters::{Animation, IconSize};
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::cutters::pieces::piece_sheet_problems;
+use crate::operations::error::{ProcessorError, ProcessorResult};
+use crate::operations::{IconOperationConfig, InputIcon, OperationMode, ProcessorPayload};
+use crate::util::icon_ops::{dedupe_frames, turned_clockwise};
+use crate::util::repeat_for;
+
+/// Name of the generated state when there's no `output_name`
+const DEFAULT_STATE_NAME: &str = "edge";
+
+/// Cuts the border overlays drawn over a floor where it meets one that spills
+/// over on to it, like grass over sand. Both pieces are drawn for a neighbour
+/// to the north (or north east) and turned to face every other direction
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct TurfEdges {
+    /// Size of each piece, which has to be square for them to be turned
+    pub icon_size: IconSize,
+    /// Name of the generated state, `edge` by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub output_name: Option<String>,
+    pub positions: EdgePositions,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub animation: Option<Animation>,
+}
+
+/// Where each piece is in the input, counted in pieces from the left
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct EdgePositions {
+    /// Drawn along the north side, for a neighbour to the north
+    pub edge: u32,
+    /// Drawn in the north east corner, for a neighbour only to the north east
+    pub corner: u32,
+}
+
+impl Default for EdgePositions {
+    fn default() -> Self {
+        Self { edge: 0, corner: 1 }
+    }
+}
+
+/// Byond's order for the directions of an eight directional state, each with
+/// whether it's diagonal and how far clockwise its piece is turned
+const DIRECTIONS: [(bool, u32); 8] = [
+    // south, north, east, west
+    (false, 180),
+    (false, 0),
+    (false, 90),
+    (false, 270),
+    // south east, south west, north east, north west
+    (true, 90),
+    (true, 180),
+    (true, 0),
+    (true, 270),
+];
+
+impl IconOperationConfig for TurfEdges {
+    #[tracing::instrument(skip(input, _mode))]
+    fn perform_operation(
+        &self,
+        input: &InputIcon,
+        _mode: OperationMode,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ProcessorPayload> {
+        let InputIcon::DynamicImage(img) = input else {
+            return Err(ProcessorError::ImageNotFound);
+        };
+        let num_frames = img.height() / self.icon_size.y;
+
+        let mut images = vec![];
+        for (diagonal, turn) in DIRECTIONS {
+            cancel.check()?;
+            let position = if diagonal {
+                self.positions.corner
+            } else {
+                self.positions.edge
+            };
+            images.extend((0..num_frames).map(|frame| {
+                let piece = img.crop_imm(
+                    position * self.icon_size.x,
+                    frame * self.icon_size.y,
+                    self.icon_size.x,
+                    self.icon_size.y,
+                );
+                turned_clockwise(&piece, turn)
+            }));
+        }
+
+        let delay = self
+            .animation
+            .as_ref()
+            .filter(|_| num_frames > 1)
+            .map(|animation| repeat_for(&animation.delays_in_deciseconds(), num_frames as usize));
+        let state = IconState {
+            name: self
+                .output_name
+                .clone()
+                .unwrap_or_else(|| DEFAULT_STATE_NAME.to_string()),
+            dirs: DIRECTIONS.len() as u8,
+            frames: num_frames,
+            images,
+            delay,
+            rewind: self
+                .animation
+                .as_ref()
+                .and_then(|animation| animation.rewind)
+                .unwrap_or(false),
+            loop_flag: self
+                .animation
+                .as_ref()
+                .map_or(Looping::Indefinitely, Animation::looping),
+            ..Default::default()
+        };
+        Ok(ProcessorPayload::from_icon(Icon {
+            width: self.icon_size.x,
+            height: self.icon_size.y,
+            states: vec![dedupe_frames(state, self.animation.as_ref())],
+            ..Default::default()
+        }))
+    }
+
+    fn verify_config(&self) -> ProcessorResult<()> {
+        let mut problems = vec![];
+        if self.icon_size.x == 0 || self.icon_size.y == 0 {
+            problems.push(ProcessorError::ConfigError(
+                "icon_size can't be zero".to_string(),
+            ));
+        } else if self.icon_size.x != self.icon_size.y {
+            problems.push(ProcessorError::ConfigError(format!(
+                "icon_size has to be square for pieces to be turned, but it's {}x{}",
+                self.icon_size.x, self.icon_size.y
+            )));
+        }
+        ProcessorError::check_all(problems)
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        let InputIcon::DynamicImage(img) = input else {
+            return vec![];
+        };
+        piece_sheet_problems(
+            img,
+            self.icon_size,
+            self.positions.edge.max(self.positions.corner) + 1,
+            self.animation.as_ref(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{DynamicImage, GenericImageView, Rgba};
+
+    use super::*;
+    use crate::operations::{single_dmi, tile_sheet};
+
+    /// A 4x4 edge with its top row filled, then a corner with only its top
+    /// right pixel filled
+    fn input() -> DynamicImage {
+        tile_sheet((4, 4), (2, 1), |piece, x, y| {
+            match (piece, x, y) {
+                (0, _, 0) => Rgba([0, 255, 0, 255]),
+                (1, 3, 0) => Rgba([0, 128, 0, 255]),
+                _ => Rgba([0, 0, 0, 0]),
+            }
+        })
+    }
+
+    #[test]
+    fn turns_pieces() {
+        let config = TurfEdges {
+            icon_size: IconSize { x: 4, y: 4 },
+            ..Default::default()
+        };
+        let payload = config
+            .do_operation(&InputIcon::DynamicImage(input()), OperationMode::Standard)
+            .unwrap();
+        let icon = single_dmi(payload);
+        let [state] = icon.states.as_slice() else {
+            panic!("Expected one state");
+        };
+        assert_eq!(state.name, "edge");
+        assert_eq!(state.dirs, 8);
+        let filled = |dir: usize, x, y| state.images[dir].get_pixel(x, y)[3] != 0;
+        // south edge along the bottom, east along the right, west along the
+        // left
+        assert!(filled(0, 1, 3) && !filled(0, 1, 0));
+        assert!(filled(1, 1, 0));
+        assert!(filled(2, 3, 1) && !filled(2, 0, 1));
+        assert!(filled(3, 0, 1));
+        // corners in the matching corner
+        assert!(filled(4, 3, 3));
+        assert!(filled(5, 0, 3));
+        assert!(filled(6, 3, 0));
+        assert!(filled(7, 0, 0));
+    }
+
+    #[test]
+    fn animated() {
+        let config = TurfEdges {
+            icon_size: IconSize { x: 4, y: 4 },
+            animation: Some(Animation {
+                delays: vec![1.0],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let states = |img: &DynamicImage| {
+            let payload = config
+                .do_operation(
+                    &InputIcon::DynamicImage(img.clone()),
+                    OperationMode::Standard,
+                )
+                .unwrap();
+            single_dmi(payload).states
+        };
+
+        // a second frame that only differs in its corner is kept in every dir
+        let mut two_frames = DynamicImage::new_rgba8(8, 8);
+        image::imageops::overlay(&mut two_frames, &input(), 0, 0);
+        image::imageops::overlay(&mut two_frames, &input(), 0, 4);
+        two_frames
+            .as_mut_rgba8()
+            .unwrap()
+            .put_pixel(7, 4, Rgba([0, 0, 0, 0]));
+        let state = &states(&two_frames)[0];
+        assert_eq!((state.frames, state.images.len()), (2, 16));
+        assert_eq!(state.delay, Some(vec![1.0, 1.0]));
+
+        // repeated frames are merged in every dir at once
+        let mut repeated = DynamicImage::new_rgba8(8, 8);
+        image::imageops::overlay(&mut repeated, &input(), 0, 0);
+        image::imageops::overlay(&mut repeated, &input(), 0, 4);
+        let state = &states(&repeated)[0];
+        assert_eq!((state.frames, state.images.len()), (1, 8));
+        assert_eq!(state.delay, Some(vec![2.0]));
+    }
+
+    #[test]
+    fn problems() {
+        let config = TurfEdges {
+            icon_size: IconSize { x: 4, y: 3 },
+            ..Default::default()
+        };
+        assert!(config.verify_config().is_err());
+
+        let config = TurfEdges {
+            icon_size: IconSize { x: 4, y: 4 },
+            positions: EdgePositions { edge: 0, corner: 3 },
+            ..Default::default()
+        };
+        assert_eq!(
+            config
+                .input_problems(&InputIcon::DynamicImage(input()))
+                .len(),
+            1
+        );
+    }
+}
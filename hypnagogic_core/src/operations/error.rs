@@ -1,3 +1,6 @@
+use std::fmt;
+
+use serde::Serialize;
 use thiserror::Error;
 use user_error::UFE;
 
@@ -15,10 +18,72 @@ pub enum ProcessorError {
     GenerationFailed(#[from] crate::generation::error::GenerationError),
     #[error("Error within image config:\n{0}")]
     ConfigError(String),
+    #[error("Operation Cancelled")]
+    Cancelled,
+    #[error("Preview Encoding Error")]
+    PreviewEncodingFailed(#[from] png::EncodingError),
+    #[error("DMI Encoding Error")]
+    DmiEncodingFailed(#[from] dmi::error::DmiError),
+    #[error("Aseprite Encoding Error")]
+    AsepriteEncodingFailed(#[from] crate::util::aseprite::AsepriteError),
+    #[error("Frame Count Mismatch")]
+    FrameCountMismatch {
+        expected: u32,
+        image_height: u32,
+        icon_height: u32,
+    },
+    #[error("Found {} Problems", .0.len())]
+    Multiple(Vec<ProcessorError>),
 }
 
 pub type ProcessorResult<T> = Result<T, ProcessorError>;
 
+impl ProcessorError {
+    /// Fails with every problem in `problems` at once, so they can all be
+    /// fixed before running again. `Ok` if there aren't any
+    /// # Errors
+    /// Errors if there are any problems, with the only one or all of them
+    pub fn check_all(problems: Vec<ProcessorError>) -> ProcessorResult<()> {
+        let mut problems: Vec<ProcessorError> = problems
+            .into_iter()
+            .flat_map(|problem| {
+                match problem {
+                    ProcessorError::Multiple(inner) => inner,
+                    problem => vec![problem],
+                }
+            })
+            .collect();
+        match problems.len() {
+            0 => Ok(()),
+            1 => Err(problems.remove(0)),
+            _ => Err(ProcessorError::Multiple(problems)),
+        }
+    }
+}
+
+/// A problem an operation ran in to that isn't worth failing over, like a
+/// prefab that's never used. Handed back alongside the payload for consumers
+/// to show however they like
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ProcessorWarning {
+    pub message: String,
+}
+
+impl ProcessorWarning {
+    #[must_use]
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ProcessorWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
 impl UFE for ProcessorError {
     fn summary(&self) -> String {
         format!("{self}")
@@ -33,9 +98,38 @@ impl UFE for ProcessorError {
                 Some(vec!["This operation only accepts DMIs".to_string()])
             }
             ProcessorError::ImageError(error) => Some(vec![format!("{}", error)]),
+            ProcessorError::PreviewEncodingFailed(error) => Some(vec![format!("{}", error)]),
+            ProcessorError::DmiEncodingFailed(error) => Some(vec![format!("{}", error)]),
+            ProcessorError::AsepriteEncodingFailed(error) => Some(vec![format!("{}", error)]),
             ProcessorError::RestorationFailed(error) => error.reasons(),
             ProcessorError::GenerationFailed(error) => error.reasons(),
             ProcessorError::ConfigError(config) => Some(vec![format!("{}", config)]),
+            ProcessorError::Cancelled => {
+                Some(vec![
+                    "The operation was cancelled before it could finish".to_string()
+                ])
+            }
+            ProcessorError::FrameCountMismatch {
+                expected,
+                image_height,
+                icon_height,
+            } => {
+                Some(vec![
+                    format!("Expected {expected} frames of {icon_height}px each"),
+                    format!(
+                        "The input is {image_height}px tall, which would need to be {}px",
+                        expected * icon_height
+                    ),
+                ])
+            }
+            ProcessorError::Multiple(problems) => {
+                Some(
+                    problems
+                        .iter()
+                        .flat_map(|problem| problem.reasons().unwrap_or_default())
+                        .collect(),
+                )
+            }
         }
     }
 
@@ -53,12 +147,31 @@ impl UFE for ProcessorError {
                         .to_string(),
                 )
             }
-            ProcessorError::ImageError(_) => None,
+            ProcessorError::ImageError(_)
+            | ProcessorError::PreviewEncodingFailed(_)
+            | ProcessorError::DmiEncodingFailed(_)
+            | ProcessorError::AsepriteEncodingFailed(_)
+            | ProcessorError::Cancelled => None,
             ProcessorError::RestorationFailed(error) => error.helptext(),
             ProcessorError::GenerationFailed(error) => error.helptext(),
             ProcessorError::ConfigError(_config) => {
                 Some("TBH this needs to be its own error type".to_string())
             }
+            ProcessorError::FrameCountMismatch { .. } => {
+                Some(
+                    "Check the input was exported at the right size, or update animation.frames"
+                        .to_string(),
+                )
+            }
+            ProcessorError::Multiple(problems) => {
+                let mut helptexts: Vec<String> = vec![];
+                for helptext in problems.iter().filter_map(UFE::helptext) {
+                    if !helptexts.contains(&helptext) {
+                        helptexts.push(helptext);
+                    }
+                }
+                (!helptexts.is_empty()).then(|| helptexts.join("\n"))
+            }
         }
     }
 }
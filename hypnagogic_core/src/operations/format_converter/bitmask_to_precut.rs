@@ -1,15 +1,18 @@
 use dmi::icon::IconState;
 use image::{DynamicImage, GenericImage};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use tracing::debug;
 
-use crate::config::blocks::cutters::StringMap;
+use crate::config::blocks::cutters::{DelayUnit, StringMap};
+use crate::config::provenance::Provenance;
+use crate::operations::cancellation::CancellationToken;
 use crate::operations::error::{ProcessorError, ProcessorResult};
 use crate::operations::format_converter::error::{InconsistentDelay, RestrorationError};
 use crate::operations::{IconOperationConfig, InputIcon, OperationMode, ProcessorPayload};
 use crate::util::delays::text_delays;
 
-#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct BitmaskSliceReconstruct {
     // List of icon states to extract
     pub extract: Vec<String>,
@@ -18,6 +21,21 @@ pub struct BitmaskSliceReconstruct {
     // Map of key -> value to set on the created config
     // Exists to let you set arbitrary values
     pub set: Option<StringMap>,
+    // Unit to write the created config's delays in, deciseconds if unset
+    #[serde(default)]
+    pub delay_unit: Option<DelayUnit>,
+}
+
+impl Default for BitmaskSliceReconstruct {
+    /// Extracts the junctions a standard bitmask slice sheet is cut from
+    fn default() -> Self {
+        Self {
+            extract: ["0", "3", "12", "15", "255"].map(String::from).to_vec(),
+            bespoke: None,
+            set: None,
+            delay_unit: None,
+        }
+    }
 }
 
 impl IconOperationConfig for BitmaskSliceReconstruct {
@@ -26,6 +44,7 @@ impl IconOperationConfig for BitmaskSliceReconstruct {
         &self,
         input: &InputIcon,
         mode: OperationMode,
+        cancel: &CancellationToken,
     ) -> ProcessorResult<ProcessorPayload> {
         debug!("Starting bitmask slice reconstruction");
         let InputIcon::Dmi(icon) = input else {
@@ -57,7 +76,7 @@ impl IconOperationConfig for BitmaskSliceReconstruct {
                 if prefix != output_prefix {
                     problem_entries.push(full_name.clone());
                 }
-                let suffix = split_name.last().unwrap_or(prefix.unwrap_or_default());
+                let suffix = split_name.next_back().unwrap_or(prefix.unwrap_or_default());
                 (state, suffix.to_string())
             })
             .collect::<Vec<(IconState, String)>>();
@@ -101,9 +120,7 @@ impl IconOperationConfig for BitmaskSliceReconstruct {
         let ignored_states = frames_drop_prefix
             .into_iter()
             .filter_map(|(_, suffix)| {
-                if suffix.parse::<i32>().is_ok()
-                    || strings_caught.iter().any(|caught| *caught == suffix)
-                {
+                if suffix.parse::<i32>().is_ok() || strings_caught.contains(&suffix) {
                     None
                 } else {
                     Some(format!("({suffix})"))
@@ -165,9 +182,13 @@ impl IconOperationConfig for BitmaskSliceReconstruct {
             .first()
             .and_then(|first_frame| Some(first_frame.rewind))
             .unwrap_or(false);
+        let hotspot = trimmed_frames
+            .first()
+            .and_then(|first_frame| first_frame.hotspot);
 
         let mut problem_states: Vec<InconsistentDelay> = vec![];
         for (x, state) in trimmed_frames.into_iter().enumerate() {
+            cancel.check()?;
             if delays != state.delay {
                 problem_states.push(InconsistentDelay {
                     state: state.name,
@@ -197,6 +218,12 @@ impl IconOperationConfig for BitmaskSliceReconstruct {
         if let Some(prefix_name) = output_prefix {
             config.push(format!("output_name = \"{prefix_name}\""));
         }
+        if let Some(hotspot) = hotspot {
+            config.push(format!(
+                "hotspot = {{ x = {}, y = {} }}",
+                hotspot.x, hotspot.y
+            ));
+        }
         if let Some(map) = &self.set {
             map.0.clone().into_iter().for_each(|entry| {
                 config.push(format!("{} = {}", entry.0, entry.1));
@@ -214,12 +241,22 @@ impl IconOperationConfig for BitmaskSliceReconstruct {
         }
         if let Some(actual_delay) = delays {
             config.push("[animation]".to_string());
-            config.push(format!("delays = {}", text_delays(&actual_delay, "")));
+            let delays: Vec<f32> = match self.delay_unit {
+                Some(unit) => {
+                    config.push(format!("delay_unit = \"{unit}\""));
+                    actual_delay
+                        .iter()
+                        .map(|delay| unit.from_deciseconds(*delay))
+                        .collect()
+                }
+                None => actual_delay,
+            };
+            config.push(format!("delays = {}", text_delays(&delays, "")));
             if rewind {
                 config.push(format!("rewind = {rewind}"));
             }
             config.push(String::new());
-        };
+        }
         config.push("[icon_size]".to_string());
         config.push(format!("x = {}", icon.width));
         config.push(format!("y = {}", icon.height));
@@ -233,6 +270,8 @@ impl IconOperationConfig for BitmaskSliceReconstruct {
         config.push(format!("y = {}", icon.height / 2));
         // Newline gang
         config.push(String::new());
+        let inventory = icon.states.iter().map(|state| state.name.clone()).collect();
+        config.push(Provenance::now(inventory).render());
         Ok(ProcessorPayload::wrap_png_config(
             ProcessorPayload::from_image(output_image),
             config.join("\n"),
@@ -0,0 +1,113 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::error::{ProcessorError, ProcessorResult};
+use crate::operations::{
+    IconOperationConfig,
+    InputIcon,
+    OperationMode,
+    OutputImage,
+    ProcessorPayload,
+};
+use crate::util::aseprite;
+
+/// Where each state's dirs go in the aseprite files written
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AsepriteDirs {
+    /// A layer for each dir, named after it
+    #[default]
+    Layers,
+    /// One after another on the frames, every frame of each dir together
+    Frames,
+}
+
+/// Converts between dmis and aseprite files, whichever the input is. Dmis are
+/// written next to the input as `<input>.aseprite` with each state a tag over
+/// its frames, and aseprite files as `<input>.dmi`, each tag a state. Delays,
+/// looping and rewinding carry over to aseprite's own, and the rest is kept
+/// in each tag's user data so nothing's lost going there and back
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct DmiAseprite {
+    /// Where dirs go in the aseprite files written, `layers` if unset. Read
+    /// back either way
+    #[serde(default)]
+    pub dirs: AsepriteDirs,
+}
+
+impl IconOperationConfig for DmiAseprite {
+    #[tracing::instrument(skip(input, _mode))]
+    fn perform_operation(
+        &self,
+        input: &InputIcon,
+        _mode: OperationMode,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ProcessorPayload> {
+        cancel.check()?;
+        match input {
+            InputIcon::Dmi(icon) => {
+                let written = aseprite::write(icon, self.dirs == AsepriteDirs::Layers)?;
+                Ok(ProcessorPayload::Single(Box::new(OutputImage::Aseprite(
+                    written,
+                ))))
+            }
+            InputIcon::Aseprite(icon) => Ok(ProcessorPayload::from_icon(icon.clone())),
+            InputIcon::DynamicImage(_) => Err(ProcessorError::DMINotFound),
+        }
+    }
+
+    fn verify_config(&self) -> ProcessorResult<()> {
+        Ok(())
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        match input {
+            InputIcon::Dmi(_) | InputIcon::Aseprite(_) => vec![],
+            InputIcon::DynamicImage(_) => vec![ProcessorError::DMINotFound],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use dmi::icon::{Icon, IconState};
+    use image::DynamicImage;
+
+    use super::*;
+    use crate::operations::single_dmi;
+
+    #[test]
+    fn converts_both_ways() {
+        let icon = Icon {
+            width: 2,
+            height: 2,
+            states: vec![IconState {
+                name: "lamp".to_string(),
+                dirs: 4,
+                images: vec![DynamicImage::new_rgba8(2, 2); 4],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let config = DmiAseprite {
+            dirs: AsepriteDirs::Frames,
+        };
+        let payload = config
+            .do_operation(&InputIcon::Dmi(icon.clone()), OperationMode::Standard)
+            .unwrap();
+        let ProcessorPayload::Single(output) = payload else {
+            panic!("Expected a single aseprite file");
+        };
+        let OutputImage::Aseprite(written) = *output else {
+            panic!("Expected an aseprite file");
+        };
+        let read = aseprite::read(written.as_slice()).unwrap();
+        assert_eq!(read, icon);
+
+        let payload = config
+            .do_operation(&InputIcon::Aseprite(read), OperationMode::Standard)
+            .unwrap();
+        assert_eq!(single_dmi(payload), icon);
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::BTreeMap;
+
+use dmi::icon::{Icon, IconState, Looping};
+use image::{imageops, DynamicImage};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::error::{ProcessorError, ProcessorResult};
+use crate::operations::{
+    IconOperationConfig,
+    InputIcon,
+    OperationMode,
+    OutputImage,
+    OutputText,
+    ProcessorPayload,
+};
+
+/// Names of dirs in the order byond stores them in
+pub const DIR_NAMES: [&str; 8] = [
+    "south",
+    "north",
+    "east",
+    "west",
+    "southeast",
+    "southwest",
+    "northeast",
+    "northwest",
+];
+
+/// How images are laid out on the exported sheet
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportLayout {
+    /// A row for each state, its images in the order they're stored
+    #[default]
+    Rows,
+    /// Every image of every state in one long row
+    Strip,
+}
+
+/// Dumps any dmi to a png sheet of every image in it, along with a json
+/// manifest of its states and where each of their images is on the sheet.
+/// Nothing about the dmi is lost, so other tools can read it without knowing
+/// anything about dmis. Written next to the input as `<input>.png` and
+/// `<input>.json`
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct DmiExport {
+    /// How images are laid out on the sheet, `rows` if unset
+    #[serde(default)]
+    pub layout: ExportLayout,
+}
+
+/// Where one image of a state is on the sheet
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ExportedImage {
+    /// One of [`DIR_NAMES`], or its index in them
+    pub dir: String,
+    pub frame: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+/// A state of the dmi, as written to the manifest
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ExportedState {
+    pub name: String,
+    pub dirs: u8,
+    pub frames: u32,
+    /// In deciseconds, as many as the dmi has, which should be one a frame
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub delays: Option<Vec<f32>>,
+    /// Times the animation plays before stopping, forever if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub loop_count: Option<u32>,
+    #[serde(default)]
+    pub rewind: bool,
+    #[serde(default)]
+    pub movement: bool,
+    /// Measured from the bottom left, as byond does
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub hotspot: Option<[u32; 2]>,
+    /// Settings byond wrote that nothing here reads, kept as they were
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    #[serde(default)]
+    pub settings: BTreeMap<String, String>,
+    /// Every image, frame by frame with each frame's dirs in order
+    pub images: Vec<ExportedImage>,
+}
+
+/// Everything in a dmi but its pixels, which are on the sheet next to it
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ExportManifest {
+    /// Size of each image
+    pub width: u32,
+    pub height: u32,
+    /// Size of the sheet, for tools reading it. Left out by tools writing it
+    /// is fine
+    #[serde(default)]
+    pub sheet_width: u32,
+    #[serde(default)]
+    pub sheet_height: u32,
+    pub states: Vec<ExportedState>,
+}
+
+impl DmiExport {
+    /// Where the `index`th image of the `row`th state goes, counting every
+    /// image before it in `before`
+    fn cell(&self, row: u32, index: u32, before: u32) -> (u32, u32) {
+        match self.layout {
+            ExportLayout::Rows => (index, row),
+            ExportLayout::Strip => (before + index, 0),
+        }
+    }
+
+    /// `icon` as a sheet and its manifest
+    /// # Errors
+    /// Errors if cancelled
+    pub fn export(
+        &self,
+        icon: &Icon,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<(DynamicImage, ExportManifest)> {
+        let mut states = vec![];
+        let mut placed: Vec<(&DynamicImage, u32, u32)> = vec![];
+        let mut before = 0;
+        for (row, state) in icon.states.iter().enumerate() {
+            cancel.check()?;
+            let mut images = vec![];
+            for (index, image) in state.images.iter().enumerate() {
+                let index = index as u32;
+                let (column, row) = self.cell(row as u32, index, before);
+                let (x, y) = (column * icon.width, row * icon.height);
+                let dirs = u32::from(state.dirs.max(1));
+                images.push(ExportedImage {
+                    dir: DIR_NAMES
+                        .get((index % dirs) as usize)
+                        .map_or_else(|| (index % dirs).to_string(), |dir| (*dir).to_string()),
+                    frame: index / dirs,
+                    x,
+                    y,
+                });
+                placed.push((image, x, y));
+            }
+            before += state.images.len() as u32;
+            states.push(exported_state(state, images));
+        }
+
+        let sheet_width = placed
+            .iter()
+            .map(|(_, x, _)| x + icon.width)
+            .max()
+            .unwrap_or(0);
+        let sheet_height = placed
+            .iter()
+            .map(|(_, _, y)| y + icon.height)
+            .max()
+            .unwrap_or(0);
+        let mut sheet = DynamicImage::new_rgba8(sheet_width, sheet_height);
+        for (image, x, y) in placed {
+            imageops::replace(&mut sheet, image, i64::from(x), i64::from(y));
+        }
+        Ok((
+            sheet,
+            ExportManifest {
+                width: icon.width,
+                height: icon.height,
+                sheet_width,
+                sheet_height,
+                states,
+            },
+        ))
+    }
+}
+
+fn exported_state(state: &IconState, images: Vec<ExportedImage>) -> ExportedState {
+    ExportedState {
+        name: state.name.clone(),
+        dirs: state.dirs,
+        frames: state.frames,
+        delays: state.delay.clone(),
+        loop_count: match state.loop_flag {
+            Looping::Indefinitely => None,
+            Looping::NTimes(times) => Some(times.get()),
+        },
+        rewind: state.rewind,
+        movement: state.movement,
+        hotspot: state.hotspot.map(|hotspot| [hotspot.x, hotspot.y]),
+        settings: state
+            .unknown_settings
+            .iter()
+            .flatten()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+        images,
+    }
+}
+
+impl IconOperationConfig for DmiExport {
+    #[tracing::instrument(skip(input, _mode))]
+    fn perform_operation(
+        &self,
+        input: &InputIcon,
+        _mode: OperationMode,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ProcessorPayload> {
+        let InputIcon::Dmi(icon) = input else {
+            return Err(ProcessorError::DMINotFound);
+        };
+        let (sheet, manifest) = self.export(icon, cancel)?;
+        let text = serde_json::to_string_pretty(&manifest)
+            .expect("export manifests are always valid json");
+        Ok(ProcessorPayload::ConfigWrapped(
+            Box::new(ProcessorPayload::Single(Box::new(OutputImage::Png(sheet)))),
+            Box::new(OutputText::SheetManifest(text)),
+        ))
+    }
+
+    fn verify_config(&self) -> ProcessorResult<()> {
+        Ok(())
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        match input {
+            InputIcon::Dmi(_) => vec![],
+            InputIcon::DynamicImage(_) | InputIcon::Aseprite(_) => {
+                vec![ProcessorError::DMINotFound]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use dmi::icon::Hotspot;
+    use image::{GenericImageView, Rgba};
+
+    use super::*;
+
+    fn image(shade: u8) -> DynamicImage {
+        DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(4, 4, Rgba([shade, 0, 0, 255])))
+    }
+
+    fn icon() -> Icon {
+        Icon {
+            width: 4,
+            height: 4,
+            states: vec![
+                IconState {
+                    name: "door".to_string(),
+                    dirs: 2,
+                    frames: 2,
+                    delay: Some(vec![1.0, 2.5]),
+                    loop_flag: Looping::new(3),
+                    hotspot: Some(Hotspot { x: 1, y: 2 }),
+                    images: vec![image(0), image(1), image(2), image(3)],
+                    ..Default::default()
+                },
+                IconState {
+                    name: "sign".to_string(),
+                    images: vec![image(4)],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn exports_rows() {
+        let (sheet, manifest) = DmiExport::default()
+            .export(&icon(), &CancellationToken::new())
+            .unwrap();
+        assert_eq!((sheet.width(), sheet.height()), (16, 8));
+        assert_eq!((manifest.sheet_width, manifest.sheet_height), (16, 8));
+
+        let door = &manifest.states[0];
+        assert_eq!(door.delays, Some(vec![1.0, 2.5]));
+        assert_eq!(door.loop_count, Some(3));
+        assert_eq!(door.hotspot, Some([1, 2]));
+        let third = &door.images[2];
+        assert_eq!((third.dir.as_str(), third.frame), ("south", 1));
+        assert_eq!(sheet.get_pixel(third.x, third.y), Rgba([2, 0, 0, 255]));
+
+        let sign = &manifest.states[1].images[0];
+        assert_eq!((sign.x, sign.y), (0, 4));
+        assert_eq!(sheet.get_pixel(sign.x, sign.y), Rgba([4, 0, 0, 255]));
+    }
+
+    #[test]
+    fn exports_strip() {
+        let config = DmiExport {
+            layout: ExportLayout::Strip,
+        };
+        let (sheet, manifest) = config.export(&icon(), &CancellationToken::new()).unwrap();
+        assert_eq!((sheet.width(), sheet.height()), (20, 4));
+        let sign = &manifest.states[1].images[0];
+        assert_eq!((sign.x, sign.y), (16, 0));
+        assert_eq!(sheet.get_pixel(sign.x, sign.y), Rgba([4, 0, 0, 255]));
+    }
+}
@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+
+use dmi::icon::{Hotspot, Icon, IconState, Looping};
+use image::DynamicImage;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::error::{ProcessorError, ProcessorResult, ProcessorWarning};
+use crate::operations::format_converter::dmi_export::{
+    ExportManifest,
+    ExportedImage,
+    ExportedState,
+    DIR_NAMES,
+};
+use crate::operations::{IconOperationConfig, InputIcon, OperationMode, ProcessorPayload};
+use crate::util::repeat_for;
+use crate::util::state_names::fits_in_dmi;
+
+/// Builds a dmi from a png sheet and a json manifest of where its states'
+/// images are, the inverse of `DmiExport`, so pipelines can write dmis
+/// without writing their metadata themselves. The input is the sheet
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct DmiImport {
+    /// Json manifest describing the sheet, relative to the config, in the
+    /// format `DmiExport` writes
+    pub manifest: String,
+    /// The manifest at `manifest`, read in by `IconOperation::load_side_files`
+    #[serde(skip)]
+    pub manifest_data: Option<ExportManifest>,
+}
+
+/// Index of the dir called `dir` in a state with `dirs` dirs, by name or by
+/// index
+fn dir_index(dir: &str, dirs: u8) -> Option<usize> {
+    DIR_NAMES
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(dir))
+        .or_else(|| dir.parse().ok())
+        .filter(|index| *index < usize::from(dirs))
+}
+
+impl DmiImport {
+    /// The images of `state` cut from `sheet`, in the order a dmi stores them
+    fn state_images(
+        state: &ExportedState,
+        sheet: &DynamicImage,
+        (width, height): (u32, u32),
+        problems: &mut Vec<ProcessorError>,
+    ) -> Vec<DynamicImage> {
+        let mut problem = |message: String| {
+            problems.push(ProcessorError::ConfigError(format!(
+                "`{}`: {message}",
+                state.name
+            )));
+        };
+        let dirs = usize::from(state.dirs);
+        let mut slots: Vec<Option<DynamicImage>> = vec![None; dirs * state.frames as usize];
+        for ExportedImage { dir, frame, x, y } in &state.images {
+            let Some(dir_index) = dir_index(dir, state.dirs) else {
+                problem(format!(
+                    "`{dir}` isn't one of its {} dirs, expected one of {}",
+                    state.dirs,
+                    DIR_NAMES[..dirs.min(DIR_NAMES.len())].join(", ")
+                ));
+                continue;
+            };
+            if *frame >= state.frames {
+                problem(format!(
+                    "has an image for frame {frame}, but only {} frames",
+                    state.frames
+                ));
+                continue;
+            }
+            if x.saturating_add(width) > sheet.width() || y.saturating_add(height) > sheet.height()
+            {
+                problem(format!(
+                    "the image at {x},{y} goes off the {}x{} sheet",
+                    sheet.width(),
+                    sheet.height()
+                ));
+                continue;
+            }
+            let slot = &mut slots[*frame as usize * dirs + dir_index];
+            if slot.is_some() {
+                problem(format!("has more than one image for {dir} frame {frame}"));
+                continue;
+            }
+            *slot = Some(sheet.crop_imm(*x, *y, width, height));
+        }
+        let missing = slots.iter().filter(|slot| slot.is_none()).count();
+        if missing > 0 {
+            problem(format!(
+                "is missing {missing} of its {} images",
+                slots.len()
+            ));
+        }
+        slots.into_iter().flatten().collect()
+    }
+
+    /// Builds the dmi `manifest` describes out of `sheet`, along with
+    /// warnings about delays that had to be made up or cut down to the frames
+    /// there are
+    /// # Errors
+    /// Errors with everything in the manifest that doesn't fit the sheet or
+    /// can't go in a dmi, or if cancelled
+    pub fn import(
+        &self,
+        sheet: &DynamicImage,
+        manifest: &ExportManifest,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<(Icon, Vec<ProcessorWarning>)> {
+        let mut problems = vec![];
+        let mut warnings = vec![];
+        if manifest.width == 0 || manifest.height == 0 {
+            return Err(ProcessorError::ConfigError(format!(
+                "the manifest's images are {}x{}, they need to be at least 1x1",
+                manifest.width, manifest.height
+            )));
+        }
+        let mut states = vec![];
+        for state in &manifest.states {
+            cancel.check()?;
+            if !fits_in_dmi(&state.name) {
+                problems.push(ProcessorError::ConfigError(format!(
+                    "`{}` can't be the name of a state in a dmi",
+                    state.name.escape_default()
+                )));
+            }
+            if ![1, 4, 8].contains(&state.dirs) || state.frames == 0 {
+                problems.push(ProcessorError::ConfigError(format!(
+                    "`{}` has {} dirs and {} frames, states need 1, 4 or 8 dirs and at least one \
+                     frame",
+                    state.name, state.dirs, state.frames
+                )));
+                continue;
+            }
+            for (key, value) in &state.settings {
+                if !fits_in_dmi(key) || !fits_in_dmi(value) || key.contains('=') {
+                    problems.push(ProcessorError::ConfigError(format!(
+                        "`{}` has a setting `{}` that can't be written in to a dmi",
+                        state.name,
+                        key.escape_default()
+                    )));
+                }
+            }
+            let images = Self::state_images(
+                state,
+                sheet,
+                (manifest.width, manifest.height),
+                &mut problems,
+            );
+            let frames = state.frames as usize;
+            // dmis can't be saved with a delay for each frame missing
+            let delay = match &state.delays {
+                _ if frames == 1 => None,
+                Some(delays) if delays.len() == frames => Some(delays.clone()),
+                Some(delays) if !delays.is_empty() => {
+                    warnings.push(ProcessorWarning::new(format!(
+                        "`{}` has {} delays for {frames} frames, so they're repeated or cut off \
+                         to fit",
+                        state.name,
+                        delays.len()
+                    )));
+                    Some(repeat_for(delays, frames))
+                }
+                _ => {
+                    warnings.push(ProcessorWarning::new(format!(
+                        "`{}` has {frames} frames but no delays, so each is shown for 1 decisecond",
+                        state.name
+                    )));
+                    Some(vec![1.0; frames])
+                }
+            };
+            states.push(IconState {
+                name: state.name.clone(),
+                dirs: state.dirs,
+                frames: state.frames,
+                images,
+                delay,
+                loop_flag: state
+                    .loop_count
+                    .filter(|count| *count > 0)
+                    .map_or(Looping::Indefinitely, Looping::new),
+                rewind: state.rewind,
+                movement: state.movement,
+                hotspot: state.hotspot.map(|[x, y]| Hotspot { x, y }),
+                unknown_settings: (!state.settings.is_empty()).then(|| {
+                    state
+                        .settings
+                        .iter()
+                        .map(|(key, value)| (key.clone(), value.clone()))
+                        .collect::<HashMap<String, String>>()
+                }),
+            });
+        }
+        ProcessorError::check_all(problems)?;
+        Ok((
+            Icon {
+                width: manifest.width,
+                height: manifest.height,
+                states,
+                ..Default::default()
+            },
+            warnings,
+        ))
+    }
+}
+
+impl IconOperationConfig for DmiImport {
+    #[tracing::instrument(skip(input, _mode))]
+    fn perform_operation(
+        &self,
+        input: &InputIcon,
+        _mode: OperationMode,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ProcessorPayload> {
+        let InputIcon::DynamicImage(sheet) = input else {
+            return Err(ProcessorError::ImageNotFound);
+        };
+        let Some(manifest) = &self.manifest_data else {
+            return Err(ProcessorError::ConfigError(
+                "the manifest at `manifest` hasn't been read".to_string(),
+            ));
+        };
+        let (icon, warnings) = self.import(sheet, manifest, cancel)?;
+        Ok(ProcessorPayload::from_icon(icon).with_warnings(warnings))
+    }
+
+    fn verify_config(&self) -> ProcessorResult<()> {
+        if self.manifest.is_empty() {
+            return Err(ProcessorError::ConfigError(
+                "manifest is empty, it needs the json describing the sheet".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        match input {
+            InputIcon::DynamicImage(_) => vec![],
+            InputIcon::Dmi(_) | InputIcon::Aseprite(_) => vec![ProcessorError::ImageNotFound],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::Rgba;
+
+    use super::*;
+    use crate::operations::format_converter::dmi_export::DmiExport;
+
+    fn image(shade: u8) -> DynamicImage {
+        DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(4, 4, Rgba([shade, 0, 0, 255])))
+    }
+
+    fn icon() -> Icon {
+        Icon {
+            width: 4,
+            height: 4,
+            states: vec![
+                IconState {
+                    name: "door".to_string(),
+                    dirs: 4,
+                    frames: 2,
+                    delay: Some(vec![1.0, 2.5]),
+                    loop_flag: Looping::new(3),
+                    rewind: true,
+                    hotspot: Some(Hotspot { x: 1, y: 2 }),
+                    images: (0..8).map(image).collect(),
+                    ..Default::default()
+                },
+                IconState {
+                    name: "sign".to_string(),
+                    movement: true,
+                    images: vec![image(9)],
+                    unknown_settings: Some(HashMap::from([(
+                        "future".to_string(),
+                        "1".to_string(),
+                    )])),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn import(sheet: &DynamicImage, manifest: &ExportManifest) -> ProcessorResult<Icon> {
+        Ok(DmiImport::default()
+            .import(sheet, manifest, &CancellationToken::new())?
+            .0)
+    }
+
+    #[test]
+    fn round_trips() {
+        let icon = icon();
+        let (sheet, manifest) = DmiExport::default()
+            .export(&icon, &CancellationToken::new())
+            .unwrap();
+        let json = serde_json::to_string(&manifest).unwrap();
+        let imported = import(&sheet, &serde_json::from_str(&json).unwrap()).unwrap();
+        assert_eq!(imported, icon);
+    }
+
+    #[test]
+    fn reads_other_manifests() {
+        // images in any order, dirs by index and anything optional left out
+        let json = r#"{
+            "width": 4,
+            "height": 4,
+            "states": [{
+                "name": "blink",
+                "dirs": 1,
+                "frames": 2,
+                "images": [
+                    { "dir": "0", "frame": 1, "x": 4, "y": 0 },
+                    { "dir": "south", "frame": 0, "x": 0, "y": 0 }
+                ]
+            }]
+        }"#;
+        let mut sheet = DynamicImage::new_rgba8(8, 4);
+        image::imageops::replace(&mut sheet, &image(7), 4, 0);
+        let (icon, warnings) = DmiImport::default()
+            .import(
+                &sheet,
+                &serde_json::from_str(json).unwrap(),
+                &CancellationToken::new(),
+            )
+            .unwrap();
+        assert_eq!(icon.states[0].images[1], image(7));
+        assert_eq!(icon.states[0].delay, Some(vec![1.0, 1.0]));
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn problems() {
+        let (sheet, manifest) = DmiExport::default()
+            .export(&icon(), &CancellationToken::new())
+            .unwrap();
+        let broken = |change: fn(&mut ExportManifest)| {
+            let mut manifest = manifest.clone();
+            change(&mut manifest);
+            import(&sheet, &manifest).is_err()
+        };
+        assert!(broken(|manifest| manifest.states[0].images.truncate(7)));
+        assert!(broken(|manifest| manifest.states[0].images[0].x = 100));
+        assert!(broken(|manifest| {
+            manifest.states[0].images[0].dir = "up".to_string();
+        }));
+        assert!(broken(|manifest| manifest.states[0].images[1].frame = 5));
+        assert!(broken(|manifest| manifest.states[0].dirs = 3));
+        assert!(broken(|manifest| {
+            manifest.states[1].name = "a\"b".to_string();
+        }));
+        assert!(broken(|manifest| manifest.width = 0));
+        assert!(DmiImport::default().verify_config().is_err());
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+use dmi::icon::{Icon, IconState};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::error::{ProcessorError, ProcessorResult, ProcessorWarning};
+use crate::operations::{
+    IconOperationConfig,
+    InputIcon,
+    NamedIcon,
+    OperationMode,
+    OutputImage,
+    ProcessorPayload,
+};
+
+/// Name hint of the merged icon, so it's written next to the input as
+/// `<input>-merged.dmi` rather than over it
+pub const MERGED_NAME_HINT: &str = "merged";
+
+/// Added to the names of renamed states when `rename_suffix` is unset, giving
+/// names like `door-1`
+pub const DEFAULT_RENAME_SUFFIX: &str = "-{index}";
+
+/// What to do with a state whose name an earlier icon already used
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Fail, listing every clashing state
+    #[default]
+    Error,
+    /// Keep both, adding `rename_suffix` to the later state's name
+    Rename,
+    /// Keep the state from the earliest icon, dropping the rest
+    PreferFirst,
+}
+
+impl ConflictPolicy {
+    /// Every policy's name, as used for `on_conflict` in configs
+    pub const NAMES: [&'static str; 3] = ["error", "rename", "prefer_first"];
+}
+
+impl fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ConflictPolicy::Error => "error",
+            ConflictPolicy::Rename => "rename",
+            ConflictPolicy::PreferFirst => "prefer_first",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "error" => Ok(ConflictPolicy::Error),
+            "rename" => Ok(ConflictPolicy::Rename),
+            "prefer_first" => Ok(ConflictPolicy::PreferFirst),
+            _ => {
+                Err(format!(
+                    "there's no conflict policy called `{name}`, expected one of {}",
+                    ConflictPolicy::NAMES.join(", ")
+                ))
+            }
+        }
+    }
+}
+
+/// Merges other dmis in to the input dmi, one after another, for putting
+/// icons kept in separate files back together in to the one the game reads.
+/// Every icon has to be the same size
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct DmiMerge {
+    /// Dmis next to the config to merge in after the input's states, in order
+    pub with: Vec<String>,
+    /// What happens to a state whose name an earlier icon already used,
+    /// `error` if unset. States sharing a name within one icon are left be
+    #[serde(default)]
+    pub on_conflict: ConflictPolicy,
+    /// Added to the names of states `rename` renames, with `{index}` swapped
+    /// for which icon they came from, counting the input as 0. `-{index}` if
+    /// unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub rename_suffix: Option<String>,
+    /// The loaded dmis of `with`, filled in by `IconOperation::load_side_files`
+    #[serde(skip)]
+    pub with_icons: Vec<Icon>,
+}
+
+impl DmiMerge {
+    fn rename_suffix(&self) -> &str {
+        self.rename_suffix
+            .as_deref()
+            .unwrap_or(DEFAULT_RENAME_SUFFIX)
+    }
+
+    /// What the icon at `index` of those being merged is called in problems
+    fn source(&self, index: usize) -> String {
+        match index.checked_sub(1) {
+            Some(with) => format!("`{}`", self.with[with]),
+            None => "the input".to_string(),
+        }
+    }
+
+    /// Merges `icons` in order, the first being the input and the rest the
+    /// icons of `with`, settling clashing state names by `on_conflict`
+    /// # Errors
+    /// Errors with every clash if `on_conflict` is `error`, and with any
+    /// renamed state that still clashes if it's `rename`
+    pub fn merge(&self, icons: &[&Icon], cancel: &CancellationToken) -> ProcessorResult<Icon> {
+        let Some(first) = icons.first() else {
+            return Ok(Icon::default());
+        };
+        let mut merged = Icon {
+            states: vec![],
+            ..(*first).clone()
+        };
+        let mut problems = vec![];
+        // names used by earlier icons, each with the icon that used it first
+        let mut taken: Vec<(String, usize)> = vec![];
+        for (index, icon) in icons.iter().enumerate() {
+            cancel.check()?;
+            let mut names = BTreeSet::new();
+            for state in &icon.states {
+                let earlier = taken.iter().find(|(name, _)| *name == state.name);
+                let Some((_, earlier)) = earlier else {
+                    names.insert(state.name.clone());
+                    merged.states.push(state.clone());
+                    continue;
+                };
+                match self.on_conflict {
+                    ConflictPolicy::Error => {
+                        problems.push(ProcessorError::ConfigError(format!(
+                            "`{}` from {} is already in {}",
+                            state.name,
+                            self.source(index),
+                            self.source(*earlier)
+                        )));
+                    }
+                    ConflictPolicy::Rename => {
+                        let name = format!(
+                            "{}{}",
+                            state.name,
+                            self.rename_suffix().replace("{index}", &index.to_string())
+                        );
+                        let clashes = taken.iter().any(|(taken, _)| *taken == name)
+                            || icon.states.iter().any(|state| state.name == name);
+                        if clashes {
+                            problems.push(ProcessorError::ConfigError(format!(
+                                "`{}` from {} is renamed to `{name}`, but that's taken too",
+                                state.name,
+                                self.source(index)
+                            )));
+                            continue;
+                        }
+                        names.insert(name.clone());
+                        merged.states.push(IconState {
+                            name,
+                            ..state.clone()
+                        });
+                    }
+                    ConflictPolicy::PreferFirst => {}
+                }
+            }
+            taken.extend(names.into_iter().map(|name| (name, index)));
+        }
+        ProcessorError::check_all(problems)?;
+        Ok(merged)
+    }
+}
+
+impl IconOperationConfig for DmiMerge {
+    #[tracing::instrument(skip(input, _mode))]
+    fn perform_operation(
+        &self,
+        input: &InputIcon,
+        _mode: OperationMode,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ProcessorPayload> {
+        let InputIcon::Dmi(icon) = input else {
+            return Err(ProcessorError::DMINotFound);
+        };
+        if self.with_icons.len() != self.with.len() {
+            return Err(ProcessorError::ConfigError(
+                "the dmis of `with` haven't been loaded".to_string(),
+            ));
+        }
+        let icons: Vec<&Icon> = std::iter::once(icon).chain(&self.with_icons).collect();
+        let merged = self.merge(&icons, cancel)?;
+        Ok(ProcessorPayload::SingleNamed(Box::new(NamedIcon {
+            path_hint: None,
+            name_hint: Some(MERGED_NAME_HINT.to_string()),
+            image: OutputImage::Dmi(merged),
+        })))
+    }
+
+    fn verify_config(&self) -> ProcessorResult<()> {
+        let mut problems = vec![];
+        if self.with.is_empty() {
+            problems.push(ProcessorError::ConfigError(
+                "with is empty, it needs the dmis to merge in".to_string(),
+            ));
+        }
+        let suffix = self.rename_suffix();
+        if suffix.is_empty() || suffix.contains(['"', '\\']) {
+            problems.push(ProcessorError::ConfigError(
+                "rename_suffix must be something that can go in a state name".to_string(),
+            ));
+        }
+        ProcessorError::check_all(problems)
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        let InputIcon::Dmi(icon) = input else {
+            return vec![ProcessorError::DMINotFound];
+        };
+        self.with
+            .iter()
+            .zip(&self.with_icons)
+            .filter(|(_, with)| (with.width, with.height) != (icon.width, icon.height))
+            .map(|(file, with)| {
+                ProcessorError::ConfigError(format!(
+                    "`{file}` is {}x{}, but the input is {}x{}. Merged icons all have to be the \
+                     same size",
+                    with.width, with.height, icon.width, icon.height
+                ))
+            })
+            .collect()
+    }
+
+    fn config_warnings(&self) -> Vec<ProcessorWarning> {
+        if self.rename_suffix.is_some() && self.on_conflict != ConflictPolicy::Rename {
+            return vec![ProcessorWarning::new(format!(
+                "rename_suffix is set, but on_conflict is `{}` so nothing's renamed",
+                self.on_conflict
+            ))];
+        }
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::DynamicImage;
+
+    use super::*;
+
+    fn icon(size: u32, names: &[&str]) -> Icon {
+        Icon {
+            width: size,
+            height: size,
+            states: names
+                .iter()
+                .map(|name| {
+                    IconState {
+                        name: (*name).to_string(),
+                        images: vec![DynamicImage::new_rgba8(size, size)],
+                        ..Default::default()
+                    }
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn config(on_conflict: ConflictPolicy, with: Vec<Icon>) -> DmiMerge {
+        DmiMerge {
+            with: (1..=with.len())
+                .map(|index| format!("{index}.dmi"))
+                .collect(),
+            on_conflict,
+            rename_suffix: None,
+            with_icons: with,
+        }
+    }
+
+    fn merged_names(config: &DmiMerge, input: Icon) -> ProcessorResult<Vec<String>> {
+        let payload = config.do_operation(&InputIcon::Dmi(input), OperationMode::Standard)?;
+        let ProcessorPayload::SingleNamed(named) = payload else {
+            panic!("Expected a single named icon");
+        };
+        let OutputImage::Dmi(icon) = named.image else {
+            panic!("Expected a dmi");
+        };
+        Ok(icon.states.into_iter().map(|state| state.name).collect())
+    }
+
+    #[test]
+    fn conflict_policies() {
+        let with = || vec![icon(32, &["b", "c"]), icon(32, &["a", "c", "d"])];
+        let input = || icon(32, &["a", "b", "b"]);
+
+        let error = config(ConflictPolicy::Error, with());
+        let Err(ProcessorError::Multiple(problems)) = merged_names(&error, input()) else {
+            panic!("Expected every clash");
+        };
+        assert_eq!(problems.len(), 3);
+
+        let rename = config(ConflictPolicy::Rename, with());
+        assert_eq!(
+            merged_names(&rename, input()).unwrap(),
+            ["a", "b", "b", "b-1", "c", "a-2", "c-2", "d"]
+        );
+
+        let prefer_first = config(ConflictPolicy::PreferFirst, with());
+        assert_eq!(
+            merged_names(&prefer_first, input()).unwrap(),
+            ["a", "b", "b", "c", "d"]
+        );
+    }
+
+    #[test]
+    fn renames_that_still_clash() {
+        let config = DmiMerge {
+            rename_suffix: Some("-copy".to_string()),
+            ..config(
+                ConflictPolicy::Rename,
+                vec![icon(32, &["a"]), icon(32, &["a"])],
+            )
+        };
+        assert!(merged_names(&config, icon(32, &["a"])).is_err());
+    }
+
+    #[test]
+    fn problems() {
+        let config = config(ConflictPolicy::Error, vec![icon(32, &[]), icon(64, &[])]);
+        let problems = config.input_problems(&InputIcon::Dmi(icon(32, &[])));
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].to_string().contains("2.dmi"));
+        assert_eq!(
+            config
+                .input_problems(&InputIcon::DynamicImage(DynamicImage::new_rgba8(1, 1)))
+                .len(),
+            1
+        );
+
+        assert!(DmiMerge::default().verify_config().is_err());
+        assert_eq!("prefer_first".parse(), Ok(ConflictPolicy::PreferFirst));
+        assert!("first".parse::<ConflictPolicy>().is_err());
+    }
+}
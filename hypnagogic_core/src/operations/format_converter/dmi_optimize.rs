@@ -0,0 +1,241 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+
+use dmi::icon::{Icon, IconState};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::error::{ProcessorError, ProcessorResult, ProcessorWarning};
+use crate::operations::{
+    IconOperationConfig,
+    InputIcon,
+    NamedIcon,
+    OperationMode,
+    OutputImage,
+    ProcessorPayload,
+};
+use crate::util::dmi_encoding::encode_smallest;
+use crate::util::icon_ops::dedupe_frames;
+
+/// Name hint of the optimized icon, so it's written next to the input as
+/// `<input>-optimized.dmi` rather than over it
+pub const OPTIMIZED_NAME_HINT: &str = "optimized";
+
+/// Rewrites a dmi to take up as little space as it can while looking the
+/// same in game. Repeated frames are merged, delays of frames that don't
+/// exist are dropped, and the png is encoded as small as it'll go
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct DmiOptimize {
+    /// Merges frames that repeat the one before them in to it, adding on
+    /// their delay, and drops states that are exact copies of an earlier one
+    /// with the same name, which byond never shows. On unless false
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub dedupe: Option<bool>,
+}
+
+/// Hash of what `state` looks like, leaving out its name
+fn content_hash(state: &IconState) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (state.dirs, state.frames, state.movement, state.rewind).hash(&mut hasher);
+    for delay in state.delay.iter().flatten() {
+        delay.to_bits().hash(&mut hasher);
+    }
+    for image in &state.images {
+        image.as_bytes().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Whether `first` and `second` look the same, whatever they're named
+fn same_content(first: &IconState, second: &IconState) -> bool {
+    *first
+        == IconState {
+            name: first.name.clone(),
+            ..second.clone()
+        }
+}
+
+impl DmiOptimize {
+    fn dedupe(&self) -> bool {
+        self.dedupe != Some(false)
+    }
+
+    /// `icon` with its repeated frames merged, copies of states byond never
+    /// shows dropped and delays for frames that don't exist trimmed, along
+    /// with warnings about states that are copies under different names
+    /// # Errors
+    /// Errors if cancelled
+    pub fn optimize(
+        &self,
+        icon: &Icon,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<(Icon, Vec<ProcessorWarning>)> {
+        let mut states: Vec<IconState> = vec![];
+        let mut warnings = vec![];
+        // states kept so far, by content_hash
+        let mut seen: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+        for state in &icon.states {
+            cancel.check()?;
+            let mut state = if self.dedupe() {
+                dedupe_frames(state.clone(), None)
+            } else {
+                state.clone()
+            };
+            if state.frames <= 1 {
+                state.delay = None;
+            } else if let Some(delay) = &mut state.delay {
+                delay.truncate(state.frames as usize);
+            }
+            if !self.dedupe() {
+                states.push(state);
+                continue;
+            }
+            let copies = seen.entry(content_hash(&state)).or_default();
+            let copy_of = copies
+                .iter()
+                .map(|&index| &states[index])
+                .find(|kept| same_content(kept, &state));
+            match copy_of {
+                Some(kept) if kept.name == state.name => continue,
+                Some(kept) => {
+                    warnings.push(ProcessorWarning::new(format!(
+                        "`{}` is a copy of `{}`, and could be dropped for it",
+                        state.name, kept.name
+                    )));
+                }
+                None => copies.push(states.len()),
+            }
+            states.push(state);
+        }
+        Ok((
+            Icon {
+                states,
+                ..icon.clone()
+            },
+            warnings,
+        ))
+    }
+}
+
+impl IconOperationConfig for DmiOptimize {
+    #[tracing::instrument(skip(input, _mode))]
+    fn perform_operation(
+        &self,
+        input: &InputIcon,
+        _mode: OperationMode,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ProcessorPayload> {
+        let InputIcon::Dmi(icon) = input else {
+            return Err(ProcessorError::DMINotFound);
+        };
+        let (optimized, warnings) = self.optimize(icon, cancel)?;
+        cancel.check()?;
+        let payload = ProcessorPayload::SingleNamed(Box::new(NamedIcon {
+            path_hint: None,
+            name_hint: Some(OPTIMIZED_NAME_HINT.to_string()),
+            image: OutputImage::EncodedDmi(encode_smallest(&optimized)?),
+        }));
+        Ok(payload.with_warnings(warnings))
+    }
+
+    fn verify_config(&self) -> ProcessorResult<()> {
+        Ok(())
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        match input {
+            InputIcon::Dmi(_) => vec![],
+            InputIcon::DynamicImage(_) | InputIcon::Aseprite(_) => {
+                vec![ProcessorError::DMINotFound]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{DynamicImage, Rgba};
+
+    use super::*;
+
+    fn frame(shade: u8) -> DynamicImage {
+        let mut image = DynamicImage::new_rgba8(4, 4);
+        image
+            .as_mut_rgba8()
+            .unwrap()
+            .put_pixel(1, 1, Rgba([shade, 0, 0, 255]));
+        image
+    }
+
+    fn state(name: &str, shades: &[u8], delay: Option<Vec<f32>>) -> IconState {
+        IconState {
+            name: name.to_string(),
+            frames: shades.len() as u32,
+            delay,
+            images: shades.iter().map(|shade| frame(*shade)).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn input() -> Icon {
+        Icon {
+            width: 4,
+            height: 4,
+            states: vec![
+                state("blink", &[1, 1, 2], Some(vec![1.0, 2.0, 3.0, 4.0])),
+                state("still", &[3], Some(vec![5.0])),
+                state("still", &[3], None),
+                state("same", &[3], None),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn optimizes() {
+        let (icon, warnings) = DmiOptimize::default()
+            .optimize(&input(), &CancellationToken::new())
+            .unwrap();
+        let names: Vec<&str> = icon
+            .states
+            .iter()
+            .map(|state| state.name.as_str())
+            .collect();
+        assert_eq!(names, ["blink", "still", "same"]);
+        assert_eq!(icon.states[0].frames, 2);
+        assert_eq!(icon.states[0].delay, Some(vec![3.0, 3.0]));
+        assert_eq!(icon.states[1].delay, None);
+        assert_eq!(warnings.len(), 1);
+
+        let (icon, warnings) = DmiOptimize {
+            dedupe: Some(false),
+        }
+        .optimize(&input(), &CancellationToken::new())
+        .unwrap();
+        assert_eq!(icon.states.len(), 4);
+        assert_eq!(icon.states[0].delay, Some(vec![1.0, 2.0, 3.0]));
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn writes_smaller() {
+        let payload = DmiOptimize::default()
+            .do_operation(&InputIcon::Dmi(input()), OperationMode::Standard)
+            .unwrap();
+        let ProcessorPayload::Warned(payload, _) = payload else {
+            panic!("Expected a warning about `same`");
+        };
+        let ProcessorPayload::SingleNamed(named) = *payload else {
+            panic!("Expected a single named icon");
+        };
+        let OutputImage::EncodedDmi(encoded) = named.image else {
+            panic!("Expected an encoded dmi");
+        };
+        let icon = Icon::load(encoded.as_slice()).unwrap();
+        assert_eq!(icon.states.len(), 3);
+        assert_eq!(icon.states[0].images[1], frame(2));
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::BTreeMap;
+
+use dmi::icon::{Icon, IconState};
+use regex_automata::meta::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::error::{ProcessorError, ProcessorResult, ProcessorWarning};
+use crate::operations::{
+    IconOperationConfig,
+    InputIcon,
+    NamedIcon,
+    OperationMode,
+    OutputImage,
+    ProcessorPayload,
+};
+use crate::util::state_names::fits_in_dmi;
+
+/// Name hint of the renamed icon, so it's written next to the input as
+/// `<input>-renamed.dmi` rather than over it
+pub const RENAMED_NAME_HINT: &str = "renamed";
+
+/// Renames the states of a dmi by a map of names and regex substitutions, for
+/// scripting migrations between naming conventions, like old junction names
+/// to new ones. Everything but the names is left as it is
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct DmiRename {
+    /// New names of states, by their current name. States in it skip
+    /// `substitutions`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    #[serde(default)]
+    pub renames: BTreeMap<String, String>,
+    /// Regex substitutions made to the names of states not in `renames`, in
+    /// order, each on the result of the last
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub substitutions: Vec<Substitution>,
+}
+
+/// Replaces every match of a regex in state names
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Substitution {
+    /// Regex to match
+    pub pattern: String,
+    /// What each match is replaced with. `$1` or `${name}` put in what a
+    /// group matched
+    pub replace: String,
+}
+
+impl Substitution {
+    fn regex(&self) -> ProcessorResult<Regex> {
+        Regex::new(&self.pattern).map_err(|err| {
+            ProcessorError::ConfigError(format!(
+                "substitution pattern `{}` isn't a valid regex: {err}",
+                self.pattern
+            ))
+        })
+    }
+}
+
+/// `name` with every match of `regex` replaced by `replace`
+fn substitute(regex: &Regex, name: &str, replace: &str) -> String {
+    let mut out = String::new();
+    let mut last = 0;
+    for captures in regex.captures_iter(name) {
+        let Some(found) = captures.get_match() else {
+            continue;
+        };
+        out.push_str(&name[last..found.start()]);
+        captures.interpolate_string_into(name, replace, &mut out);
+        last = found.end();
+    }
+    out.push_str(&name[last..]);
+    out
+}
+
+impl DmiRename {
+    /// New name of the state named `name`, with `regexes` being the compiled
+    /// patterns of `substitutions`
+    fn renamed(&self, name: &str, regexes: &[Regex]) -> String {
+        if let Some(renamed) = self.renames.get(name) {
+            return renamed.clone();
+        }
+        self.substitutions
+            .iter()
+            .zip(regexes)
+            .fold(name.to_string(), |name, (substitution, regex)| {
+                substitute(regex, &name, &substitution.replace)
+            })
+    }
+
+    /// `icon` with its states renamed
+    /// # Errors
+    /// Errors if a pattern isn't valid, or renaming gives states that had
+    /// different names the same one, or one that can't go in a dmi
+    pub fn rename(&self, icon: &Icon, cancel: &CancellationToken) -> ProcessorResult<Icon> {
+        let regexes = self
+            .substitutions
+            .iter()
+            .map(Substitution::regex)
+            .collect::<ProcessorResult<Vec<Regex>>>()?;
+        let mut problems = vec![];
+        let mut states = vec![];
+        // the first name each new name was given to, to catch renames that
+        // merge states together. States already sharing a name still can
+        let mut given: BTreeMap<String, &str> = BTreeMap::new();
+        for state in &icon.states {
+            cancel.check()?;
+            let name = self.renamed(&state.name, &regexes);
+            if !fits_in_dmi(&name) {
+                problems.push(ProcessorError::ConfigError(format!(
+                    "`{}` is renamed to `{}`, which can't go in a dmi",
+                    state.name,
+                    name.escape_default()
+                )));
+            }
+            match given.get(&name) {
+                Some(first) if *first != state.name => {
+                    problems.push(ProcessorError::ConfigError(format!(
+                        "`{first}` and `{}` are both renamed to `{name}`",
+                        state.name
+                    )));
+                }
+                Some(_) => {}
+                None => {
+                    given.insert(name.clone(), &state.name);
+                }
+            }
+            states.push(IconState {
+                name,
+                ..state.clone()
+            });
+        }
+        ProcessorError::check_all(problems)?;
+        Ok(Icon {
+            states,
+            ..icon.clone()
+        })
+    }
+}
+
+impl IconOperationConfig for DmiRename {
+    #[tracing::instrument(skip(input, _mode))]
+    fn perform_operation(
+        &self,
+        input: &InputIcon,
+        _mode: OperationMode,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ProcessorPayload> {
+        let InputIcon::Dmi(icon) = input else {
+            return Err(ProcessorError::DMINotFound);
+        };
+        let renamed = self.rename(icon, cancel)?;
+        let warnings = self
+            .renames
+            .keys()
+            .filter(|name| !icon.states.iter().any(|state| state.name == **name))
+            .map(|name| {
+                ProcessorWarning::new(format!(
+                    "renames has `{name}`, but there's no state with that name"
+                ))
+            })
+            .collect();
+        let payload = ProcessorPayload::SingleNamed(Box::new(NamedIcon {
+            path_hint: None,
+            name_hint: Some(RENAMED_NAME_HINT.to_string()),
+            image: OutputImage::Dmi(renamed),
+        }));
+        Ok(payload.with_warnings(warnings))
+    }
+
+    fn verify_config(&self) -> ProcessorResult<()> {
+        let mut problems = vec![];
+        if self.renames.is_empty() && self.substitutions.is_empty() {
+            problems.push(ProcessorError::ConfigError(
+                "renames and substitutions are both empty, so nothing's renamed".to_string(),
+            ));
+        }
+        for (name, renamed) in &self.renames {
+            if !fits_in_dmi(renamed) {
+                problems.push(ProcessorError::ConfigError(format!(
+                    "renames gives `{name}` the name `{}`, which can't go in a dmi",
+                    renamed.escape_default()
+                )));
+            }
+        }
+        problems.extend(
+            self.substitutions
+                .iter()
+                .filter_map(|substitution| substitution.regex().err()),
+        );
+        ProcessorError::check_all(problems)
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        match input {
+            InputIcon::Dmi(_) => vec![],
+            InputIcon::DynamicImage(_) | InputIcon::Aseprite(_) => {
+                vec![ProcessorError::DMINotFound]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::DynamicImage;
+
+    use super::*;
+
+    fn icon(names: &[&str]) -> Icon {
+        Icon {
+            width: 32,
+            height: 32,
+            states: names
+                .iter()
+                .map(|name| {
+                    IconState {
+                        name: (*name).to_string(),
+                        frames: 2,
+                        delay: Some(vec![1.0, 2.0]),
+                        images: vec![DynamicImage::new_rgba8(32, 32); 2],
+                        ..Default::default()
+                    }
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn substitution(pattern: &str, replace: &str) -> Substitution {
+        Substitution {
+            pattern: pattern.to_string(),
+            replace: replace.to_string(),
+        }
+    }
+
+    fn renamed(config: &DmiRename, names: &[&str]) -> ProcessorResult<Vec<String>> {
+        let icon = config.rename(&icon(names), &CancellationToken::new())?;
+        Ok(icon.states.into_iter().map(|state| state.name).collect())
+    }
+
+    #[test]
+    fn renames_states() {
+        let config = DmiRename {
+            renames: BTreeMap::from([("wall".to_string(), "wall-0".to_string())]),
+            substitutions: vec![
+                substitution(r"^wall(\d+)$", "wall-$1"),
+                substitution("-", "_"),
+            ],
+        };
+        config.verify_config().unwrap();
+        assert_eq!(
+            renamed(
+                &config,
+                &["wall", "wall12", "wall3", "girder", "move", "move"]
+            )
+            .unwrap(),
+            ["wall-0", "wall_12", "wall_3", "girder", "move", "move"]
+        );
+
+        let icon = config
+            .rename(&icon(&["wall1"]), &CancellationToken::new())
+            .unwrap();
+        assert_eq!(icon.states[0].frames, 2);
+        assert_eq!(icon.states[0].delay, Some(vec![1.0, 2.0]));
+    }
+
+    #[test]
+    fn problems() {
+        let clashing = DmiRename {
+            substitutions: vec![substitution(r"\d", "")],
+            ..Default::default()
+        };
+        assert!(renamed(&clashing, &["wall1", "wall2"]).is_err());
+        let quoted = DmiRename {
+            substitutions: vec![substitution("a", "\"")],
+            ..Default::default()
+        };
+        assert!(renamed(&quoted, &["a"]).is_err());
+
+        for config in [
+            DmiRename::default(),
+            DmiRename {
+                substitutions: vec![substitution("wall(", "")],
+                ..Default::default()
+            },
+            DmiRename {
+                renames: BTreeMap::from([("wall".to_string(), "wall\\".to_string())]),
+                ..Default::default()
+            },
+        ] {
+            assert!(config.verify_config().is_err(), "{config:?}");
+        }
+    }
+}
@@ -0,0 +1,380 @@
+use dmi::icon::Icon;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use regex_automata::meta::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::error::{ProcessorError, ProcessorResult, ProcessorWarning};
+use crate::operations::{
+    IconOperationConfig,
+    InputIcon,
+    NamedIcon,
+    OperationMode,
+    OutputImage,
+    ProcessorPayload,
+};
+use crate::util::file_safe_name;
+
+/// Splits one dmi in to several by the names of its states, for breaking up
+/// giant legacy dmis before they're put through a reconstruct. States are
+/// copied as they are, dirs, frames, delays and all
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct DmiSplit {
+    /// Dmis to split out, each written next to the input as
+    /// `<input>-<name>.dmi`. States go to the first group that matches them
+    pub groups: Vec<SplitGroup>,
+    /// Name of the dmi every state no group matches is written to. Unmatched
+    /// states are an error if unset, so nothing's lost without noticing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub unmatched: Option<String>,
+}
+
+/// One dmi split out of the input, and the states that go in it
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct SplitGroup {
+    /// Added to the input's name to name the dmi
+    pub name: String,
+    /// Globs matching the states that go in it, like `door_*`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub states: Vec<String>,
+    /// Regex matching the states that go in it, as well as any `states` match.
+    /// Has to match the whole name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub regex: Option<String>,
+}
+
+/// A group's patterns, compiled
+struct Matcher {
+    globs: GlobSet,
+    regex: Option<Regex>,
+}
+
+impl Matcher {
+    fn is_match(&self, state_name: &str) -> bool {
+        self.globs.is_match(state_name)
+            || self
+                .regex
+                .as_ref()
+                .is_some_and(|regex| regex.is_match(state_name))
+    }
+}
+
+impl SplitGroup {
+    /// Compiles the group's patterns, failing with every one that isn't valid
+    fn matcher(&self) -> ProcessorResult<Matcher> {
+        let mut problems = vec![];
+        let mut globs = GlobSetBuilder::new();
+        for pattern in &self.states {
+            match Glob::new(pattern) {
+                Ok(glob) => {
+                    globs.add(glob);
+                }
+                Err(err) => {
+                    problems.push(ProcessorError::ConfigError(format!(
+                        "`{pattern}` of group `{}` isn't a valid glob: {err}",
+                        self.name
+                    )));
+                }
+            }
+        }
+        // anchored, so a regex has to match the whole name
+        let regex = self.regex.as_ref().and_then(|pattern| {
+            Regex::new(&format!("^(?:{pattern})$"))
+                .map_err(|err| {
+                    problems.push(ProcessorError::ConfigError(format!(
+                        "regex of group `{}` isn't valid: {err}",
+                        self.name
+                    )));
+                })
+                .ok()
+        });
+        let globs = globs.build().map_err(|err| {
+            ProcessorError::ConfigError(format!(
+                "globs of group `{}` couldn't be built: {err}",
+                self.name
+            ))
+        })?;
+        ProcessorError::check_all(problems)?;
+        Ok(Matcher { globs, regex })
+    }
+}
+
+impl DmiSplit {
+    /// Splits `icon` in to one icon per group, named after it, leaving out
+    /// groups nothing matched. Unmatched states go in an icon named
+    /// `unmatched`, if it's set
+    /// # Errors
+    /// Errors if a pattern isn't valid, or any state is unmatched and
+    /// `unmatched` isn't set
+    pub fn split(
+        &self,
+        icon: &Icon,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<Vec<(String, Icon)>> {
+        let matchers = self
+            .groups
+            .iter()
+            .map(SplitGroup::matcher)
+            .collect::<ProcessorResult<Vec<Matcher>>>()?;
+        let empty = || {
+            Icon {
+                states: vec![],
+                ..icon.clone()
+            }
+        };
+        let mut split: Vec<Icon> = self.groups.iter().map(|_| empty()).collect();
+        let mut rest = empty();
+        for state in &icon.states {
+            cancel.check()?;
+            match matchers
+                .iter()
+                .position(|matcher| matcher.is_match(&state.name))
+            {
+                Some(group) => split[group].states.push(state.clone()),
+                None => rest.states.push(state.clone()),
+            }
+        }
+        if !rest.states.is_empty() && self.unmatched.is_none() {
+            let names: Vec<&str> = rest
+                .states
+                .iter()
+                .map(|state| state.name.as_str())
+                .collect();
+            return Err(ProcessorError::ConfigError(format!(
+                "no group matches {}. Set unmatched to keep them in a dmi of their own",
+                names.join(", ")
+            )));
+        }
+        let names = self
+            .groups
+            .iter()
+            .map(|group| group.name.clone())
+            .chain(self.unmatched.clone());
+        Ok(names
+            .zip(split.into_iter().chain([rest]))
+            .filter(|(_, icon)| !icon.states.is_empty())
+            .collect())
+    }
+}
+
+impl IconOperationConfig for DmiSplit {
+    #[tracing::instrument(skip(input, _mode))]
+    fn perform_operation(
+        &self,
+        input: &InputIcon,
+        _mode: OperationMode,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ProcessorPayload> {
+        let InputIcon::Dmi(icon) = input else {
+            return Err(ProcessorError::DMINotFound);
+        };
+        let split = self.split(icon, cancel)?;
+        let warnings = self
+            .groups
+            .iter()
+            .filter(|group| !split.iter().any(|(name, _)| *name == group.name))
+            .map(|group| {
+                ProcessorWarning::new(format!(
+                    "group `{}` doesn't match any state, so it isn't written",
+                    group.name
+                ))
+            })
+            .collect();
+        let icons = split
+            .into_iter()
+            .map(|(name, icon)| {
+                NamedIcon {
+                    path_hint: None,
+                    name_hint: Some(name),
+                    image: OutputImage::Dmi(icon),
+                }
+            })
+            .collect();
+        Ok(ProcessorPayload::MultipleNamed(icons).with_warnings(warnings))
+    }
+
+    fn verify_config(&self) -> ProcessorResult<()> {
+        let mut problems = vec![];
+        if self.groups.is_empty() {
+            problems.push(ProcessorError::ConfigError(
+                "groups is empty, it needs the dmis to split out".to_string(),
+            ));
+        }
+        let names = self
+            .groups
+            .iter()
+            .map(|group| group.name.as_str())
+            .chain(self.unmatched.as_deref());
+        for name in names.clone() {
+            if name.is_empty() || file_safe_name(name) != name {
+                problems.push(ProcessorError::ConfigError(format!(
+                    "`{name}` can't be put in a file name, only letters, numbers, - and _ can"
+                )));
+            }
+        }
+        let mut seen = vec![];
+        for name in names {
+            if seen.contains(&name) {
+                problems.push(ProcessorError::ConfigError(format!(
+                    "more than one dmi is named `{name}`"
+                )));
+            }
+            seen.push(name);
+        }
+        for group in &self.groups {
+            if group.states.is_empty() && group.regex.is_none() {
+                problems.push(ProcessorError::ConfigError(format!(
+                    "group `{}` needs states or a regex to match",
+                    group.name
+                )));
+            }
+            if let Err(err) = group.matcher() {
+                problems.push(err);
+            }
+        }
+        ProcessorError::check_all(problems)
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        match input {
+            InputIcon::Dmi(_) => vec![],
+            InputIcon::DynamicImage(_) | InputIcon::Aseprite(_) => {
+                vec![ProcessorError::DMINotFound]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use dmi::icon::IconState;
+    use image::DynamicImage;
+
+    use super::*;
+
+    fn icon(names: &[&str]) -> Icon {
+        Icon {
+            width: 32,
+            height: 32,
+            states: names
+                .iter()
+                .map(|name| {
+                    IconState {
+                        name: (*name).to_string(),
+                        dirs: 4,
+                        images: vec![DynamicImage::new_rgba8(32, 32); 4],
+                        ..Default::default()
+                    }
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn group(name: &str, states: &[&str], regex: Option<&str>) -> SplitGroup {
+        SplitGroup {
+            name: name.to_string(),
+            states: states.iter().map(|state| (*state).to_string()).collect(),
+            regex: regex.map(str::to_string),
+        }
+    }
+
+    fn split_names(config: &DmiSplit, input: &Icon) -> ProcessorResult<Vec<(String, Vec<String>)>> {
+        Ok(config
+            .split(input, &CancellationToken::new())?
+            .into_iter()
+            .map(|(name, icon)| {
+                let states = icon.states.into_iter().map(|state| state.name).collect();
+                (name, states)
+            })
+            .collect())
+    }
+
+    #[test]
+    fn splits_by_pattern() {
+        let input = icon(&["door_open", "door_closed", "light", "light_off", "sign"]);
+        let config = DmiSplit {
+            groups: vec![
+                group("doors", &["door_*"], None),
+                group("lights", &[], Some("light(_\\w+)?")),
+                group("unused", &["nothing"], None),
+                group("also_doors", &["door_open"], None),
+            ],
+            unmatched: Some("misc".to_string()),
+        };
+        config.verify_config().unwrap();
+        let split = split_names(&config, &input).unwrap();
+        let expected = [
+            ("doors", vec!["door_open", "door_closed"]),
+            ("lights", vec!["light", "light_off"]),
+            ("misc", vec!["sign"]),
+        ]
+        .map(|(name, states)| {
+            (
+                name.to_string(),
+                states.into_iter().map(str::to_string).collect(),
+            )
+        });
+        assert_eq!(split, expected);
+
+        // regexes have to match the whole name
+        let config = DmiSplit {
+            groups: vec![group("lights", &[], Some("light"))],
+            unmatched: Some("misc".to_string()),
+        };
+        assert_eq!(split_names(&config, &input).unwrap()[0].1, ["light"]);
+    }
+
+    #[test]
+    fn keeps_state_metadata() {
+        let config = DmiSplit {
+            groups: vec![group("all", &["*"], None)],
+            unmatched: None,
+        };
+        let split = config
+            .split(&icon(&["a"]), &CancellationToken::new())
+            .unwrap();
+        assert_eq!(split[0].1.states[0].dirs, 4);
+        assert_eq!(split[0].1.states[0].images.len(), 4);
+    }
+
+    #[test]
+    fn problems() {
+        let input = icon(&["door", "sign"]);
+        let config = DmiSplit {
+            groups: vec![group("doors", &["door"], None)],
+            unmatched: None,
+        };
+        assert!(split_names(&config, &input).is_err());
+
+        for config in [
+            DmiSplit::default(),
+            DmiSplit {
+                groups: vec![group("doors", &[], None)],
+                unmatched: None,
+            },
+            DmiSplit {
+                groups: vec![group("doors", &["door["], None)],
+                unmatched: None,
+            },
+            DmiSplit {
+                groups: vec![group("doors", &[], Some("door("))],
+                unmatched: None,
+            },
+            DmiSplit {
+                groups: vec![group("doors/open", &["door"], None)],
+                unmatched: None,
+            },
+            DmiSplit {
+                groups: vec![group("doors", &["door"], None)],
+                unmatched: Some("doors".to_string()),
+            },
+        ] {
+            assert!(config.verify_config().is_err(), "{config:?}");
+        }
+    }
+}
@@ -0,0 +1,409 @@
+use dmi::icon::{Icon, IconState};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::cutters::bitmask_slice::{
+    BitmaskSlice,
+    CardinalSetOutput,
+    SIZE_OF_CARDINALS,
+    SIZE_OF_DIAGONALS,
+};
+use crate::operations::error::{ProcessorError, ProcessorResult, ProcessorWarning};
+use crate::operations::{
+    IconOperationConfig,
+    InputIcon,
+    OperationMode,
+    OutputText,
+    ProcessorPayload,
+};
+use crate::util::adjacency::Adjacency;
+
+/// Checks a dmi has every state a bitmask cutter config makes, with the same
+/// dirs and frames, for auditing hand maintained icons before they're
+/// converted. Writes a report next to the input as `<input>.validation.toml`
+/// rather than failing, so everything wrong is listed at once
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct DmiValidate {
+    /// Cutter config to check against, relative to this config. Its
+    /// operation has to be built on a bitmask slice
+    pub against: String,
+    /// Frames each junction state should have, as many as the cutter's input
+    /// has. Its `animation.frames` if unset, or 1 if it isn't animated.
+    /// Frames aren't checked if it's animated without either
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub frames: Option<u32>,
+    /// The bitmask slice of the config at `against`, filled in by
+    /// `IconOperation::load_side_files`
+    #[serde(skip)]
+    pub against_config: Option<BitmaskSlice>,
+}
+
+/// A state the cutter makes, and what it should look like
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ExpectedState {
+    pub name: String,
+    pub movement: bool,
+    pub dirs: u8,
+    /// Frames it has before any are deduped, if it's known
+    pub frames: Option<u32>,
+}
+
+/// A state with dirs or frames other than the cutter gives it
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct Mismatch {
+    pub state: String,
+    pub dirs: u8,
+    pub expected_dirs: u8,
+    pub frames: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected_frames: Option<u32>,
+}
+
+/// Everything about a dmi that doesn't match its cutter config. Movement
+/// states have ` (movement)` after their names
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize)]
+pub struct ValidationReport {
+    /// States the cutter makes that the dmi doesn't have
+    pub missing: Vec<String>,
+    /// States the dmi has that the cutter doesn't make
+    pub extra: Vec<String>,
+    pub mismatched: Vec<Mismatch>,
+}
+
+impl ValidationReport {
+    #[must_use]
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+fn describe(name: &str, movement: bool) -> String {
+    if movement {
+        format!("{name} (movement)")
+    } else {
+        name.to_string()
+    }
+}
+
+impl ExpectedState {
+    /// Whether `state` has the dirs and frames expected of it. Runs of the
+    /// same frame are merged when the cutter dedupes, so it can have fewer
+    fn fits(&self, state: &IconState, cutter: &BitmaskSlice) -> bool {
+        // collapsed rotations leave only the south dir
+        let dirs_fit = state.dirs == self.dirs
+            || (cutter.produce_dirs && cutter.collapse_rotations && state.dirs == 1);
+        let dedupes = cutter
+            .animation
+            .as_ref()
+            .is_some_and(|animation| animation.dedupe != Some(false));
+        let frames_fit = self.frames.is_none_or(|frames| {
+            state.frames == frames || (dedupes && (1..frames).contains(&state.frames))
+        });
+        dirs_fit && frames_fit
+    }
+}
+
+impl DmiValidate {
+    /// Frames each junction state of `cutter` has, if it can be worked out
+    fn frames(&self, cutter: &BitmaskSlice) -> Option<u32> {
+        self.frames.or(match &cutter.animation {
+            Some(animation) => animation.frames,
+            None => Some(1),
+        })
+    }
+
+    /// Every state `cutter` makes in its dmi, in the order it makes them
+    #[must_use]
+    pub fn expected_states(&self, cutter: &BitmaskSlice) -> Vec<ExpectedState> {
+        let num_frames = self.frames(cutter);
+        let dirs = if cutter.produce_dirs { 4 } else { 1 };
+        let state = |tag: Option<&str>, adjacency: Adjacency| {
+            ExpectedState {
+                name: cutter.state_name(tag, adjacency),
+                movement: false,
+                dirs,
+                frames: num_frames.map(|frames| cutter.frame_range(adjacency, frames).len() as u32),
+            }
+        };
+        let junctions = |possible_states: usize| {
+            (0..possible_states)
+                .filter_map(|bits| Adjacency::from_bits(bits as u8))
+                .filter(|adjacency| cutter.keeps_junction(*adjacency))
+                .filter(|adjacency| cutter.emits_state(*adjacency))
+        };
+        let possible_states = if cutter.smooth_diagonally {
+            SIZE_OF_DIAGONALS
+        } else {
+            SIZE_OF_CARDINALS
+        };
+
+        let mut expected: Vec<ExpectedState> = junctions(possible_states)
+            .map(|adjacency| state(None, adjacency))
+            .collect();
+        if cutter.movement_states {
+            let movement: Vec<ExpectedState> = expected
+                .iter()
+                .map(|state| {
+                    ExpectedState {
+                        movement: true,
+                        ..state.clone()
+                    }
+                })
+                .collect();
+            expected.extend(movement);
+        }
+        if cutter.cardinal_set == Some(CardinalSetOutput::SameIcon) {
+            expected.extend(
+                junctions(SIZE_OF_CARDINALS).map(|adjacency| state(Some("cardinal"), adjacency)),
+            );
+        }
+        if let Some(map_icon) = &cutter.map_icon {
+            expected.push(ExpectedState {
+                name: map_icon.icon_state_name.clone(),
+                movement: false,
+                dirs: 1,
+                frames: Some(1),
+            });
+        }
+        if let Some(diagonal_walls) = &cutter.diagonal_walls {
+            expected.extend(
+                cutter
+                    .diagonal_wall_junctions()
+                    .into_iter()
+                    .map(|(junction, _)| state(Some(&diagonal_walls.name), junction)),
+            );
+        }
+        for size_override in cutter.size_overrides.iter().flatten() {
+            expected.extend(
+                size_override
+                    .junctions
+                    .iter()
+                    .filter_map(|bits| Adjacency::from_bits(*bits))
+                    .map(|adjacency| state(Some(&size_override.name), adjacency)),
+            );
+        }
+        expected
+    }
+
+    /// Checks `icon` against `cutter`
+    /// # Errors
+    /// Errors if cancelled
+    pub fn validate(
+        &self,
+        icon: &Icon,
+        cutter: &BitmaskSlice,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ValidationReport> {
+        let expected = self.expected_states(cutter);
+        let mut report = ValidationReport::default();
+        for expected_state in &expected {
+            cancel.check()?;
+            let Some(state) = icon.states.iter().find(|state| {
+                state.name == expected_state.name && state.movement == expected_state.movement
+            }) else {
+                report
+                    .missing
+                    .push(describe(&expected_state.name, expected_state.movement));
+                continue;
+            };
+            if !expected_state.fits(state, cutter) {
+                report.mismatched.push(Mismatch {
+                    state: describe(&state.name, state.movement),
+                    dirs: state.dirs,
+                    expected_dirs: expected_state.dirs,
+                    frames: state.frames,
+                    expected_frames: expected_state.frames,
+                });
+            }
+        }
+        report.extra = icon
+            .states
+            .iter()
+            .filter(|state| {
+                !expected.iter().any(|expected_state| {
+                    expected_state.name == state.name && expected_state.movement == state.movement
+                })
+            })
+            .map(|state| describe(&state.name, state.movement))
+            .collect();
+        Ok(report)
+    }
+}
+
+impl IconOperationConfig for DmiValidate {
+    #[tracing::instrument(skip(input, _mode))]
+    fn perform_operation(
+        &self,
+        input: &InputIcon,
+        _mode: OperationMode,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ProcessorPayload> {
+        let InputIcon::Dmi(icon) = input else {
+            return Err(ProcessorError::DMINotFound);
+        };
+        let Some(cutter) = &self.against_config else {
+            return Err(ProcessorError::ConfigError(
+                "the config of `against` hasn't been loaded".to_string(),
+            ));
+        };
+        let report = self.validate(icon, cutter, cancel)?;
+        let mut warnings = vec![];
+        if !report.missing.is_empty() {
+            warnings.push(ProcessorWarning::new(format!(
+                "missing {} states of `{}`: {}",
+                report.missing.len(),
+                self.against,
+                report.missing.join(", ")
+            )));
+        }
+        for mismatch in &report.mismatched {
+            warnings.push(ProcessorWarning::new(format!(
+                "`{}` has {} dirs and {} frames, which doesn't fit `{}`",
+                mismatch.state, mismatch.dirs, mismatch.frames, self.against
+            )));
+        }
+        if !report.extra.is_empty() {
+            warnings.push(ProcessorWarning::new(format!(
+                "{} states aren't made by `{}`: {}",
+                report.extra.len(),
+                self.against,
+                report.extra.join(", ")
+            )));
+        }
+        let text = toml::to_string(&report).expect("validation reports are always valid toml");
+        // nothing but the report is written
+        let payload = ProcessorPayload::ConfigWrapped(
+            Box::new(ProcessorPayload::MultipleNamed(vec![])),
+            Box::new(OutputText::ValidationReport(text)),
+        );
+        Ok(payload.with_warnings(warnings))
+    }
+
+    fn verify_config(&self) -> ProcessorResult<()> {
+        let mut problems = vec![];
+        if self.against.is_empty() {
+            problems.push(ProcessorError::ConfigError(
+                "against is empty, it needs the cutter config to check against".to_string(),
+            ));
+        }
+        if self.frames == Some(0) {
+            problems.push(ProcessorError::ConfigError(
+                "frames is 0, states have at least one".to_string(),
+            ));
+        }
+        ProcessorError::check_all(problems)
+    }
+
+    fn input_problems(&self, input: &InputIcon) -> Vec<ProcessorError> {
+        match input {
+            InputIcon::Dmi(_) => vec![],
+            InputIcon::DynamicImage(_) | InputIcon::Aseprite(_) => {
+                vec![ProcessorError::DMINotFound]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::DynamicImage;
+
+    use super::*;
+    use crate::config::blocks::cutters::Animation;
+
+    fn state(name: &str, dirs: u8, frames: u32) -> IconState {
+        IconState {
+            name: name.to_string(),
+            dirs,
+            frames,
+            images: vec![DynamicImage::new_rgba8(32, 32); usize::from(dirs) * frames as usize],
+            ..Default::default()
+        }
+    }
+
+    fn cutter() -> BitmaskSlice {
+        BitmaskSlice {
+            output_name: Some("wall".to_string()),
+            produce_dirs: true,
+            ..Default::default()
+        }
+    }
+
+    fn validated(config: &DmiValidate, states: Vec<IconState>) -> ValidationReport {
+        let icon = Icon {
+            width: 32,
+            height: 32,
+            states,
+            ..Default::default()
+        };
+        config
+            .validate(&icon, &cutter(), &CancellationToken::new())
+            .unwrap()
+    }
+
+    #[test]
+    fn expects_every_junction() {
+        let config = DmiValidate::default();
+        let expected = config.expected_states(&cutter());
+        assert_eq!(expected.len(), SIZE_OF_CARDINALS);
+        assert!(expected.iter().all(|state| state.dirs == 4));
+        assert_eq!(expected[0].frames, Some(1));
+
+        let diagonal = BitmaskSlice {
+            smooth_diagonally: true,
+            movement_states: true,
+            ..cutter()
+        };
+        let expected = config.expected_states(&diagonal);
+        assert_eq!(expected.len(), 47 * 2);
+        assert!(expected[47].movement);
+    }
+
+    #[test]
+    fn reports_problems() {
+        let config = DmiValidate::default();
+        let mut states: Vec<IconState> = config
+            .expected_states(&cutter())
+            .into_iter()
+            .map(|expected| state(&expected.name, 4, 1))
+            .collect();
+        assert!(validated(&config, states.clone()).is_clean());
+
+        states.remove(3);
+        states[0].dirs = 1;
+        states.push(state("wall-girder", 1, 1));
+        let report = validated(&config, states);
+        assert_eq!(
+            report.missing,
+            [cutter().state_name(None, Adjacency::from_bits(3).unwrap())]
+        );
+        assert_eq!(report.extra, ["wall-girder"]);
+        assert_eq!(report.mismatched.len(), 1);
+        assert_eq!(report.mismatched[0].expected_dirs, 4);
+    }
+
+    #[test]
+    fn deduped_frames_fit() {
+        let animated = BitmaskSlice {
+            animation: Some(Animation {
+                delays: vec![1.0],
+                ..Default::default()
+            }),
+            ..cutter()
+        };
+        let config = DmiValidate {
+            frames: Some(4),
+            ..Default::default()
+        };
+        let expected = &config.expected_states(&animated)[0];
+        assert!(expected.fits(&state(&expected.name, 4, 4), &animated));
+        assert!(expected.fits(&state(&expected.name, 4, 2), &animated));
+        assert!(!expected.fits(&state(&expected.name, 4, 5), &animated));
+
+        // frames aren't known without either
+        let expected = &DmiValidate::default().expected_states(&animated)[0];
+        assert_eq!(expected.frames, None);
+    }
+}
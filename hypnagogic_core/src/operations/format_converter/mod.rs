@@ -1,2 +1,10 @@
 pub mod bitmask_to_precut;
+pub mod dmi_aseprite;
+pub mod dmi_export;
+pub mod dmi_import;
+pub mod dmi_merge;
+pub mod dmi_optimize;
+pub mod dmi_rename;
+pub mod dmi_split;
+pub mod dmi_validate;
 pub mod error;
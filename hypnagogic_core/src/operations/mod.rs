@@ -2,24 +2,53 @@ use std::fmt::Debug;
 use std::io::{BufRead, Seek};
 use std::path::{Path, PathBuf};
 
+use cutters::bitmask_corner_overlays::BitmaskCornerOverlays;
 use cutters::bitmask_dir_visibility::BitmaskDirectionalVis;
+use cutters::bitmask_lattice::BitmaskLattice;
+use cutters::bitmask_pipes::BitmaskPipes;
 use cutters::bitmask_slice::BitmaskSlice;
+use cutters::bitmask_slice_greyscale::BitmaskSliceGreyscale;
+use cutters::bitmask_slice_groups::BitmaskSliceGroups;
+use cutters::bitmask_texture_mask::BitmaskTextureMask;
+use cutters::bitmask_wall_tops::BitmaskWallTops;
 use cutters::bitmask_windows::BitmaskWindows;
+use cutters::directional_rotation::DirectionalRotation;
+use cutters::glyph_sheet::GlyphSheet;
+use cutters::grid_slice::GridSlice;
+use cutters::turf_edges::TurfEdges;
 use dmi::error::DmiError;
 use dmi::icon::Icon;
 use enum_dispatch::enum_dispatch;
 use format_converter::bitmask_to_precut::BitmaskSliceReconstruct;
-use image::{DynamicImage, ImageError, ImageFormat};
+use format_converter::dmi_aseprite::DmiAseprite;
+use format_converter::dmi_export::DmiExport;
+use format_converter::dmi_import::DmiImport;
+use format_converter::dmi_merge::DmiMerge;
+use format_converter::dmi_optimize::DmiOptimize;
+use format_converter::dmi_rename::DmiRename;
+use format_converter::dmi_split::DmiSplit;
+use format_converter::dmi_validate::DmiValidate;
+use image::{DynamicImage, GenericImageView, ImageError, ImageFormat};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use strum::{EnumDiscriminants, EnumIter, EnumString, IntoStaticStr, VariantNames};
 use thiserror::Error;
 use tracing::debug;
 use user_error::UFE;
 
-use crate::operations::error::ProcessorResult;
+use crate::operations::cancellation::CancellationToken;
+use crate::operations::error::{ProcessorError, ProcessorResult, ProcessorWarning};
+use crate::util::aseprite::{self, AsepriteError};
+use crate::util::state_inventory::StateOrigin;
 
+pub mod animation_preview;
+pub mod cancellation;
 pub mod cutters;
 pub mod error;
 pub mod format_converter;
+pub mod post_process;
+pub mod recolors;
+pub mod side_files;
 
 #[derive(Debug, Error)]
 pub enum InputError {
@@ -29,6 +58,8 @@ pub enum InputError {
     DynamicRead(#[from] ImageError),
     #[error("DMI Parsing Error")]
     DmiRead(#[from] DmiError),
+    #[error("Aseprite Parsing Error")]
+    AsepriteRead(#[from] AsepriteError),
 }
 
 impl UFE for InputError {
@@ -43,6 +74,7 @@ impl UFE for InputError {
             }
             InputError::DynamicRead(error) => Some(vec![format!("{}", error)]),
             InputError::DmiRead(error) => Some(vec![format!("{}", error)]),
+            InputError::AsepriteRead(error) => Some(vec![format!("{}", error)]),
         }
     }
 
@@ -51,7 +83,9 @@ impl UFE for InputError {
             InputError::UnsupportedFormat(_) => {
                 Some("Are you using a valid image format?".to_string())
             }
-            InputError::DynamicRead(_) | InputError::DmiRead(_) => None,
+            InputError::DynamicRead(_) | InputError::DmiRead(_) | InputError::AsepriteRead(_) => {
+                None
+            }
         }
     }
 }
@@ -60,6 +94,8 @@ impl UFE for InputError {
 pub enum InputIcon {
     DynamicImage(DynamicImage),
     Dmi(Icon),
+    /// An aseprite file, read in to a dmi
+    Aseprite(Icon),
 }
 
 impl InputIcon {
@@ -70,9 +106,20 @@ impl InputIcon {
         match extension {
             "png" => Ok(Self::DynamicImage(image::load(reader, ImageFormat::Png)?)),
             "dmi" => Ok(Self::Dmi(Icon::load(reader)?)),
+            "aseprite" | "ase" => Ok(Self::Aseprite(aseprite::read(reader)?)),
             _ => Err(InputError::UnsupportedFormat(extension.to_string())),
         }
     }
+
+    /// Whether the input is an image with nothing in it, every pixel fully
+    /// transparent
+    #[must_use]
+    pub fn is_blank(&self) -> bool {
+        match self {
+            Self::DynamicImage(image) => image.pixels().all(|(_, _, pixel)| pixel[3] == 0),
+            Self::Dmi(_) | Self::Aseprite(_) => false,
+        }
+    }
 }
 
 /// An output image, with a possible path hint and name hint.
@@ -163,6 +210,8 @@ pub enum OutputError {
     DynamicWrite(#[from] ImageError),
     #[error("DMI Writing Error")]
     DmiWrite(#[from] DmiError),
+    #[error("Preview Writing Error")]
+    PreviewWrite(#[from] png::EncodingError),
 }
 
 impl UFE for OutputError {
@@ -174,12 +223,15 @@ impl UFE for OutputError {
         match self {
             OutputError::DynamicWrite(error) => Some(vec![format!("{}", error)]),
             OutputError::DmiWrite(error) => Some(vec![format!("{}", error)]),
+            OutputError::PreviewWrite(error) => Some(vec![format!("{}", error)]),
         }
     }
 
     fn helptext(&self) -> Option<String> {
         match self {
-            OutputError::DynamicWrite(_) | OutputError::DmiWrite(_) => None,
+            OutputError::DynamicWrite(_)
+            | OutputError::DmiWrite(_)
+            | OutputError::PreviewWrite(_) => None,
         }
     }
 }
@@ -207,14 +259,24 @@ impl Output {
 pub enum OutputImage {
     Png(DynamicImage),
     Dmi(Icon),
+    /// An already encoded animated png
+    Apng(Vec<u8>),
+    /// An already encoded gif
+    Gif(Vec<u8>),
+    /// An already encoded dmi, for when how it's encoded matters
+    EncodedDmi(Vec<u8>),
+    /// An already encoded aseprite file
+    Aseprite(Vec<u8>),
 }
 
 impl OutputImage {
     #[must_use]
     pub const fn extension(&self) -> &'static str {
         match self {
-            OutputImage::Png(_) => "png",
-            OutputImage::Dmi(_) => "dmi",
+            OutputImage::Png(_) | OutputImage::Apng(_) => "png",
+            OutputImage::Dmi(_) | OutputImage::EncodedDmi(_) => "dmi",
+            OutputImage::Gif(_) => "gif",
+            OutputImage::Aseprite(_) => "aseprite",
         }
     }
 }
@@ -224,6 +286,12 @@ impl OutputImage {
 pub enum OutputText {
     PngConfig(String),
     DmiConfig(String),
+    /// DM code or notes meant to sit alongside the generated icon
+    DmCode(String),
+    /// What's wrong with an input, as checked by a validating operation
+    ValidationReport(String),
+    /// Json describing where everything is on an exported sheet
+    SheetManifest(String),
 }
 
 impl OutputText {
@@ -232,6 +300,9 @@ impl OutputText {
         match self {
             OutputText::PngConfig(_) => "png.toml",
             OutputText::DmiConfig(_) => "dmi.toml",
+            OutputText::DmCode(_) => "dm",
+            OutputText::ValidationReport(_) => "validation.toml",
+            OutputText::SheetManifest(_) => "json",
         }
     }
 }
@@ -250,6 +321,9 @@ pub enum ProcessorPayload {
     MultipleNamed(Vec<NamedIcon>),
     /// Payload of some sort with a config to produce inline with it
     ConfigWrapped(Box<ProcessorPayload>, Box<OutputText>),
+    /// Payload along with problems producing it that weren't worth failing
+    /// over. See [ProcessorWarning] for more info.
+    Warned(Box<ProcessorPayload>, Vec<ProcessorWarning>),
 }
 
 impl ProcessorPayload {
@@ -272,6 +346,79 @@ impl ProcessorPayload {
     pub fn wrap_dmi_config(payload: ProcessorPayload, text: String) -> Self {
         Self::ConfigWrapped(Box::new(payload), Box::new(OutputText::DmiConfig(text)))
     }
+
+    #[must_use]
+    pub fn wrap_dm_code(payload: ProcessorPayload, text: String) -> Self {
+        Self::ConfigWrapped(Box::new(payload), Box::new(OutputText::DmCode(text)))
+    }
+
+    /// Adds extra named icons to the payload, turning it into
+    /// `MultipleNamed` if needed
+    #[must_use]
+    pub fn with_named(self, mut extra: Vec<NamedIcon>) -> Self {
+        if extra.is_empty() {
+            return self;
+        }
+        match self {
+            Self::Single(image) => {
+                extra.insert(
+                    0,
+                    NamedIcon {
+                        path_hint: None,
+                        name_hint: None,
+                        image: *image,
+                    },
+                );
+                Self::MultipleNamed(extra)
+            }
+            Self::SingleNamed(named) => {
+                extra.insert(0, *named);
+                Self::MultipleNamed(extra)
+            }
+            Self::MultipleNamed(mut icons) => {
+                icons.extend(extra);
+                Self::MultipleNamed(icons)
+            }
+            Self::ConfigWrapped(payload, text) => {
+                Self::ConfigWrapped(Box::new(payload.with_named(extra)), text)
+            }
+            Self::Warned(payload, warnings) => {
+                Self::Warned(Box::new(payload.with_named(extra)), warnings)
+            }
+        }
+    }
+
+    /// Attaches warnings to the payload, turning it into `Warned` if needed
+    #[must_use]
+    pub fn with_warnings(self, extra: Vec<ProcessorWarning>) -> Self {
+        if extra.is_empty() {
+            return self;
+        }
+        match self {
+            Self::Warned(payload, mut warnings) => {
+                warnings.extend(extra);
+                Self::Warned(payload, warnings)
+            }
+            payload => Self::Warned(Box::new(payload), extra),
+        }
+    }
+
+    /// Splits every warning off the payload, leaving only what it outputs
+    #[must_use]
+    pub fn take_warnings(self) -> (Self, Vec<ProcessorWarning>) {
+        match self {
+            Self::Warned(payload, warnings) => {
+                let (payload, mut inner) = payload.take_warnings();
+                inner.extend(warnings);
+                (payload, inner)
+            }
+            Self::ConfigWrapped(payload, text) => {
+                let (payload, warnings) = payload.take_warnings();
+                (Self::ConfigWrapped(Box::new(payload), text), warnings)
+            }
+            payload => (payload, vec![]),
+        }
+    }
 }
 
 /// Possible generic modes of operation for an icon operation
@@ -292,22 +439,48 @@ pub trait IconOperationConfig {
     /// Should generally not be called directly, preferring to call via
     /// `do_operation`
     ///
+    /// Implementors should periodically check `cancel` during any long running
+    /// work, returning early if it has been cancelled.
+    ///
     /// # Errors
     ///
     /// Possible errors vary based on implementor; should be some kind of
-    /// `ProcessorError::ImageError`
+    /// `ProcessorError::ImageError`, or `ProcessorError::Cancelled`
     fn perform_operation(
         &self,
         input: &InputIcon,
         mode: OperationMode,
+        cancel: &CancellationToken,
     ) -> ProcessorResult<ProcessorPayload>;
 
     /// Verifies that current config values are valid within the context of the
-    /// operation to be performed # Errors
+    /// operation to be performed
+    /// # Errors
     /// Possible errors vary based on implementor; should be some kind of
     /// `ProcessorError::InvalidConfig`
     fn verify_config(&self) -> ProcessorResult<()>;
 
+    /// Everything about `input` that doesn't fit the config, like a sheet too
+    /// small for its positions, so they can be reported along with anything
+    /// wrong with the config itself rather than one at a time
+    fn input_problems(&self, _input: &InputIcon) -> Vec<ProcessorError> {
+        vec![]
+    }
+
+    /// Problems with the config that are worth pointing out but don't stop it
+    /// working, like settings that never end up used. `do_operation` returns
+    /// them with its payload
+    fn config_warnings(&self) -> Vec<ProcessorWarning> {
+        vec![]
+    }
+
+    /// Describes what produced the output state named `state_name`, for state
+    /// inventories. Operations that don't track this can leave it as
+    /// `StateOrigin::Unknown`
+    fn state_origin(&self, _state_name: &str) -> StateOrigin {
+        StateOrigin::Unknown
+    }
+
     /// Helper function to call `verify_config` and `perform_operation` in
     /// sequence.
     ///
@@ -316,24 +489,193 @@ pub trait IconOperationConfig {
     /// # Errors
     /// Possible errors vary based on implementor
     /// Error type is potentially a `ProcessorError::InvalidConfig` from a call
-    /// to `verify_config`, or a processor error from a call to
-    /// `perform_operation`
+    /// to `verify_config`, a `ProcessorError::Multiple` if it and
+    /// `input_problems` found several problems, or a processor error from a
+    /// call to `perform_operation`
     fn do_operation(
         &self,
         input: &InputIcon,
         mode: OperationMode,
     ) -> ProcessorResult<ProcessorPayload> {
-        self.verify_config()?;
-        self.perform_operation(input, mode)
+        self.do_cancellable_operation(input, mode, &CancellationToken::default())
+    }
+
+    /// Same as `do_operation`, but can be aborted part way through by
+    /// cancelling `cancel` from another thread.
+    /// # Errors
+    /// Same as `do_operation`, with the addition of
+    /// `ProcessorError::Cancelled` if `cancel` is cancelled before the
+    /// operation finishes
+    fn do_cancellable_operation(
+        &self,
+        input: &InputIcon,
+        mode: OperationMode,
+        cancel: &CancellationToken,
+    ) -> ProcessorResult<ProcessorPayload> {
+        let mut problems: Vec<ProcessorError> = self.verify_config().err().into_iter().collect();
+        problems.extend(self.input_problems(input));
+        ProcessorError::check_all(problems)?;
+        cancel.check()?;
+        let mut warnings = self.config_warnings();
+        if input.is_blank() {
+            warnings.push(ProcessorWarning::new(
+                "The input is blank, every pixel of it is transparent",
+            ));
+        }
+        let payload = self.perform_operation(input, mode, cancel)?;
+        Ok(payload.with_warnings(warnings))
     }
 }
 
 #[enum_dispatch(IconOperationConfig)]
-#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug, JsonSchema, EnumDiscriminants)]
 #[serde(tag = "mode")]
+#[strum_discriminants(
+    name(OperationKind),
+    doc = "Which operation an [`IconOperation`] is, named as its `mode` is in configs",
+    derive(EnumIter, EnumString, IntoStaticStr, VariantNames)
+)]
 pub enum IconOperation {
     BitmaskSlice,
+    BitmaskSliceGroups,
+    BitmaskSliceGreyscale,
     BitmaskDirectionalVis,
     BitmaskWindows,
     BitmaskSliceReconstruct,
+    BitmaskPipes,
+    BitmaskLattice,
+    BitmaskCornerOverlays,
+    TurfEdges,
+    DirectionalRotation,
+    BitmaskTextureMask,
+    BitmaskWallTops,
+    GridSlice,
+    GlyphSheet,
+    DmiMerge,
+    DmiSplit,
+    DmiRename,
+    DmiOptimize,
+    DmiValidate,
+    DmiExport,
+    DmiImport,
+    DmiAseprite,
+}
+
+impl IconOperation {
+    /// Every operation's name, as used for `mode` in configs
+    pub const MODES: &'static [&'static str] = OperationKind::VARIANTS;
+
+    /// The bitmask slice settings of operations built on a bitmask slice
+    #[must_use]
+    pub fn bitmask_slice_mut(&mut self) -> Option<&mut BitmaskSlice> {
+        match self {
+            IconOperation::BitmaskSlice(config) => Some(config),
+            IconOperation::BitmaskSliceGroups(config) => Some(&mut config.bitmask_slice_config),
+            IconOperation::BitmaskSliceGreyscale(config) => Some(&mut config.bitmask_slice_config),
+            IconOperation::BitmaskDirectionalVis(config) => Some(&mut config.bitmask_slice_config),
+            IconOperation::BitmaskTextureMask(config) => Some(&mut config.bitmask_slice_config),
+            IconOperation::BitmaskWallTops(config) => Some(&mut config.bitmask_slice_config),
+            IconOperation::BitmaskWindows(_)
+            | IconOperation::BitmaskSliceReconstruct(_)
+            | IconOperation::BitmaskPipes(_)
+            | IconOperation::BitmaskLattice(_)
+            | IconOperation::BitmaskCornerOverlays(_)
+            | IconOperation::TurfEdges(_)
+            | IconOperation::DirectionalRotation(_)
+            | IconOperation::GridSlice(_)
+            | IconOperation::GlyphSheet(_)
+            | IconOperation::DmiMerge(_)
+            | IconOperation::DmiSplit(_)
+            | IconOperation::DmiRename(_)
+            | IconOperation::DmiOptimize(_)
+            | IconOperation::DmiValidate(_)
+            | IconOperation::DmiExport(_)
+            | IconOperation::DmiImport(_)
+            | IconOperation::DmiAseprite(_) => None,
+        }
+    }
+
+    /// The operation called `mode` with its default settings. `None` if there's
+    /// no such operation, or it has settings that can't sensibly be defaulted
+    #[must_use]
+    pub fn default_for(mode: &str) -> Option<Self> {
+        let kind: OperationKind = mode.parse().ok()?;
+        match kind {
+            OperationKind::BitmaskSlice => Some(BitmaskSlice::default().into()),
+            OperationKind::BitmaskDirectionalVis => Some(BitmaskDirectionalVis::default().into()),
+            OperationKind::BitmaskWindows => Some(BitmaskWindows::default().into()),
+            OperationKind::BitmaskSliceReconstruct => {
+                Some(BitmaskSliceReconstruct::default().into())
+            }
+            OperationKind::BitmaskPipes => Some(BitmaskPipes::default().into()),
+            OperationKind::BitmaskLattice => Some(BitmaskLattice::default().into()),
+            OperationKind::BitmaskCornerOverlays => Some(BitmaskCornerOverlays::default().into()),
+            OperationKind::TurfEdges => Some(TurfEdges::default().into()),
+            OperationKind::DirectionalRotation => Some(DirectionalRotation::default().into()),
+            OperationKind::GridSlice => Some(GridSlice::default().into()),
+            OperationKind::GlyphSheet => Some(GlyphSheet::default().into()),
+            OperationKind::DmiOptimize => Some(DmiOptimize::default().into()),
+            OperationKind::DmiExport => Some(DmiExport::default().into()),
+            OperationKind::DmiAseprite => Some(DmiAseprite::default().into()),
+            // each needs settings only a config can give, like the files
+            // it reads
+            OperationKind::BitmaskSliceGroups
+            | OperationKind::BitmaskSliceGreyscale
+            | OperationKind::BitmaskTextureMask
+            | OperationKind::BitmaskWallTops
+            | OperationKind::DmiMerge
+            | OperationKind::DmiSplit
+            | OperationKind::DmiRename
+            | OperationKind::DmiValidate
+            | OperationKind::DmiImport => None,
+        }
+    }
+}
+
+/// The icon of an operation's output that should be a single dmi, for tests
+#[cfg(test)]
+pub(crate) fn single_dmi(payload: ProcessorPayload) -> Icon {
+    let ProcessorPayload::Single(output) = payload else {
+        panic!("Expected a single icon");
+    };
+    let OutputImage::Dmi(icon) = *output else {
+        panic!("Expected a dmi");
+    };
+    icon
+}
+
+/// An input sheet for tests, `grid` tiles of `tile` pixels across and down.
+/// `paint` colors each pixel given the index of its tile, counting along
+/// rows, and where it is in the tile
+#[cfg(test)]
+pub(crate) fn tile_sheet(
+    tile: (u32, u32),
+    grid: (u32, u32),
+    paint: impl Fn(u32, u32, u32) -> image::Rgba<u8>,
+) -> DynamicImage {
+    let (width, height) = tile;
+    DynamicImage::ImageRgba8(image::RgbaImage::from_fn(
+        width * grid.0,
+        height * grid.1,
+        |x, y| paint(y / height * grid.0 + x / width, x % width, y % height),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    #[test]
+    fn defaults_are_their_mode() {
+        assert_eq!(IconOperation::MODES.len(), OperationKind::iter().count());
+        for mode in IconOperation::MODES {
+            if let Some(operation) = IconOperation::default_for(mode) {
+                assert_eq!(<&str>::from(OperationKind::from(&operation)), *mode);
+            }
+        }
+        assert_eq!(IconOperation::default_for("BitmaskSliceGroups"), None);
+        assert_eq!(IconOperation::default_for("Nonsense"), None);
+    }
 }
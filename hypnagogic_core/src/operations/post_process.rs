@@ -0,0 +1,404 @@
+//! Filters run over every frame a config generates before it's written, for
+//! touches like outlines and shadows that would otherwise be redone in an
+//! image editor after every cut
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use toml::Value;
+
+use crate::config::error::{ConfigError, ConfigResult};
+use crate::operations::error::{ProcessorError, ProcessorResult};
+use crate::operations::{OutputImage, ProcessorPayload};
+use crate::util::blend::{blend_onto, BlendMode};
+use crate::util::color::Color;
+
+/// Key of the table a config lists the filters run over its generated frames
+/// in, like
+/// `[post_process] filters = [{ filter = "outline", color = "#000000" }]`
+pub const POST_PROCESS_KEY: &str = "post_process";
+
+/// Filters run over every frame of every state a config generates
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PostProcess {
+    /// Run in order, each over the last one's output
+    #[serde(default)]
+    pub filters: Vec<Filter>,
+}
+
+/// A change made to a whole frame
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "filter", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Filter {
+    /// Fills the transparent pixels touching opaque ones with `color`
+    Outline {
+        color: Color,
+        /// Also counts pixels that only touch diagonally
+        #[serde(default)]
+        diagonals: bool,
+    },
+    /// A copy of the frame in `color`, drawn under it at `offset`
+    DropShadow {
+        color: Color,
+        #[serde(default = "shadow_offset")]
+        offset: FilterOffset,
+    },
+    /// `color` spread `radius` pixels out from the frame, fading as it goes,
+    /// drawn under it
+    Glow {
+        color: Color,
+        #[serde(default = "glow_radius")]
+        radius: u32,
+    },
+    /// Multiplies every color by `amount`, so 1 leaves it alone
+    Brightness { amount: f32 },
+    /// Moves every color towards grey, all the way at an `amount` of 1
+    Desaturate {
+        #[serde(default = "full_amount")]
+        amount: f32,
+    },
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct FilterOffset {
+    #[serde(default)]
+    pub x: i32,
+    #[serde(default)]
+    pub y: i32,
+}
+
+const fn shadow_offset() -> FilterOffset {
+    FilterOffset { x: 1, y: 1 }
+}
+
+const fn glow_radius() -> u32 {
+    2
+}
+
+const fn full_amount() -> f32 {
+    1.0
+}
+
+impl PostProcess {
+    /// Runs every filter over every frame of the icons in `payload`. Anything
+    /// that isn't an icon, like the pngs of debug output, is left alone
+    /// # Errors
+    /// Errors if there are filters but `payload` has no icons to run them
+    /// over, like the pngs and already encoded files some operations output
+    pub fn apply(&self, payload: &mut ProcessorPayload) -> ProcessorResult<()> {
+        if self.filters.is_empty() || self.apply_to_payload(payload) {
+            Ok(())
+        } else {
+            Err(ProcessorError::ConfigError(format!(
+                "`{POST_PROCESS_KEY}` can't be applied, the operation doesn't output any dmis to \
+                 filter"
+            )))
+        }
+    }
+
+    /// Whether any icon in `payload` was filtered
+    fn apply_to_payload(&self, payload: &mut ProcessorPayload) -> bool {
+        match payload {
+            ProcessorPayload::Single(image) => self.apply_to_image(image),
+            ProcessorPayload::SingleNamed(named) => self.apply_to_image(&mut named.image),
+            ProcessorPayload::MultipleNamed(icons) => {
+                let mut filtered = false;
+                for named in icons {
+                    filtered |= self.apply_to_image(&mut named.image);
+                }
+                filtered
+            }
+            ProcessorPayload::ConfigWrapped(payload, _) | ProcessorPayload::Warned(payload, _) => {
+                self.apply_to_payload(payload)
+            }
+        }
+    }
+
+    fn apply_to_image(&self, image: &mut OutputImage) -> bool {
+        let OutputImage::Dmi(icon) = image else {
+            return false;
+        };
+        for frame in icon.states.iter_mut().flat_map(|state| &mut state.images) {
+            for filter in &self.filters {
+                filter.apply(frame);
+            }
+        }
+        true
+    }
+
+    fn problems(&self) -> Vec<String> {
+        self.filters
+            .iter()
+            .filter_map(|filter| {
+                match filter {
+                    Filter::Brightness { amount } if *amount < 0.0 => {
+                        Some(format!("brightness amount {amount} can't be negative"))
+                    }
+                    Filter::Desaturate { amount } if !(0.0..=1.0).contains(amount) => {
+                        Some(format!("desaturate amount {amount} has to be from 0 to 1"))
+                    }
+                    Filter::Glow { radius: 0, .. } => {
+                        Some("glow radius has to be at least 1".to_string())
+                    }
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Filter {
+    /// Runs the filter over `frame`
+    pub fn apply(&self, frame: &mut DynamicImage) {
+        let source = frame.to_rgba8();
+        match self {
+            Filter::Outline { color, diagonals } => {
+                *frame = DynamicImage::ImageRgba8(outline(&source, *color, *diagonals));
+            }
+            Filter::DropShadow { color, offset } => {
+                let mut shadow = DynamicImage::new_rgba8(source.width(), source.height());
+                let silhouette = DynamicImage::ImageRgba8(tinted(&source, *color));
+                blend_onto(
+                    &mut shadow,
+                    &silhouette,
+                    i64::from(offset.x),
+                    i64::from(offset.y),
+                    BlendMode::Normal,
+                );
+                blend_onto(&mut shadow, frame, 0, 0, BlendMode::Normal);
+                *frame = shadow;
+            }
+            Filter::Glow { color, radius } => {
+                let mut glow = DynamicImage::ImageRgba8(glow(&source, *color, *radius));
+                blend_onto(&mut glow, frame, 0, 0, BlendMode::Normal);
+                *frame = glow;
+            }
+            Filter::Brightness { amount } => {
+                *frame = DynamicImage::ImageRgba8(map_colors(&source, |value, _| value * amount));
+            }
+            Filter::Desaturate { amount } => {
+                *frame = DynamicImage::ImageRgba8(map_colors(&source, |value, grey| {
+                    value + (grey - value) * amount
+                }));
+            }
+        }
+    }
+}
+
+/// `image` with every pixel that isn't transparent set to `color`, keeping
+/// how transparent it was
+fn tinted(image: &RgbaImage, color: Color) -> RgbaImage {
+    RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        let alpha = u16::from(image.get_pixel(x, y)[3]) * u16::from(color.alpha) / 255;
+        Rgba([color.red, color.green, color.blue, alpha as u8])
+    })
+}
+
+fn outline(image: &RgbaImage, color: Color, diagonals: bool) -> RgbaImage {
+    let mut out = image.clone();
+    for (x, y, pixel) in out.enumerate_pixels_mut() {
+        if pixel[3] != 0 {
+            continue;
+        }
+        let touches = neighbours(image, x, y, 1)
+            .filter(|(dx, dy)| diagonals || dx.abs() + dy.abs() == 1)
+            .any(|(dx, dy)| {
+                let (x, y) = (x as i64 + dx, y as i64 + dy);
+                image.get_pixel(x as u32, y as u32)[3] != 0
+            });
+        if touches {
+            *pixel = Rgba(color.into());
+        }
+    }
+    out
+}
+
+fn glow(image: &RgbaImage, color: Color, radius: u32) -> RgbaImage {
+    RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        let closest = neighbours(image, x, y, radius)
+            .filter(|(dx, dy)| {
+                let (x, y) = (x as i64 + dx, y as i64 + dy);
+                image.get_pixel(x as u32, y as u32)[3] != 0
+            })
+            .map(|(dx, dy)| ((dx * dx + dy * dy) as f32).sqrt())
+            .reduce(f32::min);
+        let Some(distance) = closest.filter(|distance| *distance <= radius as f32) else {
+            return Rgba([0, 0, 0, 0]);
+        };
+        let strength = 1.0 - distance / (radius as f32 + 1.0);
+        let alpha = (f32::from(color.alpha) * strength).round() as u8;
+        Rgba([color.red, color.green, color.blue, alpha])
+    })
+}
+
+/// Offsets from `x`, `y` to every pixel of `image` up to `reach` away in
+/// both directions, leaving out `x`, `y` itself
+fn neighbours(
+    image: &RgbaImage,
+    x: u32,
+    y: u32,
+    reach: u32,
+) -> impl Iterator<Item = (i64, i64)> + '_ {
+    let reach = i64::from(reach);
+    let (x, y) = (i64::from(x), i64::from(y));
+    (-reach..=reach)
+        .flat_map(move |dy| (-reach..=reach).map(move |dx| (dx, dy)))
+        .filter(move |(dx, dy)| {
+            (*dx, *dy) != (0, 0)
+                && (0..i64::from(image.width())).contains(&(x + dx))
+                && (0..i64::from(image.height())).contains(&(y + dy))
+        })
+}
+
+/// `image` with `change` run on each color channel of every pixel, given the
+/// channel and the pixel's grey, both from 0 to 1
+fn map_colors(image: &RgbaImage, change: impl Fn(f32, f32) -> f32) -> RgbaImage {
+    let mut out = image.clone();
+    for pixel in out.pixels_mut() {
+        let grey = Color::from(pixel.0).luminance();
+        for channel in 0..3 {
+            let value = change(f32::from(pixel[channel]) / 255.0, grey);
+            pixel[channel] = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+    }
+    out
+}
+
+/// Removes [`POST_PROCESS_KEY`] from a config, returning the filters it lists
+pub(crate) fn take_post_process(value: &mut Value) -> ConfigResult<PostProcess> {
+    let Value::Table(table) = value else {
+        return Ok(PostProcess::default());
+    };
+    let Some(post_process) = table.remove(POST_PROCESS_KEY) else {
+        return Ok(PostProcess::default());
+    };
+    let post_process: PostProcess = post_process
+        .try_into()
+        .map_err(|err| ConfigError::Config(format!("invalid `{POST_PROCESS_KEY}`: {err}")))?;
+    match post_process.problems().first() {
+        Some(problem) => {
+            Err(ConfigError::Config(format!(
+                "invalid `{POST_PROCESS_KEY}`: {problem}"
+            )))
+        }
+        None => Ok(post_process),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use dmi::icon::{Icon, IconState};
+    use image::GenericImageView;
+
+    use super::*;
+    use crate::operations::single_dmi;
+
+    /// A 5x5 frame with a single red pixel in the middle
+    fn dot() -> DynamicImage {
+        let mut image = RgbaImage::new(5, 5);
+        image.put_pixel(2, 2, Rgba([255, 0, 0, 255]));
+        DynamicImage::ImageRgba8(image)
+    }
+
+    #[test]
+    fn filters() {
+        let black = Color::new_rgb(0, 0, 0);
+        let mut outlined = dot();
+        Filter::Outline {
+            color: black,
+            diagonals: false,
+        }
+        .apply(&mut outlined);
+        assert_eq!(outlined.get_pixel(2, 1), Rgba([0, 0, 0, 255]));
+        assert_eq!(outlined.get_pixel(1, 1), Rgba([0, 0, 0, 0]));
+        assert_eq!(outlined.get_pixel(2, 2), Rgba([255, 0, 0, 255]));
+
+        let mut shadowed = dot();
+        Filter::DropShadow {
+            color: black,
+            offset: shadow_offset(),
+        }
+        .apply(&mut shadowed);
+        assert_eq!(shadowed.get_pixel(3, 3), Rgba([0, 0, 0, 255]));
+        assert_eq!(shadowed.get_pixel(2, 2), Rgba([255, 0, 0, 255]));
+
+        let mut glowing = dot();
+        Filter::Glow {
+            color: Color::new_rgb(255, 255, 0),
+            radius: 2,
+        }
+        .apply(&mut glowing);
+        assert_eq!(glowing.get_pixel(2, 0), Rgba([255, 255, 0, 85]));
+        assert_eq!(glowing.get_pixel(2, 1), Rgba([255, 255, 0, 170]));
+        assert_eq!(glowing.get_pixel(0, 0), Rgba([0, 0, 0, 0]));
+
+        let mut dimmed = dot();
+        Filter::Brightness { amount: 0.5 }.apply(&mut dimmed);
+        assert_eq!(dimmed.get_pixel(2, 2), Rgba([128, 0, 0, 255]));
+
+        let mut grey = dot();
+        Filter::Desaturate { amount: 1.0 }.apply(&mut grey);
+        assert_eq!(grey.get_pixel(2, 2), Rgba([76, 76, 76, 255]));
+    }
+
+    #[test]
+    fn applies_to_icons() {
+        let post_process = PostProcess {
+            filters: vec![Filter::Brightness { amount: 0.0 }],
+        };
+        let mut payload = ProcessorPayload::from_icon(Icon {
+            width: 5,
+            height: 5,
+            states: vec![IconState {
+                images: vec![dot()],
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        post_process.apply(&mut payload).unwrap();
+        let icon = single_dmi(payload);
+        assert_eq!(
+            icon.states[0].images[0].get_pixel(2, 2),
+            Rgba([0, 0, 0, 255])
+        );
+
+        let mut encoded = ProcessorPayload::Single(Box::new(OutputImage::EncodedDmi(vec![])));
+        assert!(post_process.apply(&mut encoded).is_err());
+        assert!(PostProcess::default().apply(&mut encoded).is_ok());
+    }
+
+    #[test]
+    fn reads_post_process() {
+        let mut value: Value = toml::from_str(
+            r##"
+            [post_process]
+            filters = [
+                { filter = "outline", color = "#000000" },
+                { filter = "desaturate", amount = 0.5 },
+            ]
+            "##,
+        )
+        .unwrap();
+        let post_process = take_post_process(&mut value).unwrap();
+        assert_eq!(
+            post_process.filters,
+            vec![
+                Filter::Outline {
+                    color: Color::new_rgb(0, 0, 0),
+                    diagonals: false,
+                },
+                Filter::Desaturate { amount: 0.5 },
+            ]
+        );
+        assert!(value.get(POST_PROCESS_KEY).is_none());
+
+        for bad in [
+            "[post_process]\nfilters = [{ filter = \"blur\" }]",
+            "[post_process]\nfilters = [{ filter = \"brightness\", amount = -1.0 }]",
+        ] {
+            let mut value: Value = toml::from_str(bad).unwrap();
+            assert!(take_post_process(&mut value).is_err());
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! Recolors of everything a config generates, each swapping some colors for
+//! others and output alongside it, so a set drawn once can come in several
+//! colors without a hand edited copy of its sheet for each
+
+use image::{DynamicImage, Rgba};
+use toml::map::Map;
+use toml::Value;
+
+use crate::config::error::{ConfigError, ConfigResult};
+use crate::operations::error::{ProcessorError, ProcessorResult};
+use crate::operations::{OutputImage, ProcessorPayload};
+use crate::util::color::Color;
+
+/// Key of the table a config declares its recolors in, each a table of the
+/// colors it swaps, like `[recolors.red]` with `"#808080" = "#A02020"` under
+/// it
+pub const RECOLORS_KEY: &str = "recolors";
+
+/// A recolor of a config's output, written alongside it named `<output>-<name>`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Recolor {
+    pub name: String,
+    /// Each color swapped and what it's swapped for. Only the red, green and
+    /// blue of a source are matched, and a pixel keeps its own transparency
+    /// scaled by its target's
+    pub palette: Vec<(Color, Color)>,
+}
+
+impl Recolor {
+    /// Swaps the colors of every frame of the icons and images in `payload`.
+    /// Previews, which are already encoded, are left alone
+    /// # Errors
+    /// Errors if nothing in `payload` can be recolored, like the already
+    /// encoded dmis and aseprite files some operations output, rather than
+    /// writing an unchanged copy
+    pub fn apply(&self, payload: &mut ProcessorPayload) -> ProcessorResult<()> {
+        if self.apply_to_payload(payload) {
+            Ok(())
+        } else {
+            Err(ProcessorError::ConfigError(format!(
+                "recolor `{}` can't be applied, the operation's output is already encoded",
+                self.name
+            )))
+        }
+    }
+
+    /// Whether anything in `payload` was recolored
+    fn apply_to_payload(&self, payload: &mut ProcessorPayload) -> bool {
+        match payload {
+            ProcessorPayload::Single(image) => self.apply_to_image(image),
+            ProcessorPayload::SingleNamed(named) => self.apply_to_image(&mut named.image),
+            ProcessorPayload::MultipleNamed(icons) => {
+                let mut recolored = false;
+                for named in icons {
+                    recolored |= self.apply_to_image(&mut named.image);
+                }
+                recolored
+            }
+            ProcessorPayload::ConfigWrapped(payload, _) | ProcessorPayload::Warned(payload, _) => {
+                self.apply_to_payload(payload)
+            }
+        }
+    }
+
+    fn apply_to_image(&self, image: &mut OutputImage) -> bool {
+        match image {
+            OutputImage::Png(png) => self.recolor(png),
+            OutputImage::Dmi(icon) => {
+                for frame in icon.states.iter_mut().flat_map(|state| &mut state.images) {
+                    self.recolor(frame);
+                }
+            }
+            OutputImage::Apng(_)
+            | OutputImage::Gif(_)
+            | OutputImage::EncodedDmi(_)
+            | OutputImage::Aseprite(_) => return false,
+        }
+        true
+    }
+
+    /// Swaps the colors of `image`
+    pub fn recolor(&self, image: &mut DynamicImage) {
+        let mut rgba = image.to_rgba8();
+        for pixel in rgba.pixels_mut() {
+            let [red, green, blue, alpha] = pixel.0;
+            if alpha == 0 {
+                continue;
+            }
+            let target = self
+                .palette
+                .iter()
+                .find(|(source, _)| (source.red, source.green, source.blue) == (red, green, blue));
+            if let Some((_, target)) = target {
+                let alpha = u16::from(alpha) * u16::from(target.alpha) / 255;
+                *pixel = Rgba([target.red, target.green, target.blue, alpha as u8]);
+            }
+        }
+        *image = DynamicImage::ImageRgba8(rgba);
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Table(
+            self.palette
+                .iter()
+                .map(|(source, target)| (source.to_hex_str(), Value::String(target.to_hex_str())))
+                .collect(),
+        )
+    }
+}
+
+/// The recolors as they'd be written under [`RECOLORS_KEY`]
+pub(crate) fn recolors_value(recolors: &[Recolor]) -> Value {
+    Value::Table(
+        recolors
+            .iter()
+            .map(|recolor| (recolor.name.clone(), recolor.to_value()))
+            .collect(),
+    )
+}
+
+/// Removes [`RECOLORS_KEY`] from a config, returning its recolors
+pub(crate) fn take_recolors(value: &mut Value) -> ConfigResult<Vec<Recolor>> {
+    let Value::Table(table) = value else {
+        return Ok(vec![]);
+    };
+    let recolors = match table.remove(RECOLORS_KEY) {
+        Some(Value::Table(recolors)) => recolors,
+        Some(_) => {
+            return Err(ConfigError::Config(format!(
+                "`{RECOLORS_KEY}` must be a table of recolors, each a table of colors to swap"
+            )))
+        }
+        None => return Ok(vec![]),
+    };
+    recolors
+        .into_iter()
+        .map(|(name, palette)| {
+            if name.is_empty() || name.contains(['/', '\\']) {
+                return Err(ConfigError::Config(format!(
+                    "recolor `{name}` needs a name that can go in a file name"
+                )));
+            }
+            let Value::Table(palette) = palette else {
+                return Err(ConfigError::Config(format!(
+                    "recolor `{name}` must be a table of colors to swap, like \"#808080\" = \
+                     \"#A02020\""
+                )));
+            };
+            Ok(Recolor {
+                palette: read_palette(&name, palette)?,
+                name,
+            })
+        })
+        .collect()
+}
+
+fn read_palette(name: &str, palette: Map<String, Value>) -> ConfigResult<Vec<(Color, Color)>> {
+    let invalid = |detail: String| ConfigError::Config(format!("in recolor `{name}`: {detail}"));
+    let mut out: Vec<(Color, Color)> = vec![];
+    for (source, target) in palette {
+        let source_color = Color::from_hex_str(&source).map_err(|err| invalid(err.to_string()))?;
+        let target = target
+            .as_str()
+            .ok_or_else(|| invalid(format!("`{source}` must be swapped for a color")))
+            .and_then(|target| {
+                Color::from_hex_str(target).map_err(|err| invalid(err.to_string()))
+            })?;
+        let repeated = out.iter().any(|(existing, _)| {
+            (existing.red, existing.green, existing.blue)
+                == (source_color.red, source_color.green, source_color.blue)
+        });
+        if repeated {
+            return Err(invalid(format!("`{source}` is swapped more than once")));
+        }
+        out.push((source_color, target));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use image::GenericImageView;
+
+    use super::*;
+
+    #[test]
+    fn recolors() {
+        let mut value: Value = toml::from_str(
+            r##"
+            [recolors.red]
+            "#808080" = "#A02020"
+            "#fff" = "#FF000080"
+            "##,
+        )
+        .unwrap();
+        let recolors = take_recolors(&mut value).unwrap();
+        assert!(value.get(RECOLORS_KEY).is_none());
+        assert_eq!(recolors.len(), 1);
+        assert_eq!(recolors[0].name, "red");
+
+        let mut image = DynamicImage::new_rgba8(3, 1);
+        let pixels = image.as_mut_rgba8().unwrap();
+        pixels.put_pixel(0, 0, Rgba([128, 128, 128, 255]));
+        pixels.put_pixel(1, 0, Rgba([255, 255, 255, 255]));
+        pixels.put_pixel(2, 0, Rgba([1, 2, 3, 255]));
+        recolors[0].recolor(&mut image);
+        assert_eq!(image.get_pixel(0, 0), Rgba([160, 32, 32, 255]));
+        assert_eq!(image.get_pixel(1, 0), Rgba([255, 0, 0, 128]));
+        assert_eq!(image.get_pixel(2, 0), Rgba([1, 2, 3, 255]));
+
+        let mut reread = Value::Table(Map::from_iter([(
+            RECOLORS_KEY.to_string(),
+            recolors_value(&recolors),
+        )]));
+        assert_eq!(take_recolors(&mut reread).unwrap(), recolors);
+
+        let mut payload = ProcessorPayload::Single(Box::new(OutputImage::Png(image)));
+        assert!(recolors[0].apply(&mut payload).is_ok());
+        let mut encoded = ProcessorPayload::Single(Box::new(OutputImage::EncodedDmi(vec![])));
+        assert!(recolors[0].apply(&mut encoded).is_err());
+
+        for bad in [
+            "recolors = [\"red\"]",
+            "[recolors.red]\n\"808080\" = \"#A02020\"",
+            "[recolors.red]\n\"#808080\" = 5",
+            "[recolors.red]\n\"#fff\" = \"#000\"\n\"#FFFFFF\" = \"#111\"",
+        ] {
+            let mut value: Value = toml::from_str(bad).unwrap();
+            assert!(take_recolors(&mut value).is_err(), "{bad}");
+        }
+    }
+}
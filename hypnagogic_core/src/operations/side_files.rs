@@ -0,0 +1,214 @@
+//! Files some operations read besides their input, like a bitmask slice's
+//! corner atlas or the manifest `DmiImport` builds from. Configs name them
+//! relative to themselves, so only whatever read the config can find them,
+//! and it hands them over through a [`SideFiles`]
+
+use std::io::{BufRead, Seek};
+
+use dmi::icon::Icon;
+use image::{DynamicImage, ImageFormat};
+use tracing::debug;
+
+use crate::operations::error::ProcessorError;
+use crate::operations::{IconOperation, InputError};
+
+/// Finds the files a config names besides its input
+pub trait SideFiles {
+    type Reader: BufRead + Seek;
+    type Error: From<InputError> + From<ProcessorError>;
+
+    /// Opens `file`, as it's written in the config
+    /// # Errors
+    /// Errors if there's no such file, or it can't be read
+    fn open(&self, file: &str) -> Result<Self::Reader, Self::Error>;
+
+    /// Reads the config at `file`, as it's written in the config, resolving
+    /// its templates
+    /// # Errors
+    /// Errors if there's no such config, or it isn't valid
+    fn config(&self, file: &str) -> Result<IconOperation, Self::Error>;
+}
+
+fn load_png<F: SideFiles>(files: &F, file: &str) -> Result<DynamicImage, F::Error> {
+    Ok(image::load(files.open(file)?, ImageFormat::Png).map_err(InputError::from)?)
+}
+
+impl IconOperation {
+    /// Reads in every file the operation needs besides its input from `files`,
+    /// filling in the fields they're kept in. Needs doing before the
+    /// operation's run, and again if those files change
+    /// # Errors
+    /// Errors if any of the files are missing or invalid
+    pub fn load_side_files<F: SideFiles>(&mut self, files: &F) -> Result<(), F::Error> {
+        if let Some(config) = self.bitmask_slice_mut() {
+            if let Some(atlas) = &config.corner_atlas {
+                config.corner_atlas_image = Some(load_png(files, atlas)?);
+                debug!(atlas, "Loaded corner atlas");
+            }
+            let prefab_files: Vec<String> = config
+                .prefabs
+                .iter()
+                .flat_map(|prefabs| prefabs.files().into_keys().map(str::to_string))
+                .collect();
+            for file in prefab_files {
+                let image = load_png(files, &file)?;
+                debug!(file, "Loaded prefab file");
+                config.prefab_images.insert(file, image);
+            }
+        }
+        match self {
+            IconOperation::BitmaskSliceGreyscale(config) => {
+                config.mask_image = Some(load_png(files, &config.mask)?);
+                debug!(mask = config.mask, "Loaded greyscale mask");
+            }
+            IconOperation::BitmaskTextureMask(config) => {
+                config.texture_image = Some(load_png(files, &config.texture)?);
+                debug!(texture = config.texture, "Loaded texture");
+            }
+            IconOperation::DmiMerge(config) => {
+                config.with_icons.clear();
+                for file in &config.with {
+                    let icon = Icon::load(files.open(file)?).map_err(InputError::from)?;
+                    debug!(file, "Loaded icon to merge");
+                    config.with_icons.push(icon);
+                }
+            }
+            IconOperation::DmiValidate(config) => {
+                let mut against = files.config(&config.against)?;
+                let Some(cutter) = against.bitmask_slice_mut() else {
+                    return Err(ProcessorError::ConfigError(format!(
+                        "`{}` isn't built on a bitmask slice, so there's nothing to validate \
+                         against",
+                        config.against
+                    ))
+                    .into());
+                };
+                debug!(
+                    against = config.against,
+                    "Loaded config to validate against"
+                );
+                config.against_config = Some(cutter.clone());
+            }
+            IconOperation::DmiImport(config) => {
+                let manifest =
+                    serde_json::from_reader(files.open(&config.manifest)?).map_err(|err| {
+                        ProcessorError::ConfigError(format!(
+                            "`{}` isn't a valid manifest: {err}",
+                            config.manifest
+                        ))
+                    })?;
+                debug!(manifest = config.manifest, "Loaded manifest to import");
+                config.manifest_data = Some(manifest);
+            }
+            IconOperation::BitmaskSlice(_)
+            | IconOperation::BitmaskSliceGroups(_)
+            | IconOperation::BitmaskDirectionalVis(_)
+            | IconOperation::BitmaskWindows(_)
+            | IconOperation::BitmaskSliceReconstruct(_)
+            | IconOperation::BitmaskPipes(_)
+            | IconOperation::BitmaskLattice(_)
+            | IconOperation::BitmaskCornerOverlays(_)
+            | IconOperation::TurfEdges(_)
+            | IconOperation::DirectionalRotation(_)
+            | IconOperation::BitmaskWallTops(_)
+            | IconOperation::GridSlice(_)
+            | IconOperation::GlyphSheet(_)
+            | IconOperation::DmiSplit(_)
+            | IconOperation::DmiRename(_)
+            | IconOperation::DmiOptimize(_)
+            | IconOperation::DmiExport(_)
+            | IconOperation::DmiAseprite(_) => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::operations::cutters::bitmask_slice::BitmaskSlice;
+    use crate::operations::format_converter::dmi_import::DmiImport;
+    use crate::operations::format_converter::dmi_validate::DmiValidate;
+
+    #[derive(Debug)]
+    struct TestError;
+
+    impl From<InputError> for TestError {
+        fn from(_: InputError) -> Self {
+            TestError
+        }
+    }
+
+    impl From<ProcessorError> for TestError {
+        fn from(_: ProcessorError) -> Self {
+            TestError
+        }
+    }
+
+    struct TestFiles(HashMap<&'static str, Vec<u8>>);
+
+    impl SideFiles for TestFiles {
+        type Error = TestError;
+        type Reader = Cursor<Vec<u8>>;
+
+        fn open(&self, file: &str) -> Result<Self::Reader, Self::Error> {
+            self.0.get(file).cloned().map(Cursor::new).ok_or(TestError)
+        }
+
+        fn config(&self, file: &str) -> Result<IconOperation, Self::Error> {
+            match file {
+                "wall.png.toml" => Ok(BitmaskSlice::default().into()),
+                _ => Ok(DmiImport::default().into()),
+            }
+        }
+    }
+
+    #[test]
+    fn loads_side_files() {
+        let files = TestFiles(HashMap::from([(
+            "sheet.json",
+            br#"{ "width": 32, "height": 32, "states": [] }"#.to_vec(),
+        )]));
+
+        let mut import: IconOperation = DmiImport {
+            manifest: "sheet.json".to_string(),
+            ..Default::default()
+        }
+        .into();
+        import.load_side_files(&files).unwrap();
+        let IconOperation::DmiImport(import) = import else {
+            unreachable!()
+        };
+        assert_eq!(
+            import.manifest_data.map(|manifest| manifest.width),
+            Some(32)
+        );
+
+        let mut missing: IconOperation = DmiImport {
+            manifest: "missing.json".to_string(),
+            ..Default::default()
+        }
+        .into();
+        assert!(missing.load_side_files(&files).is_err());
+
+        let validate = |against: &str| {
+            let mut validate: IconOperation = DmiValidate {
+                against: against.to_string(),
+                ..Default::default()
+            }
+            .into();
+            validate.load_side_files(&files).map(|()| validate)
+        };
+        assert!(matches!(
+            validate("wall.png.toml"),
+            Ok(IconOperation::DmiValidate(DmiValidate {
+                against_config: Some(_),
+                ..
+            }))
+        ));
+        assert!(validate("import.png.toml").is_err());
+    }
+}
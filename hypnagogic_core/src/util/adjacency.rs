@@ -95,6 +95,14 @@ impl Adjacency {
         self.has_no_orphaned_corner()
     }
 
+    /// Whether the diagonal at `corner` is set without both of the cardinals
+    /// next to it
+    #[must_use]
+    pub const fn is_orphaned(self, corner: Corner) -> bool {
+        let diagonal = Adjacency::from_corner(corner);
+        self.contains(diagonal) && !self.adjacent_corners_filled(diagonal)
+    }
+
     // implemented as const for usage in get corner type
     const fn from_corner(corner: Corner) -> Self {
         match corner {
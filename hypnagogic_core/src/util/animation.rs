@@ -0,0 +1,153 @@
+use std::borrow::Borrow;
+use std::time::Duration;
+
+use dmi::icon::IconState;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, DynamicImage, Frame, ImageError};
+use png::{BitDepth, ColorType, Encoder, EncodingError};
+
+/// Gets the frames of an icon state that belong to the given dir index, in
+/// playback order. DMIs store images frame by frame, with each frame holding
+/// every dir.
+#[must_use]
+pub fn frames_for_dir(state: &IconState, dir_index: u8) -> Vec<&DynamicImage> {
+    state
+        .images
+        .iter()
+        .skip(dir_index as usize)
+        .step_by(state.dirs.max(1) as usize)
+        .collect()
+}
+
+/// Pairs each frame with the delay (in deciseconds) it's shown for, in the
+/// order byond plays them. Delays are cycled if there are fewer delays than
+/// frames. If `rewind` is set the frames play backwards again after reaching
+/// the end, mirroring byond's rewind flag.
+/// Generic so it can be used with frame indexes as well as the frames
+/// themselves.
+#[must_use]
+pub fn playback_sequence<T: Copy>(
+    frames: &[T],
+    delays: Option<&[f32]>,
+    rewind: bool,
+) -> Vec<(T, f32)> {
+    let mut sequence: Vec<(T, f32)> = frames
+        .iter()
+        .enumerate()
+        .map(|(index, frame)| {
+            let delay = delays
+                .filter(|delays| !delays.is_empty())
+                .map_or(1.0, |delays| delays[index % delays.len()]);
+            (*frame, delay)
+        })
+        .collect();
+    if rewind && sequence.len() > 2 {
+        let reversed: Vec<(T, f32)> = sequence[1..sequence.len() - 1]
+            .iter()
+            .rev()
+            .copied()
+            .collect();
+        sequence.extend(reversed);
+    }
+    sequence
+}
+
+/// Encodes frames as an APNG, looping forever like byond does. See
+/// [`playback_sequence`] for how `delays` and `rewind` are handled.
+///
+/// A single frame produces a plain PNG.
+/// # Errors
+/// Errors if encoding fails, or if frames are of differing sizes
+/// # Panics
+/// Panics if `frames` is empty
+pub fn encode_apng(
+    frames: &[&DynamicImage],
+    delays: Option<&[f32]>,
+    rewind: bool,
+) -> Result<Vec<u8>, EncodingError> {
+    encode_apng_sequence(&playback_sequence(frames, delays, rewind))
+}
+
+/// Encodes an already built sequence of frames and their delays in
+/// deciseconds as an APNG, looping forever
+/// # Errors
+/// Errors if encoding fails, or if frames are of differing sizes
+/// # Panics
+/// Panics if `sequence` is empty
+pub fn encode_apng_sequence<F: Borrow<DynamicImage>>(
+    sequence: &[(F, f32)],
+) -> Result<Vec<u8>, EncodingError> {
+    let first = sequence
+        .first()
+        .expect("Can't encode an animation without frames")
+        .0
+        .borrow();
+    let mut buffer = vec![];
+    let mut encoder = Encoder::new(&mut buffer, first.width(), first.height());
+    encoder.set_color(ColorType::Rgba);
+    encoder.set_depth(BitDepth::Eight);
+    let animated = sequence.len() > 1;
+    if animated {
+        encoder.set_animated(sequence.len() as u32, 0)?;
+    }
+    let mut writer = encoder.write_header()?;
+    for (frame, delay) in sequence {
+        if animated {
+            // deciseconds to centiseconds, which is as fine as browsers care about
+            writer.set_frame_delay((delay * 10.0).round() as u16, 100)?;
+        }
+        writer.write_image_data(frame.borrow().to_rgba8().as_raw())?;
+    }
+    writer.finish()?;
+    Ok(buffer)
+}
+
+/// Same as [`encode_apng_sequence`], but as a GIF. GIFs only have on/off
+/// transparency, so partially transparent pixels won't look quite right
+/// # Errors
+/// Errors if encoding fails
+pub fn encode_gif_sequence<F: Borrow<DynamicImage>>(
+    sequence: &[(F, f32)],
+) -> Result<Vec<u8>, ImageError> {
+    let mut buffer = vec![];
+    {
+        let mut encoder = GifEncoder::new(&mut buffer);
+        encoder.set_repeat(Repeat::Infinite)?;
+        let frames = sequence.iter().map(|(frame, delay)| {
+            let delay = Duration::from_millis((delay * 100.0).round() as u64);
+            Frame::from_parts(
+                frame.borrow().to_rgba8(),
+                0,
+                0,
+                Delay::from_saturating_duration(delay),
+            )
+        });
+        encoder.encode_frames(frames)?;
+    }
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frames_for_dir_test() {
+        let image = |shade: u8| {
+            DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                1,
+                1,
+                image::Rgba([shade, 0, 0, 255]),
+            ))
+        };
+        // two frames of two dirs, stored frame by frame
+        let state = IconState {
+            dirs: 2,
+            frames: 2,
+            images: vec![image(0), image(1), image(2), image(3)],
+            ..Default::default()
+        };
+        let second_dir = frames_for_dir(&state, 1);
+        assert_eq!(second_dir, vec![&image(1), &image(3)]);
+    }
+}
@@ -0,0 +1,889 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read, Write};
+
+use dmi::icon::{Hotspot, Icon, IconState, Looping};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::operations::format_converter::dmi_export::DIR_NAMES;
+use crate::util::blend::{blend_onto_faded, BlendMode};
+use crate::util::state_names::fits_in_dmi;
+
+// Laid out as in aseprite's docs/ase-file-specs.md
+const FILE_MAGIC: u16 = 0xA5E0;
+const FRAME_MAGIC: u16 = 0xF1FA;
+const HEADER_SIZE: usize = 128;
+
+const LAYER_CHUNK: u16 = 0x2004;
+const CEL_CHUNK: u16 = 0x2005;
+const TAGS_CHUNK: u16 = 0x2018;
+const PALETTE_CHUNK: u16 = 0x2019;
+const USER_DATA_CHUNK: u16 = 0x2020;
+
+const LAYER_VISIBLE: u16 = 1;
+const LAYER_EDITABLE: u16 = 2;
+const LAYER_BACKGROUND: u16 = 8;
+const LAYER_REFERENCE: u16 = 64;
+const GROUP_LAYER: u16 = 1;
+
+const RAW_CEL: u16 = 0;
+const LINKED_CEL: u16 = 1;
+const COMPRESSED_CEL: u16 = 2;
+
+const FORWARD: u8 = 0;
+const REVERSE: u8 = 1;
+const PING_PONG: u8 = 2;
+const PING_PONG_REVERSE: u8 = 3;
+
+const USER_DATA_TEXT: u32 = 1;
+
+/// Name of the only layer of files with their dirs laid out as frames
+const FRAMES_LAYER: &str = "icon";
+
+#[derive(Debug, Error)]
+pub enum AsepriteError {
+    #[error("Couldn't read the file: {0}")]
+    Io(#[from] io::Error),
+    #[error("Not an aseprite file")]
+    NotAseprite,
+    #[error("The file ends part way through")]
+    Truncated,
+    #[error("{0}")]
+    Unsupported(String),
+    #[error("{0}")]
+    Invalid(String),
+}
+
+pub type AsepriteResult<T> = Result<T, AsepriteError>;
+
+/// Everything about a state aseprite has no place for, kept as json in the
+/// user data of its tag
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+struct TagData {
+    dirs: u8,
+    #[serde(default)]
+    movement: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    hotspot: Option<[u32; 2]>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    #[serde(default)]
+    settings: BTreeMap<String, String>,
+}
+
+struct Tag {
+    from: usize,
+    to: usize,
+    direction: u8,
+    repeat: u16,
+    name: String,
+    data: Option<String>,
+}
+
+/// Little endian values, as aseprite files are written
+#[derive(Default)]
+struct Bytes(Vec<u8>);
+
+impl Bytes {
+    fn byte(&mut self, value: u8) -> &mut Self {
+        self.0.push(value);
+        self
+    }
+
+    fn word(&mut self, value: u16) -> &mut Self {
+        self.0.extend(value.to_le_bytes());
+        self
+    }
+
+    fn short(&mut self, value: i16) -> &mut Self {
+        self.0.extend(value.to_le_bytes());
+        self
+    }
+
+    fn dword(&mut self, value: u32) -> &mut Self {
+        self.0.extend(value.to_le_bytes());
+        self
+    }
+
+    fn zeros(&mut self, count: usize) -> &mut Self {
+        self.0.resize(self.0.len() + count, 0);
+        self
+    }
+
+    fn string(&mut self, value: &str) -> AsepriteResult<&mut Self> {
+        self.word(fit(value.len(), "strings")?);
+        self.0.extend(value.as_bytes());
+        Ok(self)
+    }
+
+    fn chunk(&mut self, kind: u16, body: &Bytes) -> AsepriteResult<&mut Self> {
+        self.dword(fit(body.0.len() + 6, "chunks")?).word(kind);
+        self.0.extend(&body.0);
+        Ok(self)
+    }
+}
+
+/// `value` as `T`, if it fits in one
+fn fit<T: TryFrom<usize>>(value: usize, what: &str) -> AsepriteResult<T> {
+    T::try_from(value).map_err(|_| {
+        AsepriteError::Unsupported(format!(
+            "{what} this big can't be written in to aseprite files"
+        ))
+    })
+}
+
+/// How long each frame of `state` is shown for, in milliseconds
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn duration(state: &IconState, frame: usize) -> u16 {
+    let delay = state
+        .delay
+        .as_ref()
+        .and_then(|delay| delay.get(frame))
+        .copied()
+        .unwrap_or(1.0);
+    (delay * 100.0).round().clamp(1.0, f32::from(u16::MAX)) as u16
+}
+
+fn cel_chunk(layer: usize, image: &DynamicImage) -> AsepriteResult<Bytes> {
+    let mut encoder = ZlibEncoder::new(vec![], Compression::best());
+    encoder.write_all(image.to_rgba8().as_raw())?;
+    let mut body = Bytes::default();
+    body.word(fit(layer, "layers")?)
+        .short(0)
+        .short(0)
+        .byte(255)
+        .word(COMPRESSED_CEL)
+        .short(0)
+        .zeros(5)
+        .word(fit(image.width() as usize, "images")?)
+        .word(fit(image.height() as usize, "images")?);
+    body.0.extend(encoder.finish()?);
+    Ok(body)
+}
+
+/// `icon` as an aseprite file, each state a tag over its frames. Its dirs
+/// are either each on a layer named after the dir, or with `dirs_as_layers`
+/// false, one after another on the frames, every frame of the first dir then
+/// every frame of the next. What aseprite has no place for is kept in the
+/// tag's user data, so it all comes back when read
+/// # Errors
+/// Errors if the icon has more frames, or bigger images, than aseprite files
+/// can hold
+pub fn write(icon: &Icon, dirs_as_layers: bool) -> AsepriteResult<Vec<u8>> {
+    let layers: Vec<&str> = if dirs_as_layers {
+        let dirs = icon
+            .states
+            .iter()
+            .map(|state| usize::from(state.dirs))
+            .max()
+            .unwrap_or(1);
+        DIR_NAMES[..dirs.clamp(1, DIR_NAMES.len())].to_vec()
+    } else {
+        vec![FRAMES_LAYER]
+    };
+    // duration of each frame of the file, and what's on each of its layers
+    let mut frames: Vec<(u16, Vec<Option<&DynamicImage>>)> = vec![];
+    let mut tags = vec![];
+    for state in &icon.states {
+        let dirs = usize::from(state.dirs.max(1));
+        let count = state.frames.max(1) as usize;
+        let image = |frame: usize, dir: usize| state.images.get(frame * dirs + dir);
+        let from = frames.len();
+        if dirs_as_layers {
+            for frame in 0..count {
+                let images = (0..layers.len())
+                    .map(|dir| (dir < dirs).then(|| image(frame, dir)).flatten())
+                    .collect();
+                frames.push((duration(state, frame), images));
+            }
+        } else {
+            for dir in 0..dirs {
+                for frame in 0..count {
+                    frames.push((duration(state, frame), vec![image(frame, dir)]));
+                }
+            }
+        }
+        let data = TagData {
+            dirs: state.dirs,
+            movement: state.movement,
+            hotspot: state.hotspot.map(|hotspot| [hotspot.x, hotspot.y]),
+            settings: state
+                .unknown_settings
+                .iter()
+                .flatten()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        };
+        tags.push(Tag {
+            from,
+            to: frames.len() - 1,
+            direction: if state.rewind { PING_PONG } else { FORWARD },
+            repeat: match state.loop_flag {
+                Looping::Indefinitely => 0,
+                Looping::NTimes(times) => u16::try_from(times.get()).unwrap_or(u16::MAX),
+            },
+            name: state.name.clone(),
+            data: Some(serde_json::to_string(&data).expect("tag data is always valid json")),
+        });
+    }
+    if frames.is_empty() {
+        // aseprite files need at least one frame, even with nothing on it
+        frames.push((100, vec![None; layers.len()]));
+    }
+    let layers: Vec<(&str, u16)> = layers
+        .into_iter()
+        .map(|name| (name, LAYER_VISIBLE | LAYER_EDITABLE))
+        .collect();
+    encode((icon.width, icon.height), &layers, &frames, &tags)
+}
+
+/// An aseprite file of `frames`, their images on `layers` (named, with their
+/// flags) and `tags` over them
+fn encode(
+    (width, height): (u32, u32),
+    layers: &[(&str, u16)],
+    frames: &[(u16, Vec<Option<&DynamicImage>>)],
+    tags: &[Tag],
+) -> AsepriteResult<Vec<u8>> {
+    let mut header = Bytes::default();
+    header
+        .word(FILE_MAGIC)
+        .word(fit(frames.len(), "animations")?)
+        .word(fit(width as usize, "images")?)
+        .word(fit(height as usize, "images")?)
+        .word(32)
+        .dword(1)
+        .word(100)
+        .zeros(8)
+        .byte(0)
+        .zeros(3)
+        .word(1)
+        .byte(1)
+        .byte(1)
+        .short(0)
+        .short(0)
+        .word(16)
+        .word(16);
+    header.zeros(HEADER_SIZE - 4 - header.0.len());
+
+    let mut body = header;
+    for (index, (duration, images)) in frames.iter().enumerate() {
+        let mut chunks = vec![];
+        if index == 0 {
+            let mut palette = Bytes::default();
+            palette.dword(1).dword(0).dword(0).zeros(8).word(0);
+            palette.byte(0).byte(0).byte(0).byte(255);
+            chunks.push((PALETTE_CHUNK, palette));
+            for (name, flags) in layers {
+                let mut layer = Bytes::default();
+                layer
+                    .word(*flags)
+                    .zeros(10)
+                    .byte(255)
+                    .zeros(3)
+                    .string(name)?;
+                chunks.push((LAYER_CHUNK, layer));
+            }
+            if !tags.is_empty() {
+                let mut chunk = Bytes::default();
+                chunk.word(fit(tags.len(), "tag lists")?).zeros(8);
+                for tag in tags {
+                    chunk
+                        .word(fit(tag.from, "animations")?)
+                        .word(fit(tag.to, "animations")?)
+                        .byte(tag.direction)
+                        .word(tag.repeat)
+                        .zeros(10)
+                        .string(&tag.name)?;
+                }
+                chunks.push((TAGS_CHUNK, chunk));
+                for text in tags.iter().filter_map(|tag| tag.data.as_ref()) {
+                    let mut user_data = Bytes::default();
+                    user_data.dword(USER_DATA_TEXT).string(text)?;
+                    chunks.push((USER_DATA_CHUNK, user_data));
+                }
+            }
+        }
+        for (layer, image) in images.iter().enumerate() {
+            if let Some(image) =
+                image.filter(|image| image.pixels().any(|(.., pixel)| pixel[3] > 0))
+            {
+                chunks.push((CEL_CHUNK, cel_chunk(layer, image)?));
+            }
+        }
+        let mut frame = Bytes::default();
+        for (kind, chunk) in &chunks {
+            frame.chunk(*kind, chunk)?;
+        }
+        body.dword(fit(frame.0.len() + 16, "frames")?)
+            .word(FRAME_MAGIC)
+            .word(u16::try_from(chunks.len()).unwrap_or(u16::MAX))
+            .word(*duration)
+            .zeros(2)
+            .dword(fit(chunks.len(), "frames")?);
+        body.0.extend(frame.0);
+    }
+    let mut file = Bytes::default();
+    file.dword(fit(body.0.len() + 4, "files")?);
+    file.0.extend(body.0);
+    Ok(file.0)
+}
+
+/// Reads little endian values out of an aseprite file
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, count: usize) -> AsepriteResult<&'a [u8]> {
+        let taken = self
+            .bytes
+            .get(self.at..self.at + count)
+            .ok_or(AsepriteError::Truncated)?;
+        self.at += count;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> AsepriteResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn word(&mut self) -> AsepriteResult<u16> {
+        Ok(u16::from_le_bytes([self.byte()?, self.byte()?]))
+    }
+
+    fn short(&mut self) -> AsepriteResult<i16> {
+        Ok(i16::from_le_bytes([self.byte()?, self.byte()?]))
+    }
+
+    fn dword(&mut self) -> AsepriteResult<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn string(&mut self) -> AsepriteResult<String> {
+        let length = self.word()?;
+        Ok(String::from_utf8_lossy(self.take(usize::from(length))?).into_owned())
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.bytes[self.at.min(self.bytes.len())..]
+    }
+}
+
+struct Layer {
+    name: String,
+    child_level: u16,
+    opacity: f32,
+    blend: BlendMode,
+    /// Whether it's shown, counting the groups it's in
+    visible: bool,
+    /// The layer or group at the top of the groups it's in
+    root: usize,
+}
+
+struct Cel {
+    x: i64,
+    y: i64,
+    opacity: f32,
+    image: DynamicImage,
+}
+
+/// Everything read out of an aseprite file that makes up a dmi
+struct Sprite {
+    width: u32,
+    height: u32,
+    durations: Vec<u16>,
+    layers: Vec<Layer>,
+    cels: HashMap<(usize, usize), Cel>,
+    tags: Vec<Tag>,
+}
+
+impl Sprite {
+    /// Frame `frame` as drawn from the visible layers `layers` picks out
+    fn draw(&self, frame: usize, layers: impl Fn(&Layer) -> bool) -> DynamicImage {
+        let mut image = DynamicImage::new_rgba8(self.width, self.height);
+        for (index, layer) in self.layers.iter().enumerate() {
+            if !layer.visible || !layers(layer) {
+                continue;
+            }
+            if let Some(cel) = self.cels.get(&(frame, index)) {
+                blend_onto_faded(
+                    &mut image,
+                    &cel.image,
+                    cel.x,
+                    cel.y,
+                    layer.blend,
+                    cel.opacity * layer.opacity,
+                );
+            }
+        }
+        image
+    }
+
+    /// The top level layer or group of each dir, for files with their dirs
+    /// as layers
+    fn dir_layers(&self) -> Vec<Option<usize>> {
+        DIR_NAMES
+            .iter()
+            .map(|dir| {
+                self.layers.iter().position(|layer| {
+                    layer.child_level == 0 && layer.name.eq_ignore_ascii_case(dir)
+                })
+            })
+            .collect()
+    }
+
+    fn into_icon(self) -> AsepriteResult<Icon> {
+        let dir_layers = self.dir_layers();
+        let layer_dirs = match dir_layers.iter().rposition(Option::is_some) {
+            None => None,
+            Some(0) => Some(1),
+            Some(1..=3) => Some(4),
+            Some(_) => Some(8),
+        };
+        let untagged = [Tag {
+            from: 0,
+            to: self.durations.len() - 1,
+            direction: FORWARD,
+            repeat: 0,
+            name: String::new(),
+            data: None,
+        }];
+        let tags = if self.tags.is_empty() {
+            &untagged[..]
+        } else {
+            &self.tags
+        };
+        let mut states = vec![];
+        for tag in tags {
+            if tag.from > tag.to || tag.to >= self.durations.len() {
+                return Err(AsepriteError::Invalid(format!(
+                    "The tag `{}` goes past the last frame",
+                    tag.name
+                )));
+            }
+            if !fits_in_dmi(&tag.name) {
+                return Err(AsepriteError::Invalid(format!(
+                    "`{}` can't be the name of a state in a dmi",
+                    tag.name.escape_default()
+                )));
+            }
+            let data: TagData = tag
+                .data
+                .as_deref()
+                .and_then(|text| serde_json::from_str(text).ok())
+                .unwrap_or(TagData {
+                    dirs: layer_dirs.unwrap_or(1),
+                    ..Default::default()
+                });
+            if ![1, 4, 8].contains(&data.dirs) {
+                return Err(AsepriteError::Invalid(format!(
+                    "`{}` has {} dirs, states need 1, 4 or 8",
+                    tag.name, data.dirs
+                )));
+            }
+            let mut order: Vec<usize> = (tag.from..=tag.to).collect();
+            if matches!(tag.direction, REVERSE | PING_PONG_REVERSE) {
+                order.reverse();
+            }
+            let dirs = usize::from(data.dirs);
+            let (frames, images) = if layer_dirs.is_some() {
+                let images = order
+                    .iter()
+                    .flat_map(|frame| {
+                        dir_layers[..dirs]
+                            .iter()
+                            .map(|root| self.draw(*frame, |layer| Some(layer.root) == *root))
+                    })
+                    .collect();
+                (order, images)
+            } else {
+                if !order.len().is_multiple_of(dirs) {
+                    return Err(AsepriteError::Invalid(format!(
+                        "`{}` has {} frames, which can't be split evenly between its {dirs} dirs",
+                        tag.name,
+                        order.len()
+                    )));
+                }
+                let count = order.len() / dirs;
+                let images = (0..count)
+                    .flat_map(|frame| {
+                        let order = &order;
+                        (0..dirs).map(move |dir| order[dir * count + frame])
+                    })
+                    .map(|frame| self.draw(frame, |_| true))
+                    .collect();
+                (order[..count].to_vec(), images)
+            };
+            states.push(IconState {
+                name: tag.name.clone(),
+                dirs: data.dirs,
+                frames: frames.len() as u32,
+                images,
+                delay: (frames.len() > 1).then(|| {
+                    frames
+                        .iter()
+                        .map(|frame| f32::from(self.durations[*frame]) / 100.0)
+                        .collect()
+                }),
+                loop_flag: if tag.repeat == 0 {
+                    Looping::Indefinitely
+                } else {
+                    Looping::new(u32::from(tag.repeat))
+                },
+                rewind: matches!(tag.direction, PING_PONG | PING_PONG_REVERSE),
+                movement: data.movement,
+                hotspot: data.hotspot.map(|[x, y]| Hotspot { x, y }),
+                unknown_settings: (!data.settings.is_empty())
+                    .then(|| data.settings.into_iter().collect()),
+            });
+        }
+        Ok(Icon {
+            width: self.width,
+            height: self.height,
+            states,
+            ..Default::default()
+        })
+    }
+}
+
+/// Pixels of a cel, in any of the color depths aseprite writes
+fn cel_image(
+    pixels: &[u8],
+    (width, height): (u16, u16),
+    depth: u16,
+    palette: &[Rgba<u8>],
+    (transparent, background): (u8, bool),
+) -> AsepriteResult<DynamicImage> {
+    let count = usize::from(width) * usize::from(height);
+    let per_pixel = usize::from(depth / 8);
+    if pixels.len() < count * per_pixel {
+        return Err(AsepriteError::Truncated);
+    }
+    let colors = pixels.chunks_exact(per_pixel).take(count).map(|pixel| {
+        match pixel {
+            [red, green, blue, alpha] => Rgba([*red, *green, *blue, *alpha]),
+            [value, alpha] => Rgba([*value, *value, *value, *alpha]),
+            [index] if *index == transparent && !background => Rgba([0; 4]),
+            [index] => {
+                palette
+                    .get(usize::from(*index))
+                    .copied()
+                    .unwrap_or(Rgba([0; 4]))
+            }
+            _ => unreachable!("pixels are 1, 2 or 4 bytes"),
+        }
+    });
+    let image = RgbaImage::from_vec(
+        u32::from(width),
+        u32::from(height),
+        colors.flat_map(|color| color.0).collect(),
+    )
+    .ok_or(AsepriteError::Truncated)?;
+    Ok(DynamicImage::ImageRgba8(image))
+}
+
+/// Reads an aseprite file in to a dmi, each tag a state and any frames with
+/// no tag left out, or every frame a single unnamed state if there are no
+/// tags. Dirs are read from top level layers or groups named after them if
+/// there are any, leaving out every other layer, otherwise from the frames,
+/// laid out as [`write`] does. Visible layers are flattened in to the images
+/// # Errors
+/// Errors if the file can't be read, isn't an aseprite file, has no frames,
+/// uses tilemaps or has tags that can't be states
+pub fn read(mut reader: impl Read) -> AsepriteResult<Icon> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    let mut cursor = Cursor {
+        bytes: &bytes,
+        at: 0,
+    };
+    cursor.dword()?;
+    if cursor.word()? != FILE_MAGIC {
+        return Err(AsepriteError::NotAseprite);
+    }
+    let frame_count = cursor.word()?;
+    if frame_count == 0 {
+        return Err(AsepriteError::Invalid("The file has no frames".to_string()));
+    }
+    let width = u32::from(cursor.word()?);
+    let height = u32::from(cursor.word()?);
+    let depth = cursor.word()?;
+    if ![8, 16, 32].contains(&depth) {
+        return Err(AsepriteError::Unsupported(format!(
+            "{depth} bit color isn't supported"
+        )));
+    }
+    let layer_opacity = cursor.dword()? & 1 != 0;
+    cursor.take(2 + 8)?;
+    let transparent = cursor.byte()?;
+    cursor.at = HEADER_SIZE;
+
+    let mut sprite = Sprite {
+        width,
+        height,
+        durations: vec![],
+        layers: vec![],
+        cels: HashMap::new(),
+        tags: vec![],
+    };
+    let mut palette = vec![Rgba([0; 4]); 256];
+    // groups holding the next layer, and whether they're shown
+    let mut groups: Vec<(usize, bool)> = vec![];
+    let mut backgrounds = vec![];
+    for frame in 0..usize::from(frame_count) {
+        let start = cursor.at;
+        let size = cursor.dword()? as usize;
+        if cursor.word()? != FRAME_MAGIC {
+            return Err(AsepriteError::NotAseprite);
+        }
+        let old_chunks = cursor.word()?;
+        sprite.durations.push(cursor.word()?);
+        cursor.take(2)?;
+        let chunks = match cursor.dword()? {
+            0 => u32::from(old_chunks),
+            chunks => chunks,
+        };
+        // tag the next user data chunk belongs to
+        let mut next_tag = None;
+        for _ in 0..chunks {
+            let chunk_start = cursor.at;
+            let chunk_size = cursor.dword()? as usize;
+            let kind = cursor.word()?;
+            let mut chunk = Cursor {
+                bytes: bytes
+                    .get(chunk_start..chunk_start + chunk_size)
+                    .ok_or(AsepriteError::Truncated)?,
+                at: 6,
+            };
+            match kind {
+                LAYER_CHUNK => {
+                    let flags = chunk.word()?;
+                    let kind = chunk.word()?;
+                    let child_level = chunk.word()?;
+                    chunk.take(4)?;
+                    let blend = match chunk.word()? {
+                        1 => BlendMode::Multiply,
+                        2 => BlendMode::Screen,
+                        16 => BlendMode::Add,
+                        _ => BlendMode::Normal,
+                    };
+                    let opacity = chunk.byte()?;
+                    chunk.take(3)?;
+                    let name = chunk.string()?;
+                    let index = sprite.layers.len();
+                    groups.truncate(usize::from(child_level));
+                    let visible = groups.last().is_none_or(|(_, shown)| *shown)
+                        && flags & LAYER_VISIBLE != 0
+                        && flags & LAYER_REFERENCE == 0;
+                    sprite.layers.push(Layer {
+                        name,
+                        child_level,
+                        opacity: if layer_opacity {
+                            f32::from(opacity) / 255.0
+                        } else {
+                            1.0
+                        },
+                        blend,
+                        visible: visible && kind != GROUP_LAYER,
+                        root: groups.first().map_or(index, |(root, _)| *root),
+                    });
+                    backgrounds.push(flags & LAYER_BACKGROUND != 0);
+                    if kind == GROUP_LAYER {
+                        groups.push((index, visible));
+                    }
+                }
+                CEL_CHUNK => {
+                    let layer = usize::from(chunk.word()?);
+                    let x = i64::from(chunk.short()?);
+                    let y = i64::from(chunk.short()?);
+                    let opacity = f32::from(chunk.byte()?) / 255.0;
+                    let kind = chunk.word()?;
+                    chunk.take(7)?;
+                    let image = match kind {
+                        LINKED_CEL => {
+                            let linked = usize::from(chunk.word()?);
+                            match sprite.cels.get(&(linked, layer)) {
+                                Some(cel) => cel.image.clone(),
+                                None => continue,
+                            }
+                        }
+                        RAW_CEL | COMPRESSED_CEL => {
+                            let size = (chunk.word()?, chunk.word()?);
+                            let mut pixels = vec![];
+                            if kind == RAW_CEL {
+                                pixels.extend(chunk.rest());
+                            } else {
+                                ZlibDecoder::new(chunk.rest())
+                                    .read_to_end(&mut pixels)
+                                    .map_err(|_| {
+                                        AsepriteError::Invalid(
+                                            "A cel's pixels couldn't be decompressed".to_string(),
+                                        )
+                                    })?;
+                            }
+                            let background = backgrounds.get(layer).copied().unwrap_or(false);
+                            cel_image(&pixels, size, depth, &palette, (transparent, background))?
+                        }
+                        _ => {
+                            return Err(AsepriteError::Unsupported(
+                                "Tilemaps aren't supported".to_string(),
+                            ))
+                        }
+                    };
+                    sprite.cels.insert(
+                        (frame, layer),
+                        Cel {
+                            x,
+                            y,
+                            opacity,
+                            image,
+                        },
+                    );
+                }
+                PALETTE_CHUNK => {
+                    chunk.dword()?;
+                    let first = chunk.dword()? as usize;
+                    let last = chunk.dword()? as usize;
+                    chunk.take(8)?;
+                    for index in first..=last {
+                        let flags = chunk.word()?;
+                        let color = chunk.take(4)?;
+                        if let Some(entry) = palette.get_mut(index) {
+                            *entry = Rgba([color[0], color[1], color[2], color[3]]);
+                        }
+                        if flags & 1 != 0 {
+                            chunk.string()?;
+                        }
+                    }
+                }
+                TAGS_CHUNK => {
+                    let count = chunk.word()?;
+                    chunk.take(8)?;
+                    next_tag = Some(sprite.tags.len());
+                    for _ in 0..count {
+                        let from = usize::from(chunk.word()?);
+                        let to = usize::from(chunk.word()?);
+                        let direction = chunk.byte()?;
+                        let repeat = chunk.word()?;
+                        chunk.take(10)?;
+                        sprite.tags.push(Tag {
+                            from,
+                            to,
+                            direction,
+                            repeat,
+                            name: chunk.string()?,
+                            data: None,
+                        });
+                    }
+                }
+                USER_DATA_CHUNK => {
+                    if let Some(tag) = next_tag.and_then(|index| sprite.tags.get_mut(index)) {
+                        if chunk.dword()? & USER_DATA_TEXT != 0 {
+                            tag.data = Some(chunk.string()?);
+                        }
+                        next_tag = next_tag.map(|index| index + 1);
+                    }
+                }
+                _ => {}
+            }
+            if !matches!(kind, TAGS_CHUNK | USER_DATA_CHUNK) {
+                next_tag = None;
+            }
+            cursor.at = chunk_start + chunk_size;
+        }
+        cursor.at = start + size;
+    }
+    sprite.into_icon()
+}
+
+#[cfg(test)]
+mod test {
+    use image::Rgba;
+
+    use super::*;
+
+    fn image(shade: u8) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([shade, 0, 0, 255])))
+    }
+
+    fn icon() -> Icon {
+        Icon {
+            width: 4,
+            height: 4,
+            states: vec![
+                IconState {
+                    name: "door".to_string(),
+                    dirs: 4,
+                    frames: 2,
+                    delay: Some(vec![1.0, 2.5]),
+                    loop_flag: Looping::new(3),
+                    rewind: true,
+                    hotspot: Some(Hotspot { x: 1, y: 2 }),
+                    images: (0..8).map(image).collect(),
+                    ..Default::default()
+                },
+                IconState {
+                    name: "sign".to_string(),
+                    movement: true,
+                    images: vec![DynamicImage::new_rgba8(4, 4)],
+                    unknown_settings: Some(HashMap::from([(
+                        "future".to_string(),
+                        "1".to_string(),
+                    )])),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        for dirs_as_layers in [true, false] {
+            let written = write(&icon(), dirs_as_layers).unwrap();
+            assert_eq!(read(written.as_slice()).unwrap(), icon());
+        }
+    }
+
+    #[test]
+    fn reads_drawn_files() {
+        // no tags, a dir layer and a sketch over it that isn't a dir
+        let (south, sketch) = (image(1), image(2));
+        let frames = [
+            (100, vec![Some(&south), Some(&sketch)]),
+            (200, vec![None, Some(&sketch)]),
+        ];
+        let layers = [("South", LAYER_VISIBLE), ("sketch", LAYER_VISIBLE)];
+        let written = encode((4, 4), &layers, &frames, &[]).unwrap();
+        let icon = read(written.as_slice()).unwrap();
+        let state = &icon.states[0];
+        assert_eq!((state.name.as_str(), state.dirs), ("", 1));
+        assert_eq!(state.delay, Some(vec![1.0, 2.0]));
+        assert_eq!(state.images, [south, DynamicImage::new_rgba8(4, 4)]);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(matches!(
+            read([0_u8; 200].as_slice()),
+            Err(AsepriteError::NotAseprite)
+        ));
+        let written = write(&icon(), true).unwrap();
+        assert!(matches!(
+            read(&written[..written.len() / 2]),
+            Err(AsepriteError::Truncated)
+        ));
+        let empty = encode((4, 4), &[("South", LAYER_VISIBLE)], &[], &[]).unwrap();
+        assert!(matches!(
+            read(empty.as_slice()),
+            Err(AsepriteError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,113 @@
+use image::{DynamicImage, GenericImageView, Rgba};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// How an image is drawn over the one under it
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+    /// Drawn over the top, blending by alpha
+    #[default]
+    Normal,
+    /// Darkens what's under it, for shading and grime
+    Multiply,
+    /// Adds its colour to what's under it, for glows
+    Add,
+    /// Lightens what's under it, the opposite of multiply
+    Screen,
+}
+
+impl BlendMode {
+    /// One channel blended, both between 0 and 1
+    fn channel(self, under: f32, over: f32) -> f32 {
+        match self {
+            Self::Normal => over,
+            Self::Multiply => under * over,
+            Self::Add => (under + over).min(1.0),
+            Self::Screen => 1.0 - (1.0 - under) * (1.0 - over),
+        }
+    }
+}
+
+/// Draws `layer` over `base` with its top left at `x`, `y` (which can be
+/// negative, or past the edge), blending with `mode`. Whatever falls outside
+/// `base` is dropped
+pub fn blend_onto(base: &mut DynamicImage, layer: &DynamicImage, x: i64, y: i64, mode: BlendMode) {
+    blend_onto_faded(base, layer, x, y, mode, 1.0);
+}
+
+/// Draws `layer` over `base` like [`blend_onto`], faded to `opacity` between
+/// 0 and 1
+pub fn blend_onto_faded(
+    base: &mut DynamicImage,
+    layer: &DynamicImage,
+    x: i64,
+    y: i64,
+    mode: BlendMode,
+    opacity: f32,
+) {
+    let mut out = base.to_rgba8();
+    for (layer_x, layer_y, over) in layer.pixels() {
+        let (Ok(out_x), Ok(out_y)) = (
+            u32::try_from(x + i64::from(layer_x)),
+            u32::try_from(y + i64::from(layer_y)),
+        ) else {
+            continue;
+        };
+        if out_x >= out.width() || out_y >= out.height() || over[3] == 0 {
+            continue;
+        }
+        let under = out.get_pixel_mut(out_x, out_y);
+        *under = blend_pixel(*under, over, mode, opacity);
+    }
+    *base = DynamicImage::ImageRgba8(out);
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn blend_pixel(under: Rgba<u8>, over: Rgba<u8>, mode: BlendMode, opacity: f32) -> Rgba<u8> {
+    let to_unit = |value: u8| f32::from(value) / 255.0;
+    let under_alpha = to_unit(under[3]);
+    let over_alpha = to_unit(over[3]) * opacity.clamp(0.0, 1.0);
+    let alpha = over_alpha + under_alpha * (1.0 - over_alpha);
+    if alpha <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let mut out = [0; 4];
+    for channel in 0..3 {
+        let under_value = to_unit(under[channel]);
+        let over_value = to_unit(over[channel]);
+        // blend modes only apply where there's something under, elsewhere
+        // the layer is drawn as it is
+        let blended =
+            mode.channel(under_value, over_value) * under_alpha + over_value * (1.0 - under_alpha);
+        let value = (blended * over_alpha + under_value * under_alpha * (1.0 - over_alpha)) / alpha;
+        out[channel] = (value * 255.0).round() as u8;
+    }
+    out[3] = (alpha * 255.0).round() as u8;
+    Rgba(out)
+}
+
+#[cfg(test)]
+mod test {
+    use image::RgbaImage;
+
+    use super::*;
+
+    #[test]
+    fn blends() {
+        let grey = Rgba([128, 128, 128, 255]);
+        let mut base = DynamicImage::ImageRgba8(RgbaImage::from_pixel(2, 1, grey));
+        let layer = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([255, 0, 0, 255])));
+
+        let mut normal = base.clone();
+        blend_onto(&mut normal, &layer, 1, 0, BlendMode::Normal);
+        assert_eq!(normal.get_pixel(0, 0), grey);
+        assert_eq!(normal.get_pixel(1, 0), Rgba([255, 0, 0, 255]));
+
+        blend_onto(&mut base, &layer, 0, 0, BlendMode::Multiply);
+        // hanging off the edge is fine, it's cut off
+        blend_onto(&mut base, &layer, -1, 0, BlendMode::Add);
+        assert_eq!(base.get_pixel(0, 0), Rgba([128, 0, 0, 255]));
+        assert_eq!(base.get_pixel(1, 0), grey);
+    }
+}
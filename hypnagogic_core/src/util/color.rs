@@ -1,6 +1,8 @@
+use std::borrow::Cow;
 use std::num::ParseIntError;
 
 use image::DynamicImage;
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -119,6 +121,19 @@ impl<'de> Deserialize<'de> for Color {
     }
 }
 
+impl JsonSchema for Color {
+    fn schema_name() -> Cow<'static, str> {
+        "Color".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "string",
+            "pattern": "^#?([0-9a-fA-F]{6}|[0-9a-fA-F]{8})$",
+        })
+    }
+}
+
 impl From<Color> for [u8; 4] {
     fn from(color: Color) -> Self {
         [color.red, color.green, color.blue, color.alpha]
@@ -2,12 +2,25 @@ use std::fmt::{Display, Formatter};
 
 use enum_iterator::Sequence;
 use fixed_map::Key;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 /// Represents a "side" of a given tile. Directions correspond to unrotated
 /// cardinal directions, with "North" pointing "upwards."
 #[derive(
-    Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd, Debug, Sequence, Serialize, Deserialize, Key,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Ord,
+    PartialOrd,
+    Debug,
+    Sequence,
+    Serialize,
+    Deserialize,
+    Key,
+    JsonSchema,
 )]
 #[serde(rename_all = "snake_case")]
 pub enum Side {
@@ -73,7 +86,19 @@ impl Side {
 }
 
 #[derive(
-    Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd, Debug, Sequence, Serialize, Deserialize, Key,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Ord,
+    PartialOrd,
+    Debug,
+    Sequence,
+    Serialize,
+    Deserialize,
+    Key,
+    JsonSchema,
 )]
 #[serde(rename_all = "snake_case")]
 pub enum Corner {
@@ -104,9 +129,33 @@ impl Corner {
     }
 }
 
+impl Display for Corner {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Corner::NorthEast => write!(f, "north_east"),
+            Corner::SouthEast => write!(f, "south_east"),
+            Corner::SouthWest => write!(f, "south_west"),
+            Corner::NorthWest => write!(f, "north_west"),
+        }
+    }
+}
+
 /// Represents the five possible given states for a corner to be in when bitmask
 /// smoothing
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd, Debug, Deserialize, Serialize, Key)]
+#[derive(
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Ord,
+    PartialOrd,
+    Debug,
+    Deserialize,
+    Serialize,
+    Key,
+    JsonSchema,
+)]
 #[serde(rename_all = "snake_case")]
 pub enum CornerType {
     Convex,
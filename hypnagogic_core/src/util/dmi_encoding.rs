@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+
+use dmi::icon::Icon;
+use dmi::RawDmi;
+use image::{ImageFormat, Rgba, RgbaImage};
+use png::{AdaptiveFilterType, BitDepth, ColorType, Compression, Encoder, FilterType};
+
+use crate::operations::error::ProcessorResult;
+
+/// Where the metadata chunk goes in an encoded png, right after the 8 byte
+/// signature and the 25 byte header chunk
+const METADATA_OFFSET: usize = 8 + 25;
+
+/// Row filters tried on every encoding. Which compresses best depends on the
+/// art, so they're all tried
+const FILTERS: [(FilterType, AdaptiveFilterType); 6] = [
+    (FilterType::NoFilter, AdaptiveFilterType::NonAdaptive),
+    (FilterType::Sub, AdaptiveFilterType::NonAdaptive),
+    (FilterType::Up, AdaptiveFilterType::NonAdaptive),
+    (FilterType::Avg, AdaptiveFilterType::NonAdaptive),
+    (FilterType::Paeth, AdaptiveFilterType::NonAdaptive),
+    (FilterType::Sub, AdaptiveFilterType::Adaptive),
+];
+
+/// The pixels of a sheet as a palette and indexes in to it, for sheets with
+/// few enough colors
+struct Indexed {
+    /// Colors of the palette, any with transparency first so the
+    /// transparency chunk can leave out the opaque ones
+    palette: Vec<Rgba<u8>>,
+    depth: BitDepth,
+    /// Rows of indexes, packed to `depth`
+    data: Vec<u8>,
+}
+
+impl Indexed {
+    /// `sheet` as a palette, if it has 256 colors or less
+    fn new(sheet: &RgbaImage) -> Option<Self> {
+        let mut colors: BTreeMap<(bool, [u8; 4]), u8> = BTreeMap::new();
+        for pixel in sheet.pixels() {
+            if colors.len() > 256 {
+                return None;
+            }
+            colors.entry((pixel[3] == 255, pixel.0)).or_insert(0);
+        }
+        if colors.len() > 256 {
+            return None;
+        }
+        let palette: Vec<Rgba<u8>> = colors.keys().map(|(_, color)| Rgba(*color)).collect();
+        for (index, slot) in colors.values_mut().enumerate() {
+            *slot = index as u8;
+        }
+        let (depth, bits) = match palette.len() {
+            0..=2 => (BitDepth::One, 1),
+            3..=4 => (BitDepth::Two, 2),
+            5..=16 => (BitDepth::Four, 4),
+            _ => (BitDepth::Eight, 8),
+        };
+        let per_byte = 8 / bits;
+        let mut data = vec![];
+        for row in sheet.rows() {
+            let indexes: Vec<u8> = row
+                .map(|pixel| colors[&(pixel[3] == 255, pixel.0)])
+                .collect();
+            for chunk in indexes.chunks(per_byte) {
+                let mut byte = 0;
+                for (place, index) in chunk.iter().enumerate() {
+                    byte |= index << (8 - bits * (place + 1));
+                }
+                data.push(byte);
+            }
+        }
+        Some(Self {
+            palette,
+            depth,
+            data,
+        })
+    }
+}
+
+/// `sheet` encoded as a png with `filter`, as a palette if `indexed` is given
+fn encode_png(
+    sheet: &RgbaImage,
+    indexed: Option<&Indexed>,
+    (filter, adaptive): (FilterType, AdaptiveFilterType),
+) -> Result<Vec<u8>, png::EncodingError> {
+    let mut out = vec![];
+    let mut encoder = Encoder::new(&mut out, sheet.width(), sheet.height());
+    encoder.set_compression(Compression::Best);
+    encoder.set_filter(filter);
+    encoder.set_adaptive_filter(adaptive);
+    if let Some(indexed) = indexed {
+        encoder.set_color(ColorType::Indexed);
+        encoder.set_depth(indexed.depth);
+        let palette: Vec<u8> = indexed
+            .palette
+            .iter()
+            .flat_map(|color| [color[0], color[1], color[2]])
+            .collect();
+        let transparency: Vec<u8> = indexed
+            .palette
+            .iter()
+            .map(|color| color[3])
+            .take_while(|alpha| *alpha != 255)
+            .collect();
+        encoder.set_palette(palette);
+        if !transparency.is_empty() {
+            encoder.set_trns(transparency);
+        }
+        encoder.write_header()?.write_image_data(&indexed.data)?;
+    } else {
+        encoder.set_color(ColorType::Rgba);
+        encoder.set_depth(BitDepth::Eight);
+        encoder.write_header()?.write_image_data(sheet.as_raw())?;
+    }
+    Ok(out)
+}
+
+/// Encodes `icon` as small as it'll go, trying every row filter at the best
+/// compression, as a palette too if it has few enough colors, and keeping
+/// whichever's smallest. Never any bigger than [`Icon::save`] writes it
+/// # Errors
+/// Errors if the icon can't be saved at all
+pub fn encode_smallest(icon: &Icon) -> ProcessorResult<Vec<u8>> {
+    let mut plain = vec![];
+    icon.save(&mut plain)?;
+    let Some(metadata) = RawDmi::load(plain.as_slice())?.chunk_ztxt else {
+        return Ok(plain);
+    };
+    let mut metadata_chunk = vec![];
+    metadata.save(&mut metadata_chunk)?;
+    let sheet = image::load_from_memory_with_format(&plain, ImageFormat::Png)?.into_rgba8();
+    let indexed = Indexed::new(&sheet);
+
+    let mut smallest = plain;
+    for filter in FILTERS {
+        let candidates = [
+            encode_png(&sheet, None, filter),
+            indexed.as_ref().map_or(Ok(vec![]), |indexed| {
+                encode_png(&sheet, Some(indexed), filter)
+            }),
+        ];
+        // anything that fails to encode one way is left to another
+        for mut candidate in candidates.into_iter().flatten() {
+            if candidate.len() < METADATA_OFFSET {
+                continue;
+            }
+            candidate.splice(
+                METADATA_OFFSET..METADATA_OFFSET,
+                metadata_chunk.iter().copied(),
+            );
+            if candidate.len() < smallest.len() {
+                smallest = candidate;
+            }
+        }
+    }
+    Ok(smallest)
+}
+
+#[cfg(test)]
+mod test {
+    use dmi::icon::IconState;
+    use image::DynamicImage;
+
+    use super::*;
+
+    fn icon(colors: u8) -> Icon {
+        let mut image = DynamicImage::new_rgba8(32, 32);
+        let pixels = image.as_mut_rgba8().unwrap();
+        for (x, y, pixel) in pixels.enumerate_pixels_mut() {
+            let shade = ((x + y) % u32::from(colors)) as u8;
+            *pixel = Rgba([shade * 5, 0, 200, if shade == 0 { 0 } else { 255 }]);
+        }
+        Icon {
+            width: 32,
+            height: 32,
+            states: vec![
+                IconState {
+                    name: "first".to_string(),
+                    images: vec![image.clone()],
+                    ..Default::default()
+                },
+                IconState {
+                    name: "second".to_string(),
+                    dirs: 4,
+                    images: vec![image; 4],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn smaller_and_the_same() {
+        for colors in [2, 3, 12, 40] {
+            let icon = icon(colors);
+            let mut plain = vec![];
+            icon.save(&mut plain).unwrap();
+            let encoded = encode_smallest(&icon).unwrap();
+            assert!(encoded.len() < plain.len(), "{colors} colors");
+
+            let loaded = Icon::load(encoded.as_slice()).unwrap();
+            assert_eq!(loaded.states.len(), 2);
+            assert_eq!(loaded.states[1].dirs, 4);
+            for (loaded, state) in loaded.states.iter().zip(&icon.states) {
+                for (loaded, image) in loaded.images.iter().zip(&state.images) {
+                    assert_eq!(loaded.to_rgba8(), image.to_rgba8(), "{colors} colors");
+                }
+            }
+        }
+    }
+}
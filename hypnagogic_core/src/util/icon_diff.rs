@@ -0,0 +1,376 @@
+use std::fmt::Write;
+
+use dmi::icon::{Icon, IconState};
+use image::{imageops, DynamicImage, GenericImageView, Rgba};
+
+use crate::generation::rect::draw_rect;
+use crate::generation::text::generate_label;
+use crate::util::color::Color;
+
+/// Color used to mark pixels that differ in a state diff image
+const CHANGED_COLOR: Rgba<u8> = Rgba([255, 0, 255, 255]);
+
+#[derive(Clone, Debug)]
+pub enum StateChange {
+    /// Only exists in the new icon
+    Added,
+    /// Only exists in the old icon
+    Removed,
+    /// Exists in both, but differs. `reasons` describes what differs, and
+    /// `diff` is an image of every frame with changed pixels highlighted, if
+    /// the icon sizes match
+    Changed {
+        reasons: Vec<String>,
+        diff: Option<DynamicImage>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct StateDiff {
+    pub name: String,
+    pub movement: bool,
+    pub change: StateChange,
+}
+
+/// Differences between two versions of the same icon
+#[derive(Clone, Debug)]
+pub struct IconDiff {
+    /// Old and new icon sizes, if they differ
+    pub size_change: Option<((u32, u32), (u32, u32))>,
+    /// Every state that was added, removed or changed. Identical states are
+    /// left out
+    pub states: Vec<StateDiff>,
+    pub unchanged: usize,
+}
+
+impl IconDiff {
+    #[must_use]
+    pub fn is_identical(&self) -> bool {
+        self.size_change.is_none() && self.states.is_empty()
+    }
+
+    /// Human readable summary of the diff, one line per state
+    #[must_use]
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+        if let Some(((old_x, old_y), (new_x, new_y))) = self.size_change {
+            let _ = writeln!(
+                summary,
+                "icon size changed from {old_x}x{old_y} to {new_x}x{new_y}"
+            );
+        }
+        for state in &self.states {
+            let name = if state.movement {
+                format!("\"{}\" (movement)", state.name)
+            } else {
+                format!("\"{}\"", state.name)
+            };
+            match &state.change {
+                StateChange::Added => {
+                    let _ = writeln!(summary, "+ {name}");
+                }
+                StateChange::Removed => {
+                    let _ = writeln!(summary, "- {name}");
+                }
+                StateChange::Changed { reasons, .. } => {
+                    let _ = writeln!(summary, "~ {name}: {}", reasons.join(", "));
+                }
+            }
+        }
+        let count = |predicate: fn(&StateChange) -> bool| {
+            self.states
+                .iter()
+                .filter(|state| predicate(&state.change))
+                .count()
+        };
+        let _ = writeln!(
+            summary,
+            "{} added, {} removed, {} changed, {} unchanged",
+            count(|change| matches!(change, StateChange::Added)),
+            count(|change| matches!(change, StateChange::Removed)),
+            count(|change| matches!(change, StateChange::Changed { .. })),
+            self.unchanged
+        );
+        summary
+    }
+}
+
+/// Compares two icons state by state. States are matched up by name and
+/// movement flag, so reordering states alone isn't considered a change
+#[must_use]
+pub fn diff_icons(old: &Icon, new: &Icon) -> IconDiff {
+    let same_size = old.width == new.width && old.height == new.height;
+    let mut states = vec![];
+    let mut unchanged = 0;
+    let mut matched_old = vec![false; old.states.len()];
+    for new_state in &new.states {
+        // duplicate names get matched in order, so the second "foo" in new
+        // pairs with the second "foo" in old
+        let old_index = old.states.iter().enumerate().position(|(index, state)| {
+            !matched_old[index]
+                && state.name == new_state.name
+                && state.movement == new_state.movement
+        });
+        let Some(old_index) = old_index else {
+            states.push(StateDiff {
+                name: new_state.name.clone(),
+                movement: new_state.movement,
+                change: StateChange::Added,
+            });
+            continue;
+        };
+        matched_old[old_index] = true;
+        let old_state = &old.states[old_index];
+
+        let mut reasons = vec![];
+        if old_state.dirs != new_state.dirs {
+            reasons.push(format!("dirs {} -> {}", old_state.dirs, new_state.dirs));
+        }
+        if old_state.frames != new_state.frames {
+            reasons.push(format!(
+                "frames {} -> {}",
+                old_state.frames, new_state.frames
+            ));
+        }
+        if old_state.delay != new_state.delay {
+            reasons.push(format!(
+                "delays {:?} -> {:?}",
+                old_state.delay.as_deref().unwrap_or_default(),
+                new_state.delay.as_deref().unwrap_or_default()
+            ));
+        }
+        if old_state.rewind != new_state.rewind {
+            reasons.push(format!(
+                "rewind {} -> {}",
+                old_state.rewind, new_state.rewind
+            ));
+        }
+        if old_state.loop_flag != new_state.loop_flag {
+            reasons.push("loop changed".to_string());
+        }
+        if old_state.hotspot != new_state.hotspot {
+            reasons.push("hotspot changed".to_string());
+        }
+        let pixels_changed = old_state.images != new_state.images;
+        if pixels_changed {
+            reasons.push("pixels changed".to_string());
+        }
+
+        if reasons.is_empty() {
+            unchanged += 1;
+            continue;
+        }
+        let diff = (same_size && pixels_changed)
+            .then(|| diff_image(old_state, new_state, new.width, new.height));
+        states.push(StateDiff {
+            name: new_state.name.clone(),
+            movement: new_state.movement,
+            change: StateChange::Changed { reasons, diff },
+        });
+    }
+    for (old_state, _) in old
+        .states
+        .iter()
+        .zip(matched_old)
+        .filter(|(_, matched)| !matched)
+    {
+        states.push(StateDiff {
+            name: old_state.name.clone(),
+            movement: old_state.movement,
+            change: StateChange::Removed,
+        });
+    }
+
+    IconDiff {
+        size_change: (!same_size).then_some(((old.width, old.height), (new.width, new.height))),
+        states,
+        unchanged,
+    }
+}
+
+/// Spacing around and between everything in the before/after composite
+const COMPOSITE_PADDING: u32 = 4;
+/// Height of a line of label text, plus a pixel of spacing
+const LABEL_HEIGHT: u32 = 6;
+
+/// Renders every added, removed or changed state as a row of old images next
+/// to new images, labelled with the state name. Returns `None` if nothing
+/// changed
+#[must_use]
+pub fn before_after_composite(old: &Icon, new: &Icon, diff: &IconDiff) -> Option<DynamicImage> {
+    if diff.states.is_empty() {
+        return None;
+    }
+    let rows: Vec<(&StateDiff, Option<&IconState>, Option<&IconState>)> = diff
+        .states
+        .iter()
+        .map(|state| {
+            match state.change {
+                StateChange::Added => (state, None, find_state(new, state)),
+                StateChange::Removed => (state, find_state(old, state), None),
+                StateChange::Changed { .. } => {
+                    (state, find_state(old, state), find_state(new, state))
+                }
+            }
+        })
+        .collect();
+
+    let images_width = |state: Option<&IconState>, width: u32| {
+        state.map_or(0, |state| state.images.len() as u32 * width)
+    };
+    let before_width = rows
+        .iter()
+        .map(|(_, before, _)| images_width(*before, old.width))
+        .max()
+        .unwrap_or(0)
+        .max(old.width);
+    let after_width = rows
+        .iter()
+        .map(|(_, _, after)| images_width(*after, new.width))
+        .max()
+        .unwrap_or(0)
+        .max(new.width);
+    let row_height = LABEL_HEIGHT + old.height.max(new.height) + COMPOSITE_PADDING;
+    let after_x = COMPOSITE_PADDING * 2 + before_width;
+
+    let width = after_x + after_width + COMPOSITE_PADDING;
+    let height = COMPOSITE_PADDING + LABEL_HEIGHT + row_height * rows.len() as u32;
+    let mut composite = DynamicImage::new_rgba8(width, height);
+    let background = Color::new(60, 60, 60, 255);
+    let text_color = Color::new(238, 238, 238, 255);
+    draw_rect(&mut composite, 0, 0, width, height, background);
+    let mut draw = |image: &DynamicImage, x: u32, y: u32| {
+        imageops::overlay(&mut composite, image, x as i64, y as i64);
+    };
+
+    let headers = [("before", COMPOSITE_PADDING), ("after", after_x)];
+    for (header, x) in headers {
+        draw(
+            &generate_label(header, text_color).unwrap(),
+            x,
+            COMPOSITE_PADDING,
+        );
+    }
+    for (index, (state, before, after)) in rows.iter().enumerate() {
+        let y = COMPOSITE_PADDING + LABEL_HEIGHT + row_height * index as u32;
+        let marker = match state.change {
+            StateChange::Added => "+",
+            StateChange::Removed => "-",
+            StateChange::Changed { .. } => "~",
+        };
+        let label = format!("{marker} {}", state.name);
+        draw(
+            &generate_label(&label, text_color).unwrap(),
+            COMPOSITE_PADDING,
+            y,
+        );
+        let images_y = y + LABEL_HEIGHT;
+        for (icon_state, x, width) in [
+            (before, COMPOSITE_PADDING, old.width),
+            (after, after_x, new.width),
+        ] {
+            let Some(icon_state) = icon_state else {
+                continue;
+            };
+            for (frame, image) in icon_state.images.iter().enumerate() {
+                draw(image, x + frame as u32 * width, images_y);
+            }
+        }
+    }
+    Some(composite)
+}
+
+fn find_state<'a>(icon: &'a Icon, state: &StateDiff) -> Option<&'a IconState> {
+    icon.states
+        .iter()
+        .find(|candidate| candidate.name == state.name && candidate.movement == state.movement)
+}
+
+/// Lays out every image of the new state side by side, faded, with pixels that
+/// differ from the old state drawn in a solid highlight color. Images missing
+/// from either side count as transparent
+fn diff_image(old: &IconState, new: &IconState, width: u32, height: u32) -> DynamicImage {
+    let count = old.images.len().max(new.images.len());
+    let blank = DynamicImage::new_rgba8(width, height);
+    let mut out = DynamicImage::new_rgba8(width * count as u32, height);
+    for index in 0..count {
+        let old_image = old.images.get(index).unwrap_or(&blank);
+        let new_image = new.images.get(index).unwrap_or(&blank);
+        let mut cell = image::RgbaImage::new(width, height);
+        for (x, y, pixel) in cell.enumerate_pixels_mut() {
+            let old_pixel = old_image.get_pixel(x, y);
+            let new_pixel = new_image.get_pixel(x, y);
+            *pixel = if old_pixel == new_pixel {
+                let [r, g, b, a] = new_pixel.0;
+                Rgba([r, g, b, a / 4])
+            } else {
+                CHANGED_COLOR
+            };
+        }
+        imageops::replace(&mut out, &cell, (index as u32 * width) as i64, 0);
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn state(name: &str, shade: u8) -> IconState {
+        IconState {
+            name: name.to_string(),
+            dirs: 1,
+            frames: 1,
+            images: vec![DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                2,
+                2,
+                Rgba([shade, 0, 0, 255]),
+            ))],
+            ..Default::default()
+        }
+    }
+
+    fn icon(states: Vec<IconState>) -> Icon {
+        Icon {
+            version: dmi::icon::DmiVersion::default(),
+            width: 2,
+            height: 2,
+            states,
+        }
+    }
+
+    #[test]
+    fn diff_icons_test() {
+        let old = icon(vec![
+            state("same", 0),
+            state("changed", 0),
+            state("gone", 0),
+        ]);
+        let new = icon(vec![state("changed", 1), state("same", 0), state("new", 0)]);
+        let diff = diff_icons(&old, &new);
+
+        assert!(!diff.is_identical());
+        assert_eq!(diff.unchanged, 1);
+        let summary = diff.summary();
+        assert!(summary.contains("~ \"changed\": pixels changed"));
+        assert!(summary.contains("+ \"new\""));
+        assert!(summary.contains("- \"gone\""));
+        assert!(summary.contains("1 added, 1 removed, 1 changed, 1 unchanged"));
+
+        let StateChange::Changed {
+            diff: Some(image), ..
+        } = &diff.states[0].change
+        else {
+            panic!("Expected a diff image for the changed state");
+        };
+        assert_eq!(image.get_pixel(0, 0), CHANGED_COLOR);
+
+        assert!(diff_icons(&old, &old).is_identical());
+
+        let composite = before_after_composite(&old, &new, &diff).unwrap();
+        // a header line, then a label and a row of images for each of the 3
+        // differing states
+        assert_eq!(composite.height(), 4 + 6 + (6 + 2 + 4) * 3);
+        assert!(before_after_composite(&old, &old, &diff_icons(&old, &old)).is_none());
+    }
+}
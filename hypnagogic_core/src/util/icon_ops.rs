@@ -1,60 +1,102 @@
 use dmi::icon::IconState;
-use image::{DynamicImage, GenericImageView};
+use image::{imageops, DynamicImage, GenericImageView};
 
+use crate::config::blocks::cutters::Animation;
 use crate::util::color::Color;
 
-// Removes duplicate frames from the icon state's animation, if it has any
+// Removes duplicate frames from the icon state's animation, if it has any and
+// `animation` doesn't turn deduping off. A frame only counts as a duplicate
+// when it matches in every dir
 #[must_use]
-pub fn dedupe_frames(icon_state: IconState) -> IconState {
-    struct AccumulatedAnim {
-        delays: Vec<f32>,
-        frames: Vec<DynamicImage>,
-        working_index: u32,
-    }
-
-    if icon_state.frames <= 1 {
+pub fn dedupe_frames(icon_state: IconState, animation: Option<&Animation>) -> IconState {
+    if icon_state.frames <= 1 || animation.is_some_and(|animation| animation.dedupe == Some(false))
+    {
         return icon_state;
     }
+    let tolerance = animation
+        .and_then(|animation| animation.dedupe_tolerance)
+        .unwrap_or(0);
     let Some(current_delays) = &icon_state.delay else {
         return icon_state;
     };
+    let num_frames = icon_state.frames as usize;
+    let image = |dir: usize, frame: usize| &icon_state.images[dir * num_frames + frame];
 
     // As we walk through the frames in this icon state, we're going to keep track
     // of the ones that Are duplicates, and "dedupe" them by simply adding extra
-    // frame delay and removing the extra frame
-    let deduped_anim = current_delays.iter().zip(icon_state.images).fold(
-        AccumulatedAnim {
-            delays: Vec::new(),
-            frames: Vec::new(),
-            working_index: 0,
-        },
-        |mut acc, elem| {
-            let (&current_delay, current_frame) = elem;
-            if acc.frames.is_empty() {
-                acc.delays.push(current_delay);
-                acc.frames.push(current_frame);
-                return acc;
-            }
-            let current_index = acc.working_index;
-            if acc.frames[current_index as usize] == current_frame {
-                acc.delays[current_index as usize] += current_delay;
-            } else {
-                acc.delays.push(current_delay);
-                acc.frames.push(current_frame);
-                acc.working_index += 1;
-            }
-            acc
-        },
-    );
+    // frame delay to the frame they repeat
+    let mut kept: Vec<usize> = vec![];
+    let mut delays: Vec<f32> = vec![];
+    for (frame, &delay) in current_delays.iter().enumerate().take(num_frames) {
+        let repeats_last = kept.last().is_some_and(|&last| {
+            (0..usize::from(icon_state.dirs))
+                .all(|dir| frames_match(image(dir, last), image(dir, frame), tolerance))
+        });
+        if repeats_last {
+            *delays.last_mut().unwrap() += delay;
+        } else {
+            kept.push(frame);
+            delays.push(delay);
+        }
+    }
 
+    let images = (0..usize::from(icon_state.dirs))
+        .flat_map(|dir| kept.iter().map(move |&frame| (dir, frame)))
+        .map(|(dir, frame)| image(dir, frame).clone())
+        .collect();
     IconState {
-        frames: deduped_anim.working_index + 1,
-        images: deduped_anim.frames,
-        delay: Some(deduped_anim.delays),
+        frames: kept.len() as u32,
+        images,
+        delay: Some(delays),
         ..icon_state
     }
 }
 
+/// Whether every channel of every pixel in `first` is within `tolerance` of
+/// the same pixel in `second`
+#[must_use]
+pub fn frames_match(first: &DynamicImage, second: &DynamicImage, tolerance: u8) -> bool {
+    if tolerance == 0 {
+        return first == second;
+    }
+    first.dimensions() == second.dimensions()
+        && first
+            .pixels()
+            .zip(second.pixels())
+            .all(|((_, _, first), (_, _, second))| {
+                first
+                    .0
+                    .iter()
+                    .zip(second.0)
+                    .all(|(first, second)| first.abs_diff(second) <= tolerance)
+            })
+}
+
+/// `image` turned `degrees` clockwise, which should be a multiple of 90
+#[must_use]
+pub fn turned_clockwise(image: &DynamicImage, degrees: u32) -> DynamicImage {
+    match degrees % 360 {
+        90 => image.rotate90(),
+        180 => image.rotate180(),
+        270 => image.rotate270(),
+        _ => image.clone(),
+    }
+}
+
+/// Places `image` on a transparent canvas of `width` by `height`, anchored to
+/// the bottom left like byond draws icons larger than a tile
+#[must_use]
+pub fn pad_to_canvas(image: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+    let mut canvas = DynamicImage::new_rgba8(width, height);
+    imageops::overlay(
+        &mut canvas,
+        image,
+        0,
+        i64::from(height) - i64::from(image.height()),
+    );
+    canvas
+}
+
 #[must_use]
 pub fn colors_in_image(image: &DynamicImage) -> Vec<Color> {
     let mut colors = Vec::new();
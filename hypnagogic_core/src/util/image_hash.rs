@@ -0,0 +1,115 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use image::imageops::FilterType;
+use image::DynamicImage;
+
+/// Hashes of a single image, for spotting copies of the same art
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ImageHashes {
+    /// Same for images that are pixel for pixel identical
+    pub exact: u64,
+    /// Same for images with identical dimensions and transparency, whatever
+    /// their colors are. Matching shape hashes usually mean one is a recolor
+    /// of the other. `None` for fully opaque images, since those all share a
+    /// shape
+    pub shape: Option<u64>,
+    /// Perceptual hash of the image's brightness gradients, close images have
+    /// a small [`ImageHashes::distance`] between them
+    pub difference: u64,
+}
+
+impl ImageHashes {
+    #[must_use]
+    pub fn new(image: &DynamicImage) -> Self {
+        let rgba = image.to_rgba8();
+
+        let mut exact = DefaultHasher::new();
+        rgba.dimensions().hash(&mut exact);
+        rgba.as_raw().hash(&mut exact);
+
+        let has_transparency = rgba.pixels().any(|pixel| pixel[3] == 0);
+        let shape = has_transparency.then(|| {
+            let mut shape = DefaultHasher::new();
+            rgba.dimensions().hash(&mut shape);
+            for pixel in rgba.pixels() {
+                (pixel[3] > 0).hash(&mut shape);
+            }
+            shape.finish()
+        });
+
+        Self {
+            exact: exact.finish(),
+            shape,
+            difference: difference_hash(image),
+        }
+    }
+
+    /// How many bits of the perceptual hashes differ, 0 to 64. Anything 6 or
+    /// under is generally the same image with small edits
+    #[must_use]
+    pub fn distance(&self, other: &Self) -> u32 {
+        (self.difference ^ other.difference).count_ones()
+    }
+}
+
+/// dHash: shrinks the image down to 9x8 greyscale, then records whether each
+/// pixel is brighter than the one to its right
+fn difference_hash(image: &DynamicImage) -> u64 {
+    // transparent pixels are counted as black, so the transparency of
+    // sprites still shapes the hash
+    let mut flattened = image.to_rgba8();
+    for pixel in flattened.pixels_mut() {
+        if pixel[3] == 0 {
+            *pixel = image::Rgba([0, 0, 0, 255]);
+        }
+    }
+    let small = DynamicImage::ImageRgba8(flattened)
+        .resize_exact(9, 8, FilterType::Triangle)
+        .to_luma8();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod test {
+    use image::Rgba;
+
+    use super::*;
+
+    fn gradient(tint: [u8; 3]) -> DynamicImage {
+        let mut image = image::RgbaImage::new(32, 32);
+        // the top row is left transparent, to give the images a shape
+        for (x, _y, pixel) in image.enumerate_pixels_mut().skip(32) {
+            let shade = (x * 8) as u8;
+            *pixel = Rgba([
+                shade.saturating_add(tint[0]),
+                shade.saturating_add(tint[1]),
+                shade.saturating_add(tint[2]),
+                255,
+            ]);
+        }
+        DynamicImage::ImageRgba8(image)
+    }
+
+    #[test]
+    fn hashes_test() {
+        let base = ImageHashes::new(&gradient([0, 0, 0]));
+        let same = ImageHashes::new(&gradient([0, 0, 0]));
+        let tinted = ImageHashes::new(&gradient([20, 0, 0]));
+
+        assert_eq!(base, same);
+        assert_ne!(base.exact, tinted.exact);
+        assert!(base.shape.is_some());
+        assert_eq!(base.shape, tinted.shape);
+        assert!(base.distance(&tinted) <= 6);
+    }
+}
@@ -0,0 +1,402 @@
+//! Where a bitmask cutter reads each piece of its input from, and where it
+//! puts them in its output. Preview tools and linters can use this to draw the
+//! exact grid the cutter uses instead of working it out again.
+//!
+//! Everything is in pixels, with the origin at the top left of the image
+
+use enum_iterator::all;
+use serde::{Deserialize, Serialize};
+
+use crate::config::blocks::cutters::{
+    CutPosition,
+    IconSize,
+    InputLayout,
+    OutputIconPosition,
+    OutputIconSize,
+};
+use crate::util::corners::{Corner, CornerType, Side};
+
+/// The span of a tile a side covers, along the axis it's on. North and south
+/// span rows, east and west span columns
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct SideSpacing {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl SideSpacing {
+    #[must_use]
+    pub fn step(self) -> u32 {
+        self.end - self.start
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    /// The same rect, moved right by `x` and down by `y`
+    #[must_use]
+    pub const fn offset(self, x: u32, y: u32) -> Self {
+        Self {
+            x: self.x + x,
+            y: self.y + y,
+            ..self
+        }
+    }
+}
+
+/// The part of a tile `side` covers, with tiles split at `cut_pos`
+#[must_use]
+pub fn side_spacing(icon_size: IconSize, cut_pos: CutPosition, side: Side) -> SideSpacing {
+    match side {
+        Side::North => {
+            SideSpacing {
+                start: 0,
+                end: cut_pos.y,
+            }
+        }
+        Side::South => {
+            SideSpacing {
+                start: cut_pos.y,
+                end: icon_size.y,
+            }
+        }
+        Side::East => {
+            SideSpacing {
+                start: cut_pos.x,
+                end: icon_size.x,
+            }
+        }
+        Side::West => {
+            SideSpacing {
+                start: 0,
+                end: cut_pos.x,
+            }
+        }
+    }
+}
+
+/// Where `corner` is within a tile. Corners are placed in the output at the
+/// same spot they're cut from
+#[must_use]
+pub fn corner_rect(icon_size: IconSize, cut_pos: CutPosition, corner: Corner) -> Rect {
+    let (horizontal, vertical) = corner.sides_of_corner();
+    let horizontal = side_spacing(icon_size, cut_pos, horizontal);
+    let vertical = side_spacing(icon_size, cut_pos, vertical);
+    Rect {
+        x: horizontal.start,
+        y: vertical.start,
+        width: horizontal.step(),
+        height: vertical.step(),
+    }
+}
+
+/// A rect the size of `corner`'s, centered on the cut point instead of
+/// reaching out to the tile's corner, so it's clear of the tile's edges
+#[must_use]
+pub fn centered_corner_rect(icon_size: IconSize, cut_pos: CutPosition, corner: Corner) -> Rect {
+    let rect = corner_rect(icon_size, cut_pos, corner);
+    Rect {
+        x: cut_pos
+            .x
+            .saturating_sub(rect.width / 2)
+            .min(icon_size.x - rect.width),
+        y: cut_pos
+            .y
+            .saturating_sub(rect.height / 2)
+            .min(icon_size.y - rect.height),
+        ..rect
+    }
+}
+
+/// How the tiles of an input sheet are arranged. Positions run left to right,
+/// wrapping on to the next row every `columns` tiles if set, and each frame
+/// is `rows` rows of tiles below the last. A vertical layout is the same
+/// turned on its side, so rows become columns and the other way around
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct InputGrid {
+    pub layout: InputLayout,
+    pub columns: Option<u32>,
+    pub rows: u32,
+}
+
+impl Default for InputGrid {
+    /// One long strip of positions, with a row per frame
+    fn default() -> Self {
+        Self {
+            layout: InputLayout::Horizontal,
+            columns: None,
+            rows: 1,
+        }
+    }
+}
+
+impl InputGrid {
+    /// Column and row of `position` within a frame
+    #[must_use]
+    pub const fn cell(self, position: u32) -> (u32, u32) {
+        match self.columns {
+            Some(columns) => (position % columns, position / columns),
+            None => (position, 0),
+        }
+    }
+}
+
+/// Where the tile at `position` is in an input sheet, in the rows of `frame`
+#[must_use]
+pub const fn tile_rect(icon_size: IconSize, grid: InputGrid, position: u32, frame: u32) -> Rect {
+    let (column, row) = grid.cell(position);
+    let row = frame * grid.rows + row;
+    let (x, y) = match grid.layout {
+        InputLayout::Horizontal => (column, row),
+        InputLayout::Vertical => (row, column),
+    };
+    Rect {
+        x: x * icon_size.x,
+        y: y * icon_size.y,
+        width: icon_size.x,
+        height: icon_size.y,
+    }
+}
+
+/// Where `corner` is cut from the tile at `position`, in the rows of `frame`
+#[must_use]
+pub fn cut_rect(
+    icon_size: IconSize,
+    cut_pos: CutPosition,
+    grid: InputGrid,
+    corner: Corner,
+    position: u32,
+    frame: u32,
+) -> Rect {
+    let tile = tile_rect(icon_size, grid, position, frame);
+    corner_rect(icon_size, cut_pos, corner).offset(tile.x, tile.y)
+}
+
+/// Everything a cutter reads from an input sheet and writes to each output
+/// state
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct SheetLayout {
+    pub icon_size: IconSize,
+    pub cut_pos: CutPosition,
+    pub grid: InputGrid,
+    pub frames: u32,
+    /// Every tile that's read, in order of position
+    pub slots: Vec<SlotLayout>,
+    pub output: OutputLayout,
+}
+
+/// One tile of an input sheet, and what it's used for
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct SlotLayout {
+    pub position: u32,
+    /// The tile on every frame's row
+    pub frames: Vec<Rect>,
+    #[serde(flatten)]
+    pub contents: SlotContents,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SlotContents {
+    /// Cut in to corners of a type
+    Corners {
+        corner_type: CornerType,
+        corners: Vec<CornerCut>,
+    },
+    /// Copied whole as a junction's state
+    Prefab { junction: u8 },
+}
+
+/// Where one corner is cut from on every frame
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct CornerCut {
+    pub corner: Corner,
+    pub frames: Vec<Rect>,
+}
+
+/// Where the pieces of an input end up in each output state
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct OutputLayout {
+    pub size: OutputIconSize,
+    /// Where each corner is placed in states assembled from corners
+    pub corners: Vec<CornerPlacement>,
+    /// Where prefabs are placed
+    pub prefab: Rect,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct CornerPlacement {
+    pub corner: Corner,
+    pub rect: Rect,
+}
+
+impl SlotLayout {
+    /// A tile that's cut in to corners of `corner_type`, each split at its
+    /// `cut_pos`
+    #[must_use]
+    pub fn corners(
+        icon_size: IconSize,
+        cut_pos: impl Fn(Corner) -> CutPosition,
+        grid: InputGrid,
+        corner_type: CornerType,
+        position: u32,
+        frames: u32,
+    ) -> Self {
+        let corners = all::<Corner>()
+            .map(|corner| {
+                CornerCut {
+                    corner,
+                    frames: (0..frames)
+                        .map(|frame| {
+                            cut_rect(icon_size, cut_pos(corner), grid, corner, position, frame)
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+        Self {
+            position,
+            frames: Self::tiles(icon_size, grid, position, frames),
+            contents: SlotContents::Corners {
+                corner_type,
+                corners,
+            },
+        }
+    }
+
+    /// A tile that's copied whole as the state of `junction`
+    #[must_use]
+    pub fn prefab(
+        icon_size: IconSize,
+        grid: InputGrid,
+        junction: u8,
+        position: u32,
+        frames: u32,
+    ) -> Self {
+        Self {
+            position,
+            frames: Self::tiles(icon_size, grid, position, frames),
+            contents: SlotContents::Prefab { junction },
+        }
+    }
+
+    fn tiles(icon_size: IconSize, grid: InputGrid, position: u32, frames: u32) -> Vec<Rect> {
+        (0..frames)
+            .map(|frame| tile_rect(icon_size, grid, position, frame))
+            .collect()
+    }
+}
+
+impl OutputLayout {
+    /// Where everything is placed, with each corner split at its `cut_pos`
+    #[must_use]
+    pub fn new(
+        icon_size: IconSize,
+        cut_pos: impl Fn(Corner) -> CutPosition,
+        size: OutputIconSize,
+        prefab_pos: OutputIconPosition,
+    ) -> Self {
+        let corners = all::<Corner>()
+            .map(|corner| {
+                CornerPlacement {
+                    corner,
+                    rect: corner_rect(icon_size, cut_pos(corner), corner),
+                }
+            })
+            .collect();
+        Self {
+            size,
+            corners,
+            prefab: Rect {
+                x: prefab_pos.x,
+                y: prefab_pos.y,
+                width: icon_size.x,
+                height: icon_size.y,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn corners_cover_tile() {
+        let icon_size = IconSize { x: 32, y: 48 };
+        let cut_pos = CutPosition { x: 12, y: 20 };
+        let area: u32 = all::<Corner>()
+            .map(|corner| {
+                let rect = corner_rect(icon_size, cut_pos, corner);
+                rect.width * rect.height
+            })
+            .sum();
+        assert_eq!(area, 32 * 48);
+
+        assert_eq!(
+            cut_rect(
+                icon_size,
+                cut_pos,
+                InputGrid::default(),
+                Corner::SouthEast,
+                2,
+                1
+            ),
+            Rect {
+                x: 2 * 32 + 12,
+                y: 48 + 20,
+                width: 20,
+                height: 28,
+            }
+        );
+        assert_eq!(
+            centered_corner_rect(icon_size, cut_pos, Corner::SouthEast),
+            Rect {
+                x: 2,
+                y: 6,
+                width: 20,
+                height: 28,
+            }
+        );
+    }
+
+    #[test]
+    fn grid_wrapping() {
+        let icon_size = IconSize { x: 32, y: 32 };
+        let grid = InputGrid {
+            layout: InputLayout::Horizontal,
+            columns: Some(4),
+            rows: 2,
+        };
+        // the second row of the second frame
+        assert_eq!(
+            tile_rect(icon_size, grid, 5, 1),
+            Rect {
+                x: 32,
+                y: 3 * 32,
+                width: 32,
+                height: 32,
+            }
+        );
+        let vertical = InputGrid {
+            layout: InputLayout::Vertical,
+            ..grid
+        };
+        assert_eq!(
+            tile_rect(icon_size, vertical, 5, 1),
+            Rect {
+                x: 3 * 32,
+                y: 32,
+                width: 32,
+                height: 32,
+            }
+        );
+    }
+}
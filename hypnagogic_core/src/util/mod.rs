@@ -1,22 +1,82 @@
+use std::collections::BTreeMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use toml::map::Map;
 use toml::Value;
 
 pub mod adjacency;
+pub mod animation;
+pub mod aseprite;
+pub mod blend;
 pub mod color;
 pub mod corners;
 pub mod delays;
+pub mod dmi_encoding;
+pub mod icon_diff;
 pub mod icon_ops;
+pub mod image_hash;
+pub mod layout;
+pub mod state_inventory;
+pub mod state_names;
 
 #[tracing::instrument]
 pub(crate) fn deep_merge_toml(first: &mut Value, second: Value) {
-    match (first, second) {
-        (first @ &mut Value::Table(_), Value::Table(second)) => {
+    deep_merge_toml_with(first, second, &BTreeMap::new());
+}
+
+/// How a value is merged on top of the one it's replacing
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+    /// Tables are merged key by key, anything else is replaced
+    #[default]
+    Merge,
+    /// Replaced wholesale, even tables
+    Replace,
+    /// Arrays are added on to the end of the array they're merged over,
+    /// anything else is merged like [`MergeStrategy::Merge`]
+    Append,
+}
+
+/// Deep merges `second` over `first`, using the strategy in `strategies` for
+/// any value whose dotted path (like `prefabs` or `animation.delays`) is in
+/// it
+pub(crate) fn deep_merge_toml_with(
+    first: &mut Value,
+    second: Value,
+    strategies: &BTreeMap<String, MergeStrategy>,
+) {
+    merge_at(first, second, strategies, "");
+}
+
+fn merge_at(
+    first: &mut Value,
+    second: Value,
+    strategies: &BTreeMap<String, MergeStrategy>,
+    path: &str,
+) {
+    let strategy = strategies.get(path).copied().unwrap_or_default();
+    match (strategy, first, second) {
+        (MergeStrategy::Replace, first, second) => *first = second,
+        (MergeStrategy::Append, Value::Array(first), Value::Array(second)) => first.extend(second),
+        (_, first @ &mut Value::Table(_), Value::Table(second)) => {
             let first = first.as_table_mut().unwrap();
             for (k, v) in second {
-                deep_merge_toml(first.entry(k).or_insert(Value::Table(Map::new())), v);
+                let inner_path = if path.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{path}.{k}")
+                };
+                merge_at(
+                    first.entry(k).or_insert(Value::Table(Map::new())),
+                    v,
+                    strategies,
+                    &inner_path,
+                );
             }
         }
-        (first, second) => *first = second,
+        (_, first, second) => *first = second,
     }
 }
 
@@ -25,12 +85,43 @@ pub fn repeat_for<T: Clone>(to_repeat: &[T], amount: usize) -> Vec<T> {
     to_repeat.iter().cycle().take(amount).cloned().collect()
 }
 
+/// Swaps anything that isn't alphanumeric, `-` or `_` out for `_`, so icon
+/// state names can be used in file names
+#[must_use]
+pub fn file_safe_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Levenshtein distance between `first` and `second`, counted in chars
+#[must_use]
+pub fn edit_distance(first: &str, second: &str) -> usize {
+    let second: Vec<char> = second.chars().collect();
+    let mut previous: Vec<usize> = (0..=second.len()).collect();
+    for (i, first_char) in first.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, second_char) in second.iter().enumerate() {
+            let substitution = previous[j] + usize::from(first_char != *second_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[second.len()]
+}
+
 #[cfg(test)]
 mod test {
 
     use toml::Value;
 
-    use crate::util::deep_merge_toml;
+    use super::*;
 
     #[test]
     fn deep_merge_simple() {
@@ -155,4 +246,49 @@ mod test {
 
         assert_eq!(left, expected);
     }
+
+    #[test]
+    fn merge_strategies() {
+        let mut left: Value = toml::from_str(
+            r"
+            extract = [1, 2]
+            [prefabs]
+            1 = 5
+            2 = 6
+            [positions]
+            convex = 0
+            concave = 1
+            ",
+        )
+        .unwrap();
+        let right: Value = toml::from_str(
+            r"
+            extract = [3]
+            [prefabs]
+            3 = 7
+            [positions]
+            convex = 2
+            ",
+        )
+        .unwrap();
+        let strategies = BTreeMap::from([
+            ("extract".to_string(), MergeStrategy::Append),
+            ("positions".to_string(), MergeStrategy::Replace),
+        ]);
+        deep_merge_toml_with(&mut left, right, &strategies);
+
+        let expected: Value = toml::from_str(
+            r"
+            extract = [1, 2, 3]
+            [prefabs]
+            1 = 5
+            2 = 6
+            3 = 7
+            [positions]
+            convex = 2
+            ",
+        )
+        .unwrap();
+        assert_eq!(left, expected);
+    }
 }
@@ -0,0 +1,69 @@
+use dmi::icon::{Icon, IconState};
+use serde::Serialize;
+
+/// What produced an icon state, as far as the operation that made it knows
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StateOrigin {
+    /// Assembled from corners
+    Junction { junction: u8 },
+    /// Copied from a prefab in the input
+    Prefab { junction: u8 },
+    /// A generated map icon
+    MapIcon,
+    /// A junction emitted again by a size override
+    Override { name: String, junction: u8 },
+    /// A diagonal wall copied from the input
+    DiagonalWall { junction: u8 },
+    /// The top of a junction's wall, by itself
+    WallTop { junction: u8 },
+    /// Anything the operation doesn't describe
+    Unknown,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct InventoryState {
+    pub name: String,
+    pub dirs: u8,
+    pub frames: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delays: Option<Vec<f32>>,
+    pub rewind: bool,
+    pub origin: StateOrigin,
+}
+
+/// Every state in a dmi, for linters and code generation to consume without
+/// having to parse the dmi
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StateInventory {
+    pub width: u32,
+    pub height: u32,
+    pub states: Vec<InventoryState>,
+}
+
+impl StateInventory {
+    /// Lists every state of `icon`, using `origin` to work out where each one
+    /// came from
+    #[must_use]
+    pub fn new(icon: &Icon, origin: impl Fn(&IconState) -> StateOrigin) -> Self {
+        let states = icon
+            .states
+            .iter()
+            .map(|state| {
+                InventoryState {
+                    name: state.name.clone(),
+                    dirs: state.dirs,
+                    frames: state.frames,
+                    delays: state.delay.clone(),
+                    rewind: state.rewind,
+                    origin: origin(state),
+                }
+            })
+            .collect();
+        Self {
+            width: icon.width,
+            height: icon.height,
+            states,
+        }
+    }
+}
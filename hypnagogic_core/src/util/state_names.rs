@@ -0,0 +1,333 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::util::adjacency::Adjacency;
+
+/// Format used for the names of junction states when none is set, giving
+/// names like `wall-cardinal-12`
+pub const DEFAULT_STATE_NAME_FORMAT: &str = "{prefix}-{tag}-{bits}";
+
+/// Formats that can be set by name instead of written out, for matching the
+/// naming of a particular codebase
+const PRESETS: &[(&str, &str)] = &[
+    ("default", DEFAULT_STATE_NAME_FORMAT),
+    // goonstation's smoothing appends the connected cardinals straight on to
+    // the icon state prefix, like `wall12`, with the same bits for each dir
+    ("goonstation", "{prefix}{cardinal_bits}"),
+];
+
+/// Characters that are dropped along with a placeholder that's empty, so an
+/// unset prefix doesn't leave a stray `-` behind
+const SEPARATORS: &[char] = &['-', '_', '.', ' '];
+
+/// Whether `name` can be written in to a dmi's metadata as it is
+#[must_use]
+pub fn fits_in_dmi(name: &str) -> bool {
+    !name.contains(['"', '\\']) && !name.chars().any(char::is_control)
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum StateNameFormatError {
+    #[error("`{{` in state name format `{0}` is never closed")]
+    Unclosed(String),
+    #[error(
+        "Unknown placeholder `{{{0}}}`, expected one of {{prefix}}, {{tag}}, {{bits}}, \
+         {{cardinal_bits}}, {{cardinal_letters}} or {{diagonal_letters}}"
+    )]
+    UnknownPlaceholder(String),
+    #[error(
+        "`{0}` has no placeholders and isn't a preset, expected a preset like `goonstation` or a \
+         format like `{{prefix}}-{{bits}}`"
+    )]
+    UnknownPreset(String),
+    #[error("Placeholder `{{{0}}}` has an invalid padding, it should look like `{{bits:03}}`")]
+    InvalidPadding(String),
+}
+
+/// Something filled in when naming a state
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Field {
+    /// The operation's `output_name`
+    Prefix,
+    /// What sets the state apart from the regular one for the same junction,
+    /// like `cardinal` or the name of a size override
+    Tag,
+    /// The junction as a number, zero padded to the given width
+    Bits(usize),
+    /// The cardinals in the junction as a number from 0 to 15, zero padded to
+    /// the given width
+    CardinalBits(usize),
+    /// The cardinals in the junction, as `N`, `S`, `E` and `W` in that order
+    CardinalLetters,
+    /// The diagonals in the junction, as `NE`, `SE`, `SW` and `NW` in that
+    /// order
+    DiagonalLetters,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Piece {
+    Literal(String),
+    Field(Field),
+}
+
+/// How junction states are named, written with placeholders like
+/// `{prefix}-{bits}` or as the name of one of the [`PRESETS`]. A placeholder
+/// that's empty, like `{prefix}` without an `output_name`, takes the separator
+/// after it (or before it, at the end) with it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StateNameFormat {
+    format: String,
+    pieces: Vec<Piece>,
+}
+
+impl StateNameFormat {
+    /// Names the state for `junction`
+    #[must_use]
+    pub fn name(&self, prefix: Option<&str>, tag: Option<&str>, junction: Adjacency) -> String {
+        let values: Vec<String> = self
+            .pieces
+            .iter()
+            .map(|piece| {
+                match piece {
+                    Piece::Literal(literal) => literal.clone(),
+                    Piece::Field(field) => field_value(*field, prefix, tag, junction),
+                }
+            })
+            .collect();
+        let is_separator = |index: usize| {
+            match &self.pieces[index] {
+                Piece::Literal(literal) => literal.chars().all(|c| SEPARATORS.contains(&c)),
+                Piece::Field(_) => false,
+            }
+        };
+        let mut dropped = vec![false; values.len()];
+        for (index, piece) in self.pieces.iter().enumerate() {
+            if !matches!(piece, Piece::Field(_)) || !values[index].is_empty() {
+                continue;
+            }
+            if index + 1 < values.len() && is_separator(index + 1) {
+                dropped[index + 1] = true;
+            } else if index + 1 == values.len() && index > 0 && is_separator(index - 1) {
+                dropped[index - 1] = true;
+            }
+        }
+        values
+            .into_iter()
+            .zip(dropped)
+            .filter(|(_, dropped)| !dropped)
+            .map(|(value, _)| value)
+            .collect()
+    }
+}
+
+fn field_value(
+    field: Field,
+    prefix: Option<&str>,
+    tag: Option<&str>,
+    junction: Adjacency,
+) -> String {
+    match field {
+        Field::Prefix => prefix.unwrap_or_default().to_string(),
+        Field::Tag => tag.unwrap_or_default().to_string(),
+        Field::Bits(width) => format!("{:0width$}", junction.bits()),
+        Field::CardinalBits(width) => {
+            format!("{:0width$}", (junction & Adjacency::CARDINALS).bits())
+        }
+        Field::CardinalLetters => {
+            letters(
+                junction,
+                &[
+                    (Adjacency::N, "N"),
+                    (Adjacency::S, "S"),
+                    (Adjacency::E, "E"),
+                    (Adjacency::W, "W"),
+                ],
+            )
+        }
+        Field::DiagonalLetters => {
+            letters(
+                junction,
+                &[
+                    (Adjacency::NE, "NE"),
+                    (Adjacency::SE, "SE"),
+                    (Adjacency::SW, "SW"),
+                    (Adjacency::NW, "NW"),
+                ],
+            )
+        }
+    }
+}
+
+fn letters(junction: Adjacency, dirs: &[(Adjacency, &str)]) -> String {
+    dirs.iter()
+        .filter(|(dir, _)| junction.contains(*dir))
+        .map(|(_, letters)| *letters)
+        .collect()
+}
+
+fn parse_field(placeholder: &str) -> Result<Field, StateNameFormatError> {
+    let (name, padding) = match placeholder.split_once(':') {
+        Some((name, padding)) => (name, Some(padding)),
+        None => (placeholder, None),
+    };
+    let field = match name {
+        "prefix" => Field::Prefix,
+        "tag" => Field::Tag,
+        "bits" => Field::Bits(0),
+        "cardinal_bits" => Field::CardinalBits(0),
+        "cardinal_letters" => Field::CardinalLetters,
+        "diagonal_letters" => Field::DiagonalLetters,
+        _ => {
+            return Err(StateNameFormatError::UnknownPlaceholder(
+                placeholder.to_string(),
+            ))
+        }
+    };
+    match (field, padding) {
+        (field, None) => Ok(field),
+        (Field::Bits(_) | Field::CardinalBits(_), Some(padding)) => {
+            padding
+                .strip_prefix('0')
+                .and_then(|width| width.parse().ok())
+                .map(|width| {
+                    match field {
+                        Field::CardinalBits(_) => Field::CardinalBits(width),
+                        _ => Field::Bits(width),
+                    }
+                })
+                .ok_or_else(|| StateNameFormatError::InvalidPadding(placeholder.to_string()))
+        }
+        (_, Some(_)) => {
+            Err(StateNameFormatError::InvalidPadding(
+                placeholder.to_string(),
+            ))
+        }
+    }
+}
+
+impl FromStr for StateNameFormat {
+    type Err = StateNameFormatError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        let preset = PRESETS.iter().find(|(name, _)| *name == format);
+        if preset.is_none() && !format.contains('{') {
+            return Err(StateNameFormatError::UnknownPreset(format.to_string()));
+        }
+        let mut pieces = vec![];
+        let mut rest = preset.map_or(format, |(_, preset_format)| *preset_format);
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                pieces.push(Piece::Literal(rest[..start].to_string()));
+            }
+            let Some(end) = rest[start..].find('}') else {
+                return Err(StateNameFormatError::Unclosed(format.to_string()));
+            };
+            pieces.push(Piece::Field(parse_field(&rest[start + 1..start + end])?));
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            pieces.push(Piece::Literal(rest.to_string()));
+        }
+        Ok(Self {
+            format: format.to_string(),
+            pieces,
+        })
+    }
+}
+
+impl Default for StateNameFormat {
+    fn default() -> Self {
+        DEFAULT_STATE_NAME_FORMAT.parse().unwrap()
+    }
+}
+
+impl fmt::Display for StateNameFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format)
+    }
+}
+
+impl Serialize for StateNameFormat {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.format)
+    }
+}
+
+impl<'de> Deserialize<'de> for StateNameFormat {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let format = String::deserialize(deserializer)?;
+        format.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl JsonSchema for StateNameFormat {
+    fn schema_name() -> Cow<'static, str> {
+        "StateNameFormat".into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        String::json_schema(generator)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn default_names() {
+        let format = StateNameFormat::default();
+        let junction = Adjacency::N | Adjacency::S;
+        assert_eq!(format.name(None, None, junction), "3");
+        assert_eq!(format.name(Some("wall"), None, junction), "wall-3");
+        assert_eq!(format.name(None, Some("cardinal"), junction), "cardinal-3");
+        assert_eq!(
+            format.name(Some("wall"), Some("cardinal"), junction),
+            "wall-cardinal-3"
+        );
+    }
+
+    #[test]
+    fn custom_names() {
+        let format: StateNameFormat = "{prefix}_{cardinal_letters}".parse().unwrap();
+        let junction = Adjacency::N | Adjacency::E | Adjacency::NE;
+        assert_eq!(format.name(Some("wall"), None, junction), "wall_NE");
+        assert_eq!(format.name(Some("wall"), None, Adjacency::empty()), "wall");
+
+        let format: StateNameFormat = "{prefix}{bits:03}{diagonal_letters}".parse().unwrap();
+        assert_eq!(format.name(Some("wall"), None, junction), "wall021NE");
+
+        let format: StateNameFormat = "goonstation".parse().unwrap();
+        assert_eq!(format.name(Some("wall"), None, junction), "wall5");
+        assert_eq!(format.name(Some("wall"), None, Adjacency::all()), "wall15");
+        assert_eq!(format.to_string(), "goonstation");
+
+        assert!(matches!(
+            "{prefix}-{junction}".parse::<StateNameFormat>(),
+            Err(StateNameFormatError::UnknownPlaceholder(_))
+        ));
+        assert!(matches!(
+            "goon".parse::<StateNameFormat>(),
+            Err(StateNameFormatError::UnknownPreset(_))
+        ));
+        assert!(matches!(
+            "{prefix:03}".parse::<StateNameFormat>(),
+            Err(StateNameFormatError::InvalidPadding(_))
+        ));
+        assert!(matches!(
+            "{prefix".parse::<StateNameFormat>(),
+            Err(StateNameFormatError::Unclosed(_))
+        ));
+    }
+}